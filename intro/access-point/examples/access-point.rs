@@ -0,0 +1,443 @@
+#![no_std]
+#![no_main]
+
+use core::{cell::RefCell, convert::Infallible, fmt};
+use critical_section::Mutex;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    time::{Duration, Instant},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{
+        event::{ApStaConnected, ApStaDisconnected, EventExt},
+        AccessPointConfig, AuthMethod, ModeConfig, WifiDevice, WifiError,
+    },
+    InitializationError,
+};
+use example_support::{create_interface, timestamp};
+use heapless::Vec;
+
+use smoltcp::{
+    iface::{Interface, SocketHandle, SocketSet, SocketStorage},
+    socket::{tcp, udp},
+    wire::{IpAddress, IpCidr, Ipv4Address},
+};
+
+// ANCHOR: config
+/// The name of the network, e.g. `AP_SSID=field-kit`
+const AP_SSID: &str = match option_env!("AP_SSID") {
+    Some(ssid) => ssid,
+    None => "esp-ap",
+};
+/// The network is protected with WPA2 if there is a password, it needs at least 8 characters
+const AP_PASSWORD: Option<&str> = option_env!("AP_PASSWORD");
+/// The address of the device in its own network, stations get the addresses after it
+const AP_ADDRESS: [u8; 4] = [192, 168, 4, 1];
+/// Stations that can join at the same time, each of them gets an address
+const MAX_STATIONS: usize = 4;
+// ANCHOR_END: config
+
+/// The port of the echo service, the standard one from RFC 862
+const ECHO_PORT: u16 = 7;
+/// Connections to the echo service at the same time
+const CONNECTIONS: usize = 3;
+/// Connections of stations that went away without closing them are dropped after this time
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the station list is printed if nothing changes
+const REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+// ANCHOR: stations
+/// A station that joined the access point
+#[derive(Debug, Clone, Copy)]
+struct Station {
+    mac: [u8; 6],
+    /// The association id the access point gave the station
+    aid: u8,
+    /// When the station joined, in milliseconds since boot
+    since: u64,
+}
+
+/// The stations that joined, kept up to date by the Wi-Fi event handlers
+///
+/// The handlers run in the task of the Wi-Fi driver, not in our loop, so the list is shared
+/// through a critical section, like the button in `button-interrupt`.
+static STATIONS: Mutex<RefCell<Vec<Station, MAX_STATIONS>>> = Mutex::new(RefCell::new(Vec::new()));
+
+/// Starts keeping track of the stations, the handlers stay registered when Wi-Fi starts over
+fn track_stations() {
+    critical_section::with(|cs| STATIONS.borrow_ref_mut(cs).clear());
+
+    // the previous handlers are our own from the last run, or the default ones that do nothing
+    let _ = ApStaConnected::replace_handler(|event| {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(event.mac());
+        let station = Station {
+            mac,
+            aid: event.aid(),
+            since: Instant::now().duration_since_epoch().as_millis(),
+        };
+        // the access point doesn't accept more than `MAX_STATIONS` stations
+        critical_section::with(|cs| STATIONS.borrow_ref_mut(cs).push(station).ok());
+    });
+    let _ = ApStaDisconnected::replace_handler(|event| {
+        critical_section::with(|cs| {
+            STATIONS
+                .borrow_ref_mut(cs)
+                .retain(|station| station.mac != event.mac())
+        });
+    });
+}
+// ANCHOR_END: stations
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Runs the access point with the DHCP server and the echo service
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+
+    // ANCHOR: access_point
+    let mut device = interfaces.ap;
+    let mut iface = create_interface(&mut device);
+    // nobody hands out an address to us, we are the DHCP server
+    iface.update_ip_addrs(|addrs| {
+        let address = IpAddress::Ipv4(Ipv4Address::from(AP_ADDRESS));
+        addrs.push(IpCidr::new(address, 24)).ok();
+    });
+
+    let ap_config = AccessPointConfig::default()
+        .with_ssid(AP_SSID.into())
+        .with_max_connections(MAX_STATIONS as u16);
+    let ap_config = match AP_PASSWORD {
+        Some(password) if password.len() < 8 => return Err(Error::PasswordTooShort),
+        Some(password) => ap_config
+            .with_auth_method(AuthMethod::Wpa2Personal)
+            .with_password(password.into()),
+        None => ap_config.with_auth_method(AuthMethod::None),
+    };
+    controller.set_config(&ModeConfig::AccessPoint(ap_config))?;
+    // ANCHOR_END: access_point
+
+    // ANCHOR: sockets
+    // the DHCP server needs a UDP socket, the echo service a TCP socket per connection
+    let mut dhcp_rx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dhcp_rx_payload = [0u8; 1024];
+    let mut dhcp_tx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dhcp_tx_payload = [0u8; 1024];
+    let mut dhcp_socket = udp::Socket::new(
+        udp::PacketBuffer::new(&mut dhcp_rx_metadata[..], &mut dhcp_rx_payload[..]),
+        udp::PacketBuffer::new(&mut dhcp_tx_metadata[..], &mut dhcp_tx_payload[..]),
+    );
+    dhcp_socket.bind(dhcp_server::SERVER_PORT)?;
+
+    let mut socket_set_entries: [SocketStorage; CONNECTIONS + 1] = Default::default();
+    let mut sockets = SocketSet::new(&mut socket_set_entries[..]);
+    let dhcp = sockets.add(dhcp_socket);
+
+    let mut rx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut tx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut echo_sockets: Vec<SocketHandle, CONNECTIONS> = Vec::new();
+    for (rx_buffer, tx_buffer) in rx_buffers.iter_mut().zip(tx_buffers.iter_mut()) {
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(&mut rx_buffer[..]),
+            tcp::SocketBuffer::new(&mut tx_buffer[..]),
+        );
+        // a station that leaves doesn't close its connections, probe them and drop them once
+        // the station doesn't answer anymore
+        let idle_timeout = smoltcp::time::Duration::from_millis(IDLE_TIMEOUT.as_millis());
+        socket.set_keep_alive(Some(idle_timeout / 2));
+        socket.set_timeout(Some(idle_timeout));
+        echo_sockets.push(sockets.add(socket)).ok();
+    }
+    // ANCHOR_END: sockets
+
+    let mut network = Network {
+        iface,
+        device,
+        sockets,
+    };
+    // there is no internet behind the access point, and no DNS server on it
+    let dhcp_config = dhcp_server::Config {
+        dns_server: None,
+        ..dhcp_server::Config::new(AP_ADDRESS)
+    };
+    let mut dhcp_server: dhcp_server::Server<MAX_STATIONS> = dhcp_server::Server::new(dhcp_config);
+
+    track_stations();
+    controller.start()?;
+    println!(
+        "Join the network {} and connect to {}:{}",
+        AP_SSID,
+        Ipv4Address::from(AP_ADDRESS),
+        ECHO_PORT
+    );
+
+    // ANCHOR: serve
+    let mut report = Report::default();
+    loop {
+        network.poll();
+        serve_dhcp(&mut network, dhcp, &mut dhcp_server);
+
+        // a failing connection doesn't affect the others, it is dropped and the socket reused
+        for &handle in &echo_sockets {
+            let socket = network.sockets.get_mut::<tcp::Socket>(handle);
+            if let Err(err) = echo(socket) {
+                println!("Echo connection failed: {:?}", err);
+                socket.abort();
+            }
+        }
+
+        report.update(&dhcp_server);
+    }
+    // ANCHOR_END: serve
+}
+
+// ANCHOR: dhcp
+/// Answers the DHCP messages of stations joining the access point
+fn serve_dhcp(
+    network: &mut Network<'_, '_>,
+    handle: SocketHandle,
+    server: &mut dhcp_server::Server<MAX_STATIONS>,
+) {
+    let socket = network.sockets.get_mut::<udp::Socket>(handle);
+    let mut message = [0u8; 576];
+    let mut reply = [0u8; 576];
+    loop {
+        let len = match socket.recv_slice(&mut message) {
+            Ok((len, _)) => len,
+            Err(udp::RecvError::Exhausted) => break,
+            // too large for a DHCP message, smoltcp drops it
+            Err(udp::RecvError::Truncated) => continue,
+        };
+        let now = Instant::now().duration_since_epoch().as_millis();
+        match server.handle(now, &message[..len], &mut reply) {
+            Ok(Some((len, destination))) => {
+                // stations without an address get broadcasts, smoltcp sends them to every station
+                let destination = IpAddress::Ipv4(Ipv4Address::from(destination));
+                if let Err(err) =
+                    socket.send_slice(&reply[..len], (destination, dhcp_server::CLIENT_PORT))
+                {
+                    println!("Dropping DHCP reply: {:?}", err);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => println!("Ignoring DHCP message: {:?}", err),
+        }
+    }
+}
+// ANCHOR_END: dhcp
+
+// ANCHOR: echo
+/// Sends back whatever the client sends, until it closes its end of the connection
+fn echo(socket: &mut tcp::Socket<'_>) -> Result<(), Error> {
+    // a closed socket waits for the next connection
+    if !socket.is_open() {
+        socket.listen(ECHO_PORT)?;
+        return Ok(());
+    }
+
+    // only take what fits into the send buffer, the rest waits in the receive buffer and the
+    // client has to slow down
+    let mut buffer = [0u8; 512];
+    while socket.can_recv() && socket.can_send() {
+        let space = (socket.send_capacity() - socket.send_queue()).min(buffer.len());
+        let len = socket.recv_slice(&mut buffer[..space])?;
+        socket.send_slice(&buffer[..len])?;
+    }
+
+    // the client is done, close our end once everything it sent is on its way back
+    if socket.state() == tcp::State::CloseWait && !socket.can_recv() {
+        socket.close();
+    }
+    Ok(())
+}
+// ANCHOR_END: echo
+
+// ANCHOR: report
+/// Prints the stations with their addresses whenever they change, and every `REPORT_INTERVAL`
+#[derive(Default)]
+struct Report {
+    /// The stations and leases last printed
+    stations: Vec<[u8; 6], MAX_STATIONS>,
+    leases: usize,
+    printed_at: Option<Instant>,
+}
+
+impl Report {
+    fn update(&mut self, dhcp_server: &dhcp_server::Server<MAX_STATIONS>) {
+        let stations = critical_section::with(|cs| STATIONS.borrow_ref(cs).clone());
+        let now = Instant::now().duration_since_epoch().as_millis();
+        let leases = dhcp_server.leases(now).filter(|lease| lease.bound).count();
+
+        for station in &stations {
+            if !self.stations.contains(&station.mac) {
+                println!("{} joined", Mac(station.mac));
+            }
+        }
+        for mac in &self.stations {
+            if !stations.iter().any(|station| station.mac == *mac) {
+                println!("{} left", Mac(*mac));
+            }
+        }
+
+        let changed = stations.len() != self.stations.len()
+            || stations
+                .iter()
+                .any(|station| !self.stations.contains(&station.mac))
+            || leases != self.leases;
+        let due = self
+            .printed_at
+            .is_none_or(|printed_at| printed_at.elapsed() >= REPORT_INTERVAL);
+        if !changed && !due {
+            return;
+        }
+
+        println!("{} of {} stations:", stations.len(), MAX_STATIONS);
+        for station in &stations {
+            // the address is only known once the station asked the DHCP server for one
+            let lease = dhcp_server
+                .leases(now)
+                .find(|lease| lease.bound && lease.mac == station.mac);
+            let address = lease.map(|lease| Ipv4Address::from(lease.address));
+            let hostname = lease.and_then(|lease| lease.hostname()).unwrap_or("-");
+            println!(
+                "  {}  aid {}  {:<15}  {:<20}  {} s",
+                Mac(station.mac),
+                station.aid,
+                Address(address),
+                hostname,
+                (now - station.since) / 1_000
+            );
+        }
+
+        self.stations = stations.iter().map(|station| station.mac).collect();
+        self.leases = leases;
+        self.printed_at = Some(Instant::now());
+    }
+}
+// ANCHOR_END: report
+
+/// Formats a MAC address as `aa:bb:cc:dd:ee:ff`
+struct Mac([u8; 6]);
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// Formats the address of a station, `-` if it has none yet
+struct Address(Option<Ipv4Address>);
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(address) => address.fmt(f),
+            None => f.pad("-"),
+        }
+    }
+}
+
+/// The smoltcp interface and our sockets
+struct Network<'s, 'd> {
+    iface: Interface,
+    device: WifiDevice<'d>,
+    sockets: SocketSet<'s>,
+}
+
+impl Network<'_, '_> {
+    /// Sends and receives packets
+    fn poll(&mut self) {
+        self.iface
+            .poll(timestamp(), &mut self.device, &mut self.sockets);
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// WPA2 needs a password of at least 8 characters
+    PasswordTooShort,
+    /// A UDP socket couldn't be bound
+    Bind(udp::BindError),
+    /// A TCP socket couldn't listen for connections
+    Listen(tcp::ListenError),
+    /// Reading from a TCP socket failed
+    Recv(tcp::RecvError),
+    /// Writing to a TCP socket failed
+    Send(tcp::SendError),
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<udp::BindError> for Error {
+    fn from(err: udp::BindError) -> Self {
+        Self::Bind(err)
+    }
+}
+
+impl From<tcp::ListenError> for Error {
+    fn from(err: tcp::ListenError) -> Self {
+        Self::Listen(err)
+    }
+}
+
+impl From<tcp::RecvError> for Error {
+    fn from(err: tcp::RecvError) -> Self {
+        Self::Recv(err)
+    }
+}
+
+impl From<tcp::SendError> for Error {
+    fn from(err: tcp::SendError) -> Self {
+        Self::Send(err)
+    }
+}
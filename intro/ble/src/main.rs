@@ -0,0 +1,270 @@
+#![no_std]
+#![no_main]
+
+use core::convert::Infallible;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    efuse::Efuse,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    ram,
+    timer::timg::TimerGroup,
+};
+use esp_println::println;
+use esp_radio::ble::controller::{BleConnector, BleConnectorError};
+use trouble_host::prelude::*;
+
+/// The name in the advertisements and the GAP service, at most 22 bytes
+const NAME: &str = "esp32c3-board";
+/// We serve one central at a time and advertise again when it disconnects
+const CONNECTIONS: usize = 1;
+/// The attribute protocol runs on a fixed channel, we don't need any others
+const L2CAP_CHANNELS: usize = 1;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+/// Our own service, standard services have 16-bit UUIDs assigned by the Bluetooth SIG
+const BOARD_SERVICE: u128 = 0x2a7d1c00_5f8b_4e2a_9c61_3b0f4d8e7a10;
+
+/// The attribute table: the GAP and GATT services, followed by our own
+#[gatt_server]
+struct Server {
+    board: BoardService,
+}
+
+/// The LED and the button of the board
+#[gatt_service(uuid = BOARD_SERVICE)]
+struct BoardService {
+    /// Writing `true` switches the LED on, `false` off
+    #[characteristic(
+        uuid = "2a7d1c01-5f8b-4e2a-9c61-3b0f4d8e7a10",
+        read,
+        write,
+        value = false
+    )]
+    led: bool,
+    /// `true` while the button is pressed, every change is notified to subscribed centrals
+    #[characteristic(
+        uuid = "2a7d1c02-5f8b-4e2a-9c61-3b0f4d8e7a10",
+        read,
+        notify,
+        value = false
+    )]
+    button: bool,
+}
+
+/// The state of the button, set by the `watch_button` task and notified by `serve`
+///
+/// Only the latest state is kept. While nobody is connected, `serve` stores it as the value of
+/// the characteristic, so a central that connects reads the current state.
+static PRESSED: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the LED from `blinky` and the button from `button-interrupt`
+    let mut led = Output::new(peripherals.GPIO7, Level::Low, OutputConfig::default());
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+    spawner.spawn(watch_button(button)).unwrap();
+
+    let esp_radio_ctrl = esp_radio::init().unwrap();
+    let connector = BleConnector::new(&esp_radio_ctrl, peripherals.BT, Default::default()).unwrap();
+    // the host talks HCI to the controller in the radio, with up to 20 commands in flight
+    let controller: ExternalController<_, 20> = ExternalController::new(connector);
+
+    // a static random address has to have the two most significant bits set, the bytes are in
+    // little-endian order
+    let mut address = Efuse::mac_address();
+    address.reverse();
+    address[5] |= 0xc0;
+
+    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS, L2CAP_CHANNELS> =
+        HostResources::new();
+    let stack =
+        trouble_host::new(controller, &mut resources).set_random_address(Address::random(address));
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    println!("BLE address {:?}", Address::random(address));
+
+    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+        name: NAME,
+        appearance: &appearance::UNKNOWN,
+    }))
+    .unwrap();
+
+    // the runner processes the HCI events and has to run as long as the stack is in use, `serve`
+    // only returns if something went wrong
+    loop {
+        let err = match select(runner.run(), serve(&mut peripheral, &server, &mut led)).await {
+            Either::First(Ok(())) => continue,
+            Either::First(Err(err)) => Error::from(err),
+            Either::Second(Err(err)) => err,
+        };
+        println!("Error: {:?}, restarting in 5 seconds", err);
+        Timer::after(Duration::from_secs(5)).await;
+    }
+}
+
+/// Advertises, and serves the connected centrals one after another
+async fn serve<C: Controller<Error = BleConnectorError>>(
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+    server: &Server<'_>,
+    led: &mut Output<'_>,
+) -> Result<Infallible, Error> {
+    loop {
+        let conn = match select(advertise(peripheral, server), track_button(server)).await {
+            Either::First(conn) => conn?,
+            Either::Second(Err(err)) => return Err(err),
+        };
+        println!("Connected to {:?}", conn.raw().peer_address());
+
+        handle(&conn, server, led).await?;
+    }
+}
+
+/// Keeps the value of the button characteristic up to date while nobody is connected
+async fn track_button(server: &Server<'_>) -> Result<Infallible, Error> {
+    loop {
+        let pressed = PRESSED.wait().await;
+        server.set(&server.board.button, &pressed)?;
+    }
+}
+
+/// Advertises until a central connects
+async fn advertise<'a, 's, C: Controller<Error = BleConnectorError>>(
+    peripheral: &mut Peripheral<'a, C, DefaultPacketPool>,
+    server: &'s Server<'_>,
+) -> Result<GattConnection<'a, 's, DefaultPacketPool>, Error> {
+    // advertisements are at most 31 bytes, the name goes into the scan response
+    let mut adv_data = [0; 31];
+    let adv_len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ServiceUuids128(&[BOARD_SERVICE.to_le_bytes()]),
+        ],
+        &mut adv_data,
+    )
+    .map_err(trouble_host::Error::from)?;
+    let mut scan_data = [0; 31];
+    let scan_len = AdStructure::encode_slice(
+        &[AdStructure::CompleteLocalName(NAME.as_bytes())],
+        &mut scan_data,
+    )
+    .map_err(trouble_host::Error::from)?;
+
+    println!("Advertising as {}", NAME);
+    let advertiser = peripheral
+        .advertise(
+            &Default::default(),
+            Advertisement::ConnectableScannableUndirected {
+                adv_data: &adv_data[..adv_len],
+                scan_data: &scan_data[..scan_len],
+            },
+        )
+        .await?;
+    // the controller stops advertising as soon as a central connects
+    let conn = advertiser.accept().await?.with_attribute_server(server)?;
+    Ok(conn)
+}
+
+/// Processes the requests of a connected central and notifies it about the button
+///
+/// Returns once the central disconnected.
+async fn handle(
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    server: &Server<'_>,
+    led: &mut Output<'_>,
+) -> Result<(), Error> {
+    let board = &server.board;
+    loop {
+        match select(conn.next(), PRESSED.wait()).await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                println!("Disconnected: {:?}", reason);
+                return Ok(());
+            }
+            Either::First(GattConnectionEvent::Gatt { event }) => {
+                // a write has to be accepted before the server stores the new value
+                let led_written =
+                    matches!(&event, GattEvent::Write(write) if write.handle() == board.led.handle);
+                event.accept()?.send().await;
+                if led_written {
+                    let on = server.get(&board.led)?;
+                    // Switch the LED on or off
+                    println!("LED switched {}", if on { "on" } else { "off" });
+                }
+            }
+            // e.g. updated connection parameters, nothing for us to do
+            Either::First(_) => {}
+            Either::Second(pressed) => {
+                // also updates the value that is read, notifications are only sent to a central
+                // that subscribed
+                // Notify the central with `board.button.notify`
+            }
+        }
+    }
+}
+
+/// Hands every change of the button to `serve`
+#[embassy_executor::task]
+async fn watch_button(mut button: Input<'static>) {
+    loop {
+        // this waits for the GPIO interrupt, the task doesn't run in the meantime
+        button.wait_for_any_edge().await;
+        // the contacts of the button bounce for a few milliseconds, ignore that
+        Timer::after(Duration::from_millis(50)).await;
+
+        // the button pulls GPIO9 to ground while it is pressed
+        let pressed = button.is_low();
+        println!("Button {}", if pressed { "pressed" } else { "released" });
+        PRESSED.signal(pressed);
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The controller in the radio reported an error
+    Controller(BleConnectorError),
+    /// The host stack reported an error, e.g. the connection broke down
+    Host(trouble_host::Error),
+}
+
+impl From<BleHostError<BleConnectorError>> for Error {
+    fn from(err: BleHostError<BleConnectorError>) -> Self {
+        match err {
+            BleHostError::Controller(err) => Self::Controller(err),
+            BleHostError::BleHost(err) => Self::Host(err),
+        }
+    }
+}
+
+impl From<trouble_host::Error> for Error {
+    fn from(err: trouble_host::Error) -> Self {
+        Self::Host(err)
+    }
+}
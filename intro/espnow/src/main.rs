@@ -0,0 +1,241 @@
+#![no_std]
+#![no_main]
+
+use core::{convert::Infallible, fmt};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::{FLASH, WIFI},
+    ram,
+    rng::Rng,
+    time::Instant,
+};
+use esp_println::println;
+use esp_radio::{
+    esp_now::{EspNow, EspNowError, EspNowWifiInterface, PeerInfo, BROADCAST_ADDRESS},
+    wifi::{ClientConfig, ModeConfig, WifiError},
+    InitializationError,
+};
+use esp_storage::FlashStorage;
+use espnow_link::{derive_key, Action, Address, Event, Link, Message};
+use example_support::{count_boot, open_store, StoreError};
+
+/// All boards have to use the same channel
+const CHANNEL: u8 = 1;
+/// The primary master key, which encrypts the local master keys
+///
+/// The key that authenticates the announcements is derived from it, so only boards with the key
+/// become peers. All boards need the same keys, change both of them for your own boards.
+const PMK: [u8; 16] = *b"no_std-train-pmk";
+/// The local master key, which encrypts the frames to and from peers
+const LMK: [u8; 16] = *b"no_std-train-lmk";
+/// Number of boards we keep track of, ESP-NOW encrypts the frames of up to 7 peers
+const PEERS: usize = 4;
+
+/// The contacts of the button bounce for a few milliseconds, changes within this time are ignored
+const DEBOUNCE_MS: u64 = 50;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the LED from `blinky` and the button from `button`
+    let mut led = Output::new(peripherals.GPIO7, Level::Low, OutputConfig::default());
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+
+    let mut wifi = peripherals.WIFI;
+    let mut flash = peripherals.FLASH;
+    example_support::restart(|| run(wifi.reborrow(), flash.reborrow(), &mut led, &button))
+}
+
+/// Finds the other boards and toggles their LEDs whenever the button is pressed
+fn run(
+    wifi: WIFI<'_>,
+    flash: FLASH<'_>,
+    led: &mut Output<'_>,
+    button: &Input<'_>,
+) -> Result<Infallible, Error> {
+    // ANCHOR: boot
+    // peers only accept announcements that are newer than the last one they got from us, the
+    // number of the start makes ours newer than the ones before the restart
+    let boot = count_boot(&mut open_store(FlashStorage::new(flash))?)?;
+    // ANCHOR_END: boot
+
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+
+    // ESP-NOW needs the radio in station mode, but we don't connect to an access point
+    controller.set_config(&ModeConfig::Client(ClientConfig::default()))?;
+    controller.start()?;
+
+    let address = interfaces.sta.mac_address();
+    let mut esp_now = interfaces.esp_now;
+    esp_now.set_channel(CHANNEL)?;
+    esp_now.set_pmk(&PMK)?;
+    println!(
+        "ESP-NOW version {} on channel {}, this board is {}",
+        esp_now.version()?,
+        CHANNEL,
+        Mac(address)
+    );
+
+    // a random start, so peers don't mistake our toggles after a restart for repeated ones
+    let seq = Rng::new().random() as u16;
+    // a key of its own for the announcements, the PMK only encrypts the keys of the peers
+    let key = derive_key(&PMK);
+    let mut link: Link<PEERS> = Link::new(espnow_link::Config::default(), address, key, seq, boot);
+
+    let mut pressed = false;
+    let mut changed_at = 0;
+    loop {
+        let now = Instant::now().duration_since_epoch().as_millis();
+
+        // the button pulls GPIO9 to ground while it is pressed
+        if button.is_low() != pressed && now - changed_at >= DEBOUNCE_MS {
+            pressed = !pressed;
+            changed_at = now;
+            if pressed {
+                println!("Button pressed, toggling {} peers", link.peers().count());
+                link.toggle_peers(now);
+            }
+        }
+
+        while let Some(frame) = esp_now.receive() {
+            let from = frame.info.src_address;
+            let broadcast = frame.info.dst_address == BROADCAST_ADDRESS;
+            match link.receive(now, from, broadcast, frame.data()) {
+                Ok(Some(event)) => handle(event, &esp_now, &mut link, led),
+                Ok(None) => {}
+                // e.g. another application using ESP-NOW nearby, or a board with other keys
+                Err(err) => println!("Ignoring a frame from {}: {:?}", Mac(from), err),
+            }
+        }
+
+        while let Some(action) = link.poll(now) {
+            match action {
+                Action::Broadcast(message) => send(&mut esp_now, &BROADCAST_ADDRESS, message)?,
+                Action::Send(peer, message) => send(&mut esp_now, &peer, message)?,
+                Action::Lost(peer) => {
+                    println!("Lost peer {}", Mac(peer));
+                    // the radio keeps the peer, which is no reason to start over
+                    if let Err(err) = esp_now.remove_peer(&peer) {
+                        println!("Couldn't unregister peer {}: {:?}", Mac(peer), err);
+                    }
+                }
+                Action::Unacknowledged(peer, seq) => {
+                    println!("{} didn't acknowledge toggle {}", Mac(peer), seq)
+                }
+            }
+        }
+    }
+}
+
+/// Carries out what a received message asks for
+fn handle(event: Event, esp_now: &EspNow<'_>, link: &mut Link<PEERS>, led: &mut Output<'_>) {
+    match event {
+        Event::Discovered(peer) => {
+            // Register the peer with `esp_now.add_peer`, so the frames to and from it are
+            // encrypted with `LMK`. Only frames to a registered peer can be encrypted, broadcasts
+            // never are.
+            // If that fails, `link.forget(peer)`, it's discovered again with its next
+            // announcement.
+            // esp_now.add_peer(PeerInfo { ... });
+            println!("Found peer {}", Mac(peer));
+        }
+        Event::Toggled { from, led: on } => {
+            // Switch the LED on or off
+            println!("{} toggled our LED, it's {}", Mac(from), state(on));
+        }
+        Event::Acknowledged { from, led } => {
+            println!("{} acknowledged, its LED is {}", Mac(from), state(led));
+        }
+    }
+}
+
+/// Sends `message` and waits until it's out
+///
+/// A peer's radio acknowledges every frame to it. If it doesn't, e.g. because the peer is out of
+/// range, the link repeats the message, so that is no reason to start over.
+fn send(esp_now: &mut EspNow<'_>, to: &Address, message: Message) -> Result<(), Error> {
+    match esp_now.send(to, &message.encode())?.wait() {
+        Ok(()) | Err(EspNowError::SendFailed) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn state(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Formats a MAC address as `aa:bb:cc:dd:ee:ff`
+struct Mac(Address);
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// ESP-NOW reported an error, e.g. a frame couldn't be queued
+    EspNow(EspNowError),
+    /// The store in the NVS partition couldn't be opened, read or written
+    Store(StoreError),
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<EspNowError> for Error {
+    fn from(err: EspNowError) -> Self {
+        Self::EspNow(err)
+    }
+}
+
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
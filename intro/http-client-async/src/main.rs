@@ -0,0 +1,175 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use embassy_executor::Spawner;
+use embassy_net::{
+    dns::DnsSocket,
+    tcp::client::{TcpClient, TcpClientState},
+    Runner, Stack, StackResources,
+};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Read;
+use embedded_nal_async::{Dns, TcpConnect};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock, interrupt::software::SoftwareInterruptControl, ram, rng::Rng,
+    timer::timg::TimerGroup,
+};
+use esp_println::{print, println};
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, ScanConfig, WifiController, WifiDevice, WifiEvent},
+    Controller,
+};
+use reqwless::{client::HttpClient, request::Method};
+use static_cell::StaticCell;
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+const URL: &str = "http://www.mobile-j.de/";
+
+esp_bootloader_esp_idf::esp_app_desc!();
+// tasks can only borrow data that lives forever
+static RADIO: StaticCell<Controller<'static>> = StaticCell::new();
+static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer, the scheduler and the Wifi controller
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let esp_radio_ctrl = RADIO.init(esp_radio::init().unwrap());
+    let (controller, interfaces) =
+        esp_radio::wifi::new(esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
+
+    // the network stack gets its address via DHCP
+    let mut dhcp_config = embassy_net::DhcpConfig::default();
+    dhcp_config.hostname = Some("esp-radio".try_into().unwrap());
+    let config = embassy_net::Config::dhcpv4(dhcp_config);
+
+    let rng = Rng::new();
+    let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+
+    let (stack, runner) = embassy_net::new(
+        interfaces.sta,
+        config,
+        RESOURCES.init(StackResources::new()),
+        seed,
+    );
+    spawner.spawn(connection(controller)).unwrap();
+    spawner.spawn(net_task(runner)).unwrap();
+    spawner.spawn(http_client(stack)).unwrap();
+
+    // the tasks do all the work, we only report changes of the IP configuration
+    loop {
+        println!("Wait to get an ip address");
+        stack.wait_config_up().await;
+        println!("got ip {:?}", stack.config_v4());
+
+        stack.wait_config_down().await;
+        println!("Lost ip address");
+    }
+}
+/// Keeps the Wi-Fi link up, reconnecting whenever the access point goes away
+#[embassy_executor::task]
+async fn connection(mut controller: WifiController<'static>) {
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config).unwrap();
+
+    println!("Starting wifi");
+    controller.start_async().await.unwrap();
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    println!("Start Wifi Scan");
+    let scan_config = ScanConfig::default().with_max(10);
+    match controller.scan_with_config_async(scan_config).await {
+        Ok(aps) => {
+            for ap in aps {
+                println!("{:?}", ap);
+            }
+        }
+        Err(err) => println!("Scan failed: {:?}", err),
+    }
+
+    loop {
+        println!("Wait to get connected");
+        match controller.connect_async().await {
+            Ok(()) => {
+                println!("Wifi connected!");
+                controller.wait_for_event(WifiEvent::StaDisconnected).await;
+                println!("Wifi disconnected");
+            }
+            Err(err) => println!("Failed to connect to wifi: {:?}", err),
+        }
+        Timer::after(Duration::from_secs(5)).await;
+    }
+}
+
+/// Runs the network stack, including the DHCP client
+#[embassy_executor::task]
+async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
+    runner.run().await
+}
+/// Fetches `URL` every few seconds while we have an IP address
+#[embassy_executor::task]
+async fn http_client(stack: Stack<'static>) {
+    // Create a `TcpClient` and a `DnsSocket` on top of the stack and use them to create a
+    // `reqwless` `HttpClient`
+    // let tcp_state = ...
+    // let tcp_client = ...
+    // let dns = ...
+    // let mut client = ...
+
+    let mut buffer = [0u8; 2048];
+    loop {
+        // Wait until the DHCP client got us an IP address
+        // stack...
+
+        println!("Making HTTP request");
+        if let Err(err) = fetch(&mut client, &mut buffer).await {
+            println!("HTTP request failed: {:?}", err);
+        }
+
+        Timer::after(Duration::from_secs(5)).await;
+    }
+}
+
+async fn fetch<T: TcpConnect, D: Dns>(
+    client: &mut HttpClient<'_, T, D>,
+    buffer: &mut [u8],
+) -> Result<(), reqwless::Error> {
+    // Send a GET request to `URL`, print the status and stream the body through `print_text`
+    // let mut request = ...
+    // let response = ...
+
+    Ok(())
+}
+
+/// Prints `data` as text, escaping anything that isn't valid UTF-8
+fn print_text(data: &[u8]) {
+    for chunk in data.utf8_chunks() {
+        print!("{}", chunk.valid());
+        for byte in chunk.invalid() {
+            print!("\\x{:02x}", byte);
+        }
+    }
+}
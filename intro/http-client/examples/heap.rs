@@ -0,0 +1,338 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Display, panic::PanicInfo};
+use esp_alloc::HEAP;
+use esp_hal::{
+    clock::CpuClock,
+    delay::Delay,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiError},
+    InitializationError,
+};
+
+// ANCHOR: config
+/// The blocks the heap is filled with to fragment it
+const BLOCK_SIZE: usize = 1024;
+/// A record in the cache that grows until the heap runs out
+const RECORD_SIZE: usize = 2 * 1024;
+/// How often a record is added
+const INTERVAL: Duration = Duration::from_millis(500);
+/// How many records are added, more than fit into the heap
+const RECORDS: usize = 100;
+// ANCHOR_END: config
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    // ANCHOR: regions
+    // the same heap as the other examples: the RAM the bootloader used, and some of the rest
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+    // ANCHOR_END: regions
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut watermarks = Watermarks::new();
+    watermarks.print("at boot");
+
+    // ANCHOR: radio
+    // everything the Wi-Fi driver allocates is given back when it's dropped
+    match start_wifi(peripherals.WIFI, &mut watermarks) {
+        Ok(()) => watermarks.print("Wi-Fi stopped"),
+        Err(err) => println!("Wi-Fi failed: {:?}", err),
+    }
+    // ANCHOR_END: radio
+
+    collections(&mut watermarks);
+    fragmentation(&mut watermarks);
+
+    // ANCHOR: cache
+    // a cache that grows forever, evicting records when an allocation fails keeps it in bounds
+    let mut cache: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut next = 0u8;
+    for _ in 0..RECORDS {
+        Delay::new().delay(INTERVAL);
+
+        let Some(mut record) = try_allocate_or_evict(RECORD_SIZE, &mut cache) else {
+            println!("The cache is empty and there still isn't enough memory, giving up");
+            break;
+        };
+        record.resize(RECORD_SIZE, next);
+        next = next.wrapping_add(1);
+        if cache.try_reserve(1).is_err() {
+            // losing a record is better than a panic
+            println!("No memory for the cache itself, dropping the record");
+            continue;
+        }
+        cache.push_back(record);
+        // `format_args!` doesn't allocate, `format!` could fail here
+        watermarks.print(format_args!("{} records", cache.len()));
+    }
+    // ANCHOR_END: cache
+
+    // ANCHOR: out_of_memory
+    // `vec!` and `push_back` can't report a failure, when the heap is full the allocation error
+    // handler panics, and `panic` below tells what the heap looked like
+    println!("Adding records without evicting, this ends in a panic");
+    loop {
+        Delay::new().delay(INTERVAL);
+        cache.push_back(vec![next; RECORD_SIZE]);
+        watermarks.print(format_args!("{} records", cache.len()));
+    }
+    // ANCHOR_END: out_of_memory
+}
+
+/// Starts Wi-Fi and prints how much of the heap it takes
+fn start_wifi(wifi: WIFI<'_>, watermarks: &mut Watermarks) -> Result<(), Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    watermarks.print("radio initialized");
+
+    let (mut controller, _interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    watermarks.print("Wi-Fi driver created");
+
+    // we don't connect anywhere, a started station is enough to see what Wi-Fi needs
+    controller.set_config(&ModeConfig::Client(ClientConfig::default()))?;
+    controller.start()?;
+    watermarks.print("Wi-Fi started");
+
+    controller.stop()?;
+    Ok(())
+}
+
+// ANCHOR: collections
+/// Builds a few `alloc` collections and prints what each of them costs
+fn collections(watermarks: &mut Watermarks) {
+    let before = HEAP.used();
+    let names: Vec<String> = (0..16).map(|i| format!("sensor-{}", i)).collect();
+    let after_names = HEAP.used();
+    println!("16 names in a Vec<String>: {} bytes", after_names - before);
+
+    let mut readings: BTreeMap<&str, i32> = BTreeMap::new();
+    for (i, name) in names.iter().enumerate() {
+        readings.insert(name, i as i32 * 10);
+    }
+    let after_map = HEAP.used();
+    println!(
+        "16 entries in a BTreeMap: {} bytes",
+        after_map - after_names
+    );
+
+    let boxed: Box<[u32]> = (0..256).collect();
+    println!("256 u32 in a Box<[u32]>: {} bytes", HEAP.used() - after_map);
+    watermarks.print("collections");
+
+    // the collections are dropped here, and their memory is free again
+    drop(boxed);
+    drop(readings);
+    drop(names);
+    watermarks.print("collections dropped");
+}
+// ANCHOR_END: collections
+
+// ANCHOR: fragmentation
+/// Fills the heap with blocks and frees every other one
+fn fragmentation(watermarks: &mut Watermarks) {
+    // room for the pointers to all blocks, the `Vec` must not grow while the heap is full
+    let mut blocks: Vec<Vec<u8>> = Vec::with_capacity(HEAP.stats().size / BLOCK_SIZE);
+    while blocks.len() < blocks.capacity() {
+        let mut block = Vec::new();
+        if block.try_reserve_exact(BLOCK_SIZE).is_err() {
+            break;
+        }
+        blocks.push(block);
+    }
+    println!("Allocated {} blocks of {} bytes", blocks.len(), BLOCK_SIZE);
+    watermarks.print("heap full");
+
+    for block in blocks.iter_mut().step_by(2) {
+        *block = Vec::new();
+    }
+    println!(
+        "Freed every other block: {} bytes free, but the largest block is {} bytes",
+        HEAP.free(),
+        largest_block()
+    );
+    watermarks.print("fragmented");
+
+    // the heap has more than enough free memory, just not in one piece
+    let mut buffer: Vec<u8> = Vec::new();
+    if let Err(err) = buffer.try_reserve_exact(4 * BLOCK_SIZE) {
+        println!("Allocating {} bytes failed: {}", 4 * BLOCK_SIZE, err);
+    }
+
+    drop(blocks);
+    println!(
+        "Freed all blocks: {} bytes free, the largest block is {} bytes",
+        HEAP.free(),
+        largest_block()
+    );
+}
+// ANCHOR_END: fragmentation
+
+// ANCHOR: oom
+/// Reserves `size` bytes, and evicts records from the cache until they fit
+///
+/// `try_reserve_exact` reports the failure instead of calling the allocation error handler, so
+/// this reservation never ends up in `panic`.
+fn try_allocate_or_evict(size: usize, cache: &mut VecDeque<Vec<u8>>) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    while buffer.try_reserve_exact(size).is_err() {
+        if !evict(size, cache) {
+            return None;
+        }
+    }
+    Some(buffer)
+}
+
+/// Tells what didn't fit, and frees the older half of the cache
+///
+/// Returns `false` if there is nothing left to free.
+fn evict(size: usize, cache: &mut VecDeque<Vec<u8>>) -> bool {
+    println!(
+        "Out of memory: {} bytes requested, {} bytes free, the largest block is {} bytes",
+        size,
+        HEAP.free(),
+        largest_block()
+    );
+    println!("{}", HEAP.stats());
+    if cache.is_empty() {
+        return false;
+    }
+    let evict = cache.len().div_ceil(2);
+    cache.drain(..evict);
+    println!("Evicted {} records from the cache", evict);
+    true
+}
+// ANCHOR_END: oom
+
+// ANCHOR: panic
+/// Prints the panic and the state of the heap, and stops
+///
+/// This replaces the handler of `esp-backtrace`, so there is no backtrace. An allocation that
+/// can't report its failure ends up here: the allocation error handler panics with
+/// `memory allocation of 2048 bytes failed`, and the statistics tell whether the heap was full
+/// or only fragmented.
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("{}", info);
+    println!("{}", HEAP.stats());
+    loop {
+        Delay::new().delay_millis(1_000);
+    }
+}
+// ANCHOR_END: panic
+
+/// The largest allocation that succeeds right now
+///
+/// The allocator doesn't tell, so this searches for it with allocations that are freed at once.
+fn largest_block() -> usize {
+    let (mut fits, mut fails) = (0, HEAP.free() + 1);
+    while fails - fits > 1 {
+        let size = fits + (fails - fits) / 2;
+        if Vec::<u8>::new().try_reserve_exact(size).is_ok() {
+            fits = size;
+        } else {
+            fails = size;
+        }
+    }
+    fits
+}
+
+// ANCHOR: watermarks
+/// The least free memory of each region since boot
+///
+/// The allocator only knows the current usage, so this only sees the moments it's asked.
+struct Watermarks {
+    min_free: [usize; 3],
+}
+
+impl Watermarks {
+    fn new() -> Self {
+        Self {
+            min_free: [usize::MAX; 3],
+        }
+    }
+
+    /// Prints the usage of each region and updates the watermarks
+    fn print(&mut self, label: impl Display) {
+        let uptime = time::Instant::now().duration_since_epoch().as_millis();
+        println!("[{:>6} ms] {}", uptime, label);
+        let stats = HEAP.stats();
+        for (i, region) in stats.region_stats.iter().enumerate() {
+            let Some(region) = region else {
+                continue;
+            };
+            self.min_free[i] = self.min_free[i].min(region.free);
+            println!(
+                "  region {}: {:>6} used, {:>6} free, {:>6} least free of {:>6} bytes",
+                i, region.used, region.free, self.min_free[i], region.size
+            );
+        }
+    }
+}
+// ANCHOR_END: watermarks
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+        }
+    }
+}
@@ -0,0 +1,489 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::vec;
+
+use blocking_network_stack::{Error as NetworkError, IoError, Socket, Stack};
+use core::{cell::Cell, convert::Infallible};
+use embedded_io::{Read as _, Write as _};
+use embedded_io_07::Write as _;
+use embedded_tls::{
+    blocking::{
+        Aes128GcmSha256, Certificate, CryptoProvider, NoClock, TlsConfig, TlsConnection,
+        TlsContext, TlsError, TlsVerifier,
+    },
+    pki::CertVerifier,
+    CryptoRngCore,
+};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::{Rng, Trng, TrngError},
+    time::{self, Duration},
+};
+use esp_println::{print, println};
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, ScanConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, wait_for_ip, DnsServers};
+use http_response::{Event, Parser};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, IpAddress},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: server
+#[cfg(not(feature = "local-tls-server"))]
+mod server {
+    pub const HOST: &str = "valid-isrgrootx1.letsencrypt.org";
+    pub const PORT: u16 = 443;
+    /// The certificate of the CA that signed the server's certificate chain, `ISRG Root X1` of
+    /// Let's Encrypt
+    pub const CA: &[u8] = include_bytes!("../certs/isrg-root-x1.der");
+}
+
+#[cfg(feature = "local-tls-server")]
+mod server {
+    /// The name in the certificate created by `tls/generate-certs.sh`
+    pub const HOST: &str = "tls-test.local";
+    pub const PORT: u16 = 8443;
+    /// The self-signed CA created by `tls/generate-certs.sh`
+    pub const CA: &[u8] = include_bytes!("../tls/ca.der");
+    /// The IPv4 address of the machine running `tls/server.sh`, there is no DNS entry for `HOST`
+    pub const ADDRESS: &str = env!("TLS_SERVER");
+}
+
+use server::{CA, HOST, PORT};
+// ANCHOR_END: server
+
+/// A TLS record can be up to 16 KiB plus some overhead, we need to be able to hold a whole one
+const TLS_READ_BUFFER_SIZE: usize = 16_640;
+/// Records we send can be split, this only needs to fit the handshake messages
+const TLS_WRITE_BUFFER_SIZE: usize = 4_096;
+/// The largest certificate chain we can verify
+///
+/// The verifier keeps a copy of the whole chain the server sent, not only the server's certificate.
+/// Let's Encrypt sends up to three certificates of about 1.4 KiB each, which doesn't fit in 4 KiB.
+const MAX_CERTIFICATE_SIZE: usize = 8_192;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and fetches `HOST` over HTTPS over and over again
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    // Initialize and configure Wifi
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    socket_set.add(dhcp_socket);
+    // Wait for getting an ip address
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+
+    controller.start()?;
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    println!("Start Wifi Scan");
+    let scan_config = ScanConfig::default().with_max(10);
+    let res = controller.scan_with_config(scan_config)?;
+    for ap in res {
+        println!("{:?}", ap);
+    }
+
+    println!("{:?}", controller.capabilities());
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+    // use the DNS servers handed out by the DHCP server to resolve host names, the servers are
+    // set after every reconnect
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    stack.configure_dns(&[], &mut dns_queries);
+    let mut dns_servers = DnsServers::default();
+
+    println!("Start busy loop on main");
+
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+
+    // these are too large for the stack, and we only need to allocate them once
+    let mut tls_read_buffer = vec![0u8; TLS_READ_BUFFER_SIZE];
+    let mut tls_write_buffer = vec![0u8; TLS_WRITE_BUFFER_SIZE];
+
+    loop {
+        // make sure we are (still) connected before making a request
+        wait_for_ip(&mut controller, &stack, &mut supervisor);
+        dns_servers.update(&stack)?;
+
+        // a failed request is no reason to start over, we simply try again
+        if let Err(err) = request(
+            &stack,
+            &mut socket,
+            &mut tls_read_buffer,
+            &mut tls_write_buffer,
+        ) {
+            println!("HTTPS request failed: {:?}", err);
+        }
+
+        socket.disconnect();
+
+        let deadline = time::Instant::now() + Duration::from_secs(5);
+        while time::Instant::now() < deadline {
+            socket.work();
+        }
+    }
+}
+
+/// Connects to `HOST`, sends a GET request over TLS and prints the response
+fn request(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    tls_read_buffer: &mut [u8],
+    tls_write_buffer: &mut [u8],
+) -> Result<(), Error> {
+    let address = resolve(stack)?;
+
+    println!("Making HTTPS request");
+    socket.work();
+
+    socket.open(address, PORT)?;
+
+    let socket_error = Cell::new(None);
+    let socket = Compat {
+        socket,
+        error: &socket_error,
+    };
+    let result = get(socket, tls_read_buffer, tls_write_buffer);
+    // `embedded-tls` only tells us the kind of a socket error, report the error itself
+    match (result, socket_error.take()) {
+        (Err(Error::Tls(TlsError::Io(_))), Some(err)) => Err(err.into()),
+        (result, _) => result,
+    }
+}
+
+/// Sends a GET request over TLS on the connected `socket` and prints the response
+fn get(
+    socket: Compat<'_, '_, '_, WifiDevice<'_>>,
+    tls_read_buffer: &mut [u8],
+    tls_write_buffer: &mut [u8],
+) -> Result<(), Error> {
+    // ANCHOR: handshake
+    // the hardware RNG only produces true random numbers while the radio is on, `Trng` makes sure
+    // that is the case
+    let mut provider = Provider {
+        rng: Trng::try_new()?,
+        // with `NoClock` the verifier doesn't know what time it is, so it doesn't check the
+        // validity period: expired and not yet valid certificates are accepted
+        verifier: CertVerifier::new(Certificate::X509(CA)),
+    };
+    // the server name is sent to the server and checked against the name in its certificate
+    let config = TlsConfig::new()
+        .with_server_name(HOST)
+        .enable_rsa_signatures();
+
+    let mut tls: TlsConnection<_, Aes128GcmSha256> =
+        TlsConnection::new(socket, tls_read_buffer, tls_write_buffer);
+    tls.open(TlsContext::new(&config, &mut provider))?;
+    println!("TLS connection established");
+    // ANCHOR_END: handshake
+
+    // from here on we use `tls` instead of the socket, everything is encrypted
+    tls.write_all(b"GET / HTTP/1.0\r\nHost: ")?;
+    tls.write_all(HOST.as_bytes())?;
+    tls.write_all(b"\r\n\r\n")?;
+    tls.flush()?;
+
+    let deadline = time::Instant::now() + Duration::from_secs(20);
+    let mut buffer = [0u8; 512];
+    let mut parser = Parser::<256>::new();
+    'read: loop {
+        let len = match tls.read(&mut buffer) {
+            // the server closed the connection
+            Ok(0) | Err(TlsError::ConnectionClosed) => break,
+            Ok(len) => len,
+            Err(err) => return Err(err.into()),
+        };
+
+        // the parser picks up where it left off with every chunk we read
+        let mut data = &buffer[..len];
+        loop {
+            let (consumed, event) = parser.parse(data)?;
+            data = &data[consumed..];
+
+            match event {
+                Some(Event::Status(status)) => {
+                    println!("Status: {} {}", status.code, status.reason)
+                }
+                Some(Event::Header(header)) => {
+                    print!("{}: ", header.name);
+                    print_text(header.value);
+                    println!();
+                }
+                Some(Event::HeadersEnd) => println!(),
+                Some(Event::Body(body)) => print_text(body),
+                Some(Event::End) => break 'read,
+                // we need more data
+                None => break,
+            }
+        }
+
+        if time::Instant::now() > deadline {
+            println!();
+            return Err(Error::Timeout);
+        }
+    }
+    println!();
+
+    // without `Content-Length` the body ends when the server closes the connection
+    parser.finish()?;
+
+    // let the server know we are done, it doesn't matter if it already closed the connection
+    tls.close().ok();
+
+    Ok(())
+}
+
+/// Returns the address of `HOST`
+#[cfg(not(feature = "local-tls-server"))]
+fn resolve(stack: &Stack<'_, WifiDevice<'_>>) -> Result<IpAddress, Error> {
+    use smoltcp::wire::DnsQueryType;
+
+    // resolve the host name on every request, the address behind it might change
+    println!("Resolving {}", HOST);
+    let addresses = stack.dns_query(HOST, DnsQueryType::A)?;
+    let address = *addresses.first().ok_or(Error::NoAddress)?;
+    println!("{} resolved to {}", HOST, address);
+    Ok(address)
+}
+
+/// Returns the address of `HOST`
+#[cfg(feature = "local-tls-server")]
+fn resolve(_stack: &Stack<'_, WifiDevice<'_>>) -> Result<IpAddress, Error> {
+    // the local server has no DNS entry, we connect to its address and use `HOST` only for TLS
+    let address = server::ADDRESS
+        .parse()
+        .map_err(|_| Error::NoAddress)
+        .map(IpAddress::Ipv4)?;
+    println!("Connecting to {} at {}", HOST, address);
+    Ok(address)
+}
+
+// ANCHOR: provider
+/// Supplies the TLS connection with random numbers and verifies the server's certificate
+struct Provider {
+    rng: Trng,
+    /// Checks the chain against `CA` and the name against `HOST`, but not the validity period
+    verifier: CertVerifier<'static, Aes128GcmSha256, NoClock, MAX_CERTIFICATE_SIZE>,
+}
+
+impl CryptoProvider for Provider {
+    type CipherSuite = Aes128GcmSha256;
+    // we don't authenticate with a client certificate, so we never sign anything
+    type Signature = &'static [u8];
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+}
+// ANCHOR_END: provider
+
+// ANCHOR: compat
+/// `embedded-tls` uses version 0.7 of the `embedded-io` traits, the socket implements 0.6
+///
+/// The traits only pass on the kind of an error, the error itself is kept in `error`.
+struct Compat<'a, 's, 'n, D: smoltcp::phy::Device> {
+    socket: &'a mut Socket<'s, 'n, D>,
+    error: &'a Cell<Option<IoError>>,
+}
+
+impl<D: smoltcp::phy::Device> Compat<'_, '_, '_, D> {
+    /// Keeps `err` and returns its kind
+    fn error_kind(&self, err: IoError) -> embedded_io_07::ErrorKind {
+        let kind = match err {
+            IoError::SocketClosed => embedded_io_07::ErrorKind::NotConnected,
+            _ => embedded_io_07::ErrorKind::Other,
+        };
+        self.error.set(Some(err));
+        kind
+    }
+}
+
+impl<D: smoltcp::phy::Device> embedded_io_07::ErrorType for Compat<'_, '_, '_, D> {
+    type Error = embedded_io_07::ErrorKind;
+}
+
+impl<D: smoltcp::phy::Device> embedded_io_07::Read for Compat<'_, '_, '_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.socket.read(buf) {
+            // in 0.7, the end of the stream is signalled by reading nothing
+            Err(IoError::SocketClosed) => Ok(0),
+            result => result.map_err(|err| self.error_kind(err)),
+        }
+    }
+}
+
+impl<D: smoltcp::phy::Device> embedded_io_07::Write for Compat<'_, '_, '_, D> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let result = self.socket.write(buf);
+        result.map_err(|err| self.error_kind(err))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let result = self.socket.flush();
+        result.map_err(|err| self.error_kind(err))
+    }
+}
+// ANCHOR_END: compat
+
+/// Prints `data` as text, escaping anything that isn't valid UTF-8
+fn print_text(data: &[u8]) {
+    for chunk in data.utf8_chunks() {
+        print!("{}", chunk.valid());
+        for byte in chunk.invalid() {
+            print!("\\x{:02x}", byte);
+        }
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The network stack reported an error, e.g. we don't have an ip address or DNS failed
+    Network(NetworkError),
+    /// Reading from or writing to the socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// There is no source of true random numbers
+    Rng(TrngError),
+    /// The TLS handshake failed (e.g. the certificate couldn't be verified) or the connection broke
+    Tls(TlsError),
+    /// The host name didn't resolve to any address
+    NoAddress,
+    /// The server sent something that isn't a valid HTTP response
+    Response(http_response::Error),
+    /// The server didn't finish its response in time
+    Timeout,
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<TrngError> for Error {
+    fn from(err: TrngError) -> Self {
+        Self::Rng(err)
+    }
+}
+
+impl From<TlsError> for Error {
+    fn from(err: TlsError) -> Self {
+        Self::Tls(err)
+    }
+}
+
+impl From<http_response::Error> for Error {
+    fn from(err: http_response::Error) -> Self {
+        Self::Response(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Network(err) => defmt::write!(f, "Network({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::Rng(err) => defmt::write!(f, "Rng({})", defmt::Debug2Format(err)),
+            Self::Tls(err) => defmt::write!(f, "Tls({})", defmt::Debug2Format(err)),
+            Self::NoAddress => defmt::write!(f, "NoAddress"),
+            Self::Response(err) => defmt::write!(f, "Response({})", err),
+            Self::Timeout => defmt::write!(f, "Timeout"),
+        }
+    }
+}
@@ -0,0 +1,494 @@
+#![no_std]
+#![no_main]
+
+use blocking_network_stack::{IoError, Socket, Stack, UdpSocket};
+use core::convert::Infallible;
+use embedded_io::*;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, PowerSaveMode, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, supervise, wait_for_ip};
+use iperf::{
+    fill_pattern, ClientHeader, Datagram, Receiver, ServerReport, Throughput, DATAGRAM_HEADER_LEN,
+    PORT, SERVER_REPORT_LEN, UDP_PAYLOAD_LEN,
+};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::udp::{PacketMetadata, RecvError},
+    wire::{DhcpOption, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: config
+/// The computer that runs `iperf -s` and `iperf -s -u`, e.g. `IPERF_SERVER=192.168.1.20`
+const IPERF_SERVER: &str = match option_env!("IPERF_SERVER") {
+    Some(server) => server,
+    None => "192.168.1.10",
+};
+/// Every TCP test runs once with each of these sizes for the socket buffers
+///
+/// 1536 bytes is what the other examples use, the others hold 2, 4 and 8 TCP segments.
+const BUFFER_SIZES: [usize; 4] = [1536, 2920, 5840, 11680];
+/// How long each test sends
+const DURATION: Duration = Duration::from_secs(10);
+/// The rate of the UDP datagrams in both directions, in bits per second
+const UDP_RATE: u32 = 10_000_000;
+/// Time between two rounds of tests
+const PAUSE: Duration = Duration::from_secs(60);
+// ANCHOR_END: config
+
+/// The buffer a TCP socket neither sends from nor receives into, it only carries
+/// acknowledgements
+const SMALL_BUFFER: usize = 256;
+/// The buffers of all TCP sockets, a sender and a receiver for each size
+const TCP_MEMORY: usize = {
+    let mut len = 0;
+    let mut i = 0;
+    while i < BUFFER_SIZES.len() {
+        len += 2 * (BUFFER_SIZES[i] + SMALL_BUFFER);
+        i += 1;
+    }
+    len
+};
+/// The datagrams the UDP socket holds until we read them
+const UDP_RX_DATAGRAMS: usize = 8;
+const UDP_TX_DATAGRAMS: usize = 2;
+/// How long we wait for the server: to connect back, to close a connection, or for data
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The last datagram of a UDP test is sent this often until the report arrives, like iperf
+const REPORT_TRIES: usize = 10;
+const REPORT_TIMEOUT: Duration = Duration::from_millis(250);
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+// ANCHOR: sockets
+/// The two TCP sockets of a buffer size
+///
+/// The server connects back as soon as the device's test is over, the receiver has to listen
+/// by then. The sender is still closing its connection, so it can't be the same socket.
+struct TcpSockets<'s, 'n, 'd> {
+    size: usize,
+    /// Sends from a tx buffer of `size` bytes
+    sender: Socket<'s, 'n, WifiDevice<'d>>,
+    /// Receives into an rx buffer of `size` bytes
+    receiver: Socket<'s, 'n, WifiDevice<'d>>,
+}
+
+/// Splits the first `len` bytes off `memory`
+fn take<'m>(memory: &mut &'m mut [u8], len: usize) -> &'m mut [u8] {
+    let (buffer, rest) = core::mem::take(memory).split_at_mut(len);
+    *memory = rest;
+    buffer
+}
+// ANCHOR_END: sockets
+
+/// Connects to the Wi-Fi network and runs the tests against `IPERF_SERVER` over and over again
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let server = IPERF_SERVER
+        .parse::<Ipv4Address>()
+        .map_err(|_| Error::InvalidServer)?;
+    let server = IpAddress::Ipv4(server);
+
+    // ANCHOR: buffers
+    // the sockets borrow their buffers for as long as the network stack lives, so every buffer
+    // is created here, before the stack
+    let mut tcp_memory = [0u8; TCP_MEMORY];
+    let mut udp_rx_meta = [PacketMetadata::EMPTY; UDP_RX_DATAGRAMS];
+    let mut udp_rx_buffer = [0u8; UDP_RX_DATAGRAMS * UDP_PAYLOAD_LEN];
+    let mut udp_tx_meta = [PacketMetadata::EMPTY; UDP_TX_DATAGRAMS];
+    let mut udp_tx_buffer = [0u8; UDP_TX_DATAGRAMS * UDP_PAYLOAD_LEN];
+    // ANCHOR_END: buffers
+
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    // DHCP, the TCP sockets and the UDP socket
+    let mut socket_set_entries: [SocketStorage; 2 + 2 * BUFFER_SIZES.len()] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    socket_set.add(dhcp_socket);
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    // the radio sleeping between beacons costs more throughput than anything else, see the
+    // `power-save` example
+    controller.set_power_saving(PowerSaveMode::None)?;
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+    let mut memory = &mut tcp_memory[..];
+    let mut tcp = BUFFER_SIZES.map(|size| TcpSockets {
+        size,
+        sender: stack.get_socket(take(&mut memory, SMALL_BUFFER), take(&mut memory, size)),
+        receiver: stack.get_socket(take(&mut memory, size), take(&mut memory, SMALL_BUFFER)),
+    });
+    // the UDP socket sends from and receives on the same port as the server
+    let mut udp = stack.get_udp_socket(
+        &mut udp_rx_meta,
+        &mut udp_rx_buffer,
+        &mut udp_tx_meta,
+        &mut udp_tx_buffer,
+    );
+    udp.bind(PORT)?;
+
+    loop {
+        println!(
+            "Testing against {}, run `iperf -s` and `iperf -s -u` there",
+            IPERF_SERVER
+        );
+
+        // ANCHOR: round
+        for sockets in &mut tcp {
+            wait_for_ip(&mut controller, &stack, &mut supervisor);
+            let result = tcp_send(sockets, server).and_then(|sent| {
+                let received = tcp_receive(sockets)?;
+                Ok((sent, received))
+            });
+            match result {
+                Ok((sent, received)) => println!(
+                    "TCP, {:>5} byte buffers: sent {}, received {}",
+                    sockets.size, sent, received
+                ),
+                Err(err) => {
+                    // start the next test with closed sockets
+                    sockets.sender.disconnect();
+                    sockets.receiver.disconnect();
+                    println!("TCP, {:>5} byte buffers: {:?}", sockets.size, err);
+                }
+            }
+        }
+
+        wait_for_ip(&mut controller, &stack, &mut supervisor);
+        match udp_send(&mut udp, server) {
+            Ok((sent, Some(report))) => {
+                println!("UDP: sent {}, the server received {}", sent, report)
+            }
+            Ok((sent, None)) => println!("UDP: sent {}, the server didn't report", sent),
+            Err(err) => println!("UDP send: {:?}", err),
+        }
+        match udp_receive(&mut udp) {
+            Ok(report) => println!("UDP: received {}", report),
+            Err(err) => println!("UDP receive: {:?}", err),
+        }
+        // ANCHOR_END: round
+
+        println!("Next round in {} seconds", PAUSE.as_secs());
+        let next = time::Instant::now() + PAUSE;
+        while time::Instant::now() < next {
+            supervise(&mut controller, &stack, &mut supervisor);
+        }
+    }
+}
+
+// ANCHOR: tcp_send
+/// Sends to the server for `DURATION`, and asks it to send to the receiver afterwards
+fn tcp_send(sockets: &mut TcpSockets<'_, '_, '_>, server: IpAddress) -> Result<Throughput, Error> {
+    sockets.receiver.listen_unblocking(PORT)?;
+    let sender = &mut sockets.sender;
+    sender.work();
+    sender.open(server, PORT)?;
+
+    // the stream starts with the header, then it's iperf's pattern
+    let mut buffer = [0u8; 1460];
+    let header = ClientHeader {
+        tradeoff: true,
+        port: PORT,
+        duration: DURATION.as_millis() as u32,
+        ..Default::default()
+    };
+    let len = header.encode(&mut buffer)?;
+    fill_pattern(&mut buffer[len..]);
+    sender.write_all(&buffer)?;
+    fill_pattern(&mut buffer);
+
+    let start = time::Instant::now();
+    let mut bytes = buffer.len() as u64;
+    while start.elapsed() < DURATION {
+        sender.write_all(&buffer)?;
+        bytes += buffer.len() as u64;
+    }
+    // the data has arrived once the server acknowledged the end of the connection
+    sender.close();
+    wait_closed(sender)?;
+    Ok(Throughput {
+        bytes,
+        time: start.elapsed().as_micros(),
+    })
+}
+// ANCHOR_END: tcp_send
+
+// ANCHOR: tcp_receive
+/// Receives what the server sends until it closes the connection
+fn tcp_receive(sockets: &mut TcpSockets<'_, '_, '_>) -> Result<Throughput, Error> {
+    let receiver = &mut sockets.receiver;
+    let connecting = time::Instant::now();
+    while !receiver.is_connected() {
+        receiver.work();
+        if connecting.elapsed() > TIMEOUT {
+            return Err(Error::Timeout);
+        }
+    }
+
+    let start = time::Instant::now();
+    let mut last = start;
+    let mut bytes = 0;
+    let mut buffer = [0u8; 1460];
+    loop {
+        // `read` would wait for good, only read once there is something
+        match receiver.read_ready() {
+            Ok(true) => {
+                bytes += receiver.read(&mut buffer)? as u64;
+                last = time::Instant::now();
+            }
+            Ok(false) if last.elapsed() > TIMEOUT => return Err(Error::Timeout),
+            Ok(false) => {}
+            Err(IoError::SocketClosed) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    receiver.close();
+    wait_closed(receiver)?;
+    Ok(Throughput {
+        bytes,
+        time: (last - start).as_micros(),
+    })
+}
+// ANCHOR_END: tcp_receive
+
+/// Waits until the connection is closed and the socket can connect or listen again
+fn wait_closed(socket: &mut Socket<'_, '_, WifiDevice<'_>>) -> Result<(), Error> {
+    let closing = time::Instant::now();
+    while socket.is_open() {
+        socket.work();
+        if closing.elapsed() > TIMEOUT {
+            return Err(Error::Timeout);
+        }
+    }
+    Ok(())
+}
+
+// ANCHOR: udp_send
+/// Sends datagrams at `UDP_RATE` for `DURATION`, returns how many we sent and the report of the
+/// server, if it arrived
+fn udp_send(
+    socket: &mut UdpSocket<'_, '_, WifiDevice<'_>>,
+    server: IpAddress,
+) -> Result<(Throughput, Option<ServerReport>), Error> {
+    // every datagram carries the header behind its own, the server reads it from the first one
+    let mut datagram = [0u8; UDP_PAYLOAD_LEN];
+    fill_pattern(&mut datagram);
+    let header = ClientHeader {
+        tradeoff: true,
+        port: PORT,
+        buffer_len: UDP_PAYLOAD_LEN as u32,
+        rate: UDP_RATE,
+        duration: DURATION.as_millis() as u32,
+    };
+    header.encode(&mut datagram[DATAGRAM_HEADER_LEN..])?;
+
+    let interval = Duration::from_micros(UDP_PAYLOAD_LEN as u64 * 8 * 1_000_000 / UDP_RATE as u64);
+    let start = time::Instant::now();
+    let mut next = start;
+    let mut seq = 0;
+    while start.elapsed() < DURATION {
+        let now = time::Instant::now();
+        if now < next {
+            socket.work();
+            continue;
+        }
+        Datagram {
+            seq,
+            sent: now.duration_since_epoch().as_micros(),
+        }
+        .encode(&mut datagram)?;
+        socket.send(server, PORT, &datagram)?;
+        seq += 1;
+        next += interval;
+    }
+    let sent = Throughput {
+        bytes: seq as u64 * UDP_PAYLOAD_LEN as u64,
+        time: start.elapsed().as_micros(),
+    };
+
+    // the last datagram asks for the report, it's sent again until the report arrives
+    let mut answer = [0u8; UDP_PAYLOAD_LEN];
+    for _ in 0..REPORT_TRIES {
+        Datagram {
+            seq: -seq,
+            sent: time::Instant::now().duration_since_epoch().as_micros(),
+        }
+        .encode(&mut datagram)?;
+        socket.send(server, PORT, &datagram)?;
+
+        let sent_at = time::Instant::now();
+        while sent_at.elapsed() < REPORT_TIMEOUT {
+            match socket.receive(&mut answer) {
+                Ok((len, address, port)) if address == server && port == PORT => {
+                    if let Ok(report) = ServerReport::decode(&answer[..len]) {
+                        return Ok((sent, Some(report)));
+                    }
+                }
+                Ok(_) | Err(IoError::UdpRecvError(RecvError::Exhausted)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    Ok((sent, None))
+}
+// ANCHOR_END: udp_send
+
+// ANCHOR: udp_receive
+/// Counts the datagrams the server sends, and answers the last one with our report
+fn udp_receive(socket: &mut UdpSocket<'_, '_, WifiDevice<'_>>) -> Result<ServerReport, Error> {
+    let mut receiver = Receiver::new();
+    let mut datagram = [0u8; UDP_PAYLOAD_LEN];
+    let mut last = time::Instant::now();
+    loop {
+        let now = time::Instant::now();
+        let (len, address, port) = match socket.receive(&mut datagram) {
+            Ok(received) => received,
+            Err(IoError::UdpRecvError(RecvError::Exhausted)) if last.elapsed() > TIMEOUT => {
+                // the server's last datagram got lost, what arrived is still worth a report
+                if receiver.has_started() {
+                    return Ok(receiver.report());
+                }
+                return Err(Error::Timeout);
+            }
+            Err(IoError::UdpRecvError(RecvError::Exhausted)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let Ok(header) = Datagram::decode(&datagram[..len]) else {
+            continue;
+        };
+        // a late report of our own test isn't part of this one
+        if header.is_last() && !receiver.has_started() {
+            continue;
+        }
+        receiver.received(&header, len, now.duration_since_epoch().as_micros());
+        last = now;
+
+        if header.is_last() {
+            // iperf answers with a datagram as long as the ones it received
+            let report = receiver.report();
+            report.encode(&header, &mut datagram)?;
+            socket.send(address, port, &datagram[..len.max(SERVER_REPORT_LEN)])?;
+            return Ok(report);
+        }
+    }
+}
+// ANCHOR_END: udp_receive
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// Reading from or writing to a socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// A header or report doesn't fit into its buffer
+    Encode(iperf::Error),
+    /// `IPERF_SERVER` isn't an IPv4 address
+    InvalidServer,
+    /// The server didn't connect back, or stopped sending or acknowledging
+    Timeout,
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<iperf::Error> for Error {
+    fn from(err: iperf::Error) -> Self {
+        Self::Encode(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::Encode(err) => defmt::write!(f, "Encode({})", err),
+            Self::InvalidServer => defmt::write!(f, "InvalidServer"),
+            Self::Timeout => defmt::write!(f, "Timeout"),
+        }
+    }
+}
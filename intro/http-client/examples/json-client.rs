@@ -0,0 +1,270 @@
+#![no_std]
+#![no_main]
+
+use core::{convert::Infallible, task::Poll};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiController, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{supervise, timestamp};
+use heapless::String;
+use http_response::Event;
+use json_body::Body;
+use net_client::{Get, Network, Storage};
+use serde::Deserialize;
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::SocketStorage,
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, EthernetAddress},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: config
+/// A JSON API that's available over plain HTTP, it tells where our public address is
+const HOST: &str = "ip-api.com";
+const PORT: u16 = 80;
+/// Only ask for the fields we need, that keeps the response small
+const PATH: &str = "/json/?fields=status,message,country,city,lat,lon,timezone,query";
+/// The largest body we accept, larger ones are dropped
+const BODY_SIZE: usize = 512;
+// ANCHOR_END: config
+
+// ANCHOR: location
+/// What the API answers, e.g.
+/// `{"status":"success","country":"Germany","city":"Berlin","lat":52.52,"lon":13.405,...}`
+///
+/// Fields that aren't in the JSON are `None`, fields that aren't in the struct are skipped.
+#[derive(Debug, Deserialize)]
+struct Location {
+    /// `success` or `fail`
+    status: String<8>,
+    /// Why the request failed
+    message: Option<String<32>>,
+    country: Option<String<32>>,
+    city: Option<String<32>>,
+    lat: Option<f32>,
+    lon: Option<f32>,
+    timezone: Option<String<32>>,
+    /// Our public address
+    query: String<15>,
+}
+// ANCHOR_END: location
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and asks the API for our location every minute
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let device = interfaces.sta;
+
+    let mut config = net_client::Config::new(EthernetAddress(device.mac_address()).into());
+    config.dhcp_options = &[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }];
+    config.random_seed = Rng::new().random().into();
+
+    let mut sockets: [SocketStorage; 3] = Default::default();
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    let storage = Storage {
+        sockets: &mut sockets,
+        rx_buffer: &mut rx_buffer,
+        tx_buffer: &mut tx_buffer,
+        dns_queries: &mut dns_queries,
+    };
+    let mut network = Network::new(device, config, storage, timestamp());
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    let mut body = Body::<BODY_SIZE>::new();
+
+    loop {
+        while !supervisor.is_up() {
+            supervise(&mut controller, &mut network, &mut supervisor);
+        }
+
+        // ANCHOR: report
+        // a failed request is no reason to start over, we simply try again
+        match request(&mut controller, &mut network, &mut supervisor, &mut body) {
+            Ok(location) => report(&location),
+            Err(err) => println!("Request failed: {:?}", err),
+        }
+        // ANCHOR_END: report
+
+        let deadline = time::Instant::now() + Duration::from_secs(60);
+        while time::Instant::now() < deadline {
+            supervise(&mut controller, &mut network, &mut supervisor);
+        }
+    }
+}
+
+// ANCHOR: request
+/// Requests `PATH` and deserializes the body
+fn request(
+    controller: &mut WifiController<'_>,
+    network: &mut Network<'_, WifiDevice<'_>>,
+    supervisor: &mut Supervisor,
+    body: &mut Body<BODY_SIZE>,
+) -> Result<Location, Error> {
+    println!("Requesting http://{}{}", HOST, PATH);
+    body.reset();
+    let mut get = Get::new(HOST, PORT, PATH);
+    let mut status = None;
+    let mut collected = Ok(());
+    loop {
+        supervise(controller, &mut *network, supervisor);
+
+        // the headers are skipped, only the status and the body are kept
+        let poll = get.poll(network, timestamp(), |event| {
+            if let Event::Status(s) = &event {
+                status = Some(s.code);
+            }
+            if collected.is_ok() {
+                collected = body.push(&event);
+            }
+        });
+        // stop reading a body that doesn't fit, the server would send it for nothing
+        if collected.is_err() {
+            get.cancel(network);
+        }
+        if let Poll::Ready(result) = poll {
+            collected?;
+            result?;
+            break;
+        }
+    }
+
+    // error responses of APIs are often JSON too, but with other fields
+    if let Some(code) = status.filter(|&code| code != 200) {
+        return Err(Error::Status(code));
+    }
+    Ok(body.parse()?)
+}
+// ANCHOR_END: request
+
+fn report(location: &Location) {
+    if location.status != "success" {
+        println!(
+            "The API can't locate {}: {}",
+            location.query,
+            location.message.as_deref().unwrap_or("no reason given")
+        );
+        return;
+    }
+    println!(
+        "{} is in {}, {}",
+        location.query,
+        location.city.as_deref().unwrap_or("?"),
+        location.country.as_deref().unwrap_or("?")
+    );
+    if let (Some(lat), Some(lon)) = (location.lat, location.lon) {
+        println!("Coordinates: {:.4}, {:.4}", lat, lon);
+    }
+    if let Some(timezone) = &location.timezone {
+        println!("Time zone: {}", timezone);
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The request failed, see `net_client::Error`
+    Request(net_client::Error),
+    /// The server answered with another status than `200 OK`
+    Status(u16),
+    /// The body is too large, truncated or not what we expected
+    Body(json_body::Error),
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<net_client::Error> for Error {
+    fn from(err: net_client::Error) -> Self {
+        Self::Request(err)
+    }
+}
+
+impl From<json_body::Error> for Error {
+    fn from(err: json_body::Error) -> Self {
+        Self::Body(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Request(err) => defmt::write!(f, "Request({})", err),
+            Self::Status(code) => defmt::write!(f, "Status({})", code),
+            Self::Body(err) => defmt::write!(f, "Body({})", err),
+        }
+    }
+}
@@ -0,0 +1,349 @@
+#![no_std]
+#![no_main]
+
+use core::{convert::Infallible, fmt::Display, task::Poll};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiController, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{supervise, timestamp};
+use net_client::{Network, Resolve, Storage};
+use ping::{encode_request, EchoReply, Millis, Reply, Session};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketHandle, SocketStorage},
+    socket::{dns::DnsQuery, icmp},
+    wire::{DhcpOption, EthernetAddress, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: config
+/// The host pinged after the gateway, a host name or an IP address, e.g. `PING_HOST=1.1.1.1`
+const PING_HOST: &str = match option_env!("PING_HOST") {
+    Some(host) => host,
+    None => "example.com",
+};
+/// Requests sent to each host, like `ping -c 4`
+const COUNT: u16 = 4;
+/// Time between two requests, like `ping -i 1`
+const INTERVAL: Duration = Duration::from_secs(1);
+/// How long we wait for the replies after the last request
+const LINGER: Duration = Duration::from_secs(2);
+/// Bytes of payload in each request, `ping` sends 56 by default
+const PAYLOAD_LEN: usize = ping::DEFAULT_PAYLOAD_LEN;
+/// Pause between two rounds
+const PAUSE: Duration = Duration::from_secs(30);
+// ANCHOR_END: config
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and pings the gateway and `PING_HOST` every `PAUSE`
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let device = interfaces.sta;
+
+    let rng = Rng::new();
+    let mut config = net_client::Config::new(EthernetAddress(device.mac_address()).into());
+    config.dhcp_options = &[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }];
+    config.random_seed = rng.random().into();
+
+    // ANCHOR: socket
+    // the DHCP, DNS and TCP sockets of the network, and our ICMP socket
+    let mut sockets: [SocketStorage; 4] = Default::default();
+    // we don't make HTTP requests, the TCP socket gets small buffers
+    let mut rx_buffer = [0u8; 256];
+    let mut tx_buffer = [0u8; 256];
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    let storage = Storage {
+        sockets: &mut sockets,
+        rx_buffer: &mut rx_buffer,
+        tx_buffer: &mut tx_buffer,
+        dns_queries: &mut dns_queries,
+    };
+    let mut network = Network::new(device, config, storage, timestamp());
+
+    let mut icmp_rx_metadata = [icmp::PacketMetadata::EMPTY; 8];
+    let mut icmp_rx_payload = [0u8; 1024];
+    let mut icmp_tx_metadata = [icmp::PacketMetadata::EMPTY; 4];
+    let mut icmp_tx_payload = [0u8; 512];
+    let mut socket = icmp::Socket::new(
+        icmp::PacketBuffer::new(&mut icmp_rx_metadata[..], &mut icmp_rx_payload[..]),
+        icmp::PacketBuffer::new(&mut icmp_tx_metadata[..], &mut icmp_tx_payload[..]),
+    );
+    // the socket only receives the replies with our identifier, like the `ping` command, we pick
+    // a random one, so the replies to an earlier run don't count
+    let ident = rng.random() as u16;
+    socket.bind(icmp::Endpoint::Ident(ident))?;
+    let icmp = network.sockets_mut().add(socket);
+    // ANCHOR_END: socket
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+
+    loop {
+        while !supervisor.is_up() {
+            supervise(&mut controller, &mut network, &mut supervisor);
+        }
+
+        // ANCHOR: targets
+        // a failed ping is no reason to start over, we simply try again in the next round
+        match network.gateway() {
+            Some(gateway) => {
+                let address = IpAddress::Ipv4(gateway);
+                let target = Target {
+                    name: &gateway,
+                    address,
+                    icmp,
+                    ident,
+                };
+                if let Err(err) = ping(&mut controller, &mut network, &mut supervisor, target) {
+                    println!("ping: {}: {:?}", gateway, err);
+                }
+            }
+            None => println!("The DHCP server didn't announce a gateway"),
+        }
+        match resolve(&mut controller, &mut network, &mut supervisor, PING_HOST) {
+            Ok(address) => {
+                let address = IpAddress::Ipv4(address);
+                let target = Target {
+                    name: &PING_HOST,
+                    address,
+                    icmp,
+                    ident,
+                };
+                if let Err(err) = ping(&mut controller, &mut network, &mut supervisor, target) {
+                    println!("ping: {}: {:?}", PING_HOST, err);
+                }
+            }
+            Err(err) => println!("ping: {}: {:?}", PING_HOST, err),
+        }
+        // ANCHOR_END: targets
+
+        let deadline = time::Instant::now() + PAUSE;
+        while time::Instant::now() < deadline {
+            supervise(&mut controller, &mut network, &mut supervisor);
+        }
+    }
+}
+
+/// The host to ping and the socket to ping it with
+struct Target<'a> {
+    /// How the host is called in the output, its name or its address
+    name: &'a dyn Display,
+    address: IpAddress,
+    icmp: SocketHandle,
+    ident: u16,
+}
+
+// ANCHOR: ping
+/// Sends `COUNT` echo requests to the target and prints the replies and the statistics
+fn ping(
+    controller: &mut WifiController<'_>,
+    network: &mut Network<'_, WifiDevice<'_>>,
+    supervisor: &mut Supervisor,
+    target: Target<'_>,
+) -> Result<(), Error> {
+    println!(
+        "PING {} ({}) {}({}) bytes of data.",
+        target.name,
+        target.address,
+        PAYLOAD_LEN,
+        PAYLOAD_LEN + ping::HEADER_LEN + ping::IPV4_HEADER_LEN
+    );
+
+    let mut session = Session::<{ COUNT as usize }>::new();
+    for seq in 1..=COUNT {
+        let mut request = [0u8; ping::HEADER_LEN + PAYLOAD_LEN];
+        let len = encode_request(target.ident, seq, PAYLOAD_LEN, &mut request)?;
+        network
+            .sockets_mut()
+            .get_mut::<icmp::Socket>(target.icmp)
+            .send_slice(&request[..len], target.address)?;
+        session.sent(seq, micros());
+
+        // the replies arrive while we wait for the next request, or after the last one
+        let last = seq == COUNT;
+        let deadline = time::Instant::now() + if last { LINGER } else { INTERVAL };
+        while time::Instant::now() < deadline {
+            supervise(controller, &mut *network, supervisor);
+            receive(network, &target, &mut session);
+            // nothing to wait for anymore once every request got its reply
+            if last && !(1..=COUNT).any(|seq| session.is_pending(seq)) {
+                break;
+            }
+        }
+    }
+
+    println!("--- {} ping statistics ---", target.name);
+    println!("{}", session.summary(micros()));
+    Ok(())
+}
+
+/// Takes the replies from the socket and prints them
+fn receive(
+    network: &mut Network<'_, WifiDevice<'_>>,
+    target: &Target<'_>,
+    session: &mut Session<{ COUNT as usize }>,
+) {
+    let socket = network.sockets_mut().get_mut::<icmp::Socket>(target.icmp);
+    while let Ok((message, from)) = socket.recv() {
+        // late replies from the previous host don't belong to this session
+        if from != target.address {
+            continue;
+        }
+        let reply = match EchoReply::decode(message) {
+            Ok(reply) if reply.ident == target.ident => reply,
+            Ok(_) => continue,
+            Err(err) => {
+                println!("From {}: {:?}", from, err);
+                continue;
+            }
+        };
+        // smoltcp doesn't tell us the TTL of the packet, unlike `ping` we leave it out
+        let (rtt, dup) = match session.received(reply.seq, micros()) {
+            Reply::Received(rtt) => (rtt, ""),
+            Reply::Duplicate(rtt) => (rtt, " (DUP!)"),
+            Reply::Unknown => continue,
+        };
+        println!(
+            "{} bytes from {}: icmp_seq={} time={} ms{}",
+            message.len(),
+            from,
+            reply.seq,
+            Millis(rtt),
+            dup
+        );
+    }
+}
+// ANCHOR_END: ping
+
+/// Resolves `host` with the DNS servers from DHCP
+fn resolve(
+    controller: &mut WifiController<'_>,
+    network: &mut Network<'_, WifiDevice<'_>>,
+    supervisor: &mut Supervisor,
+    host: &str,
+) -> Result<Ipv4Address, net_client::Error> {
+    let mut resolve = Resolve::new(host);
+    loop {
+        supervise(controller, &mut *network, supervisor);
+        if let Poll::Ready(result) = resolve.poll(network) {
+            return result;
+        }
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The ICMP socket couldn't be bound
+    Bind(icmp::BindError),
+    /// The request couldn't be queued, e.g. the send buffer is full
+    Send(icmp::SendError),
+    /// The request doesn't fit into its buffer
+    Encode(ping::Error),
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<icmp::BindError> for Error {
+    fn from(err: icmp::BindError) -> Self {
+        Self::Bind(err)
+    }
+}
+
+impl From<icmp::SendError> for Error {
+    fn from(err: icmp::SendError) -> Self {
+        Self::Send(err)
+    }
+}
+
+impl From<ping::Error> for Error {
+    fn from(err: ping::Error) -> Self {
+        Self::Encode(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Bind(err) => defmt::write!(f, "Bind({})", defmt::Debug2Format(err)),
+            Self::Send(err) => defmt::write!(f, "Send({})", defmt::Debug2Format(err)),
+            Self::Encode(err) => defmt::write!(f, "Encode({})", err),
+        }
+    }
+}
+
+/// The time since boot in microseconds, the unit of the `ping` crate
+fn micros() -> u64 {
+    time::Instant::now().duration_since_epoch().as_micros()
+}
@@ -0,0 +1,427 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use blocking_network_stack::{Error as NetworkError, IoError, Socket, Stack};
+use core::convert::Infallible;
+use embedded_io::*;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, PowerSaveMode, WifiController, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, supervise, wait_for_ip};
+use http_response::{Event, Parser};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, DnsQueryType, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: config
+/// The HTTP server we measure against, an IP address or a host name
+///
+/// A server in the same network keeps the internet out of the numbers, serving a folder with
+/// `python3 -m http.server` is enough.
+const BENCH_SERVER: &str = match option_env!("BENCH_SERVER") {
+    Some(server) => server,
+    None => "192.168.1.10",
+};
+const BENCH_PORT: u16 = 8000;
+/// Something small for the latency, the directory listing will do
+const LATENCY_PATH: &str = "/";
+/// Something large for the throughput, e.g. `head -c 1048576 /dev/urandom > download.bin`
+const DOWNLOAD_PATH: &str = "/download.bin";
+/// Latency requests per mode
+const REQUESTS: u32 = 20;
+/// Downloads per mode
+const DOWNLOADS: u32 = 3;
+/// Pause before every request, long enough for the radio to doze off
+const PAUSE: Duration = Duration::from_millis(1_000);
+/// With `PowerSaveMode::Maximum`, the radio only wakes up for every this many beacons
+const LISTEN_INTERVAL: u16 = 3;
+// ANCHOR_END: config
+
+/// The modes we compare, from the fastest to the most frugal
+const MODES: [PowerSaveMode; 3] = [
+    PowerSaveMode::None,
+    PowerSaveMode::Minimum,
+    PowerSaveMode::Maximum,
+];
+/// A request is dropped if the server doesn't send anything for this long
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The TCP receive window, the server never sends more before we acknowledge
+const RX_BUFFER_SIZE: usize = 8 * 1024;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and measures every power-save mode over and over again
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    socket_set.add(dhcp_socket);
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    // ANCHOR: client_config
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into())
+            .with_listen_interval(LISTEN_INTERVAL),
+    );
+    controller.set_config(&client_config)?;
+    // ANCHOR_END: client_config
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+    let ip_info = stack.get_ip_info()?;
+    let dns_servers: Vec<IpAddress> = [ip_info.dns, ip_info.secondary_dns]
+        .into_iter()
+        .flatten()
+        .map(IpAddress::Ipv4)
+        .collect();
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    stack.configure_dns(&dns_servers, &mut dns_queries);
+
+    let address = match BENCH_SERVER.parse::<Ipv4Address>() {
+        Ok(address) => IpAddress::Ipv4(address),
+        Err(_) => *stack
+            .dns_query(BENCH_SERVER, DnsQueryType::A)?
+            .first()
+            .ok_or(Error::NoAddress)?,
+    };
+    println!("Measuring against http://{}:{}", address, BENCH_PORT);
+
+    // the receive buffer limits the throughput, it's too large for the stack
+    let mut rx_buffer = vec![0u8; RX_BUFFER_SIZE];
+    let mut tx_buffer = [0u8; 1536];
+    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+
+    loop {
+        // ANCHOR: measure
+        let mut results = [Results::default(); MODES.len()];
+        for (mode, results) in MODES.into_iter().zip(&mut results) {
+            controller.set_power_saving(mode)?;
+            println!("Measuring {:?}", mode);
+
+            for request in 0..REQUESTS + DOWNLOADS {
+                // give the radio time to doze off, that's what costs latency
+                idle(&mut controller, &stack, &mut supervisor, PAUSE);
+                wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+                let latency = request < REQUESTS;
+                let path = if latency { LATENCY_PATH } else { DOWNLOAD_PATH };
+                // a failed request is no reason to start over, it's part of the results
+                match fetch(&mut socket, address, path) {
+                    Ok(fetch) if latency => results.add_request(&fetch),
+                    Ok(fetch) => results.add_download(&fetch),
+                    Err(err) => {
+                        println!("Request for {} failed: {:?}", path, err);
+                        results.failed += 1;
+                    }
+                }
+                socket.disconnect();
+            }
+        }
+        // ANCHOR_END: measure
+
+        // back to the default until the next round
+        controller.set_power_saving(PowerSaveMode::None)?;
+        report(&results);
+        idle(
+            &mut controller,
+            &stack,
+            &mut supervisor,
+            Duration::from_secs(30),
+        );
+    }
+}
+
+// ANCHOR: fetch
+/// What a request took
+struct Fetch {
+    /// From opening the connection to the first byte of the response
+    latency: Duration,
+    /// From opening the connection to the last byte of the response
+    duration: Duration,
+    /// The length of the body
+    len: usize,
+}
+
+/// Sends a GET request for `path` to `BENCH_SERVER` and reads the response
+fn fetch(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    address: IpAddress,
+    path: &str,
+) -> Result<Fetch, Error> {
+    socket.work();
+    let start = time::Instant::now();
+
+    socket.open(address, BENCH_PORT)?;
+    // HTTP/1.0, so the server neither keeps the connection open nor sends the body in chunks
+    socket.write_all(b"GET ")?;
+    socket.write_all(path.as_bytes())?;
+    socket.write_all(b" HTTP/1.0\r\nHost: ")?;
+    socket.write_all(BENCH_SERVER.as_bytes())?;
+    socket.write_all(b"\r\n\r\n")?;
+    socket.flush()?;
+
+    let mut buffer = [0u8; 1536];
+    let mut parser = Parser::<256>::new();
+    let mut latency = None;
+    let mut len = 0;
+    let mut deadline = time::Instant::now() + TIMEOUT;
+    'read: loop {
+        let read = match socket.read_ready() {
+            Ok(true) => socket.read(&mut buffer)?,
+            Ok(false) if time::Instant::now() > deadline => return Err(Error::Timeout),
+            Ok(false) => continue,
+            // the server closed the connection
+            Err(IoError::SocketClosed) => break,
+            Err(err) => return Err(err.into()),
+        };
+        deadline = time::Instant::now() + TIMEOUT;
+        latency.get_or_insert_with(|| start.elapsed());
+
+        let mut data = &buffer[..read];
+        loop {
+            let (consumed, event) = parser.parse(data)?;
+            data = &data[consumed..];
+
+            match event {
+                Some(Event::Status(status)) if status.code != 200 => {
+                    return Err(Error::Status(status.code))
+                }
+                Some(Event::Body(body)) => len += body.len(),
+                Some(Event::End) => break 'read,
+                Some(_) => {}
+                // we need more data
+                None => break,
+            }
+        }
+    }
+
+    // without `Content-Length` the body ends when the server closes the connection
+    parser.finish()?;
+
+    Ok(Fetch {
+        latency: latency.ok_or(Error::Timeout)?,
+        duration: start.elapsed(),
+        len,
+    })
+}
+// ANCHOR_END: fetch
+
+// ANCHOR: results
+/// What we measured with one power-save mode
+#[derive(Clone, Copy, Default)]
+struct Results {
+    /// Latencies of the requests for `LATENCY_PATH` in microseconds
+    min_latency: u64,
+    max_latency: u64,
+    total_latency: u64,
+    requests: u32,
+    /// Bytes and microseconds of the downloads of `DOWNLOAD_PATH`
+    downloaded: u64,
+    download_time: u64,
+    downloads: u32,
+    failed: u32,
+}
+
+impl Results {
+    fn add_request(&mut self, fetch: &Fetch) {
+        let latency = fetch.latency.as_micros();
+        if self.requests == 0 || latency < self.min_latency {
+            self.min_latency = latency;
+        }
+        self.max_latency = self.max_latency.max(latency);
+        self.total_latency += latency;
+        self.requests += 1;
+    }
+
+    fn add_download(&mut self, fetch: &Fetch) {
+        self.downloaded += fetch.len as u64;
+        self.download_time += fetch.duration.as_micros();
+        self.downloads += 1;
+    }
+
+    /// The average latency in milliseconds
+    fn avg_latency(&self) -> f32 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.total_latency as f32 / self.requests as f32 / 1_000.0
+    }
+
+    /// The throughput of the downloads in KiB/s
+    fn throughput(&self) -> f32 {
+        if self.download_time == 0 {
+            return 0.0;
+        }
+        self.downloaded as f32 / 1_024.0 / (self.download_time as f32 / 1_000_000.0)
+    }
+}
+
+/// Prints the results of all modes as a table
+fn report(results: &[Results; MODES.len()]) {
+    println!("Mode      Latency min /   avg /   max   Throughput  Failed");
+    for (mode, results) in MODES.iter().zip(results) {
+        println!(
+            "{:<8} {:>9.1} / {:>5.1} / {:>5.1} ms {:>7.1} KiB/s {:>3}/{}",
+            mode_name(*mode),
+            results.min_latency as f32 / 1_000.0,
+            results.avg_latency(),
+            results.max_latency as f32 / 1_000.0,
+            results.throughput(),
+            results.failed,
+            REQUESTS + DOWNLOADS,
+        );
+    }
+}
+// ANCHOR_END: results
+
+fn mode_name(mode: PowerSaveMode) -> &'static str {
+    match mode {
+        PowerSaveMode::None => "None",
+        PowerSaveMode::Minimum => "Minimum",
+        PowerSaveMode::Maximum => "Maximum",
+        _ => "?",
+    }
+}
+
+/// Keeps the connection up for `duration`
+fn idle(
+    controller: &mut WifiController<'_>,
+    stack: &Stack<'_, WifiDevice<'_>>,
+    supervisor: &mut Supervisor,
+    duration: Duration,
+) {
+    let deadline = time::Instant::now() + duration;
+    while time::Instant::now() < deadline {
+        supervise(controller, stack, supervisor);
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The network stack reported an error, e.g. we don't have an ip address or DNS failed
+    Network(NetworkError),
+    /// Reading from or writing to the socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// The host name didn't resolve to any address
+    NoAddress,
+    /// The server sent something that isn't a valid HTTP response
+    Response(http_response::Error),
+    /// The server answered with another status than `200 OK`
+    Status(u16),
+    /// The server didn't send anything in time
+    Timeout,
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<http_response::Error> for Error {
+    fn from(err: http_response::Error) -> Self {
+        Self::Response(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Network(err) => defmt::write!(f, "Network({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::NoAddress => defmt::write!(f, "NoAddress"),
+            Self::Response(err) => defmt::write!(f, "Response({})", err),
+            Self::Status(code) => defmt::write!(f, "Status({})", code),
+            Self::Timeout => defmt::write!(f, "Timeout"),
+        }
+    }
+}
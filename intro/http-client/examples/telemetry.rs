@@ -0,0 +1,407 @@
+#![no_std]
+#![no_main]
+
+use blocking_network_stack::{Error as NetworkError, IoError, Socket, Stack};
+use core::convert::Infallible;
+use embedded_io::*;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    gpio::{Input, InputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, supervise, wait_for_ip, DnsServers};
+use http_response::{Event, Parser};
+use telemetry::{encode_post, KeepAlive, Reading, Uploader};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, DnsQueryType, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+// ANCHOR: config
+/// The server the readings are uploaded to, an IP address or a host name
+///
+/// `telemetry/server.py` receives them and prints them.
+const SERVER: &str = match option_env!("TELEMETRY_SERVER") {
+    Some(server) => server,
+    None => "192.168.1.10",
+};
+const PORT: u16 = 8000;
+const PATH: &str = "/telemetry";
+/// How often the device takes a reading
+const INTERVAL: Duration = Duration::from_secs(10);
+/// Readings kept while the server is unreachable, the oldest ones are dropped after that
+const QUEUE_LEN: usize = 32;
+/// An upload fails if the server doesn't answer within this time
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+// ANCHOR_END: config
+
+/// The contacts of the button bounce for a few milliseconds, changes within this time are ignored
+const DEBOUNCE_MS: u64 = 50;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the button from `button`, the readings count how often it was pressed
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+
+    // the queue outlives `run`, readings that weren't uploaded yet survive a restart of Wi-Fi
+    let mut uploader: Uploader<QUEUE_LEN> = Uploader::new(telemetry::Config {
+        interval: INTERVAL.as_millis(),
+        ..Default::default()
+    });
+    let mut presses = Presses::default();
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow(), &button, &mut presses, &mut uploader))
+}
+
+/// Connects to the Wi-Fi network, takes a reading every `INTERVAL` and uploads it
+fn run(
+    wifi: WIFI<'_>,
+    button: &Input<'_>,
+    presses: &mut Presses,
+    uploader: &mut Uploader<QUEUE_LEN>,
+) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    socket_set.add(dhcp_socket);
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+    // the DNS servers are set whenever we are connected, a new lease might come with other ones
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    stack.configure_dns(&[], &mut dns_queries);
+    let mut dns_servers = DnsServers::default();
+
+    // ANCHOR: loop
+    // one socket for all uploads, it stays connected as long as the server keeps it open
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+    let mut connected = false;
+
+    loop {
+        supervise(&mut controller, &stack, &mut supervisor);
+        presses.update(button);
+        let now = time::Instant::now().duration_since_epoch().as_millis();
+
+        // readings are taken whether or not we are connected, they wait in the queue
+        if uploader.reading_due(now) {
+            let reading = Reading {
+                uptime: now,
+                free_heap: esp_alloc::HEAP.free() as u32,
+                rssi: controller.rssi().ok().map(|rssi| rssi.clamp(-128, 0) as i8),
+                presses: presses.count,
+                ..Default::default()
+            };
+            if let Some(dropped) = uploader.push(now, reading) {
+                println!("Queue full, dropped reading #{}", dropped.seq);
+            }
+        }
+
+        if !supervisor.is_up() {
+            connected = false;
+            continue;
+        }
+        dns_servers.update(&stack)?;
+        let Some(reading) = uploader.next(now).copied() else {
+            continue;
+        };
+        match upload(&stack, &mut socket, &mut connected, &reading) {
+            Ok(()) => {
+                uploader.sent();
+                println!(
+                    "Uploaded reading #{}, {} queued",
+                    reading.seq,
+                    uploader.len()
+                );
+            }
+            Err(err) => {
+                socket.disconnect();
+                connected = false;
+                let delay = uploader.failed(now);
+                println!(
+                    "Uploading reading #{} failed: {:?}, retrying in {} ms, {} queued",
+                    reading.seq,
+                    err,
+                    delay,
+                    uploader.len()
+                );
+            }
+        }
+    }
+    // ANCHOR_END: loop
+}
+
+// ANCHOR: upload
+/// POSTs `reading` to the server, on the open connection if there is one
+fn upload(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    connected: &mut bool,
+    reading: &Reading,
+) -> Result<(), Error> {
+    let mut body = [0u8; 128];
+    let len = reading.encode(&mut body)?;
+    let mut request = [0u8; 512];
+    let len = encode_post(SERVER, PATH, &body[..len], &mut request)?;
+    let request = &request[..len];
+
+    let keep_alive = if *connected {
+        match exchange(socket, request) {
+            // the server closed the idle connection in the meantime, that's no failure
+            Err(Error::Closed) => {
+                println!("The server closed the connection, reconnecting");
+                socket.disconnect();
+                connect(stack, socket)?;
+                exchange(socket, request)?
+            }
+            result => result?,
+        }
+    } else {
+        connect(stack, socket)?;
+        exchange(socket, request)?
+    };
+
+    *connected = keep_alive;
+    if !keep_alive {
+        socket.disconnect();
+    }
+    Ok(())
+}
+// ANCHOR_END: upload
+
+/// Resolves `SERVER` and connects to it
+///
+/// `Socket::open` waits until the connection is established, so a server that doesn't answer
+/// at all keeps it waiting.
+fn connect(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+) -> Result<(), Error> {
+    let address = match SERVER.parse::<Ipv4Address>() {
+        Ok(address) => IpAddress::Ipv4(address),
+        Err(_) => *stack
+            .dns_query(SERVER, DnsQueryType::A)?
+            .first()
+            .ok_or(Error::NoAddress)?,
+    };
+    socket.work();
+    socket.open(address, PORT)?;
+    Ok(())
+}
+
+// ANCHOR: exchange
+/// Sends `request` and reads the response, returns whether the server keeps the connection open
+fn exchange(socket: &mut Socket<'_, '_, WifiDevice<'_>>, request: &[u8]) -> Result<bool, Error> {
+    socket.write_all(request)?;
+    socket.flush()?;
+
+    let deadline = time::Instant::now() + RESPONSE_TIMEOUT;
+    let mut buffer = [0u8; 512];
+    let mut parser = Parser::<256>::new();
+    let mut keep_alive = KeepAlive::new();
+    let mut status = 0;
+    let mut received = false;
+    'read: loop {
+        // `read` would wait for good, only read once there is something
+        match socket.read_ready() {
+            Ok(true) => {}
+            Ok(false) if time::Instant::now() > deadline => return Err(Error::Timeout),
+            Ok(false) => continue,
+            Err(IoError::SocketClosed) if !received => return Err(Error::Closed),
+            Err(IoError::SocketClosed) => {
+                // the body may end with the connection
+                parser.finish()?;
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let len = socket.read(&mut buffer)?;
+        received = true;
+
+        let mut data = &buffer[..len];
+        loop {
+            let (consumed, event) = parser.parse(data)?;
+            data = &data[consumed..];
+            let Some(event) = event else {
+                break;
+            };
+            keep_alive.update(&event);
+            match event {
+                Event::Status(s) => status = s.code,
+                Event::End => break 'read,
+                _ => {}
+            }
+        }
+    }
+
+    if !(200..300).contains(&status) {
+        return Err(Error::Status(status));
+    }
+    Ok(keep_alive.keeps_alive())
+}
+// ANCHOR_END: exchange
+
+/// Counts the presses of the button
+#[derive(Default)]
+struct Presses {
+    count: u32,
+    pressed: bool,
+    changed_at: u64,
+}
+
+impl Presses {
+    fn update(&mut self, button: &Input<'_>) {
+        let now = time::Instant::now().duration_since_epoch().as_millis();
+        // the button pulls GPIO9 to ground while it is pressed
+        if button.is_low() != self.pressed && now - self.changed_at >= DEBOUNCE_MS {
+            self.pressed = !self.pressed;
+            self.changed_at = now;
+            if self.pressed {
+                self.count += 1;
+            }
+        }
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The network stack reported an error, e.g. we don't have an ip address or DNS failed
+    Network(NetworkError),
+    /// Reading from or writing to the socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// The host name didn't resolve to any address
+    NoAddress,
+    /// The reading or the request don't fit into their buffers
+    Encode(telemetry::Error),
+    /// The server closed the connection before answering
+    Closed,
+    /// The server sent something that isn't a valid HTTP response
+    Response(http_response::Error),
+    /// The server didn't accept the reading
+    Status(u16),
+    /// The server didn't answer in time
+    Timeout,
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<telemetry::Error> for Error {
+    fn from(err: telemetry::Error) -> Self {
+        Self::Encode(err)
+    }
+}
+
+impl From<http_response::Error> for Error {
+    fn from(err: http_response::Error) -> Self {
+        Self::Response(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Network(err) => defmt::write!(f, "Network({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::NoAddress => defmt::write!(f, "NoAddress"),
+            Self::Encode(err) => defmt::write!(f, "Encode({})", err),
+            Self::Closed => defmt::write!(f, "Closed"),
+            Self::Response(err) => defmt::write!(f, "Response({})", err),
+            Self::Status(code) => defmt::write!(f, "Status({})", code),
+            Self::Timeout => defmt::write!(f, "Timeout"),
+        }
+    }
+}
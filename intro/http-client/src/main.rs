@@ -1,30 +1,45 @@
 #![no_std]
 #![no_main]
 
-extern crate alloc;
-use core::net::Ipv4Addr;
-
-use blocking_network_stack::Stack;
-use embedded_io::*;
+use core::{convert::Infallible, task::Poll};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::{
     clock::CpuClock,
     interrupt::software::SoftwareInterruptControl,
-    main, ram,
+    main,
+    peripherals::{FLASH, WIFI},
+    ram,
     rng::Rng,
     time::{self, Duration},
 };
 use esp_println::{print, println};
-use esp_radio::wifi::{ClientConfig, ModeConfig, ScanConfig};
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, ScanConfig, WifiController, WifiDevice, WifiError},
+    InitializationError,
+};
+use esp_storage::FlashStorage;
+use example_support::{
+    load_credentials, open_store, save_credentials, supervise, timestamp, wait_for_ip, Settings,
+    StoreError,
+};
+use http_response::Event;
+use net_client::{Get, Network, Storage};
+use wifi_credentials::Credentials;
+use wifi_supervisor::Supervisor;
 
 use smoltcp::{
-    iface::{SocketSet, SocketStorage},
-    wire::{DhcpOption, IpAddress},
+    iface::SocketStorage,
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, EthernetAddress},
 };
 
-const SSID: &str = env!("SSID");
-const PASSWORD: &str = env!("PASSWORD");
+// the credentials can also come from flash, see `credentials`
+const SSID: Option<&str> = option_env!("SSID");
+const PASSWORD: Option<&str> = option_env!("PASSWORD");
+
+const HOST: &str = "www.mobile-j.de";
+const PORT: u16 = 80;
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
@@ -36,134 +51,208 @@ fn main() -> ! {
     esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
     esp_alloc::heap_allocator!(size: 36 * 1024);
 
-    // Initialize the timer, rng and Wifi controller
+    // Initialize the timer and the scheduler
     // let timg0 =
     // let sw_int =
     // esp_rtos::start(
     //     ...
-    // let esp_radio_ctrl =
 
-    // Configure Wifi
+    let mut wifi = peripherals.WIFI;
+    let mut flash = peripherals.FLASH;
+    example_support::restart(|| run(wifi.reborrow(), flash.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and fetches `HOST` over and over again
+fn run(wifi: WIFI<'_>, flash: FLASH<'_>) -> Result<Infallible, Error> {
+    // a partition we can't use is reported like any other error, and we try again
+    let mut store = open_store(FlashStorage::new(flash))?;
+    let credentials = credentials(&mut store)?;
+
+    // Initialize and configure Wifi
+    // let esp_radio_ctrl =
     let (mut controller, interfaces) =
-        esp_radio::wifi::new(&esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
-    let mut device = interfaces.sta;
-    let iface = create_interface(&mut device);
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let device = interfaces.sta;
 
-    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
-    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
-    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // `Network` owns the device and the sockets, it gets an ip address by DHCP and uses the DNS
+    // servers the DHCP server hands out
+    let mut config = net_client::Config::new(EthernetAddress(device.mac_address()).into());
     // we can set a hostname here (or add other DHCP options)
-    dhcp_socket.set_outgoing_options(&[DhcpOption {
+    config.dhcp_options = &[DhcpOption {
         kind: 12,
         data: b"esp-radio",
-    }]);
-    socket_set.add(dhcp_socket);
-    // Wait for getting an ip address
-    let rng = Rng::new();
-    let now = || time::Instant::now().duration_since_epoch().as_millis();
-    let stack = Stack::new(iface, device, socket_set, now, rng.random());
-
-    // Create a Client with your Wi-Fi credentials and default configuration.
+    }];
+    config.random_seed = Rng::new().random().into();
+
+    let mut sockets: [SocketStorage; 3] = Default::default();
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    let storage = Storage {
+        sockets: &mut sockets,
+        rx_buffer: &mut rx_buffer,
+        tx_buffer: &mut tx_buffer,
+        dns_queries: &mut dns_queries,
+    };
+    let mut network = Network::new(device, config, storage, timestamp());
+
+    // the `power-save` example compares what the other modes cost
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+
+    // Create a Client with the Wi-Fi credentials from `credentials` and default configuration.
     // let client_config = ModeConfig::Client(...);
-    let res = controller.set_conf(&client_config);
-    println!("Wi-Fi set_configuration returned {:?}", res);
+    controller.set_config(&client_config)?;
 
     // Start Wi-Fi controller, scan the available networks.
-    controller.start().unwrap();
+    controller.start()?;
     println!("Is wifi started: {:?}", controller.is_started());
 
     println!("Start Wifi Scan");
     let scan_config = ScanConfig::default().with_max(10);
-    let res = controller.scan_with_config(scan_config).unwrap();
+    let res = controller.scan_with_config(scan_config)?;
     for ap in res {
         println!("{:?}", ap);
     }
 
     println!("{:?}", controller.capabilities());
-    println!("Wi-Fi connect: {:?}", controller.connect());
 
-    // Wait to get connected
-    println!("Wait to get connected");
-    loop {
-        match controller.is_connected() {
-            Ok(true) => break,
-            Ok(false) => {}
-            Err(err) => {
-                println!("{:?}", err);
-                loop {}
-            }
-        }
-    }
-    println!("{:?}", controller.is_connected());
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &mut network, &mut supervisor);
+
+    println!("Start busy loop on main");
 
-    // Wait for getting an ip address
-    println!("Wait to get an ip address");
     loop {
-        stack.work();
+        // Make sure we are (still) connected before making a request
+        wait_for_ip(&mut controller, &mut network, &mut supervisor);
 
-        if stack.is_iface_up() {
-            println!("got ip {:?}", stack.get_ip_info());
-            break;
+        // a failed request is no reason to start over, we simply try again
+        if let Err(err) = request(&mut controller, &mut network, &mut supervisor) {
+            println!("HTTP request failed: {:?}", err);
+        }
+
+        // the network has to be polled while we wait, e.g. to renew the lease
+        let deadline = time::Instant::now() + Duration::from_secs(5);
+        while time::Instant::now() < deadline {
+            supervise(&mut controller, &mut network, &mut supervisor);
         }
     }
+}
 
-    println!("Start busy loop on main");
+/// Returns the credentials set at build time and stores them, so later builds can do without
+///
+/// Without `SSID` at build time, the stored credentials are used.
+fn credentials(store: &mut Settings<'_>) -> Result<Credentials, Error> {
+    if let Some(ssid) = SSID {
+        let credentials = Credentials::new(ssid, PASSWORD.unwrap_or_default())?;
+        save_credentials(store, &credentials)?;
+        return Ok(credentials);
+    }
 
-    let mut rx_buffer = [0u8; 1536];
-    let mut tx_buffer = [0u8; 1536];
-    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+    let credentials = load_credentials(store)?.ok_or(Error::NoCredentials)?;
+    println!("Using the stored credentials");
+    Ok(credentials)
+}
 
+/// Resolves `HOST`, sends a GET request and prints the response
+fn request(
+    controller: &mut WifiController<'_>,
+    network: &mut Network<'_, WifiDevice<'_>>,
+    supervisor: &mut Supervisor,
+) -> Result<(), Error> {
+    println!("Making HTTP request");
+    // Create a GET request for the path `/` on `HOST` and `PORT`
+    // let mut get = ...;
     loop {
-        println!("Making HTTP request");
-        socket.work();
-
-        // Open the socket
-        // socket
-        //     .open(....)
-        //     .unwrap();
-        // Write and flush the socket
-        // socket...
-        // socket...
-
-        let deadline = time::Instant::now() + Duration::from_secs(20);
-        let mut buffer = [0u8; 512];
-        while let Ok(len) = socket.read(&mut buffer) {
-            let to_print = unsafe { core::str::from_utf8_unchecked(&buffer[..len]) };
-            print!("{}", to_print);
-
-            if time::Instant::now() > deadline {
-                println!("Timeout");
-                break;
+        supervise(controller, &mut *network, supervisor);
+
+        // `Get` resolves the host name, connects and parses the response, we only print it
+        let poll = get.poll(network, timestamp(), |event| match event {
+            Event::Status(status) => println!("Status: {} {}", status.code, status.reason),
+            Event::Header(header) => {
+                print!("{}: ", header.name);
+                print_text(header.value);
+                println!();
             }
+            Event::HeadersEnd => println!(),
+            Event::Body(body) => print_text(body),
+            Event::End => println!(),
+        });
+        if let Poll::Ready(result) = poll {
+            return Ok(result?);
         }
-        println!();
-
-        socket.disconnect();
+    }
+}
 
-        let deadline = time::Instant::now() + Duration::from_secs(5);
-        while time::Instant::now() < deadline {
-            socket.work();
+/// Prints `data` as text, escaping anything that isn't valid UTF-8
+fn print_text(data: &[u8]) {
+    for chunk in data.utf8_chunks() {
+        print!("{}", chunk.valid());
+        for byte in chunk.invalid() {
+            print!("\\x{:02x}", byte);
         }
     }
 }
 
-// some smoltcp boilerplate
-fn timestamp() -> smoltcp::time::Instant {
-    smoltcp::time::Instant::from_micros(
-        esp_hal::time::Instant::now()
-            .duration_since_epoch()
-            .as_micros() as i64,
-    )
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The store in the NVS partition couldn't be opened, read or written
+    Store(StoreError),
+    /// The credentials set at build time are invalid
+    Credentials(wifi_credentials::Error),
+    /// `SSID` wasn't set at build time and no credentials are stored
+    NoCredentials,
+    /// The request failed, e.g. DNS failed, the server reset the connection or didn't answer in
+    /// time, see `net_client::Error`
+    Request(net_client::Error),
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
 }
 
-pub fn create_interface(device: &mut esp_radio::wifi::WifiDevice) -> smoltcp::iface::Interface {
-    // users could create multiple instances but since they only have one WifiDevice
-    // they probably can't do anything bad with that
-    smoltcp::iface::Interface::new(
-        smoltcp::iface::Config::new(smoltcp::wire::HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress::from_bytes(&device.mac_address()),
-        )),
-        device,
-        timestamp(),
-    )
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
+impl From<wifi_credentials::Error> for Error {
+    fn from(err: wifi_credentials::Error) -> Self {
+        Self::Credentials(err)
+    }
+}
+
+impl From<net_client::Error> for Error {
+    fn from(err: net_client::Error) -> Self {
+        Self::Request(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Store(err) => defmt::write!(f, "Store({})", defmt::Debug2Format(err)),
+            Self::Credentials(err) => defmt::write!(f, "Credentials({})", err),
+            Self::NoCredentials => defmt::write!(f, "NoCredentials"),
+            Self::Request(err) => defmt::write!(f, "Request({})", err),
+        }
+    }
 }
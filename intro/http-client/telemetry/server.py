@@ -0,0 +1,51 @@
+#!/usr/bin/env python3
+"""Receives the readings of the `telemetry` example and prints them.
+
+Runs an HTTP/1.1 server on port 8000 that keeps the connections open between the requests.
+With `--fail`, every other request is answered with `503 Service Unavailable`, to watch the
+device retry.
+"""
+import json
+import sys
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+PORT = 8000
+FAIL = "--fail" in sys.argv
+
+
+class Handler(BaseHTTPRequestHandler):
+    # HTTP/1.1 keeps the connection open for the next request
+    protocol_version = "HTTP/1.1"
+    # close connections that are idle for longer, the device has to reconnect then
+    timeout = 60
+    requests = 0
+    last_seq = {}
+
+    def do_POST(self):
+        length = int(self.headers["Content-Length"])
+        reading = json.loads(self.rfile.read(length))
+
+        Handler.requests += 1
+        if FAIL and Handler.requests % 2 == 0:
+            self.answer(503)
+            return
+
+        client = self.client_address[0]
+        last = Handler.last_seq.get(client)
+        if last is not None and reading["seq"] > last + 1:
+            print(f"{client}: {reading['seq'] - last - 1} readings missing", flush=True)
+        Handler.last_seq[client] = reading["seq"]
+        print(f"{client}: {reading}", flush=True)
+        self.answer(204)
+
+    def answer(self, status):
+        self.send_response(status)
+        self.send_header("Content-Length", "0")
+        self.end_headers()
+
+    def log_message(self, format, *args):
+        pass
+
+
+print(f"Listening on port {PORT}", flush=True)
+ThreadingHTTPServer(("", PORT), Handler).serve_forever()
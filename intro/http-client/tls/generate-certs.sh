@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+# Creates a self-signed CA and a server certificate signed by it, for testing the https-client
+# example against a TLS server on the local network (see `server.sh`).
+#
+# The CA certificate is written to `ca.der`, which the example embeds when it's built with the
+# `local-tls-server` feature. Both use P-256 keys, so the example doesn't need RSA for them.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+# must match `HOST` in examples/https-client.rs
+NAME=tls-test.local
+
+openssl ecparam -name prime256v1 -genkey -noout -out ca.key
+openssl req -x509 -new -key ca.key -sha256 -days 365 -subj "/CN=esp-rs training test CA" \
+    -addext "basicConstraints=critical,CA:TRUE" \
+    -addext "keyUsage=critical,keyCertSign,cRLSign" \
+    -out ca.pem
+openssl x509 -in ca.pem -outform der -out ca.der
+
+openssl ecparam -name prime256v1 -genkey -noout -out server.key
+openssl req -new -key server.key -subj "/CN=$NAME" -out server.csr
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -sha256 -days 365 \
+    -extfile <(printf "subjectAltName=DNS:%s\nextendedKeyUsage=serverAuth\n" "$NAME") \
+    -out server.pem
+rm server.csr
+
+echo "Created ca.der, server.pem and server.key for $NAME"
@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+# Runs a TLS 1.3 server on port 8443 that answers every HTTP request with a status page.
+#
+# Create the certificates with `generate-certs.sh` first.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+openssl s_server -accept 8443 -tls1_3 -cert server.pem -key server.key -www
@@ -0,0 +1,321 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::{format, vec::Vec};
+
+use blocking_network_stack::{Error as NetworkError, IoError, Socket, Stack, UdpSocket};
+use core::convert::Infallible;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{self, Duration},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, ScanConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, wait_for_ip, Connection, Response, ServerConfig};
+use http_request::{Method, Request};
+use mdns::{Responder, Service};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::udp,
+    wire::{DhcpOption, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+const PORT: u16 = 80;
+
+/// The name of the board on the network, it's reachable as `esp-radio.local`
+const HOSTNAME: &str = "esp-radio";
+/// The web server, advertised with DNS-SD so browsers and apps can find it without the name
+const SERVICES: &[Service<'static>] = &[Service {
+    instance: "ESP32-C3 LED",
+    service_type: "_http._tcp",
+    port: PORT,
+    txt: &["path=/"],
+}];
+
+/// Number of clients served at the same time, browsers open more than one connection
+const CONNECTIONS: usize = 3;
+/// Requests with a larger head are rejected
+const MAX_REQUEST_SIZE: usize = 1024;
+/// Clients have 5 seconds to send their request, and a second to close their end after the
+/// response was sent
+const SERVER: ServerConfig = ServerConfig {
+    port: PORT,
+    request_timeout: Duration::from_secs(5),
+    close_timeout: Duration::from_secs(1),
+};
+
+const INDEX: &str = include_str!("../static/index.html");
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the LED from `blinky` and the button from `button`
+    let mut led = Output::new(peripherals.GPIO7, Level::Low, OutputConfig::default());
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow(), &mut led, &button))
+}
+
+/// Connects to the Wi-Fi network and serves HTTP requests on `PORT`
+fn run(wifi: WIFI<'_>, led: &mut Output<'_>, button: &Input<'_>) -> Result<Infallible, Error> {
+    // Initialize and configure Wifi
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    // the network stack borrows the buffers of the sockets, so they have to be created first
+    let dhcp_options = [DhcpOption {
+        kind: 12,
+        data: HOSTNAME.as_bytes(),
+    }];
+    let mut mdns_rx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut mdns_rx_payload = [0u8; 1024];
+    let mut mdns_tx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut mdns_tx_payload = [0u8; 1024];
+
+    // one socket for DHCP, one for mDNS and one per connection
+    let mut socket_set_entries: [SocketStorage; CONNECTIONS + 2] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&dhcp_options);
+    socket_set.add(dhcp_socket);
+    // Wait for getting an ip address
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+
+    controller.start()?;
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    println!("Start Wifi Scan");
+    let scan_config = ScanConfig::default().with_max(10);
+    let res = controller.scan_with_config(scan_config)?;
+    for ap in res {
+        println!("{:?}", ap);
+    }
+
+    println!("{:?}", controller.capabilities());
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+
+    let mut rx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut tx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut connections: Vec<(Socket<'_, '_, _>, Connection<MAX_REQUEST_SIZE>)> = rx_buffers
+        .iter_mut()
+        .zip(tx_buffers.iter_mut())
+        .map(|(rx_buffer, tx_buffer)| {
+            (
+                stack.get_socket(rx_buffer, tx_buffer),
+                Connection::new(SERVER),
+            )
+        })
+        .collect();
+
+    // mDNS queries are sent to a multicast group, which we have to join to receive them
+    let mut mdns_socket = stack.get_udp_socket(
+        &mut mdns_rx_metadata,
+        &mut mdns_rx_payload,
+        &mut mdns_tx_metadata,
+        &mut mdns_tx_payload,
+    );
+    mdns_socket.bind(mdns::PORT)?;
+    mdns_socket.join_multicast_group(MDNS_GROUP)?;
+    let mut responder = Responder::new(HOSTNAME, [0; 4]).with_services(SERVICES);
+
+    let mut address = None;
+    loop {
+        // keep the connection up, there is nobody to serve while we are offline
+        wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+        let ip = stack.get_ip_info()?.ip;
+        if address != Some(ip) {
+            println!("Listening on http://{}:{}/", ip, PORT);
+            address = Some(ip);
+
+            // tell the others right away, their caches may still have our old address
+            responder.set_address(ip.octets());
+            announce(&mut mdns_socket, &responder)?;
+            println!("Also reachable as http://{}.local:{}/", HOSTNAME, PORT);
+        }
+        serve_mdns(&mut mdns_socket, &responder)?;
+
+        // a failing connection doesn't affect the others, it is dropped and the socket reused
+        for (socket, connection) in &mut connections {
+            let result = connection.poll(socket, |request, _body| route(request, led, button));
+            if let Err(err) = result {
+                println!("Connection failed: {:?}", err);
+                connection.abort(socket);
+            }
+        }
+    }
+}
+
+/// Picks the response for a request based on its method and path
+fn route(request: &Request<'_>, led: &mut Output<'_>, button: &Input<'_>) -> Response {
+    match (request.method, request.path) {
+        (Method::Get, "/") => Response::new("200 OK", "text/html; charset=utf-8", INDEX),
+        // Report the state of the LED and the button on `GET /api/state`
+        // (Method::Get, "/api/state") => ...,
+        // Toggle the LED on `POST /api/led/toggle` and report the new state
+        // (Method::Post, "/api/led/toggle") => ...,
+        // Answer other methods on these paths with `405 Method Not Allowed` and an `Allow` header
+        // (_, ...) => ...,
+        _ => Response::text("404 Not Found", "Not found\n"),
+    }
+}
+
+/// Reports the state of the LED and the button as JSON
+fn state(led: &Output<'_>, button: &Input<'_>) -> Response {
+    // Format the state as `{"led_on":true,"button_pressed":false}`, the button pulls GPIO9 to
+    // ground while it is pressed
+    // let body = ...;
+    Response::new("200 OK", "application/json", body)
+}
+
+/// The group all mDNS queries and responses are sent to
+const MDNS_GROUP: IpAddress = {
+    let [a, b, c, d] = mdns::MULTICAST_ADDRESS;
+    IpAddress::Ipv4(Ipv4Address::new(a, b, c, d))
+};
+
+/// Answers the mDNS queries for our hostname and the web server
+fn serve_mdns(
+    socket: &mut UdpSocket<'_, '_, WifiDevice<'_>>,
+    responder: &Responder<'_>,
+) -> Result<(), Error> {
+    let mut query = [0u8; 512];
+    let mut response = [0u8; 512];
+    loop {
+        let len = match socket.receive(&mut query) {
+            Ok((len, _, _)) => len,
+            Err(IoError::UdpRecvError(udp::RecvError::Exhausted)) => return Ok(()),
+            // e.g. a query with a long list of answers the querier knows already
+            Err(IoError::UdpRecvError(udp::RecvError::Truncated)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        match responder.respond(&query[..len], &mut response) {
+            Ok(Some(len)) => socket.send(MDNS_GROUP, mdns::PORT, &response[..len])?,
+            // most queries are about other devices, and the responses of other devices are
+            // sent to the group as well
+            Ok(None) | Err(mdns::Error::NotAQuery) => {}
+            Err(err) => println!("Bad mDNS query: {:?}", err),
+        }
+    }
+}
+
+/// Sends all our records to the group, so others don't have to ask
+fn announce(
+    socket: &mut UdpSocket<'_, '_, WifiDevice<'_>>,
+    responder: &Responder<'_>,
+) -> Result<(), Error> {
+    let mut announcement = [0u8; 512];
+    let len = responder.announce(&mut announcement)?;
+    socket.send(MDNS_GROUP, mdns::PORT, &announcement[..len])?;
+    Ok(())
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The network stack reported an error, e.g. we don't have an ip address
+    Network(NetworkError),
+    /// Reading from or writing to the socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// The mDNS records don't fit into the buffer or our names are invalid
+    Mdns(mdns::Error),
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<mdns::Error> for Error {
+    fn from(err: mdns::Error) -> Self {
+        Self::Mdns(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Network(err) => defmt::write!(f, "Network({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::Mdns(err) => defmt::write!(f, "Mdns({})", err),
+        }
+    }
+}
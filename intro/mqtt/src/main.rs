@@ -0,0 +1,512 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::{format, string::String};
+
+use core::convert::Infallible;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select3, Either3};
+use embassy_net::{
+    dns::DnsQueryType,
+    tcp::{ConnectError, TcpSocket},
+    Runner, Stack, StackResources,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{with_deadline, with_timeout, Duration, Instant, TimeoutError, Timer};
+use embedded_io_async::Write;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    gpio::{Input, InputConfig, Level, Output, OutputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    ram,
+    rng::Rng,
+    timer::timg::TimerGroup,
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, ScanConfig, WifiController, WifiDevice, WifiEvent},
+    Controller,
+};
+use mqtt_packet::{
+    ConnAck, Connect, ConnectReturnCode, KeepAlive, KeepAliveAction, Packet, Publish, QoS,
+    Subscribe, Will,
+};
+use static_cell::StaticCell;
+use wifi_supervisor::Backoff;
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+/// Host name or IPv4 address of the broker, set `MQTT_BROKER` to use your own
+const BROKER: &str = match option_env!("MQTT_BROKER") {
+    Some(broker) => broker,
+    None => "test.mosquitto.org",
+};
+const PORT: u16 = 1883;
+/// Credentials, only needed if the broker requires them
+const MQTT_USERNAME: Option<&str> = option_env!("MQTT_USERNAME");
+const MQTT_PASSWORD: Option<&str> = option_env!("MQTT_PASSWORD");
+
+/// Longest time in seconds without sending anything, we ping the broker if there's nothing to say
+const KEEP_ALIVE: u16 = 30;
+/// How long the broker has to answer our `CONNECT`
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+// tasks can only borrow data that lives forever
+static RADIO: StaticCell<Controller<'static>> = StaticCell::new();
+static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+static CLIENT_ID: StaticCell<String> = StaticCell::new();
+
+/// Button presses, counted by the `watch_button` task and published by the `mqtt` task
+///
+/// Presses are queued while the broker is unreachable, until the channel is full.
+static PRESSES: Channel<CriticalSectionRawMutex, u32, 8> = Channel::new();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer, the scheduler and the Wifi controller
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the LED from `blinky` and the button from `button-interrupt`
+    let led = Output::new(peripherals.GPIO7, Level::Low, OutputConfig::default());
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+
+    let esp_radio_ctrl = RADIO.init(esp_radio::init().unwrap());
+
+    let (controller, interfaces) =
+        esp_radio::wifi::new(esp_radio_ctrl, peripherals.WIFI, Default::default()).unwrap();
+
+    // the broker only allows one connection per client id, the MAC address makes it unique
+    let mac = interfaces.sta.mac_address();
+    let client_id = CLIENT_ID.init(format!(
+        "esp32c3-{:02x}{:02x}{:02x}",
+        mac[3], mac[4], mac[5]
+    ));
+
+    // the network stack gets its address via DHCP
+    let mut dhcp_config = embassy_net::DhcpConfig::default();
+    dhcp_config.hostname = Some("esp-radio".try_into().unwrap());
+    let config = embassy_net::Config::dhcpv4(dhcp_config);
+
+    let rng = Rng::new();
+    let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+
+    let (stack, runner) = embassy_net::new(
+        interfaces.sta,
+        config,
+        RESOURCES.init(StackResources::new()),
+        seed,
+    );
+
+    spawner.spawn(connection(controller)).unwrap();
+    spawner.spawn(net_task(runner)).unwrap();
+    spawner.spawn(watch_button(button)).unwrap();
+    spawner.spawn(mqtt(stack, led, client_id)).unwrap();
+
+    // the tasks do all the work, we only report changes of the IP configuration
+    loop {
+        println!("Wait to get an ip address");
+        stack.wait_config_up().await;
+        println!("got ip {:?}", stack.config_v4());
+
+        stack.wait_config_down().await;
+        println!("Lost ip address");
+    }
+}
+
+/// Keeps the Wi-Fi link up, reconnecting whenever the access point goes away
+#[embassy_executor::task]
+async fn connection(mut controller: WifiController<'static>) {
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config).unwrap();
+
+    println!("Starting wifi");
+    controller.start_async().await.unwrap();
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    println!("Start Wifi Scan");
+    let scan_config = ScanConfig::default().with_max(10);
+    match controller.scan_with_config_async(scan_config).await {
+        Ok(aps) => {
+            for ap in aps {
+                println!("{:?}", ap);
+            }
+        }
+        Err(err) => println!("Scan failed: {:?}", err),
+    }
+
+    loop {
+        println!("Wait to get connected");
+        match controller.connect_async().await {
+            Ok(()) => {
+                println!("Wifi connected!");
+                controller.wait_for_event(WifiEvent::StaDisconnected).await;
+                println!("Wifi disconnected");
+            }
+            Err(err) => println!("Failed to connect to wifi: {:?}", err),
+        }
+        Timer::after(Duration::from_secs(5)).await;
+    }
+}
+
+/// Runs the network stack, including the DHCP client
+#[embassy_executor::task]
+async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
+    runner.run().await
+}
+
+/// Counts the button presses and hands them to the `mqtt` task
+#[embassy_executor::task]
+async fn watch_button(mut button: Input<'static>) {
+    let mut presses = 0;
+    loop {
+        // Wait for the button to be pressed, count the press and send the count to `PRESSES`
+        // button...
+        // presses += 1;
+        // PRESSES...
+
+        // the contacts of the button bounce for a few milliseconds, ignore that
+        Timer::after(Duration::from_millis(50)).await;
+    }
+}
+
+/// The topics of this device, all of them start with the client id
+struct Topics {
+    /// Every button press is published here, the payload is the number of presses so far
+    button: String,
+    /// Payloads `on`, `off` and `toggle` published here switch the LED
+    led_set: String,
+    /// The state of the LED, `on` or `off`
+    led: String,
+    /// `online` while we are connected, the broker publishes `offline` when we disappear
+    status: String,
+}
+
+impl Topics {
+    fn new(client_id: &str) -> Self {
+        Self {
+            button: format!("{}/button", client_id),
+            led_set: format!("{}/led/set", client_id),
+            led: format!("{}/led", client_id),
+            status: format!("{}/status", client_id),
+        }
+    }
+}
+
+/// Keeps a connection to the broker, reconnecting with increasing delays when it fails
+#[embassy_executor::task]
+async fn mqtt(stack: Stack<'static>, mut led: Output<'static>, client_id: &'static str) {
+    let topics = Topics::new(client_id);
+    println!(
+        "Watch the messages with `mosquitto_sub -h {} -t '{}/#' -v`",
+        BROKER, client_id
+    );
+
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut backoff = Backoff::new(1_000, 60_000);
+    loop {
+        stack.wait_config_up().await;
+
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        // give up on the connection if the broker doesn't acknowledge what we send
+        socket.set_timeout(Some(Duration::from_secs(KEEP_ALIVE.into())));
+
+        let Err(err) = session(
+            stack,
+            &mut socket,
+            client_id,
+            &topics,
+            &mut led,
+            &mut backoff,
+        )
+        .await;
+        println!("MQTT connection failed: {:?}", err);
+        socket.abort();
+
+        let delay = backoff.next_delay();
+        println!("Reconnecting in {} ms", delay);
+        Timer::after(Duration::from_millis(delay)).await;
+    }
+}
+
+/// Connects to the broker and handles the session until the connection fails
+async fn session(
+    stack: Stack<'_>,
+    socket: &mut TcpSocket<'_>,
+    client_id: &str,
+    topics: &Topics,
+    led: &mut Output<'_>,
+    backoff: &mut Backoff,
+) -> Result<Infallible, Error> {
+    println!("Resolving {}", BROKER);
+    let addresses = stack.dns_query(BROKER, DnsQueryType::A).await?;
+    let address = *addresses.first().ok_or(Error::NoAddress)?;
+
+    println!("Connecting to {}:{}", address, PORT);
+    with_timeout(CONNECT_TIMEOUT, socket.connect((address, PORT))).await??;
+
+    let mut client = Client {
+        socket,
+        keep_alive: KeepAlive::new(KEEP_ALIVE, now()),
+        buffer: [0; 256],
+    };
+    let mut receiver = Receiver {
+        buffer: [0; 512],
+        len: 0,
+        consumed: 0,
+    };
+
+    let connect = Connect {
+        client_id,
+        keep_alive: KEEP_ALIVE,
+        clean_session: true,
+        // published by the broker when the connection breaks
+        will: Some(Will {
+            topic: &topics.status,
+            payload: b"offline",
+            qos: QoS::AtLeastOnce,
+            retain: true,
+        }),
+        username: MQTT_USERNAME,
+        password: MQTT_PASSWORD.map(str::as_bytes),
+    };
+    client.send(|buffer| connect.encode(buffer)).await?;
+
+    // the broker answers with `CONNACK` before anything else
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        match receiver.next()? {
+            Some(Packet::ConnAck(ConnAck {
+                code: ConnectReturnCode::Accepted,
+                ..
+            })) => break,
+            Some(Packet::ConnAck(connack)) => return Err(Error::Refused(connack.code)),
+            Some(_) => return Err(Error::Protocol),
+            None => with_deadline(deadline, receiver.read(client.socket)).await??,
+        }
+    }
+    println!("Connected to the broker as {}", client_id);
+    backoff.reset();
+
+    let subscribe = Subscribe {
+        packet_id: 1,
+        topics: &[(&topics.led_set, QoS::AtLeastOnce)],
+    };
+    client.send(|buffer| subscribe.encode(buffer)).await?;
+    client.publish(&topics.status, b"online", true).await?;
+    client.publish(&topics.led, led_state(led), true).await?;
+
+    loop {
+        // handle everything the broker sent before waiting for more
+        while let Some(packet) = receiver.next()? {
+            client.keep_alive.received();
+            match packet {
+                // Switch the LED when a message arrives on `topics.led_set` and publish the new
+                // state to `topics.led`. Messages with QoS 1 have a packet id, acknowledge them
+                // with `mqtt_packet::encode_puback`.
+                // Packet::Publish(publish) if ... => {
+                // }
+                Packet::SubAck(suback) => {
+                    if suback.granted().any(|qos| qos.is_none()) {
+                        return Err(Error::SubscriptionRejected);
+                    }
+                    println!("Subscribed to {}", topics.led_set);
+                }
+                Packet::PingResp => {}
+                packet => println!("Ignoring {:?}", packet),
+            }
+        }
+
+        // wait for the broker, a button press or the time to ping the broker, whatever comes first
+        let ping_at = client
+            .keep_alive
+            .deadline()
+            .map_or(Instant::MAX, Instant::from_millis);
+        match select3(
+            receiver.read(client.socket),
+            PRESSES.receive(),
+            Timer::at(ping_at),
+        )
+        .await
+        {
+            Either3::First(result) => result?,
+            Either3::Second(presses) => {
+                let payload = format!("{}", presses);
+                client
+                    .publish(&topics.button, payload.as_bytes(), false)
+                    .await?;
+            }
+            Either3::Third(()) => match client.keep_alive.poll(now()) {
+                KeepAliveAction::Ping => client.send(mqtt_packet::encode_pingreq).await?,
+                KeepAliveAction::TimedOut => return Err(Error::Timeout),
+                KeepAliveAction::None => {}
+            },
+        }
+    }
+}
+
+fn led_state(led: &Output<'_>) -> &'static [u8] {
+    if led.is_set_high() {
+        b"on"
+    } else {
+        b"off"
+    }
+}
+
+fn now() -> u64 {
+    Instant::now().as_millis()
+}
+
+/// Sends packets to the broker
+struct Client<'s, 'a> {
+    socket: &'s mut TcpSocket<'a>,
+    keep_alive: KeepAlive,
+    buffer: [u8; 256],
+}
+
+impl Client<'_, '_> {
+    /// Sends the packet `encode` writes into the buffer
+    async fn send(
+        &mut self,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize, mqtt_packet::Error>,
+    ) -> Result<(), Error> {
+        let len = encode(&mut self.buffer)?;
+        self.socket.write_all(&self.buffer[..len]).await?;
+        self.keep_alive.sent(now());
+        Ok(())
+    }
+
+    /// Publishes a message with QoS 0
+    ///
+    /// The broker keeps the last retained message of a topic and sends it to new subscribers,
+    /// which is what we want for states, but not for events.
+    async fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Error> {
+        let publish = Publish {
+            topic,
+            payload,
+            qos: QoS::AtMostOnce,
+            retain,
+            dup: false,
+            packet_id: None,
+        };
+        self.send(|buffer| publish.encode(buffer)).await
+    }
+}
+
+/// Collects what the broker sends until it makes up whole packets
+struct Receiver {
+    buffer: [u8; 512],
+    /// Number of bytes in the buffer
+    len: usize,
+    /// Length of the packet returned by the last call to `next`
+    consumed: usize,
+}
+
+impl Receiver {
+    /// Returns the next packet in the buffer and removes the one returned before
+    fn next(&mut self) -> Result<Option<Packet<'_>>, Error> {
+        self.buffer.copy_within(self.consumed..self.len, 0);
+        self.len -= self.consumed;
+
+        let (consumed, packet) = mqtt_packet::decode(&self.buffer[..self.len])?;
+        // a packet that doesn't fit into the buffer will never be complete
+        if packet.is_none() && self.len == self.buffer.len() {
+            return Err(Error::PacketTooLarge);
+        }
+        self.consumed = consumed;
+        Ok(packet)
+    }
+
+    /// Reads more data from the broker
+    ///
+    /// Call `next` until it returns `None` first, so that there is room in the buffer.
+    async fn read(&mut self, socket: &mut TcpSocket<'_>) -> Result<(), Error> {
+        let len = socket.read(&mut self.buffer[self.len..]).await?;
+        if len == 0 {
+            return Err(Error::Closed);
+        }
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Everything that can go wrong while talking to the broker
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// Resolving the host name of the broker failed
+    Dns(embassy_net::dns::Error),
+    /// The host name didn't resolve to any address
+    NoAddress,
+    /// The TCP connection couldn't be established
+    Connect(ConnectError),
+    /// Reading from or writing to the socket failed
+    Tcp(embassy_net::tcp::Error),
+    /// A packet didn't fit into the buffer, or the broker sent an invalid one
+    Mqtt(mqtt_packet::Error),
+    /// The broker refused the connection, e.g. because of wrong credentials
+    Refused(ConnectReturnCode),
+    /// The broker didn't allow us to subscribe to the topic
+    SubscriptionRejected,
+    /// The broker sent a packet we didn't expect at that point
+    Protocol,
+    /// The broker sent a packet that doesn't fit into our buffer
+    PacketTooLarge,
+    /// The broker closed the connection
+    Closed,
+    /// The broker didn't answer in time
+    Timeout,
+}
+
+impl From<embassy_net::dns::Error> for Error {
+    fn from(err: embassy_net::dns::Error) -> Self {
+        Self::Dns(err)
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(err: ConnectError) -> Self {
+        Self::Connect(err)
+    }
+}
+
+impl From<embassy_net::tcp::Error> for Error {
+    fn from(err: embassy_net::tcp::Error) -> Self {
+        Self::Tcp(err)
+    }
+}
+
+impl From<mqtt_packet::Error> for Error {
+    fn from(err: mqtt_packet::Error) -> Self {
+        Self::Mqtt(err)
+    }
+}
+
+impl From<TimeoutError> for Error {
+    fn from(_: TimeoutError) -> Self {
+        Self::Timeout
+    }
+}
@@ -0,0 +1,516 @@
+#![no_std]
+#![no_main]
+
+use blocking_network_stack::{Error as NetworkError, IoError, Socket, Stack};
+use core::convert::Infallible;
+use embedded_io::*;
+use embedded_storage::{ReadStorage, Storage};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_bootloader_esp_idf::{
+    ota::OtaImageState,
+    ota_updater::OtaUpdater,
+    partitions::{self, FlashRegion, PARTITION_TABLE_MAX_LEN},
+};
+use esp_hal::{
+    clock::CpuClock,
+    delay::Delay,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    rtc_cntl::{Rtc, RwdtStage},
+    system::software_reset,
+    time::{self, Duration},
+};
+use esp_image::{AppDesc, Verified, Verifier, CHIP_ID_ESP32C3};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use esp_storage::FlashStorage;
+use example_support::{create_interface, supervise, wait_for_ip, DnsServers};
+use http_response::{Event, Parser};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{SocketSet, SocketStorage},
+    socket::dns::DnsQuery,
+    wire::{DhcpOption, DnsQueryType, IpAddress, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+/// The HTTP server with the new firmware, an IP address or a host name
+///
+/// Serving the folder with `firmware.bin` with `python3 -m http.server` is enough.
+const OTA_SERVER: &str = match option_env!("OTA_SERVER") {
+    Some(server) => server,
+    None => "192.168.1.10",
+};
+const OTA_PORT: u16 = 8000;
+const OTA_PATH: &str = "/firmware.bin";
+/// How often we ask the server for a new firmware
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a new firmware has to confirm that it works before the watchdog rolls it back
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The download is dropped if the server doesn't send anything for this long
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The flash is erased and written a sector at a time
+const SECTOR_LEN: usize = 4096;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    println!(
+        "Running {} {}",
+        ESP_APP_DESC.project_name(),
+        ESP_APP_DESC.version()
+    );
+
+    let mut flash = FlashStorage::new(peripherals.FLASH);
+    let mut rtc = Rtc::new(peripherals.LPWR);
+    let mut on_trial = match check_boot(&mut flash) {
+        Ok(on_trial) => on_trial,
+        // e.g. flashed without `partitions.csv`, we can still run, but not update
+        Err(err) => {
+            println!("Can't read the OTA state: {:?}", err);
+            false
+        }
+    };
+    if on_trial {
+        // if the new firmware hangs or panics before it confirmed itself, the watchdog resets the
+        // chip, and `check_boot` rolls back
+        rtc.rwdt.set_timeout(RwdtStage::Stage0, CONFIRM_TIMEOUT);
+        rtc.rwdt.enable();
+    }
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow(), &mut flash, &mut rtc, &mut on_trial))
+}
+
+/// Connects to the Wi-Fi network, confirms the running firmware and checks for updates
+fn run(
+    wifi: WIFI<'_>,
+    flash: &mut FlashStorage<'_>,
+    rtc: &mut Rtc<'_>,
+    on_trial: &mut bool,
+) -> Result<Infallible, Error> {
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
+    let mut dhcp_socket = smoltcp::socket::dhcpv4::Socket::new();
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    socket_set.add(dhcp_socket);
+    let rng = Rng::new();
+    let now = || time::Instant::now().duration_since_epoch().as_millis();
+    let stack = Stack::new(iface, device, socket_set, now, rng.random());
+
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+    controller.start()?;
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+    wait_for_ip(&mut controller, &stack, &mut supervisor);
+
+    // we got this far, so the new firmware is good enough to fetch the next one
+    if *on_trial {
+        confirm(flash)?;
+        rtc.rwdt.disable();
+        *on_trial = false;
+    }
+
+    // `OTA_SERVER` may be a host name, the DNS servers are set after every reconnect
+    let mut dns_queries: [Option<DnsQuery>; 1] = Default::default();
+    stack.configure_dns(&[], &mut dns_queries);
+    let mut dns_servers = DnsServers::default();
+
+    // a bigger receive buffer lets the server send more at once
+    let mut rx_buffer = [0u8; 4096];
+    let mut tx_buffer = [0u8; 512];
+    let mut socket = stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+
+    loop {
+        wait_for_ip(&mut controller, &stack, &mut supervisor);
+        dns_servers.update(&stack)?;
+
+        // a failed update is no reason to start over, the partition we booted from is untouched
+        match update(&stack, &mut socket, flash) {
+            Ok(true) => {
+                println!("Restarting into the new firmware");
+                Delay::new().delay_millis(100);
+                software_reset();
+            }
+            Ok(false) => println!("No new firmware"),
+            Err(err) => println!("Update failed: {:?}", err),
+        }
+
+        socket.disconnect();
+
+        let deadline = time::Instant::now() + CHECK_INTERVAL;
+        while time::Instant::now() < deadline {
+            socket.work();
+            supervise(&mut controller, &stack, &mut supervisor);
+        }
+    }
+}
+
+/// Finds out whether the running firmware was just installed, and rolls back if it failed before
+///
+/// Returns `true` if the firmware is on trial and has to confirm that it works.
+///
+/// The bootloader `espflash` flashes doesn't roll back by itself, so the firmware does it: a new
+/// firmware starts in the `New` state, which we change to `PendingVerify`. If it's still in that
+/// state on the next boot, it never confirmed itself and we go back to the previous one.
+fn check_boot(flash: &mut FlashStorage<'_>) -> Result<bool, Error> {
+    let mut table = [0; PARTITION_TABLE_MAX_LEN];
+    let mut ota = OtaUpdater::new(flash, &mut table)?;
+    let state = match ota.current_ota_state() {
+        Ok(state) => state,
+        // nothing was installed over the air yet, `espflash` erased the OTA data
+        Err(partitions::Error::InvalidState) => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    println!("Booted {:?}, {:?}", ota.selected_partition()?, state);
+
+    match state {
+        OtaImageState::New => {
+            ota.set_current_ota_state(OtaImageState::PendingVerify)?;
+            println!(
+                "New firmware, it has {} s to confirm that it works",
+                CONFIRM_TIMEOUT.as_secs()
+            );
+            Ok(true)
+        }
+        OtaImageState::PendingVerify => {
+            println!("The new firmware didn't confirm itself, rolling back");
+            // Mark this firmware as `OtaImageState::Invalid`, switch back to the other partition
+            // with `ota.activate_next_partition` and mark it as `OtaImageState::Valid`, the
+            // previous firmware confirmed itself before it installed this one.
+            software_reset();
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Marks the running firmware as working, so it's booted from now on
+fn confirm(flash: &mut FlashStorage<'_>) -> Result<(), Error> {
+    let mut table = [0; PARTITION_TABLE_MAX_LEN];
+    let mut ota = OtaUpdater::new(flash, &mut table)?;
+    ota.set_current_ota_state(OtaImageState::Valid)?;
+    println!("Firmware confirmed");
+    Ok(())
+}
+
+/// Downloads the firmware from `OTA_SERVER` into the other app partition and boots it next
+///
+/// Returns `false` if the server has the version we are running.
+fn update(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    flash: &mut FlashStorage<'_>,
+) -> Result<bool, Error> {
+    // never the partition we are running from
+    let mut table = [0; PARTITION_TABLE_MAX_LEN];
+    let mut ota = OtaUpdater::new(flash, &mut table)?;
+    let (partition, slot) = ota.next_partition()?;
+    let capacity = partition.partition_size();
+    let mut image = Image::new(partition);
+
+    let address = match OTA_SERVER.parse::<Ipv4Address>() {
+        Ok(address) => IpAddress::Ipv4(address),
+        Err(_) => *stack
+            .dns_query(OTA_SERVER, DnsQueryType::A)?
+            .first()
+            .ok_or(Error::NoAddress)?,
+    };
+    println!("Checking http://{}:{}{}", OTA_SERVER, OTA_PORT, OTA_PATH);
+
+    socket.work();
+    socket.open(address, OTA_PORT)?;
+    // HTTP/1.0, so the server neither keeps the connection open nor sends the body in chunks
+    socket.write_all(b"GET ")?;
+    socket.write_all(OTA_PATH.as_bytes())?;
+    socket.write_all(b" HTTP/1.0\r\nHost: ")?;
+    socket.write_all(OTA_SERVER.as_bytes())?;
+    socket.write_all(b"\r\n\r\n")?;
+    socket.flush()?;
+
+    let mut buffer = [0u8; 1024];
+    let mut parser = Parser::<256>::new();
+    let mut deadline = time::Instant::now() + TIMEOUT;
+    'read: loop {
+        let len = match socket.read_ready() {
+            Ok(true) => socket.read(&mut buffer)?,
+            Ok(false) if time::Instant::now() > deadline => return Err(Error::Timeout),
+            Ok(false) => continue,
+            // the server closed the connection
+            Err(IoError::SocketClosed) => break,
+            Err(err) => return Err(err.into()),
+        };
+        deadline = time::Instant::now() + TIMEOUT;
+
+        let mut data = &buffer[..len];
+        loop {
+            let (consumed, event) = parser.parse(data)?;
+            data = &data[consumed..];
+
+            match event {
+                Some(Event::Status(status)) if status.code != 200 => {
+                    return Err(Error::Status(status.code))
+                }
+                Some(Event::Header(header))
+                    if header.name.eq_ignore_ascii_case("content-length") =>
+                {
+                    // the parser made sure it's a number
+                    let len = core::str::from_utf8(header.value)
+                        .ok()
+                        .and_then(|len| len.parse().ok())
+                        .unwrap_or(usize::MAX);
+                    if len > capacity {
+                        return Err(Error::TooLarge(len));
+                    }
+                    println!("Downloading {} bytes to {:?}", len, slot);
+                }
+                Some(Event::Body(body)) => {
+                    let had_app_desc = image.app_desc().is_some();
+                    image.write(body)?;
+                    // the descriptor arrives with the first few hundred bytes
+                    if let (false, Some(desc)) = (had_app_desc, image.app_desc()) {
+                        if !is_new(desc)? {
+                            return Ok(false);
+                        }
+                    }
+                }
+                Some(Event::End) => break 'read,
+                Some(_) => {}
+                // we need more data
+                None => break,
+            }
+        }
+    }
+    // the whole body arrived, as long as `Content-Length` says
+    parser.finish()?;
+
+    let verified = image.finish()?;
+    let desc = verified.app_desc.ok_or(Error::NoAppDesc)?;
+    println!(
+        "Verified {} {}, {} bytes",
+        desc.project_name(),
+        desc.version(),
+        verified.len
+    );
+
+    // the bootloader boots the new firmware from now on, until `check_boot` rolls it back
+    // Switch to the partition we just wrote with `ota.activate_next_partition`, and set its state
+    // to `OtaImageState::New`, so `check_boot` knows that it's on trial.
+    Ok(true)
+}
+
+/// Checks that the firmware on the server is this application, and a different version
+fn is_new(desc: &AppDesc) -> Result<bool, Error> {
+    if desc.project_name() != ESP_APP_DESC.project_name() {
+        return Err(Error::WrongApplication);
+    }
+    println!("The server has version {}", desc.version());
+    Ok(desc.version() != ESP_APP_DESC.version())
+}
+
+/// Writes the new firmware to an app partition while it's downloaded and verifies it on the way
+struct Image<'a, 'd> {
+    partition: FlashRegion<'a, FlashStorage<'d>>,
+    verifier: Verifier,
+    sector: [u8; SECTOR_LEN],
+    filled: usize,
+    written: usize,
+}
+
+impl<'a, 'd> Image<'a, 'd> {
+    fn new(partition: FlashRegion<'a, FlashStorage<'d>>) -> Self {
+        Self {
+            partition,
+            verifier: Verifier::new(CHIP_ID_ESP32C3),
+            sector: [0; SECTOR_LEN],
+            filled: 0,
+            written: 0,
+        }
+    }
+
+    fn app_desc(&self) -> Option<&AppDesc> {
+        self.verifier.app_desc()
+    }
+
+    /// Verifies and writes the next piece of the image
+    fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        self.verifier.update(data)?;
+        while !data.is_empty() {
+            let len = data.len().min(SECTOR_LEN - self.filled);
+            self.sector[self.filled..self.filled + len].copy_from_slice(&data[..len]);
+            self.filled += len;
+            data = &data[len..];
+            if self.filled == SECTOR_LEN {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes what's buffered, `Storage::write` erases the sector first
+    fn flush(&mut self) -> Result<(), Error> {
+        self.partition
+            .write(self.written as u32, &self.sector[..self.filled])?;
+        self.written += self.filled;
+        self.filled = 0;
+        if self.written.is_multiple_of(64 * 1024) {
+            println!("{} KiB written", self.written / 1024);
+        }
+        Ok(())
+    }
+
+    /// Checks that the image is complete, and reads it back to check what ended up in flash
+    fn finish(mut self) -> Result<Verified, Error> {
+        self.flush()?;
+        let verified = self.verifier.finish()?;
+
+        let mut verifier = Verifier::new(CHIP_ID_ESP32C3);
+        let mut offset = 0;
+        while offset < self.written {
+            let len = (self.written - offset).min(SECTOR_LEN);
+            self.partition
+                .read(offset as u32, &mut self.sector[..len])?;
+            verifier.update(&self.sector[..len])?;
+            offset += len;
+        }
+        verifier.finish()?;
+        Ok(verified)
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The partition table has no OTA partitions, or reading or writing them failed
+    Partitions(partitions::Error),
+    /// The network stack reported an error, e.g. we don't have an ip address or DNS failed
+    Network(NetworkError),
+    /// Reading from or writing to the socket failed, this wraps the smoltcp socket errors
+    Io(IoError),
+    /// The host name didn't resolve to any address
+    NoAddress,
+    /// The server sent something that isn't a valid HTTP response
+    Response(http_response::Error),
+    /// The server answered with another status than `200 OK`, e.g. `404` without a firmware
+    Status(u16),
+    /// The firmware doesn't fit into the app partition
+    TooLarge(usize),
+    /// The server didn't send anything for too long
+    Timeout,
+    /// The download isn't a valid image for this chip, or it's damaged
+    Image(esp_image::Error),
+    /// The image has no app descriptor, so we don't know what it is
+    NoAppDesc,
+    /// The image is a different application
+    WrongApplication,
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<partitions::Error> for Error {
+    fn from(err: partitions::Error) -> Self {
+        Self::Partitions(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<http_response::Error> for Error {
+    fn from(err: http_response::Error) -> Self {
+        Self::Response(err)
+    }
+}
+
+impl From<esp_image::Error> for Error {
+    fn from(err: esp_image::Error) -> Self {
+        Self::Image(err)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Init(err) => defmt::write!(f, "Init({})", defmt::Debug2Format(err)),
+            Self::Wifi(err) => defmt::write!(f, "Wifi({})", defmt::Debug2Format(err)),
+            Self::Partitions(err) => defmt::write!(f, "Partitions({})", defmt::Debug2Format(err)),
+            Self::Network(err) => defmt::write!(f, "Network({})", defmt::Debug2Format(err)),
+            Self::Io(err) => defmt::write!(f, "Io({})", defmt::Debug2Format(err)),
+            Self::NoAddress => defmt::write!(f, "NoAddress"),
+            Self::Response(err) => defmt::write!(f, "Response({})", err),
+            Self::Status(code) => defmt::write!(f, "Status({})", code),
+            Self::TooLarge(len) => defmt::write!(f, "TooLarge({})", len),
+            Self::Timeout => defmt::write!(f, "Timeout"),
+            Self::Image(err) => defmt::write!(f, "Image({})", err),
+            Self::NoAppDesc => defmt::write!(f, "NoAppDesc"),
+            Self::WrongApplication => defmt::write!(f, "WrongApplication"),
+        }
+    }
+}
@@ -0,0 +1,555 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::{format, vec::Vec};
+
+use core::convert::Infallible;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    gpio::{Input, InputConfig},
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    system::software_reset,
+    time::{Duration, Instant},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{AccessPointConfig, ClientConfig, ModeConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use esp_storage::FlashStorage;
+use example_support::{
+    create_interface, erase_credentials, load_credentials, open_store, save_credentials, supervise,
+    timestamp, Connection, Response, ServerConfig, Settings, StoreError,
+};
+use http_request::{decode_form_value, form_fields, Method, Request};
+use wifi_credentials::{Credentials, MAX_PASSWORD_LEN, MAX_SSID_LEN};
+use wifi_supervisor::Supervisor;
+
+use smoltcp::{
+    iface::{Interface, SocketHandle, SocketSet, SocketStorage},
+    socket::{dhcpv4, tcp, udp},
+    wire::{DhcpOption, IpAddress, IpCidr, Ipv4Address},
+};
+
+// ANCHOR: portal
+/// The address of the device in its own network, clients get the addresses after it
+const AP_ADDRESS: [u8; 4] = [192, 168, 4, 1];
+/// The network is called `esp-setup-` followed by the end of the MAC address
+const AP_SSID_PREFIX: &str = "esp-setup-";
+/// Where clients are sent to, whatever they asked for
+const LOCATION: &str = "Location: http://192.168.4.1/\r\n";
+/// Number of clients that get an address
+const CLIENTS: usize = 4;
+// ANCHOR_END: portal
+
+/// Number of clients served at the same time, browsers open more than one connection
+const CONNECTIONS: usize = 3;
+/// Requests with a larger head and body are rejected
+const MAX_REQUEST_SIZE: usize = 1024;
+/// The web server works like the one of `http-server`
+const SERVER: ServerConfig = ServerConfig {
+    port: 80,
+    request_timeout: Duration::from_secs(5),
+    close_timeout: Duration::from_secs(1),
+};
+/// Time the client gets to receive the response before the device restarts
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Holding the button this long erases the credentials
+const FACTORY_RESET_HOLD: Duration = Duration::from_secs(3);
+
+const INDEX: &str = include_str!("../static/index.html");
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    // the button from `button`, holding it erases the credentials
+    let button = Input::new(peripherals.GPIO9, InputConfig::default());
+
+    // ANCHOR: load
+    let mut wifi = peripherals.WIFI;
+    let mut flash = peripherals.FLASH;
+    example_support::restart(|| {
+        // without a place to keep the credentials, there's no point in asking for them
+        let mut store = open_store(FlashStorage::new(flash.reborrow()))?;
+
+        // a record that can't be read is treated like no record, the user sets the device up again
+        let credentials = match load_credentials(&mut store) {
+            Ok(Some(credentials)) => {
+                println!("Stored network: {}", credentials.ssid());
+                Some(credentials)
+            }
+            Ok(None) => {
+                println!("No credentials stored");
+                None
+            }
+            Err(err) => {
+                println!("Stored credentials are unusable: {:?}", err);
+                None
+            }
+        };
+
+        match &credentials {
+            Some(credentials) => station(wifi.reborrow(), credentials, &button, &mut store),
+            None => portal(wifi.reborrow(), &mut store),
+        }
+    })
+    // ANCHOR_END: load
+}
+
+/// Connects to the stored network, holding the button erases the credentials
+fn station(
+    wifi: WIFI<'_>,
+    credentials: &Credentials,
+    button: &Input<'_>,
+    store: &mut Settings<'_>,
+) -> Result<Infallible, Error> {
+    // Initialize and configure Wifi
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut dhcp_socket = dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    let mut socket_set_entries: [SocketStorage; 1] = Default::default();
+    let mut sockets = SocketSet::new(&mut socket_set_entries[..]);
+    let dhcp = sockets.add(dhcp_socket);
+    let mut station = Station {
+        network: Network {
+            iface,
+            device,
+            sockets,
+        },
+        dhcp,
+    };
+
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+
+    // ANCHOR: station
+    // the credentials come from flash instead of `env!`
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(credentials.ssid().into())
+            .with_password(credentials.password().into()),
+    );
+    controller.set_config(&client_config)?;
+    // ANCHOR_END: station
+
+    controller.start()?;
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+
+    // ANCHOR: factory_reset
+    let mut pressed_since = None;
+    loop {
+        supervise(&mut controller, &mut station, &mut supervisor);
+
+        // the button pulls GPIO9 to ground while it is pressed
+        match (button.is_low(), pressed_since) {
+            (false, _) => pressed_since = None,
+            (true, None) => pressed_since = Some(Instant::now()),
+            (true, Some(since)) if since.elapsed() >= FACTORY_RESET_HOLD => {
+                erase_credentials(store)?;
+                println!("Credentials erased, restarting");
+                software_reset();
+            }
+            (true, Some(_)) => {}
+        }
+    }
+    // ANCHOR_END: factory_reset
+}
+
+/// Runs the access point with the setup form until the user submitted the credentials
+fn portal(wifi: WIFI<'_>, store: &mut Settings<'_>) -> Result<Infallible, Error> {
+    // Initialize and configure Wifi
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    // ANCHOR: access_point
+    let mut device = interfaces.ap;
+    let mut iface = create_interface(&mut device);
+    // nobody hands out an address to us, we are the DHCP server
+    iface.update_ip_addrs(|addrs| {
+        let address = IpAddress::Ipv4(Ipv4Address::from(AP_ADDRESS));
+        addrs.push(IpCidr::new(address, 24)).ok();
+    });
+
+    let mac = device.mac_address();
+    let ssid = format!(
+        "{}{:02x}{:02x}{:02x}",
+        AP_SSID_PREFIX, mac[3], mac[4], mac[5]
+    );
+    // an open network, the credentials are only sent to the device, which is nearby
+    let ap_config = ModeConfig::AccessPoint(AccessPointConfig::default().with_ssid(ssid.clone()));
+    controller.set_config(&ap_config)?;
+    // ANCHOR_END: access_point
+
+    // ANCHOR: sockets
+    // the DHCP and DNS server each need a UDP socket, the web server a TCP socket per connection
+    let udp_socket = |rx_metadata, rx_payload, tx_metadata, tx_payload| {
+        udp::Socket::new(
+            udp::PacketBuffer::new(rx_metadata, rx_payload),
+            udp::PacketBuffer::new(tx_metadata, tx_payload),
+        )
+    };
+    let mut dhcp_rx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dhcp_rx_payload = [0u8; 1024];
+    let mut dhcp_tx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dhcp_tx_payload = [0u8; 1024];
+    let mut dhcp_socket = udp_socket(
+        &mut dhcp_rx_metadata[..],
+        &mut dhcp_rx_payload[..],
+        &mut dhcp_tx_metadata[..],
+        &mut dhcp_tx_payload[..],
+    );
+    dhcp_socket.bind(dhcp_server::SERVER_PORT)?;
+
+    let mut dns_rx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dns_rx_payload = [0u8; 1024];
+    let mut dns_tx_metadata = [udp::PacketMetadata::EMPTY; 4];
+    let mut dns_tx_payload = [0u8; 1024];
+    let mut dns_socket = udp_socket(
+        &mut dns_rx_metadata[..],
+        &mut dns_rx_payload[..],
+        &mut dns_tx_metadata[..],
+        &mut dns_tx_payload[..],
+    );
+    dns_socket.bind(captive_dns::PORT)?;
+
+    let mut socket_set_entries: [SocketStorage; CONNECTIONS + 2] = Default::default();
+    let mut sockets = SocketSet::new(&mut socket_set_entries[..]);
+    let dhcp = sockets.add(dhcp_socket);
+    let dns = sockets.add(dns_socket);
+
+    let mut rx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut tx_buffers = [[0u8; 1536]; CONNECTIONS];
+    let mut connections: Vec<(SocketHandle, Connection<MAX_REQUEST_SIZE>)> = rx_buffers
+        .iter_mut()
+        .zip(tx_buffers.iter_mut())
+        .map(|(rx_buffer, tx_buffer)| {
+            let socket = tcp::Socket::new(
+                tcp::SocketBuffer::new(&mut rx_buffer[..]),
+                tcp::SocketBuffer::new(&mut tx_buffer[..]),
+            );
+            (sockets.add(socket), Connection::new(SERVER))
+        })
+        .collect();
+    // ANCHOR_END: sockets
+
+    let mut network = Network {
+        iface,
+        device,
+        sockets,
+    };
+    let mut dhcp_server: dhcp_server::Server<CLIENTS> =
+        dhcp_server::Server::new(dhcp_server::Config::new(AP_ADDRESS));
+
+    controller.start()?;
+    println!("Join the network {} and open http://192.168.4.1/", ssid);
+
+    // ANCHOR: serve
+    let mut restart_at = None;
+    loop {
+        network.poll();
+        serve_dhcp(&mut network, dhcp, &mut dhcp_server);
+        serve_dns(&mut network, dns);
+
+        // a failing connection doesn't affect the others, it is dropped and the socket reused
+        for (handle, connection) in &mut connections {
+            let socket = network.sockets.get_mut::<tcp::Socket>(*handle);
+            let mut saved = false;
+            let result = connection.poll(socket, |request, body| {
+                let (response, stored) = route(request, body, store);
+                saved = stored;
+                response
+            });
+            match result {
+                Ok(sent) => {
+                    if sent && saved {
+                        restart_at = Some(Instant::now() + RESTART_DELAY);
+                    }
+                }
+                Err(err) => {
+                    println!("Connection failed: {:?}", err);
+                    connection.abort(socket);
+                }
+            }
+        }
+
+        // keep serving until the client got the response, then start over in station mode
+        if restart_at.is_some_and(|restart_at| Instant::now() >= restart_at) {
+            println!("Restarting to connect to the new network");
+            software_reset();
+        }
+    }
+    // ANCHOR_END: serve
+}
+
+// ANCHOR: dhcp
+/// Answers the DHCP messages of clients joining the access point
+fn serve_dhcp(
+    network: &mut Network<'_, '_>,
+    handle: SocketHandle,
+    server: &mut dhcp_server::Server<CLIENTS>,
+) {
+    let socket = network.sockets.get_mut::<udp::Socket>(handle);
+    let mut message = [0u8; 576];
+    let mut reply = [0u8; 576];
+    loop {
+        let len = match socket.recv_slice(&mut message) {
+            Ok((len, _)) => len,
+            Err(udp::RecvError::Exhausted) => break,
+            // too large for a DHCP message, smoltcp drops it
+            Err(udp::RecvError::Truncated) => continue,
+        };
+        let now = Instant::now().duration_since_epoch().as_millis();
+        match server.handle(now, &message[..len], &mut reply) {
+            Ok(Some((len, destination))) => {
+                // clients without an address get broadcasts, smoltcp sends them to every station
+                let destination = IpAddress::Ipv4(Ipv4Address::from(destination));
+                if let Err(err) =
+                    socket.send_slice(&reply[..len], (destination, dhcp_server::CLIENT_PORT))
+                {
+                    println!("Dropping DHCP reply: {:?}", err);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => println!("Ignoring DHCP message: {:?}", err),
+        }
+    }
+}
+// ANCHOR_END: dhcp
+
+// ANCHOR: dns
+/// Answers every DNS query with our own address
+fn serve_dns(network: &mut Network<'_, '_>, handle: SocketHandle) {
+    let socket = network.sockets.get_mut::<udp::Socket>(handle);
+    let mut query = [0u8; 512];
+    let mut response = [0u8; 512];
+    loop {
+        let (len, metadata) = match socket.recv_slice(&mut query) {
+            Ok(received) => received,
+            Err(udp::RecvError::Exhausted) => break,
+            // larger than a plain DNS query, smoltcp drops it
+            Err(udp::RecvError::Truncated) => continue,
+        };
+        match captive_dns::respond(&query[..len], AP_ADDRESS, &mut response) {
+            Ok(len) => {
+                if let Err(err) = socket.send_slice(&response[..len], metadata.endpoint) {
+                    println!("Dropping DNS response: {:?}", err);
+                }
+            }
+            Err(err) => println!("Ignoring DNS query: {:?}", err),
+        }
+    }
+}
+// ANCHOR_END: dns
+
+// ANCHOR: route
+/// Serves the form and stores the submitted credentials
+///
+/// Returns `true` along with the response if new credentials were stored.
+fn route(request: &Request<'_>, body: &[u8], store: &mut Settings<'_>) -> (Response, bool) {
+    match (request.method, request.path) {
+        (Method::Get, "/") => (
+            Response::new("200 OK", "text/html; charset=utf-8", INDEX),
+            false,
+        ),
+        (Method::Post, "/connect") => {
+            let credentials = match parse_form(body) {
+                Ok(credentials) => credentials,
+                Err(err) => {
+                    let body = format!("Invalid network name or password: {:?}\n", err);
+                    return (Response::text("400 Bad Request", body), false);
+                }
+            };
+            match save_credentials(store, &credentials) {
+                Ok(()) => {
+                    println!("Saved credentials for {}", credentials.ssid());
+                    let body = "Saved, the device restarts and connects to the network\n";
+                    (Response::text("200 OK", body), true)
+                }
+                Err(err) => {
+                    println!("Saving the credentials failed: {:?}", err);
+                    let body = "Couldn't save the credentials\n";
+                    (Response::text("500 Internal Server Error", body), false)
+                }
+            }
+        }
+        // everything else, e.g. the connectivity checks of phones and laptops, is sent to the
+        // form, which makes them show the portal
+        _ => (
+            Response::text("302 Found", "").with_headers(LOCATION),
+            false,
+        ),
+    }
+}
+
+/// Reads the credentials from the submitted form
+fn parse_form(body: &[u8]) -> Result<Credentials, Error> {
+    let field = |name: &[u8]| {
+        form_fields(body)
+            .find(|(field, _)| *field == name)
+            .map_or(&[][..], |(_, value)| value)
+    };
+    let mut ssid = [0; MAX_SSID_LEN];
+    let mut password = [0; MAX_PASSWORD_LEN];
+    let ssid = decode_form_value(field(b"ssid"), &mut ssid)?;
+    let password = decode_form_value(field(b"password"), &mut password)?;
+    Ok(Credentials::new(ssid, password)?)
+}
+// ANCHOR_END: route
+
+/// The smoltcp interface and our sockets
+struct Network<'s, 'd> {
+    iface: Interface,
+    device: WifiDevice<'d>,
+    sockets: SocketSet<'s>,
+}
+
+impl Network<'_, '_> {
+    /// Sends and receives packets
+    fn poll(&mut self) {
+        self.iface
+            .poll(timestamp(), &mut self.device, &mut self.sockets);
+    }
+}
+
+/// Applies the configuration from the DHCP server of the network we joined
+fn apply_dhcp(network: &mut Network<'_, '_>, handle: SocketHandle) {
+    match network.sockets.get_mut::<dhcpv4::Socket>(handle).poll() {
+        None => {}
+        Some(dhcpv4::Event::Deconfigured) => {
+            network.iface.update_ip_addrs(|addrs| addrs.clear());
+            network.iface.routes_mut().remove_default_ipv4_route();
+        }
+        Some(dhcpv4::Event::Configured(config)) => {
+            network.iface.update_ip_addrs(|addrs| {
+                addrs.clear();
+                addrs.push(IpCidr::Ipv4(config.address)).ok();
+            });
+            match config.router {
+                Some(router) => {
+                    network
+                        .iface
+                        .routes_mut()
+                        .add_default_ipv4_route(router)
+                        .ok();
+                }
+                None => {
+                    network.iface.routes_mut().remove_default_ipv4_route();
+                }
+            }
+        }
+    }
+}
+
+/// The network of the station, configured by DHCP
+struct Station<'s, 'd> {
+    network: Network<'s, 'd>,
+    dhcp: SocketHandle,
+}
+
+impl example_support::Network for Station<'_, '_> {
+    fn poll(&mut self) {
+        self.network.poll();
+        apply_dhcp(&mut self.network, self.dhcp);
+    }
+
+    fn address(&self) -> Option<Ipv4Address> {
+        self.network.iface.ipv4_addr()
+    }
+
+    fn restart_dhcp(&mut self) {
+        let network = &mut self.network;
+        network.sockets.get_mut::<dhcpv4::Socket>(self.dhcp).reset();
+        network.iface.update_ip_addrs(|addrs| addrs.clear());
+        network.iface.routes_mut().remove_default_ipv4_route();
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The store in the NVS partition couldn't be opened, read or written
+    Store(StoreError),
+    /// The submitted credentials are invalid
+    Credentials(wifi_credentials::Error),
+    /// The submitted form couldn't be decoded
+    Form(http_request::Error),
+    /// A UDP socket couldn't be bound
+    Bind(udp::BindError),
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
+impl From<wifi_credentials::Error> for Error {
+    fn from(err: wifi_credentials::Error) -> Self {
+        Self::Credentials(err)
+    }
+}
+
+impl From<http_request::Error> for Error {
+    fn from(err: http_request::Error) -> Self {
+        Self::Form(err)
+    }
+}
+
+impl From<udp::BindError> for Error {
+    fn from(err: udp::BindError) -> Self {
+        Self::Bind(err)
+    }
+}
@@ -0,0 +1,203 @@
+#![no_std]
+#![no_main]
+
+use core::{cell::RefCell, convert::Infallible};
+use critical_section::Mutex;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    time::{Duration, Instant},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, PromiscuousPkt, WifiError},
+    InitializationError,
+};
+use esp_wifi_sys::include::{esp_wifi_set_channel, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE};
+use heapless::Deque;
+use wifi_capture::max_encoded_len;
+
+/// The channel to listen on, e.g. `SNIFF_CHANNEL=11`
+const SNIFF_CHANNEL: &str = match option_env!("SNIFF_CHANNEL") {
+    Some(channel) => channel,
+    None => "6",
+};
+/// Only the first bytes of a frame are sent, they hold the headers
+const SNAPLEN: usize = 512;
+/// Frames waiting for the serial port
+const QUEUE_LEN: usize = 16;
+/// How often we report the frames that didn't fit into the queue
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The driver hands us the frames with their frame check sequence, Wireshark doesn't need it
+const FCS_LEN: usize = 4;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// A received frame, cut to `SNAPLEN` bytes
+struct Frame {
+    channel: u8,
+    rssi: i8,
+    noise_floor: i8,
+    timestamp: u32,
+    len: u16,
+    captured: usize,
+    data: [u8; SNAPLEN],
+}
+
+/// The frames the driver received, and how many of them didn't fit
+struct Capture {
+    frames: Deque<Frame, QUEUE_LEN>,
+    dropped: u32,
+}
+
+/// The receive callback runs in the task of the Wi-Fi driver, not in our loop, so the queue is
+/// shared through a critical section
+static CAPTURE: Mutex<RefCell<Capture>> = Mutex::new(RefCell::new(Capture {
+    frames: Deque::new(),
+    dropped: 0,
+}));
+
+/// Called by the driver for every frame, it only copies the frame into the queue
+///
+/// Writing to the serial port takes much longer than receiving a frame, the driver would miss
+/// frames if we did it here.
+fn receive(packet: PromiscuousPkt<'_>) {
+    let len = packet.data.len().saturating_sub(FCS_LEN);
+    let captured = len.min(SNAPLEN);
+    let mut frame = Frame {
+        channel: packet.rx_cntl.channel as u8,
+        rssi: packet.rx_cntl.rssi.clamp(i8::MIN.into(), i8::MAX.into()) as i8,
+        noise_floor: packet
+            .rx_cntl
+            .noise_floor
+            .clamp(i8::MIN.into(), i8::MAX.into()) as i8,
+        timestamp: packet.rx_cntl.timestamp,
+        len: len as u16,
+        captured,
+        data: [0; SNAPLEN],
+    };
+    frame.data[..captured].copy_from_slice(&packet.data[..captured]);
+
+    critical_section::with(|cs| {
+        let mut capture = CAPTURE.borrow_ref_mut(cs);
+        if capture.frames.push_back(frame).is_err() {
+            capture.dropped += 1;
+        }
+    });
+}
+
+/// Listens on `SNIFF_CHANNEL` and writes every frame to the serial port
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let channel = parse_channel(SNIFF_CHANNEL)?;
+
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+
+    // promiscuous mode needs the radio started, but we don't connect to an access point
+    controller.set_config(&ModeConfig::Client(ClientConfig::default()))?;
+    controller.start()?;
+
+    let mut sniffer = interfaces.sniffer;
+    sniffer.set_receive_cb(receive);
+    sniffer.set_promiscuous_mode(true)?;
+    // SAFETY: the radio is started, the function only takes the channel numbers
+    let err = unsafe { esp_wifi_set_channel(channel, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE) };
+    if err != 0 {
+        return Err(Error::Channel(err));
+    }
+
+    // the frames that are still in the queue come from the last run, maybe from another channel
+    critical_section::with(|cs| {
+        let mut capture = CAPTURE.borrow_ref_mut(cs);
+        capture.frames.clear();
+        capture.dropped = 0;
+    });
+    println!(
+        "Sniffing on channel {}, with frames up to {} bytes",
+        channel, SNAPLEN
+    );
+
+    let mut buffer = [0u8; max_encoded_len(SNAPLEN)];
+    let mut next_report = Instant::now() + REPORT_INTERVAL;
+    loop {
+        let frame = critical_section::with(|cs| CAPTURE.borrow_ref_mut(cs).frames.pop_front());
+        if let Some(_frame) = frame {
+            // Turn the frame into a `wifi_capture::Record`, encode it into `buffer` and write
+            // it to the serial port with `esp_println::Printer::write_bytes`
+        }
+
+        // log text between the records is fine, `sniffer-pcap` prints it
+        if Instant::now() >= next_report {
+            next_report += REPORT_INTERVAL;
+            let dropped = critical_section::with(|cs| {
+                core::mem::take(&mut CAPTURE.borrow_ref_mut(cs).dropped)
+            });
+            if dropped > 0 {
+                println!("Dropped {} frames, the serial port is too slow", dropped);
+            }
+        }
+    }
+}
+
+/// Parses `SNIFF_CHANNEL`, the 2.4 GHz band has the channels 1 to 14
+fn parse_channel(channel: &str) -> Result<u8, Error> {
+    match channel.parse() {
+        Ok(channel @ 1..=14) => Ok(channel),
+        _ => Err(Error::InvalidChannel),
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// `SNIFF_CHANNEL` isn't a channel between 1 and 14
+    InvalidChannel,
+    /// The driver didn't switch to the channel, with the error code of ESP-IDF
+    Channel(i32),
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
@@ -0,0 +1,421 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use core::convert::Infallible;
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock,
+    interrupt::software::SoftwareInterruptControl,
+    main,
+    peripherals::WIFI,
+    ram,
+    rng::Rng,
+    time::{Duration, Instant},
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{ClientConfig, ModeConfig, WifiDevice, WifiError},
+    InitializationError,
+};
+use example_support::{create_interface, supervise, timestamp};
+use sntp::{Clock, Response, Sample, Timestamp};
+use wifi_supervisor::{Backoff, Supervisor};
+
+use smoltcp::{
+    iface::{Interface, SocketHandle, SocketSet, SocketStorage},
+    socket::{dhcpv4, dns, udp},
+    wire::{DhcpOption, DnsQueryType, IpAddress, IpCidr, Ipv4Address},
+};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+
+/// Host name or IPv4 address of the NTP server, set `NTP_SERVER` to use a specific one
+///
+/// Without it, the servers announced by the DHCP server are used, or `FALLBACK_SERVER` if there
+/// are none.
+const NTP_SERVER: Option<&str> = option_env!("NTP_SERVER");
+const FALLBACK_SERVER: &str = "pool.ntp.org";
+
+/// Options we ask the DHCP server for: subnet mask, router, DNS servers and NTP servers
+const DHCP_PARAMETERS: &[u8] = &[1, 3, 6, sntp::DHCP_OPTION_NTP_SERVERS];
+
+/// How often the clock is synchronized, servers don't like to be asked more often than every
+/// few minutes
+const RESYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Failed synchronizations are retried after 15 seconds, doubling up to `RESYNC_INTERVAL`
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+/// How long the server has to answer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the time is printed
+const PRINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Our end of the conversation with the NTP server
+const LOCAL_PORT: u16 = 50_123;
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Connects to the Wi-Fi network and keeps the clock synchronized
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    // Initialize and configure Wifi
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+    let mut device = interfaces.sta;
+    let iface = create_interface(&mut device);
+
+    let mut dhcp_buffer = [0u8; 1024];
+    let mut dhcp_socket = dhcpv4::Socket::new();
+    // we can set a hostname here (or add other DHCP options)
+    dhcp_socket.set_outgoing_options(&[DhcpOption {
+        kind: 12,
+        data: b"esp-radio",
+    }]);
+    // servers only send the options we ask for, and the socket only hands out the whole
+    // response if it has a buffer to keep it in
+    dhcp_socket.set_parameter_request_list(DHCP_PARAMETERS);
+    dhcp_socket.set_receive_packet_buffer(&mut dhcp_buffer);
+
+    let mut dns_queries: [Option<dns::DnsQuery>; 1] = Default::default();
+    let dns_socket = dns::Socket::new(&[], &mut dns_queries[..]);
+
+    let mut rx_metadata = [udp::PacketMetadata::EMPTY; 2];
+    let mut rx_payload = [0u8; 256];
+    let mut tx_metadata = [udp::PacketMetadata::EMPTY; 2];
+    let mut tx_payload = [0u8; 256];
+    let udp_socket = udp::Socket::new(
+        udp::PacketBuffer::new(&mut rx_metadata[..], &mut rx_payload[..]),
+        udp::PacketBuffer::new(&mut tx_metadata[..], &mut tx_payload[..]),
+    );
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let mut sockets = SocketSet::new(&mut socket_set_entries[..]);
+    let mut network = Network {
+        dhcp: sockets.add(dhcp_socket),
+        dns: sockets.add(dns_socket),
+        udp: sockets.add(udp_socket),
+        iface,
+        device,
+        sockets,
+        ntp_servers: Vec::new(),
+    };
+
+    controller.set_power_saving(esp_radio::wifi::PowerSaveMode::None)?;
+
+    let client_config = ModeConfig::Client(
+        ClientConfig::default()
+            .with_ssid(SSID.into())
+            .with_password(PASSWORD.into()),
+    );
+    controller.set_config(&client_config)?;
+
+    controller.start()?;
+    println!("Is wifi started: {:?}", controller.is_started());
+
+    let mut supervisor = Supervisor::new(wifi_supervisor::Config::default());
+
+    let rng = Rng::new();
+    let mut clock = Clock::new();
+    let mut backoff = Backoff::new(RETRY_DELAY.as_millis(), RESYNC_INTERVAL.as_millis());
+    let mut next_sync = Instant::now();
+    let mut next_print = Instant::now();
+    loop {
+        supervise(&mut controller, &mut network, &mut supervisor);
+        // there is nobody to ask while we are offline, the clock keeps running anyway
+        if supervisor.is_up() && Instant::now() >= next_sync {
+            match synchronize(&mut network, &mut clock, &rng) {
+                Ok(()) => {
+                    backoff.reset();
+                    next_sync = Instant::now() + RESYNC_INTERVAL;
+                }
+                Err(err) => {
+                    let delay = backoff.next_delay();
+                    println!(
+                        "Synchronization failed: {:?}, retrying in {} s",
+                        err,
+                        delay / 1000
+                    );
+                    next_sync = Instant::now() + Duration::from_millis(delay);
+                }
+            }
+        }
+
+        if Instant::now() >= next_print {
+            // Print the UTC date and time, the clock has none until it was synchronized
+            // println!("UTC: {}", ...);
+            next_print += PRINT_INTERVAL;
+        }
+    }
+}
+
+/// Asks the NTP server for the time and sets the clock
+fn synchronize(network: &mut Network<'_, '_>, clock: &mut Clock, rng: &Rng) -> Result<(), Error> {
+    let server = network.ntp_server()?;
+    println!("Asking {} for the time", server);
+
+    // a random transmit timestamp makes it hard to forge a response
+    let request = Timestamp::from_bits(((rng.random() as u64) << 32) | rng.random() as u64);
+    let sample = network.request(server, request)?;
+
+    match clock.update(micros_since_boot(), &sample) {
+        None => println!("Clock set, round trip took {} us", sample.delay),
+        Some(correction) => println!(
+            "Clock corrected by {} us, round trip took {} us",
+            correction, sample.delay
+        ),
+    }
+    Ok(())
+}
+
+/// The monotonic clock the wall clock is based on
+fn micros_since_boot() -> u64 {
+    Instant::now().duration_since_epoch().as_micros()
+}
+
+/// The smoltcp interface and our sockets
+///
+/// We don't use `blocking-network-stack` here, it handles DHCP internally and doesn't hand out
+/// the options of the response.
+struct Network<'s, 'd> {
+    iface: Interface,
+    device: WifiDevice<'d>,
+    sockets: SocketSet<'s>,
+    dhcp: SocketHandle,
+    dns: SocketHandle,
+    udp: SocketHandle,
+    /// The NTP servers announced by the DHCP server
+    ntp_servers: Vec<Ipv4Address>,
+}
+
+impl Network<'_, '_> {
+    /// Sends and receives packets and applies the configuration from DHCP
+    fn poll(&mut self) {
+        self.iface
+            .poll(timestamp(), &mut self.device, &mut self.sockets);
+
+        let dhcp_socket = self.sockets.get_mut::<dhcpv4::Socket>(self.dhcp);
+        let dns_servers: Vec<IpAddress> = match dhcp_socket.poll() {
+            None => return,
+            Some(dhcpv4::Event::Deconfigured) => {
+                self.iface.update_ip_addrs(|addrs| addrs.clear());
+                self.iface.routes_mut().remove_default_ipv4_route();
+                self.ntp_servers.clear();
+                Vec::new()
+            }
+            Some(dhcpv4::Event::Configured(config)) => {
+                self.iface.update_ip_addrs(|addrs| {
+                    addrs.clear();
+                    addrs.push(IpCidr::Ipv4(config.address)).ok();
+                });
+                match config.router {
+                    Some(router) => {
+                        self.iface.routes_mut().add_default_ipv4_route(router).ok();
+                    }
+                    None => {
+                        self.iface.routes_mut().remove_default_ipv4_route();
+                    }
+                }
+
+                // option 42 isn't part of the config, we read it from the DHCP response
+                self.ntp_servers = config
+                    .packet
+                    .iter()
+                    .flat_map(|packet| packet.options())
+                    .filter(|option| option.kind == sntp::DHCP_OPTION_NTP_SERVERS)
+                    .flat_map(|option| sntp::dhcp_ntp_servers(option.data))
+                    .map(Ipv4Address::from)
+                    .collect();
+                if !self.ntp_servers.is_empty() {
+                    println!("DHCP announced NTP servers {:?}", self.ntp_servers);
+                }
+
+                config
+                    .dns_servers
+                    .iter()
+                    .map(|&server| server.into())
+                    .collect()
+            }
+        };
+        self.sockets
+            .get_mut::<dns::Socket>(self.dns)
+            .update_servers(&dns_servers);
+    }
+
+    /// Picks the NTP server: the configured one, one announced by DHCP or the fallback
+    fn ntp_server(&mut self) -> Result<IpAddress, Error> {
+        match (NTP_SERVER, self.ntp_servers.first()) {
+            (Some(server), _) => self.resolve(server),
+            (None, Some(&server)) => Ok(server.into()),
+            (None, None) => self.resolve(FALLBACK_SERVER),
+        }
+    }
+
+    /// Looks up the IPv4 address of `host`, which can also be an address already
+    fn resolve(&mut self, host: &str) -> Result<IpAddress, Error> {
+        if let Ok(address) = host.parse::<Ipv4Address>() {
+            return Ok(address.into());
+        }
+
+        let query = self.sockets.get_mut::<dns::Socket>(self.dns).start_query(
+            self.iface.context(),
+            host,
+            DnsQueryType::A,
+        )?;
+        loop {
+            self.poll();
+            // the socket gives up by itself if the DNS server doesn't answer
+            match self
+                .sockets
+                .get_mut::<dns::Socket>(self.dns)
+                .get_query_result(query)
+            {
+                Ok(addresses) => return addresses.first().copied().ok_or(Error::NoAddress),
+                Err(dns::GetQueryResultError::Pending) => {}
+                Err(dns::GetQueryResultError::Failed) => return Err(Error::Dns),
+            }
+        }
+    }
+
+    /// Sends an SNTP request to `server` and waits for the response
+    fn request(&mut self, server: IpAddress, request: Timestamp) -> Result<Sample, Error> {
+        let socket = self.sockets.get_mut::<udp::Socket>(self.udp);
+        if !socket.is_open() {
+            socket.bind(LOCAL_PORT)?;
+        }
+        // drop late responses to earlier requests
+        while socket.recv().is_ok() {}
+
+        let mut packet = [0u8; sntp::PACKET_LEN];
+        let len = sntp::encode_request(request, &mut packet)?;
+        socket.send_slice(&packet[..len], (server, sntp::PORT))?;
+        let sent = micros_since_boot();
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+
+        loop {
+            self.poll();
+            let received = micros_since_boot();
+
+            let socket = self.sockets.get_mut::<udp::Socket>(self.udp);
+            match socket.recv_slice(&mut packet) {
+                Ok((len, metadata)) if metadata.endpoint.addr == server => {
+                    // Decode the response with `Response::decode`, which checks that it answers
+                    // `request`, and calculate the offset and delay with `Sample::new`
+                    // let response = ...;
+                    // return Ok(...);
+                }
+                // someone else sent us something
+                Ok(_) => {}
+                Err(udp::RecvError::Exhausted) if Instant::now() > deadline => {
+                    return Err(Error::Timeout)
+                }
+                Err(udp::RecvError::Exhausted) => {}
+                // larger than an SNTP packet, so it's not the response
+                Err(udp::RecvError::Truncated) => {}
+            }
+        }
+    }
+}
+
+impl example_support::Network for Network<'_, '_> {
+    fn poll(&mut self) {
+        // the `poll` above, which also reads the NTP servers from the DHCP response
+        Network::poll(self);
+    }
+
+    fn address(&self) -> Option<Ipv4Address> {
+        self.iface.ipv4_addr()
+    }
+
+    fn restart_dhcp(&mut self) {
+        self.sockets.get_mut::<dhcpv4::Socket>(self.dhcp).reset();
+        self.iface.update_ip_addrs(|addrs| addrs.clear());
+        self.iface.routes_mut().remove_default_ipv4_route();
+        self.ntp_servers.clear();
+    }
+}
+
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// The DNS query couldn't be started, e.g. because we have no DNS server
+    StartQuery(dns::StartQueryError),
+    /// The DNS server couldn't resolve the name of the NTP server
+    Dns,
+    /// The name of the NTP server has no IPv4 address
+    NoAddress,
+    /// The UDP socket couldn't be bound
+    Bind(udp::BindError),
+    /// The request couldn't be sent, e.g. because we have no route to the server
+    Send(udp::SendError),
+    /// The response was invalid or the server refused to answer
+    Sntp(sntp::Error),
+    /// The server didn't answer in time
+    Timeout,
+}
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
+
+impl From<dns::StartQueryError> for Error {
+    fn from(err: dns::StartQueryError) -> Self {
+        Self::StartQuery(err)
+    }
+}
+
+impl From<udp::BindError> for Error {
+    fn from(err: udp::BindError) -> Self {
+        Self::Bind(err)
+    }
+}
+
+impl From<udp::SendError> for Error {
+    fn from(err: udp::SendError) -> Self {
+        Self::Send(err)
+    }
+}
+
+impl From<sntp::Error> for Error {
+    fn from(err: sntp::Error) -> Self {
+        Self::Sntp(err)
+    }
+}
@@ -0,0 +1,217 @@
+#![no_std]
+#![no_main]
+
+use core::{convert::Infallible, time::Duration};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    clock::CpuClock, delay::Delay, interrupt::software::SoftwareInterruptControl, main,
+    peripherals::WIFI, ram, time::Instant,
+};
+use esp_println::println;
+use esp_radio::{
+    wifi::{
+        AccessPointInfo, AuthMethod, ClientConfig, ModeConfig, ScanConfig, ScanTypeConfig,
+        WifiError,
+    },
+    InitializationError,
+};
+use wifi_scan::{AccessPoint, Change, Mac, Security, Tracker};
+
+// ANCHOR: config
+/// Only look for this network, e.g. `SCAN_SSID=Wokwi-GUEST`
+const SCAN_SSID: Option<&str> = option_env!("SCAN_SSID");
+/// Only scan this channel, e.g. `SCAN_CHANNEL=6`
+const SCAN_CHANNEL: Option<&str> = option_env!("SCAN_CHANNEL");
+/// Number of access points we keep track of
+const MAX_APS: usize = 32;
+/// Pause between two scans in milliseconds
+const INTERVAL_MS: u32 = 5_000;
+// ANCHOR_END: config
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[main]
+fn main() -> ! {
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
+    esp_alloc::heap_allocator!(size: 36 * 1024);
+
+    // Initialize the timer and the scheduler
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(
+        timg0.timer0,
+        #[cfg(target_arch = "riscv32")]
+        sw_int.software_interrupt0,
+    );
+
+    let mut wifi = peripherals.WIFI;
+    example_support::restart(|| run(wifi.reborrow()))
+}
+
+/// Scans over and over again, alternating between active and passive scans
+fn run(wifi: WIFI<'_>) -> Result<Infallible, Error> {
+    let channel = match SCAN_CHANNEL {
+        Some(channel) => Some(parse_channel(channel)?),
+        None => None,
+    };
+
+    // ANCHOR: radio
+    let esp_radio_ctrl = esp_radio::init()?;
+    let (mut controller, _interfaces) =
+        esp_radio::wifi::new(&esp_radio_ctrl, wifi, Default::default())?;
+
+    // scanning needs the radio in station mode, but we don't connect to an access point
+    controller.set_config(&ModeConfig::Client(ClientConfig::default()))?;
+    controller.start()?;
+    // ANCHOR_END: radio
+
+    match channel {
+        Some(channel) => println!(
+            "Scanning for {} on channel {}",
+            SCAN_SSID.unwrap_or("all networks"),
+            channel
+        ),
+        None => println!("Scanning for {}", SCAN_SSID.unwrap_or("all networks")),
+    }
+
+    let mut tracker: Tracker<MAX_APS> = Tracker::new(wifi_scan::Config::default());
+    let mut passive = false;
+    loop {
+        // ANCHOR: scan
+        let config = scan_config(passive, channel);
+        let started = Instant::now();
+        let results = controller.scan_with_config(config)?;
+        println!(
+            "{} scan #{} found {} access points in {} ms",
+            if passive { "Passive" } else { "Active" },
+            tracker.scans() + 1,
+            results.len(),
+            started.elapsed().as_millis()
+        );
+
+        tracker.update(results.iter().map(access_point));
+        // ANCHOR_END: scan
+
+        // ANCHOR: report
+        for change in tracker.changes() {
+            match change {
+                Change::Appeared(ap) => println!("+ {} ({})", name(&ap), Mac(ap.bssid)),
+                Change::Disappeared(ap) => println!("- {} ({})", name(&ap), Mac(ap.bssid)),
+            }
+        }
+        if tracker.dropped() > 0 {
+            println!(
+                "{} access points didn't fit into the table",
+                tracker.dropped()
+            );
+        }
+        println!("{}\n", tracker.table());
+        // ANCHOR_END: report
+
+        passive = !passive;
+        Delay::new().delay_millis(INTERVAL_MS);
+    }
+}
+
+// ANCHOR: scan_config
+/// Configures an active or a passive scan, filtered by `SCAN_SSID` and `channel`
+///
+/// An active scan sends a probe request on every channel and waits for the answers, a passive
+/// scan only listens for the beacons the access points send about ten times a second. It takes
+/// longer, but also finds access points that don't answer probe requests.
+fn scan_config(passive: bool, channel: Option<u8>) -> ScanConfig<'static> {
+    let scan_type = if passive {
+        ScanTypeConfig::Passive(Duration::from_millis(300))
+    } else {
+        ScanTypeConfig::Active {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(120),
+        }
+    };
+
+    let mut config = ScanConfig::default()
+        .with_scan_type(scan_type)
+        .with_show_hidden(true);
+    if let Some(ssid) = SCAN_SSID {
+        config = config.with_ssid(ssid);
+    }
+    if let Some(channel) = channel {
+        config = config.with_channel(channel);
+    }
+    config
+}
+// ANCHOR_END: scan_config
+
+// ANCHOR: decode
+/// Converts what the driver found into an [`AccessPoint`] for the tracker
+fn access_point(info: &AccessPointInfo) -> AccessPoint {
+    let security = match info.auth_method {
+        Some(AuthMethod::None) => Security::Open,
+        Some(AuthMethod::Wep) => Security::Wep,
+        Some(AuthMethod::Wpa) => Security::Wpa,
+        Some(AuthMethod::Wpa2Personal) => Security::Wpa2,
+        Some(AuthMethod::WpaWpa2Personal) => Security::WpaWpa2,
+        Some(AuthMethod::Wpa2Enterprise) => Security::Wpa2Enterprise,
+        Some(AuthMethod::Wpa3Personal) => Security::Wpa3,
+        Some(AuthMethod::Wpa2Wpa3Personal) => Security::Wpa2Wpa3,
+        Some(AuthMethod::WapiPersonal) => Security::Wapi,
+        // methods added by later versions of the driver
+        Some(_) | None => Security::Unknown,
+    };
+    AccessPoint::new(
+        &info.ssid,
+        info.bssid,
+        info.channel,
+        info.signal_strength,
+        security,
+    )
+}
+// ANCHOR_END: decode
+
+/// The SSID, or a placeholder for hidden networks
+fn name(ap: &AccessPoint) -> &str {
+    if ap.is_hidden() {
+        "<hidden>"
+    } else {
+        ap.ssid()
+    }
+}
+
+/// Parses `SCAN_CHANNEL`, the 2.4 GHz band has the channels 1 to 14
+fn parse_channel(channel: &str) -> Result<u8, Error> {
+    match channel.parse() {
+        Ok(channel @ 1..=14) => Ok(channel),
+        _ => Err(Error::InvalidChannel),
+    }
+}
+
+// ANCHOR: error
+/// Everything that can go wrong in this example
+#[derive(Debug)]
+// the wrapped errors are only read when printing them
+#[allow(dead_code)]
+enum Error {
+    /// The radio couldn't be initialized
+    Init(InitializationError),
+    /// The Wi-Fi driver reported an error
+    Wifi(WifiError),
+    /// `SCAN_CHANNEL` isn't a channel between 1 and 14
+    InvalidChannel,
+}
+// ANCHOR_END: error
+
+impl From<InitializationError> for Error {
+    fn from(err: InitializationError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<WifiError> for Error {
+    fn from(err: WifiError) -> Self {
+        Self::Wifi(err)
+    }
+}
@@ -0,0 +1,76 @@
+//! Exponential backoff for retries.
+//!
+//! Reconnecting to Wi-Fi and retrying an upload both wait longer after every failure, so a
+//! network that is down isn't hammered. [`Backoff`] only computes the delays, waiting is up to
+//! the application.
+//!
+//! ```
+//! use backoff::Backoff;
+//!
+//! let mut backoff = Backoff::new(1_000, 5_000);
+//! assert_eq!(backoff.next_delay(), 1_000);
+//! assert_eq!(backoff.next_delay(), 2_000);
+//! assert_eq!(backoff.next_delay(), 4_000);
+//! assert_eq!(backoff.next_delay(), 5_000);
+//!
+//! // it worked, the next failure waits the initial delay again
+//! backoff.reset();
+//! assert_eq!(backoff.next_delay(), 1_000);
+//! ```
+
+#![no_std]
+
+/// Exponential backoff, doubling the delay after every failure
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Backoff {
+    initial: u64,
+    max: u64,
+    next: u64,
+}
+
+impl Backoff {
+    pub const fn new(initial: u64, max: u64) -> Self {
+        Self {
+            initial,
+            max,
+            next: initial,
+        }
+    }
+
+    /// Returns the delay to wait before the next attempt and doubles it for the attempt after
+    pub fn next_delay(&mut self) -> u64 {
+        let delay = self.next;
+        self.next = delay.saturating_mul(2).min(self.max);
+        delay
+    }
+
+    /// Starts over with the initial delay, call this after a success
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_max() {
+        let mut backoff = Backoff::new(100, 1_000);
+        let delays: [u64; 6] = core::array::from_fn(|_| backoff.next_delay());
+        assert_eq!(delays, [100, 200, 400, 800, 1_000, 1_000]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), 100);
+    }
+
+    #[test]
+    fn does_not_overflow() {
+        let mut backoff = Backoff::new(u64::MAX / 2 + 1, u64::MAX);
+        backoff.next_delay();
+        assert_eq!(backoff.next_delay(), u64::MAX);
+    }
+}
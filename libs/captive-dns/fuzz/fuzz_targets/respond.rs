@@ -0,0 +1,18 @@
+//! Answers arbitrary queries, which must never panic, and checks that the response repeats the
+//! question.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|query: &[u8]| {
+    let mut response = [0; 512];
+    if let Ok(len) = captive_dns::respond(query, [192, 168, 4, 1], &mut response) {
+        let question = captive_dns::parse(query).unwrap();
+        let question_len = question.name.len() + 4;
+        assert!(len >= 12 + question_len);
+        assert_eq!(response[..2], query[..2]);
+        assert_eq!(response[12..12 + question_len], query[12..12 + question_len]);
+        assert_eq!(captive_dns::parse(&response[..len]), Err(captive_dns::Error::NotAQuery));
+    }
+});
@@ -0,0 +1,285 @@
+//! DNS responder for captive portals.
+//!
+//! A device that runs a captive portal answers every DNS query of its clients with its own
+//! address. Phones and laptops check whether they are online by fetching a well-known page after
+//! joining a network, end up at the device's web server instead, and open the portal.
+//!
+//! [`respond`] answers a single query received on UDP port [`PORT`]:
+//!
+//! ```
+//! // a query for the A record of `example.com`
+//! let query = [
+//!     0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+//!     7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, // name
+//!     0x00, 0x01, 0x00, 0x01, // type A, class IN
+//! ];
+//!
+//! let mut response = [0; 512];
+//! let len = captive_dns::respond(&query, [192, 168, 4, 1], &mut response).unwrap();
+//!
+//! // the answer is appended to the question
+//! assert_eq!(&response[len - 4..len], &[192, 168, 4, 1]);
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// The UDP port of DNS servers
+pub const PORT: u16 = 53;
+
+/// How long clients may cache the answers, in seconds
+///
+/// The address is only valid while the client is connected to the portal, so keep it short.
+pub const TTL: u32 = 10;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const MAX_NAME_LEN: usize = 255;
+
+/// Errors returned by [`respond`], don't send a response for any of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The message is not a standard query, e.g. a response or a notification
+    NotAQuery,
+    /// Only queries with exactly one question are supported, which is what clients send
+    UnsupportedQuestionCount(u16),
+    /// The query is truncated or the name is invalid
+    Malformed,
+    /// The buffer is too small for the response
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// A parsed question
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Question<'a> {
+    /// The name in wire format, a sequence of length-prefixed labels ending with an empty one
+    pub name: &'a [u8],
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl Question<'_> {
+    /// The labels of the name, e.g. `example` and `com`
+    pub fn labels(&self) -> impl Iterator<Item = &[u8]> {
+        let mut name = self.name;
+        core::iter::from_fn(move || {
+            let (&len, rest) = name.split_first()?;
+            if len == 0 {
+                return None;
+            }
+            let (label, rest) = rest.split_at_checked(len as usize)?;
+            name = rest;
+            Some(label)
+        })
+    }
+}
+
+/// Parses the question of a standard query
+pub fn parse(query: &[u8]) -> Result<Question<'_>, Error> {
+    let header = query.get(..HEADER_LEN).ok_or(Error::Malformed)?;
+    // QR must be 0 (query) and the opcode 0 (standard query)
+    if header[2] & 0xf8 != 0 {
+        return Err(Error::NotAQuery);
+    }
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    if questions != 1 {
+        return Err(Error::UnsupportedQuestionCount(questions));
+    }
+
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *query.get(pos).ok_or(Error::Malformed)? as usize;
+        // compression pointers (and the reserved label types) have the upper bits set, a
+        // client has nothing to point to in the only question
+        if len & 0xc0 != 0 {
+            return Err(Error::Malformed);
+        }
+        pos += 1 + len;
+        if pos - HEADER_LEN > MAX_NAME_LEN {
+            return Err(Error::Malformed);
+        }
+        if len == 0 {
+            break;
+        }
+    }
+
+    let name = &query[HEADER_LEN..pos];
+    let fixed = query.get(pos..pos + 4).ok_or(Error::Malformed)?;
+    Ok(Question {
+        name,
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+    })
+}
+
+/// Writes the response to `query` into `response` and returns its length
+///
+/// Queries for an A record of any name are answered with `address`. Queries for other record
+/// types, e.g. AAAA, get a response without answers, so that clients fall back to IPv4.
+/// Additional records of the query, like the EDNS options, are dropped.
+pub fn respond(query: &[u8], address: [u8; 4], response: &mut [u8]) -> Result<usize, Error> {
+    let question = parse(query)?;
+    let question_len = question.name.len() + 4;
+    let answer = question.qclass == CLASS_IN && matches!(question.qtype, TYPE_A | TYPE_ANY);
+
+    let len = HEADER_LEN + question_len + if answer { 16 } else { 0 };
+    let response = response.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+
+    // the header of the query with QR (response) and AA (authoritative answer) set, keeping
+    // RD (recursion desired) and a `NOERROR` response code
+    response[..2].copy_from_slice(&query[..2]);
+    response[2] = 0x84 | (query[2] & 0x01);
+    response[3] = 0;
+    response[4..6].copy_from_slice(&1u16.to_be_bytes());
+    response[6..8].copy_from_slice(&u16::from(answer).to_be_bytes());
+    response[8..12].fill(0);
+
+    let (question_out, answer_out) = response[HEADER_LEN..].split_at_mut(question_len);
+    question_out.copy_from_slice(&query[HEADER_LEN..HEADER_LEN + question_len]);
+    if answer {
+        // the name is a pointer to the name in the question, right after the header
+        answer_out[..2].copy_from_slice(&(0xc000 | HEADER_LEN as u16).to_be_bytes());
+        answer_out[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer_out[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        answer_out[6..10].copy_from_slice(&TTL.to_be_bytes());
+        answer_out[10..12].copy_from_slice(&4u16.to_be_bytes());
+        answer_out[12..16].copy_from_slice(&address);
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const ADDRESS: [u8; 4] = [192, 168, 4, 1];
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = std::vec![0xab, 0xcd, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        query
+    }
+
+    #[test]
+    fn parses_question() {
+        let query = query("captive.apple.com", TYPE_A);
+        let question = parse(&query).unwrap();
+        assert_eq!(question.qtype, TYPE_A);
+        assert_eq!(question.qclass, CLASS_IN);
+        assert!(question
+            .labels()
+            .eq([&b"captive"[..], &b"apple"[..], &b"com"[..]]));
+    }
+
+    #[test]
+    fn answers_a_query() {
+        let query = query("connectivitycheck.gstatic.com", TYPE_A);
+        let mut response = [0; 512];
+        let len = respond(&query, ADDRESS, &mut response).unwrap();
+        let response = &response[..len];
+
+        assert_eq!(len, query.len() + 16);
+        // same id, response, authoritative, recursion desired, no error
+        assert_eq!(response[..4], [0xab, 0xcd, 0x85, 0x00]);
+        // one question, one answer, nothing else
+        assert_eq!(response[4..12], [0, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(response[12..query.len()], query[12..]);
+        assert_eq!(
+            response[query.len()..],
+            [0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 10, 0, 4, 192, 168, 4, 1]
+        );
+    }
+
+    #[test]
+    fn answers_other_types_without_records() {
+        let query = query("example.com", 28);
+        let mut response = [0; 512];
+        let len = respond(&query, ADDRESS, &mut response).unwrap();
+
+        assert_eq!(len, query.len());
+        assert_eq!(response[4..12], [0, 1, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn drops_additional_records() {
+        // an EDNS OPT record
+        let mut query = query("example.com", TYPE_A);
+        query[11] = 1;
+        let question_end = query.len();
+        query.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]);
+
+        let mut response = [0; 512];
+        let len = respond(&query, ADDRESS, &mut response).unwrap();
+        assert_eq!(len, question_end + 16);
+        assert_eq!(response[10..12], [0, 0]);
+    }
+
+    #[test]
+    fn rejects_responses_and_other_opcodes() {
+        let mut query = query("example.com", TYPE_A);
+        query[2] |= 0x80;
+        assert_eq!(parse(&query), Err(Error::NotAQuery));
+
+        query[2] = 0x28; // update
+        assert_eq!(parse(&query), Err(Error::NotAQuery));
+    }
+
+    #[test]
+    fn needs_exactly_one_question() {
+        let mut query = query("example.com", TYPE_A);
+        query[5] = 2;
+        assert_eq!(parse(&query), Err(Error::UnsupportedQuestionCount(2)));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        let query = query("example.com", TYPE_A);
+        for len in 0..query.len() {
+            assert_eq!(parse(&query[..len]), Err(Error::Malformed), "{len}");
+        }
+
+        // a compression pointer
+        let mut pointer = query.clone();
+        pointer[12] = 0xc0;
+        assert_eq!(parse(&pointer), Err(Error::Malformed));
+
+        // a name longer than 255 bytes
+        let long = std::format!("{0}.{0}.{0}.{0}.{0}", "a".repeat(60));
+        assert!(parse(&self::query(&long[..250], TYPE_A)).is_ok());
+        assert_eq!(parse(&self::query(&long, TYPE_A)), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn response_needs_room() {
+        let query = query("example.com", TYPE_A);
+        let mut response = [0; 64];
+        assert_eq!(
+            respond(&query, ADDRESS, &mut response[..query.len() + 15]),
+            Err(Error::BufferTooSmall)
+        );
+        assert!(respond(&query, ADDRESS, &mut response[..query.len() + 16]).is_ok());
+    }
+}
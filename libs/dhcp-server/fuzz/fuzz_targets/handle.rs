@@ -0,0 +1,24 @@
+//! Feeds arbitrary messages to a server, which must never panic, and checks that replies are
+//! well-formed and leases stay in the pool.
+
+#![no_main]
+
+use dhcp_server::{Config, Server};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|messages: Vec<Vec<u8>>| {
+    let mut server: Server<4> = Server::new(Config::new([192, 168, 4, 1]));
+    let mut reply = [0; 576];
+    for (now, message) in messages.iter().enumerate() {
+        let now = now as u64 * 1000;
+        if let Ok(Some((len, _))) = server.handle(now, message, &mut reply) {
+            assert!(len >= 300);
+            assert_eq!(reply[0], 2);
+            assert_eq!(reply[4..8], message[4..8]);
+        }
+        assert!(server.leases(now).count() <= 4);
+        for lease in server.leases(now) {
+            assert!((2..6).contains(&lease.address[3]));
+        }
+    }
+});
@@ -0,0 +1,771 @@
+//! Minimal DHCPv4 server for devices running an access point.
+//!
+//! Clients that join an access point expect to get an address by DHCP. [`Server`] hands out the
+//! addresses of a small pool and keeps track of the leases. Like the other libraries it doesn't
+//! touch the network: feed it the messages received on UDP port [`SERVER_PORT`] and send the
+//! replies it returns to port [`CLIENT_PORT`].
+//!
+//! ```
+//! use dhcp_server::{Config, Server};
+//!
+//! let mut server: Server<4> = Server::new(Config::new([192, 168, 4, 1]));
+//!
+//! // a DHCPDISCOVER of a client that just joined
+//! let mut discover = [0; 244];
+//! discover[..3].copy_from_slice(&[1, 1, 6]); // a request from an Ethernet client
+//! discover[28..34].copy_from_slice(&[2, 0, 0, 0, 0, 1]); // its MAC address
+//! discover[236..244].copy_from_slice(&[99, 130, 83, 99, 53, 1, 1, 255]); // DHCPDISCOVER
+//!
+//! let mut reply = [0; 576];
+//! let (len, destination) = server.handle(0, &discover, &mut reply).unwrap().unwrap();
+//!
+//! // the offer is broadcast, the client has no address yet
+//! assert_eq!(destination, [255, 255, 255, 255]);
+//! // the offered address is in the `yiaddr` field
+//! assert_eq!(reply[16..20], [192, 168, 4, 2]);
+//! ```
+//!
+//! Only what's needed for a handful of clients on one network is supported: no relay agents,
+//! no static leases and no options beyond the address, netmask, router and DNS server.
+
+#![no_std]
+
+use core::fmt;
+
+/// The UDP port the server listens on
+pub const SERVER_PORT: u16 = 67;
+/// The UDP port clients listen on
+pub const CLIENT_PORT: u16 = 68;
+
+const BROADCAST: [u8; 4] = [255, 255, 255, 255];
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Offset of the options, after the fixed fields and the magic cookie
+const OPTIONS: usize = 240;
+/// Replies are padded to the size of a BOOTP message, some clients drop shorter ones
+const MIN_REPLY_LEN: usize = 300;
+/// Offered addresses are reserved for this long, in milliseconds
+const OFFER_TIMEOUT: u64 = 10_000;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVER: u8 = 6;
+const OPTION_HOSTNAME: u8 = 12;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+/// Longest host name kept for a lease, longer names are truncated
+pub const MAX_HOSTNAME_LEN: usize = 32;
+
+/// DHCP message types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+    Inform = 8,
+}
+
+impl MessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return None,
+        })
+    }
+}
+
+/// Errors returned by [`Server::handle`], don't reply to the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The message is truncated, not a request or the options are invalid
+    Malformed,
+    /// The message has no or an unknown message type option
+    UnknownMessageType,
+    /// All addresses of the pool are leased
+    PoolExhausted,
+    /// The buffer is too small for the reply
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The network the server hands out addresses in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// The address of the server itself
+    pub server: [u8; 4],
+    /// The first address of the pool, the pool has as many addresses as the server has leases
+    pub pool_start: [u8; 4],
+    pub subnet_mask: [u8; 4],
+    /// Announced as default gateway, if any
+    pub router: Option<[u8; 4]>,
+    /// Announced as DNS server, if any
+    pub dns_server: Option<[u8; 4]>,
+    /// How long a lease is valid, in seconds
+    pub lease_time: u32,
+}
+
+impl Config {
+    /// A /24 network with the server as router and DNS server and the pool right after the
+    /// server, e.g. `192.168.4.2` for a server at `192.168.4.1`
+    pub const fn new(server: [u8; 4]) -> Self {
+        Self {
+            server,
+            pool_start: [server[0], server[1], server[2], server[3].wrapping_add(1)],
+            subnet_mask: [255, 255, 255, 0],
+            router: Some(server),
+            dns_server: Some(server),
+            lease_time: 2 * 60 * 60,
+        }
+    }
+}
+
+/// An address handed out to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Lease {
+    /// The hardware address of the client
+    pub mac: [u8; 6],
+    pub address: [u8; 4],
+    /// When the lease ends, in milliseconds on the clock passed to [`Server::handle`]
+    pub expires: u64,
+    /// `false` while the address is only offered
+    pub bound: bool,
+    hostname: [u8; MAX_HOSTNAME_LEN],
+    hostname_len: u8,
+}
+
+impl Lease {
+    /// The host name the client sent, if any
+    pub fn hostname(&self) -> Option<&str> {
+        let hostname = &self.hostname[..self.hostname_len as usize];
+        core::str::from_utf8(hostname)
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// A DHCP server with a pool of `N` addresses
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Server<const N: usize> {
+    config: Config,
+    /// Slot `i` holds the lease of the `i`-th address of the pool
+    leases: [Option<Lease>; N],
+}
+
+impl<const N: usize> Server<N> {
+    pub const fn new(config: Config) -> Self {
+        Self {
+            config,
+            leases: [None; N],
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The leases that haven't expired at `now`, including offered ones
+    pub fn leases(&self, now: u64) -> impl Iterator<Item = &Lease> {
+        self.leases
+            .iter()
+            .flatten()
+            .filter(move |lease| lease.expires > now)
+    }
+
+    /// Handles a message from a client received at `now`, in milliseconds
+    ///
+    /// Returns the length of the reply written to `reply` and the address to send it to, or
+    /// `None` if the message needs no reply.
+    pub fn handle(
+        &mut self,
+        now: u64,
+        message: &[u8],
+        reply: &mut [u8],
+    ) -> Result<Option<(usize, [u8; 4])>, Error> {
+        let request = Message::parse(message)?;
+
+        let (message_type, address) = match request.message_type {
+            MessageType::Discover => {
+                let slot = self.allocate(now, &request)?;
+                let lease = self.lease(slot, now, now + OFFER_TIMEOUT, false, &request);
+                (MessageType::Offer, lease.address)
+            }
+            MessageType::Request => {
+                // the client picked the offer of another server
+                if request.server_id.is_some_and(|id| id != self.config.server) {
+                    self.forget(&request.mac);
+                    return Ok(None);
+                }
+                let requested = request.requested_address.or(request.ciaddr);
+                match requested.and_then(|address| self.slot(address)) {
+                    Some(slot) if self.available(slot, now, &request.mac) => {
+                        let expires = now + u64::from(self.config.lease_time) * 1000;
+                        let lease = self.lease(slot, now, expires, true, &request);
+                        (MessageType::Ack, lease.address)
+                    }
+                    // the client asks for an address we don't have for it, e.g. one from
+                    // another network it was connected to before
+                    _ => (MessageType::Nak, [0; 4]),
+                }
+            }
+            MessageType::Release | MessageType::Decline => {
+                self.forget(&request.mac);
+                return Ok(None);
+            }
+            // other messages are for clients or not supported
+            _ => return Ok(None),
+        };
+
+        let len = self.encode_reply(&request, message_type, address, reply)?;
+        // clients that already have an address (renewing) are answered directly, everyone
+        // else can't receive unicast yet
+        let destination = match (message_type, request.ciaddr) {
+            (MessageType::Ack, Some(ciaddr)) => ciaddr,
+            _ => BROADCAST,
+        };
+        Ok(Some((len, destination)))
+    }
+
+    /// Picks the address to offer: the client's current one, the one it asks for, or any free
+    fn allocate(&self, now: u64, request: &Message<'_>) -> Result<usize, Error> {
+        let own = self
+            .leases
+            .iter()
+            .position(|lease| lease.is_some_and(|lease| lease.mac == request.mac));
+        let requested = request
+            .requested_address
+            .and_then(|address| self.slot(address))
+            .filter(|&slot| self.available(slot, now, &request.mac));
+        let free = || (0..N).find(|&slot| self.available(slot, now, &request.mac));
+
+        own.or(requested).or_else(free).ok_or(Error::PoolExhausted)
+    }
+
+    /// Whether the address in `slot` can be leased to `mac`
+    fn available(&self, slot: usize, now: u64, mac: &[u8; 6]) -> bool {
+        match &self.leases[slot] {
+            Some(lease) => lease.mac == *mac || lease.expires <= now,
+            None => true,
+        }
+    }
+
+    /// The slot of `address`, if it's in the pool
+    fn slot(&self, address: [u8; 4]) -> Option<usize> {
+        let start = u32::from_be_bytes(self.config.pool_start);
+        let slot = u32::from_be_bytes(address).checked_sub(start)? as usize;
+        (slot < N).then_some(slot)
+    }
+
+    fn lease(
+        &mut self,
+        slot: usize,
+        now: u64,
+        expires: u64,
+        bound: bool,
+        request: &Message<'_>,
+    ) -> Lease {
+        // a client has only one lease
+        for lease in &mut self.leases {
+            if lease.is_some_and(|lease| lease.mac == request.mac || lease.expires <= now) {
+                *lease = None;
+            }
+        }
+
+        let start = u32::from_be_bytes(self.config.pool_start);
+        let mut lease = Lease {
+            mac: request.mac,
+            address: (start + slot as u32).to_be_bytes(),
+            expires,
+            bound,
+            hostname: [0; MAX_HOSTNAME_LEN],
+            hostname_len: 0,
+        };
+        if let Some(hostname) = request.hostname {
+            let len = hostname.len().min(MAX_HOSTNAME_LEN);
+            lease.hostname[..len].copy_from_slice(&hostname[..len]);
+            lease.hostname_len = len as u8;
+        }
+        self.leases[slot] = Some(lease);
+        lease
+    }
+
+    fn forget(&mut self, mac: &[u8; 6]) {
+        for lease in &mut self.leases {
+            if lease.is_some_and(|lease| lease.mac == *mac) {
+                *lease = None;
+            }
+        }
+    }
+
+    fn encode_reply(
+        &self,
+        request: &Message<'_>,
+        message_type: MessageType,
+        address: [u8; 4],
+        reply: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut writer = Writer {
+            buffer: reply,
+            len: 0,
+        };
+        // op (reply), htype, hlen, hops
+        writer.write(&[2, 1, 6, 0])?;
+        writer.write(&request.xid)?;
+        // secs
+        writer.write(&[0, 0])?;
+        writer.write(&request.flags)?;
+        // ciaddr, yiaddr, siaddr, giaddr
+        writer.write(&request.ciaddr.unwrap_or_default())?;
+        writer.write(&address)?;
+        writer.write(&[0; 4])?;
+        writer.write(&[0; 4])?;
+        writer.write(&request.chaddr)?;
+        // sname, file
+        writer.write(&[0; 192])?;
+        writer.write(&MAGIC_COOKIE)?;
+
+        writer.option(OPTION_MESSAGE_TYPE, &[message_type as u8])?;
+        writer.option(OPTION_SERVER_ID, &self.config.server)?;
+        if message_type != MessageType::Nak {
+            writer.option(OPTION_LEASE_TIME, &self.config.lease_time.to_be_bytes())?;
+            writer.option(OPTION_SUBNET_MASK, &self.config.subnet_mask)?;
+            if let Some(router) = self.config.router {
+                writer.option(OPTION_ROUTER, &router)?;
+            }
+            if let Some(dns_server) = self.config.dns_server {
+                writer.option(OPTION_DNS_SERVER, &dns_server)?;
+            }
+        }
+        writer.write(&[OPTION_END])?;
+
+        while writer.len < MIN_REPLY_LEN {
+            writer.write(&[OPTION_PAD])?;
+        }
+        Ok(writer.len)
+    }
+}
+
+/// The parts of a client's message the server needs
+#[derive(Debug)]
+struct Message<'a> {
+    message_type: MessageType,
+    xid: [u8; 4],
+    flags: [u8; 2],
+    ciaddr: Option<[u8; 4]>,
+    /// The whole `chaddr` field, which is echoed in the reply
+    chaddr: [u8; 16],
+    mac: [u8; 6],
+    requested_address: Option<[u8; 4]>,
+    server_id: Option<[u8; 4]>,
+    hostname: Option<&'a [u8]>,
+}
+
+impl<'a> Message<'a> {
+    fn parse(message: &'a [u8]) -> Result<Self, Error> {
+        let fixed = message.get(..OPTIONS).ok_or(Error::Malformed)?;
+        // a request from a client on Ethernet (which Wi-Fi is as well)
+        if fixed[..3] != [1, 1, 6] || fixed[236..240] != MAGIC_COOKIE {
+            return Err(Error::Malformed);
+        }
+
+        let address = |offset: usize| {
+            let address = [
+                fixed[offset],
+                fixed[offset + 1],
+                fixed[offset + 2],
+                fixed[offset + 3],
+            ];
+            (address != [0; 4]).then_some(address)
+        };
+        let mut chaddr = [0; 16];
+        chaddr.copy_from_slice(&fixed[28..44]);
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&fixed[28..34]);
+
+        let mut parsed = Self {
+            message_type: MessageType::Discover,
+            xid: [fixed[4], fixed[5], fixed[6], fixed[7]],
+            flags: [fixed[10], fixed[11]],
+            ciaddr: address(12),
+            chaddr,
+            mac,
+            requested_address: None,
+            server_id: None,
+            hostname: None,
+        };
+
+        let mut message_type = None;
+        let mut options = &message[OPTIONS..];
+        loop {
+            let (&kind, rest) = options.split_first().ok_or(Error::Malformed)?;
+            match kind {
+                OPTION_END => break,
+                OPTION_PAD => {
+                    options = rest;
+                    continue;
+                }
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first().ok_or(Error::Malformed)?;
+            let (data, rest) = rest
+                .split_at_checked(len as usize)
+                .ok_or(Error::Malformed)?;
+            options = rest;
+
+            match (kind, data) {
+                (OPTION_MESSAGE_TYPE, &[value]) => message_type = MessageType::from_u8(value),
+                (OPTION_REQUESTED_ADDRESS, &[a, b, c, d]) => {
+                    parsed.requested_address = Some([a, b, c, d])
+                }
+                (OPTION_SERVER_ID, &[a, b, c, d]) => parsed.server_id = Some([a, b, c, d]),
+                (OPTION_HOSTNAME, name) => parsed.hostname = Some(name),
+                (OPTION_MESSAGE_TYPE | OPTION_REQUESTED_ADDRESS | OPTION_SERVER_ID, _) => {
+                    return Err(Error::Malformed)
+                }
+                _ => {}
+            }
+        }
+
+        parsed.message_type = message_type.ok_or(Error::UnknownMessageType)?;
+        Ok(parsed)
+    }
+}
+
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn option(&mut self, kind: u8, data: &[u8]) -> Result<(), Error> {
+        self.write(&[kind, data.len() as u8])?;
+        self.write(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const SERVER: [u8; 4] = [192, 168, 4, 1];
+    const PHONE: [u8; 6] = [2, 0, 0, 0, 0, 1];
+    const LAPTOP: [u8; 6] = [2, 0, 0, 0, 0, 2];
+    const LEASE_TIME: u64 = 2 * 60 * 60 * 1000;
+
+    /// A message from the client `mac` with the message type and further raw options
+    fn message(message_type: MessageType, mac: [u8; 6], options: &[u8]) -> Vec<u8> {
+        let mut message = std::vec![0; OPTIONS];
+        message[..4].copy_from_slice(&[1, 1, 6, 0]);
+        message[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        message[28..34].copy_from_slice(&mac);
+        message[236..240].copy_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type as u8]);
+        message.extend_from_slice(options);
+        message.push(OPTION_END);
+        message
+    }
+
+    fn request(mac: [u8; 6], address: [u8; 4]) -> Vec<u8> {
+        let mut options = std::vec![OPTION_REQUESTED_ADDRESS, 4];
+        options.extend_from_slice(&address);
+        options.extend_from_slice(&[OPTION_SERVER_ID, 4]);
+        options.extend_from_slice(&SERVER);
+        message(MessageType::Request, mac, &options)
+    }
+
+    /// The reply to `message`, with its destination
+    fn reply<const N: usize>(
+        server: &mut Server<N>,
+        now: u64,
+        message: &[u8],
+    ) -> Option<(Vec<u8>, [u8; 4])> {
+        let mut reply = [0; 576];
+        server
+            .handle(now, message, &mut reply)
+            .unwrap()
+            .map(|(len, destination)| (reply[..len].to_vec(), destination))
+    }
+
+    /// The options of a reply
+    fn options(reply: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut options = Vec::new();
+        let mut rest = &reply[OPTIONS..];
+        while rest[0] != OPTION_END {
+            let len = rest[1] as usize;
+            options.push((rest[0], &rest[2..2 + len]));
+            rest = &rest[2 + len..];
+        }
+        options
+    }
+
+    fn message_type(reply: &[u8]) -> u8 {
+        options(reply)
+            .iter()
+            .find(|(kind, _)| *kind == OPTION_MESSAGE_TYPE)
+            .unwrap()
+            .1[0]
+    }
+
+    fn yiaddr(reply: &[u8]) -> [u8; 4] {
+        reply[16..20].try_into().unwrap()
+    }
+
+    #[test]
+    fn offers_and_acknowledges_an_address() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+
+        let (offer, destination) =
+            reply(&mut server, 0, &message(MessageType::Discover, PHONE, &[])).unwrap();
+        assert_eq!(destination, BROADCAST);
+        assert_eq!(offer[..4], [2, 1, 6, 0]);
+        assert_eq!(offer[4..8], [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(offer[28..34], PHONE);
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 2]);
+        assert_eq!(offer.len(), MIN_REPLY_LEN);
+        assert_eq!(
+            options(&offer),
+            [
+                (OPTION_MESSAGE_TYPE, &[2][..]),
+                (OPTION_SERVER_ID, &SERVER[..]),
+                (OPTION_LEASE_TIME, &7200u32.to_be_bytes()[..]),
+                (OPTION_SUBNET_MASK, &[255, 255, 255, 0][..]),
+                (OPTION_ROUTER, &SERVER[..]),
+                (OPTION_DNS_SERVER, &SERVER[..]),
+            ]
+        );
+
+        let (ack, destination) =
+            reply(&mut server, 100, &request(PHONE, [192, 168, 4, 2])).unwrap();
+        assert_eq!(destination, BROADCAST);
+        assert_eq!(message_type(&ack), MessageType::Ack as u8);
+        assert_eq!(yiaddr(&ack), [192, 168, 4, 2]);
+
+        let lease = server.leases(100).next().unwrap();
+        assert_eq!(lease.mac, PHONE);
+        assert!(lease.bound);
+        assert_eq!(lease.expires, 100 + LEASE_TIME);
+    }
+
+    #[test]
+    fn gives_every_client_its_own_address() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        for (mac, address) in [(PHONE, [192, 168, 4, 2]), (LAPTOP, [192, 168, 4, 3])] {
+            let (offer, _) =
+                reply(&mut server, 0, &message(MessageType::Discover, mac, &[])).unwrap();
+            assert_eq!(yiaddr(&offer), address);
+        }
+
+        // a client that asks again gets the same address
+        let (offer, _) =
+            reply(&mut server, 0, &message(MessageType::Discover, PHONE, &[])).unwrap();
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 2]);
+        assert_eq!(server.leases(0).count(), 2);
+    }
+
+    #[test]
+    fn offers_the_requested_address_if_free() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        let discover = message(
+            MessageType::Discover,
+            PHONE,
+            &[OPTION_REQUESTED_ADDRESS, 4, 192, 168, 4, 4],
+        );
+        let (offer, _) = reply(&mut server, 0, &discover).unwrap();
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 4]);
+
+        // outside of the pool
+        let discover = message(
+            MessageType::Discover,
+            LAPTOP,
+            &[OPTION_REQUESTED_ADDRESS, 4, 10, 0, 0, 7],
+        );
+        let (offer, _) = reply(&mut server, 0, &discover).unwrap();
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 2]);
+    }
+
+    #[test]
+    fn naks_addresses_it_cant_give() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &request(PHONE, [192, 168, 4, 2])).unwrap();
+
+        // taken by someone else
+        let (nak, destination) = reply(&mut server, 0, &request(LAPTOP, [192, 168, 4, 2])).unwrap();
+        assert_eq!(destination, BROADCAST);
+        assert_eq!(message_type(&nak), MessageType::Nak as u8);
+        assert_eq!(yiaddr(&nak), [0; 4]);
+        assert_eq!(options(&nak).len(), 2);
+
+        // from another network
+        let (nak, _) = reply(&mut server, 0, &request(LAPTOP, [10, 0, 0, 7])).unwrap();
+        assert_eq!(message_type(&nak), MessageType::Nak as u8);
+    }
+
+    #[test]
+    fn renews_leases() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &request(PHONE, [192, 168, 4, 2])).unwrap();
+
+        // renewing clients put their address into `ciaddr` and don't send a server id
+        let mut renew = message(MessageType::Request, PHONE, &[]);
+        renew[12..16].copy_from_slice(&[192, 168, 4, 2]);
+        let (ack, destination) = reply(&mut server, 1000, &renew).unwrap();
+        assert_eq!(destination, [192, 168, 4, 2]);
+        assert_eq!(ack[12..16], [192, 168, 4, 2]);
+        assert_eq!(message_type(&ack), MessageType::Ack as u8);
+        assert_eq!(server.leases(0).next().unwrap().expires, 1000 + LEASE_TIME);
+    }
+
+    #[test]
+    fn reuses_expired_leases() {
+        let mut server: Server<1> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &request(PHONE, [192, 168, 4, 2])).unwrap();
+
+        let mut reply_buffer = [0; 576];
+        let discover = message(MessageType::Discover, LAPTOP, &[]);
+        assert_eq!(
+            server.handle(LEASE_TIME - 1, &discover, &mut reply_buffer),
+            Err(Error::PoolExhausted)
+        );
+
+        let (offer, _) = reply(&mut server, LEASE_TIME, &discover).unwrap();
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 2]);
+        assert_eq!(server.leases(LEASE_TIME).next().unwrap().mac, LAPTOP);
+    }
+
+    #[test]
+    fn offers_expire() {
+        let mut server: Server<1> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &message(MessageType::Discover, PHONE, &[])).unwrap();
+        assert!(!server.leases(0).next().unwrap().bound);
+        assert_eq!(server.leases(OFFER_TIMEOUT).count(), 0);
+
+        let (offer, _) = reply(
+            &mut server,
+            OFFER_TIMEOUT,
+            &message(MessageType::Discover, LAPTOP, &[]),
+        )
+        .unwrap();
+        assert_eq!(yiaddr(&offer), [192, 168, 4, 2]);
+    }
+
+    #[test]
+    fn forgets_released_leases() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &request(PHONE, [192, 168, 4, 2])).unwrap();
+        assert_eq!(
+            reply(&mut server, 0, &message(MessageType::Release, PHONE, &[])),
+            None
+        );
+        assert_eq!(server.leases(0).count(), 0);
+    }
+
+    #[test]
+    fn ignores_requests_for_other_servers() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        reply(&mut server, 0, &message(MessageType::Discover, PHONE, &[])).unwrap();
+
+        let request = message(
+            MessageType::Request,
+            PHONE,
+            &[OPTION_SERVER_ID, 4, 192, 168, 4, 254],
+        );
+        assert_eq!(reply(&mut server, 0, &request), None);
+        assert_eq!(server.leases(0).count(), 0);
+    }
+
+    #[test]
+    fn keeps_the_hostname() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        let mut options = request(PHONE, [192, 168, 4, 2]);
+        options.pop();
+        options.extend_from_slice(&[OPTION_HOSTNAME, 5, b'p', b'h', b'o', b'n', b'e', OPTION_END]);
+        reply(&mut server, 0, &options).unwrap();
+        assert_eq!(server.leases(0).next().unwrap().hostname(), Some("phone"));
+
+        reply(&mut server, 0, &request(LAPTOP, [192, 168, 4, 3])).unwrap();
+        assert_eq!(server.leases(0).nth(1).unwrap().hostname(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        let mut reply = [0; 576];
+        let discover = message(MessageType::Discover, PHONE, &[]);
+
+        for len in 0..discover.len() {
+            assert_eq!(
+                server.handle(0, &discover[..len], &mut reply),
+                Err(Error::Malformed),
+                "{len}"
+            );
+        }
+
+        let mut bad = discover.clone();
+        bad[0] = 2;
+        assert_eq!(server.handle(0, &bad, &mut reply), Err(Error::Malformed));
+
+        let mut bad = discover.clone();
+        bad[236] = 0;
+        assert_eq!(server.handle(0, &bad, &mut reply), Err(Error::Malformed));
+
+        let mut bad = discover.clone();
+        bad[OPTIONS + 2] = 42;
+        assert_eq!(
+            server.handle(0, &bad, &mut reply),
+            Err(Error::UnknownMessageType)
+        );
+
+        let bad = message(MessageType::Request, PHONE, &[OPTION_SERVER_ID, 2, 1, 2]);
+        assert_eq!(server.handle(0, &bad, &mut reply), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn reply_needs_room() {
+        let mut server: Server<4> = Server::new(Config::new(SERVER));
+        let mut reply = [0; MIN_REPLY_LEN - 1];
+        let discover = message(MessageType::Discover, PHONE, &[]);
+        assert_eq!(
+            server.handle(0, &discover, &mut reply),
+            Err(Error::BufferTooSmall)
+        );
+    }
+}
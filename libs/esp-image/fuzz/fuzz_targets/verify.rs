@@ -0,0 +1,26 @@
+//! Verifies arbitrary data, which must never panic, and checks that the result doesn't depend on
+//! how the data is split into chunks.
+
+#![no_main]
+
+use esp_image::{Verifier, CHIP_ID_ESP32C3};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, &[u8])| {
+    let (chunk_len, data) = input;
+
+    let mut whole = Verifier::new(CHIP_ID_ESP32C3);
+    let whole = whole.update(data).and_then(|()| whole.finish());
+
+    let mut chunked = Verifier::new(CHIP_ID_ESP32C3);
+    let mut result = Ok(());
+    for chunk in data.chunks(usize::from(chunk_len).max(1)) {
+        result = chunked.update(chunk);
+        if result.is_err() {
+            break;
+        }
+    }
+    let chunked = result.and_then(|()| chunked.finish());
+
+    assert_eq!(whole, chunked);
+});
@@ -0,0 +1,661 @@
+//! Streaming verification of ESP-IDF app images.
+//!
+//! The image of an application, as written by `espflash save-image` or `esptool elf2image`, is
+//! what the bootloader loads from an app partition: a header, the segments to load into memory,
+//! a checksum over the segment data and, usually, a SHA-256 digest over everything before it. The
+//! bootloader checks all of that before it runs the application, an over-the-air update should
+//! check it before it tells the bootloader to boot the new image.
+//!
+//! A [`Verifier`] checks the image while it's downloaded, chunk by chunk, without keeping it in
+//! memory. The [`AppDesc`] at the start of the first segment tells which application and version
+//! the image holds, and is available as soon as the first few hundred bytes arrived:
+//!
+//! ```
+//! use esp_image::{Verifier, CHIP_ID_ESP32C3};
+//!
+//! // an image with a single four byte segment and no digest
+//! let image = [
+//!     0xe9, 1, 2, 0x20, 0, 0, 0, 0x40, 0xee, 0, 0, 0, // magic, segments, flash settings, entry
+//!     5, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, // chip ID, revisions, no digest
+//!     0, 0, 0, 0x40, 4, 0, 0, 0, // load address and length of the segment
+//!     1, 2, 3, 4, // data of the segment
+//!     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // padding, so the checksum ends a 16 byte block
+//!     0xeb, // checksum
+//! ];
+//!
+//! let mut verifier = Verifier::new(CHIP_ID_ESP32C3);
+//! for chunk in image.chunks(10) {
+//!     verifier.update(chunk).unwrap();
+//! }
+//! let verified = verifier.finish().unwrap();
+//! assert_eq!(verified.len, image.len());
+//! // the segment is too short to hold an app descriptor
+//! assert!(verified.app_desc.is_none());
+//! ```
+
+#![no_std]
+
+use core::fmt;
+use sha2::{Digest, Sha256};
+
+/// The first byte of every image
+pub const MAGIC: u8 = 0xe9;
+
+/// The chip ID of the ESP32-C3 in the image header
+pub const CHIP_ID_ESP32C3: u16 = 5;
+
+/// The first four bytes of an [`AppDesc`], little-endian
+pub const APP_DESC_MAGIC: u32 = 0xabcd_5432;
+
+/// Length of the image header
+pub const HEADER_LEN: usize = 24;
+
+/// Length of the app descriptor at the start of the first segment
+pub const APP_DESC_LEN: usize = 256;
+
+/// Length of the SHA-256 digest appended to the image
+pub const DIGEST_LEN: usize = 32;
+
+/// The bootloader loads at most this many segments
+pub const MAX_SEGMENTS: u8 = 16;
+
+/// The largest flash chip is 16 MiB, no image or segment can be longer
+pub const MAX_LEN: usize = 16 * 1024 * 1024;
+
+const SEGMENT_HEADER_LEN: usize = 8;
+const CHECKSUM_SEED: u8 = 0xef;
+
+/// Errors found in an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The data doesn't start with the magic byte of an image
+    InvalidMagic,
+    /// The image is built for another chip, this is the chip ID in its header
+    WrongChip(u16),
+    /// The header lists no segments or more than the bootloader loads
+    InvalidSegmentCount(u8),
+    /// The image is longer than any flash chip
+    TooLong,
+    /// The checksum over the segment data doesn't match, the image is damaged
+    ChecksumMismatch,
+    /// The SHA-256 digest appended to the image doesn't match, the image is damaged
+    DigestMismatch,
+    /// The image ended early
+    Truncated,
+    /// There is more data after the end of the image
+    TrailingData,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The application descriptor, which ESP-IDF and `esp_bootloader_esp_idf::esp_app_desc!` put at
+/// the start of the first segment
+///
+/// The strings are only as long as the build tools allow, e.g. the version is cut off after 31
+/// bytes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AppDesc {
+    raw: [u8; APP_DESC_LEN],
+}
+
+impl AppDesc {
+    /// Decodes the descriptor at the start of `data`
+    ///
+    /// Returns `None` if `data` is too short, doesn't start with [`APP_DESC_MAGIC`] or a string
+    /// isn't valid UTF-8.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let raw: [u8; APP_DESC_LEN] = data.get(..APP_DESC_LEN)?.try_into().ok()?;
+        let desc = Self { raw };
+        if desc.u32_at(0) != APP_DESC_MAGIC {
+            return None;
+        }
+        for range in [16..48, 48..80, 80..96, 96..112, 112..144] {
+            core::str::from_utf8(until_nul(&desc.raw[range])).ok()?;
+        }
+        Some(desc)
+    }
+
+    /// The secure version, which anti-rollback compares against the one in eFuse
+    pub fn secure_version(&self) -> u32 {
+        self.u32_at(4)
+    }
+
+    /// The version of the application, e.g. `0.1.0`
+    pub fn version(&self) -> &str {
+        self.str_at(16, 32)
+    }
+
+    /// The name of the application, e.g. the name of the crate
+    pub fn project_name(&self) -> &str {
+        self.str_at(48, 32)
+    }
+
+    /// The time of the build, e.g. `12:34:56`
+    pub fn time(&self) -> &str {
+        self.str_at(80, 16)
+    }
+
+    /// The date of the build, e.g. `Jan 01 2025`
+    pub fn date(&self) -> &str {
+        self.str_at(96, 16)
+    }
+
+    /// The version of ESP-IDF the image is compatible with
+    pub fn idf_version(&self) -> &str {
+        self.str_at(112, 32)
+    }
+
+    /// The SHA-256 digest of the ELF file, all zeros if the build tools didn't fill it in
+    pub fn elf_sha256(&self) -> &[u8; 32] {
+        self.raw[144..176].try_into().unwrap()
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.raw[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn str_at(&self, offset: usize, len: usize) -> &str {
+        // checked by `decode`
+        core::str::from_utf8(until_nul(&self.raw[offset..offset + len])).unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for AppDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppDesc")
+            .field("project_name", &self.project_name())
+            .field("version", &self.version())
+            .field("secure_version", &self.secure_version())
+            .field("date", &self.date())
+            .field("time", &self.time())
+            .field("idf_version", &self.idf_version())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for AppDesc {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "AppDesc {{ project_name: {=str}, version: {=str}, secure_version: {=u32}, date: {=str}, time: {=str} }}",
+            self.project_name(),
+            self.version(),
+            self.secure_version(),
+            self.date(),
+            self.time(),
+        )
+    }
+}
+
+/// The strings in the descriptor are padded with NUL bytes, the last one is always NUL
+fn until_nul(field: &[u8]) -> &[u8] {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+/// What [`Verifier::finish`] found in a complete image
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Verified {
+    /// Length of the image, including the checksum and the digest
+    pub len: usize,
+    /// The digest appended to the image, if there is one
+    pub digest: Option<[u8; DIGEST_LEN]>,
+    /// The app descriptor, `None` if the image doesn't start with one, e.g. a bootloader
+    pub app_desc: Option<AppDesc>,
+}
+
+/// Where in the image the next byte is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    /// `left` counts the segments that aren't complete yet, including this one
+    SegmentHeader {
+        left: u8,
+    },
+    Segment {
+        left: u8,
+        len: usize,
+    },
+    /// Zeros up to the checksum, which is the last byte of a 16 byte block
+    Padding(usize),
+    Checksum,
+    Digest,
+    Done,
+    Failed(Error),
+}
+
+/// Checks an image that arrives in chunks
+///
+/// The verifier keeps its own state between the chunks, so they can be of any size. Once it found
+/// an error, every further call returns that error.
+pub struct Verifier {
+    chip_id: u16,
+    state: State,
+    /// The header, segment header or digest that is being read
+    buffer: [u8; DIGEST_LEN],
+    filled: usize,
+    /// Bytes of the image seen so far
+    position: usize,
+    segments: u8,
+    has_digest: bool,
+    checksum: u8,
+    sha256: Sha256,
+    digest: [u8; DIGEST_LEN],
+    desc: [u8; APP_DESC_LEN],
+    app_desc: Option<AppDesc>,
+}
+
+impl Verifier {
+    /// Creates a verifier for images built for the chip with `chip_id`, e.g. [`CHIP_ID_ESP32C3`]
+    pub fn new(chip_id: u16) -> Self {
+        Self {
+            chip_id,
+            state: State::Header,
+            buffer: [0; DIGEST_LEN],
+            filled: 0,
+            position: 0,
+            segments: 0,
+            has_digest: false,
+            checksum: CHECKSUM_SEED,
+            sha256: Sha256::new(),
+            digest: [0; DIGEST_LEN],
+            desc: [0; APP_DESC_LEN],
+            app_desc: None,
+        }
+    }
+
+    /// Checks the next chunk of the image
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            if let State::Failed(err) = self.state {
+                return Err(err);
+            }
+            // everything up to and including the checksum is covered by the digest
+            let hashed = !matches!(self.state, State::Digest | State::Done);
+            let consumed = match self.step(data) {
+                Ok(consumed) => consumed,
+                Err(err) => {
+                    self.state = State::Failed(err);
+                    return Err(err);
+                }
+            };
+            if hashed {
+                self.sha256.update(&data[..consumed]);
+            }
+            self.position += consumed;
+            data = &data[consumed..];
+        }
+        match self.state {
+            State::Failed(err) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// The app descriptor, once the first segment is long enough to hold it
+    pub fn app_desc(&self) -> Option<&AppDesc> {
+        self.app_desc.as_ref()
+    }
+
+    /// Number of bytes checked so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Whether the image is complete, more data is an error
+    pub fn is_complete(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Checks that the image is complete
+    pub fn finish(self) -> Result<Verified, Error> {
+        match self.state {
+            State::Done => Ok(Verified {
+                len: self.position,
+                digest: self.has_digest.then_some(self.digest),
+                app_desc: self.app_desc,
+            }),
+            State::Failed(err) => Err(err),
+            _ => Err(Error::Truncated),
+        }
+    }
+
+    /// Consumes the start of `data` that belongs to the current part of the image, returns how
+    /// many bytes that were
+    fn step(&mut self, data: &[u8]) -> Result<usize, Error> {
+        match self.state {
+            State::Header => {
+                let consumed = self.fill(data, HEADER_LEN);
+                if self.filled == HEADER_LEN {
+                    self.filled = 0;
+                    let header = &self.buffer[..HEADER_LEN];
+                    if header[0] != MAGIC {
+                        return Err(Error::InvalidMagic);
+                    }
+                    let chip_id = u16::from_le_bytes([header[12], header[13]]);
+                    if chip_id != self.chip_id {
+                        return Err(Error::WrongChip(chip_id));
+                    }
+                    self.segments = header[1];
+                    if self.segments == 0 || self.segments > MAX_SEGMENTS {
+                        return Err(Error::InvalidSegmentCount(self.segments));
+                    }
+                    self.has_digest = header[23] == 1;
+                    self.state = State::SegmentHeader {
+                        left: self.segments,
+                    };
+                }
+                Ok(consumed)
+            }
+            State::SegmentHeader { left } => {
+                let consumed = self.fill(data, SEGMENT_HEADER_LEN);
+                if self.filled == SEGMENT_HEADER_LEN {
+                    self.filled = 0;
+                    let len = u32::from_le_bytes(self.buffer[4..8].try_into().unwrap()) as usize;
+                    if len > MAX_LEN - self.position {
+                        return Err(Error::TooLong);
+                    }
+                    self.state = State::Segment { left, len };
+                    self.end_segment(self.position + consumed);
+                }
+                Ok(consumed)
+            }
+            State::Segment { left, len } => {
+                let consumed = data.len().min(len);
+                for &byte in &data[..consumed] {
+                    self.checksum ^= byte;
+                }
+                if left == self.segments {
+                    self.copy_desc(&data[..consumed]);
+                }
+                self.state = State::Segment {
+                    left,
+                    len: len - consumed,
+                };
+                self.end_segment(self.position + consumed);
+                Ok(consumed)
+            }
+            State::Padding(len) => {
+                let consumed = data.len().min(len);
+                self.state = if consumed == len {
+                    State::Checksum
+                } else {
+                    State::Padding(len - consumed)
+                };
+                Ok(consumed)
+            }
+            State::Checksum => {
+                if data[0] != self.checksum {
+                    return Err(Error::ChecksumMismatch);
+                }
+                self.state = if self.has_digest {
+                    State::Digest
+                } else {
+                    State::Done
+                };
+                Ok(1)
+            }
+            State::Digest => {
+                if self.filled == 0 {
+                    // the digest covers everything up to here
+                    self.digest = core::mem::take(&mut self.sha256).finalize().into();
+                }
+                let consumed = self.fill(data, DIGEST_LEN);
+                if self.filled == DIGEST_LEN {
+                    if self.buffer != self.digest {
+                        return Err(Error::DigestMismatch);
+                    }
+                    self.state = State::Done;
+                }
+                Ok(consumed)
+            }
+            State::Done => Err(Error::TrailingData),
+            State::Failed(err) => Err(err),
+        }
+    }
+
+    /// Moves on to the next segment or the checksum once the current segment is complete
+    ///
+    /// `end` is the position after the data of the current step.
+    fn end_segment(&mut self, end: usize) {
+        if let State::Segment { left, len: 0 } = self.state {
+            self.state = if left > 1 {
+                State::SegmentHeader { left: left - 1 }
+            } else {
+                State::Padding(15 - end % 16)
+            };
+        }
+    }
+
+    /// Copies what `data` holds of the app descriptor, which starts right after the first
+    /// segment header
+    fn copy_desc(&mut self, data: &[u8]) {
+        let start = HEADER_LEN + SEGMENT_HEADER_LEN;
+        let end = start + APP_DESC_LEN;
+        let from = self.position.max(start);
+        let to = (self.position + data.len()).min(end);
+        if from >= to {
+            return;
+        }
+        self.desc[from - start..to - start]
+            .copy_from_slice(&data[from - self.position..to - self.position]);
+        if to == end {
+            self.app_desc = AppDesc::decode(&self.desc);
+        }
+    }
+
+    /// Appends the start of `data` to the buffer until it holds `len` bytes
+    fn fill(&mut self, data: &[u8], len: usize) -> usize {
+        let consumed = data.len().min(len - self.filled);
+        self.buffer[self.filled..self.filled + consumed].copy_from_slice(&data[..consumed]);
+        self.filled += consumed;
+        consumed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// An app descriptor as `esp_app_desc!` builds it
+    fn app_desc(name: &str, version: &str) -> Vec<u8> {
+        let mut desc = std::vec![0; APP_DESC_LEN];
+        desc[..4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        desc[16..16 + version.len()].copy_from_slice(version.as_bytes());
+        desc[48..48 + name.len()].copy_from_slice(name.as_bytes());
+        desc[80..88].copy_from_slice(b"12:34:56");
+        desc[96..107].copy_from_slice(b"Jan 01 2025");
+        desc[112..118].copy_from_slice(b"v5.5.1");
+        desc
+    }
+
+    /// An image with the given segments, laid out like `espflash save-image` does
+    fn image(segments: &[&[u8]], digest: bool) -> Vec<u8> {
+        let mut image = std::vec![0; HEADER_LEN];
+        image[0] = MAGIC;
+        image[1] = segments.len() as u8;
+        image[12..14].copy_from_slice(&CHIP_ID_ESP32C3.to_le_bytes());
+        image[23] = digest as u8;
+
+        let mut checksum = CHECKSUM_SEED;
+        for (i, segment) in segments.iter().enumerate() {
+            image.extend_from_slice(&(0x3c00_0020 + 0x1_0000 * i as u32).to_le_bytes());
+            image.extend_from_slice(&(segment.len() as u32).to_le_bytes());
+            image.extend_from_slice(segment);
+            checksum = segment.iter().fold(checksum, |checksum, b| checksum ^ b);
+        }
+        while image.len() % 16 != 15 {
+            image.push(0);
+        }
+        image.push(checksum);
+        if digest {
+            let digest = Sha256::digest(&image);
+            image.extend_from_slice(&digest);
+        }
+        image
+    }
+
+    fn app_image() -> Vec<u8> {
+        let mut rodata = app_desc("ota", "0.2.0");
+        rodata.extend((0..1000).map(|i| i as u8));
+        image(&[&rodata, &[0x13; 300], &[0x37; 4]], true)
+    }
+
+    fn verify(image: &[u8], chunk_len: usize) -> Result<Verified, Error> {
+        let mut verifier = Verifier::new(CHIP_ID_ESP32C3);
+        for chunk in image.chunks(chunk_len) {
+            verifier.update(chunk)?;
+        }
+        verifier.finish()
+    }
+
+    #[test]
+    fn verifies_image_in_any_chunks() {
+        let image = app_image();
+        for chunk_len in [1, 7, 16, 512, image.len()] {
+            let verified = verify(&image, chunk_len).unwrap();
+            assert_eq!(verified.len, image.len());
+            assert_eq!(verified.digest.unwrap(), image[image.len() - DIGEST_LEN..]);
+            let desc = verified.app_desc.unwrap();
+            assert_eq!(desc.project_name(), "ota");
+            assert_eq!(desc.version(), "0.2.0");
+        }
+    }
+
+    #[test]
+    fn decodes_app_desc_before_image_is_complete() {
+        let image = app_image();
+        let mut verifier = Verifier::new(CHIP_ID_ESP32C3);
+        verifier.update(&image[..HEADER_LEN + 8 + 200]).unwrap();
+        assert!(verifier.app_desc().is_none());
+        verifier.update(&image[HEADER_LEN + 8 + 200..400]).unwrap();
+
+        let desc = verifier.app_desc().unwrap();
+        assert_eq!(desc.project_name(), "ota");
+        assert_eq!(desc.version(), "0.2.0");
+        assert_eq!(desc.time(), "12:34:56");
+        assert_eq!(desc.date(), "Jan 01 2025");
+        assert_eq!(desc.idf_version(), "v5.5.1");
+        assert_eq!(desc.secure_version(), 0);
+        assert_eq!(desc.elf_sha256(), &[0; 32]);
+        assert!(!verifier.is_complete());
+        assert_eq!(verifier.position(), 400);
+    }
+
+    #[test]
+    fn image_without_app_desc() {
+        // e.g. a bootloader, the first segment doesn't start with the magic word
+        let verified = verify(&image(&[&[0; 300]], false), 64).unwrap();
+        assert_eq!(verified.app_desc, None);
+        assert_eq!(verified.digest, None);
+    }
+
+    #[test]
+    fn checksum_is_aligned() {
+        for len in 0..40 {
+            let segment = std::vec![0x5a; len];
+            let image = image(&[&segment], false);
+            assert_eq!(image.len() % 16, 0);
+            assert_eq!(verify(&image, 3).unwrap().len, image.len());
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(
+            verify(b"<!DOCTYPE html><html>Not Found</html>", 8),
+            Err(Error::InvalidMagic)
+        );
+
+        let mut image = app_image();
+        image[12] = 9; // ESP32-S3
+        assert_eq!(verify(&image, 64), Err(Error::WrongChip(9)));
+    }
+
+    #[test]
+    fn rejects_invalid_segment_count() {
+        let mut image = app_image();
+        image[1] = 0;
+        assert_eq!(verify(&image, 64), Err(Error::InvalidSegmentCount(0)));
+        image[1] = 17;
+        assert_eq!(verify(&image, 64), Err(Error::InvalidSegmentCount(17)));
+    }
+
+    #[test]
+    fn rejects_damaged_images() {
+        let image = app_image();
+
+        // without a digest, only the checksum notices
+        let mut damaged = self::image(&[&[1, 2, 3, 4]], false);
+        damaged[HEADER_LEN + 8] ^= 0x10;
+        assert_eq!(verify(&damaged, 64), Err(Error::ChecksumMismatch));
+
+        // two flipped bits cancel each other out in the checksum, not in the digest
+        let mut damaged = image.clone();
+        damaged[100] ^= 0x01;
+        damaged[101] ^= 0x01;
+        assert_eq!(verify(&damaged, 64), Err(Error::DigestMismatch));
+
+        // the header is covered by the digest as well
+        let mut damaged = image.clone();
+        damaged[4] ^= 0x01;
+        assert_eq!(verify(&damaged, 64), Err(Error::DigestMismatch));
+
+        let mut damaged = image;
+        *damaged.last_mut().unwrap() ^= 0x80;
+        assert_eq!(verify(&damaged, 64), Err(Error::DigestMismatch));
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing_data() {
+        let image = app_image();
+        for len in [0, 10, HEADER_LEN, 500, image.len() - 1] {
+            assert_eq!(verify(&image[..len], 64), Err(Error::Truncated), "{len}");
+        }
+
+        let mut padded = image.clone();
+        padded.extend_from_slice(&[0xff; 16]);
+        assert_eq!(verify(&padded, 64), Err(Error::TrailingData));
+    }
+
+    #[test]
+    fn rejects_oversized_segments() {
+        let mut image = app_image();
+        image[HEADER_LEN + 4..HEADER_LEN + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(verify(&image, 64), Err(Error::TooLong));
+    }
+
+    #[test]
+    fn errors_are_sticky() {
+        let mut image = app_image();
+        image[0] = 0;
+        let mut verifier = Verifier::new(CHIP_ID_ESP32C3);
+        assert_eq!(verifier.update(&image[..30]), Err(Error::InvalidMagic));
+        assert_eq!(verifier.update(&image[30..]), Err(Error::InvalidMagic));
+        assert_eq!(verifier.update(&[]), Err(Error::InvalidMagic));
+        assert_eq!(verifier.finish(), Err(Error::InvalidMagic));
+    }
+
+    #[test]
+    fn rejects_invalid_app_desc() {
+        let mut desc = app_desc("ota", "0.2.0");
+        assert!(AppDesc::decode(&desc).is_some());
+        assert!(AppDesc::decode(&desc[..APP_DESC_LEN - 1]).is_none());
+        desc[20] = 0xff;
+        assert!(AppDesc::decode(&desc).is_none());
+        desc[20] = 0;
+        desc[0] = 0;
+        assert!(AppDesc::decode(&desc).is_none());
+    }
+}
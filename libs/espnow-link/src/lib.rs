@@ -0,0 +1,907 @@
+//! Peer discovery and acknowledged messages over ESP-NOW.
+//!
+//! ESP-NOW sends single frames between boards without an access point. It doesn't know which
+//! boards are around, and a frame that gets lost is gone. [`Link`] adds both on top:
+//!
+//! - every board broadcasts an [`Message::Announce`] regularly, boards that hear it add the
+//!   sender as peer and forget it once it stays quiet. Announcements carry a tag computed with a
+//!   key all boards share, so a device without the key can't fill the peer table. They are
+//!   numbered, and a board only accepts a higher number than the last one it got from the same
+//!   sender, so recorded announcements can't be sent again,
+//! - a [`Message::Toggle`] is sent to every peer and repeated until the peer answers with a
+//!   [`Message::Ack`]. Repeated toggles carry the same sequence number, so the peer toggles its
+//!   LED only once.
+//!
+//! Like the `wifi-supervisor` crate, [`Link`] doesn't talk to the radio itself: the application
+//! hands it the received frames and sends what [`Link::poll`] asks for, which allows testing it on
+//! the host.
+//!
+//! ```
+//! use espnow_link::{Action, Config, Error, Event, Link, Message};
+//!
+//! let key = *b"a key of 16 byte";
+//! let a = [0x02, 0, 0, 0, 0, 0xa];
+//! let b = [0x02, 0, 0, 0, 0, 0xb];
+//! let mut link: Link<4> = Link::new(Config::default(), a, key, 100, 1);
+//!
+//! // the announcement of `b` makes it a peer
+//! let announce = Message::announce(&key, b, 5, 0).encode();
+//! assert_eq!(link.receive(0, b, true, &announce), Ok(Some(Event::Discovered(b))));
+//!
+//! // a button press toggles the LED of every peer
+//! link.toggle_peers(10);
+//! assert_eq!(link.poll(10), Some(Action::Broadcast(Message::announce(&key, a, 1, 0))));
+//! assert_eq!(link.poll(10), Some(Action::Send(b, Message::Toggle { seq: 100 })));
+//! assert_eq!(link.poll(10), None);
+//!
+//! // on `b`, `a` is a peer as well
+//! let mut other: Link<4> = Link::new(Config::default(), b, key, 7, 5);
+//! let announce = Message::announce(&key, a, 1, 0).encode();
+//! other.receive(0, a, true, &announce).unwrap();
+//! let toggle = Message::Toggle { seq: 100 }.encode();
+//! let event = other.receive(20, a, false, &toggle);
+//! assert_eq!(event, Ok(Some(Event::Toggled { from: a, led: true })));
+//!
+//! // an announcement that was recorded and sent again is rejected
+//! assert_eq!(other.receive(30, a, true, &announce), Err(Error::Replayed));
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Length of every message
+pub const MESSAGE_LEN: usize = 23;
+/// Length of the tag that authenticates an announcement
+pub const TAG_LEN: usize = 8;
+const MAGIC: [u8; 2] = *b"EL";
+const VERSION: u8 = 3;
+/// Separates the announcement key from the secret it's derived from, see [`derive_key`]
+const KEY_LABEL: &[u8] = b"espnow-link announce";
+
+/// A MAC address
+pub type Address = [u8; 6];
+
+/// The key announcements are authenticated with, all boards need the same one
+pub type Key = [u8; 16];
+
+/// Derives the key for the announcements from a secret the boards share for something else
+///
+/// E.g. the PMK of ESP-NOW, which encrypts the keys of the peers. The derived key is the first
+/// half of an HMAC-SHA256 over a label only this crate uses, so it's different from the secret and
+/// from keys other applications derive from it.
+pub fn derive_key(secret: &[u8]) -> Key {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(KEY_LABEL);
+    let mut key = [0; 16];
+    key.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    key
+}
+
+/// Errors returned by [`Message::decode`] and [`Link::receive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The frame is shorter than [`MESSAGE_LEN`]
+    Truncated,
+    /// The frame doesn't start with the magic bytes, it's from another application
+    InvalidMagic,
+    /// The frame was sent by a different version of this crate
+    UnsupportedVersion(u8),
+    /// The message type isn't known
+    UnknownMessage(u8),
+    /// Toggles and acks are only accepted from peers, which send them directly to us
+    UnexpectedMessage,
+    /// The tag of an announcement doesn't match, the sender doesn't have our key
+    InvalidTag,
+    /// The announcement isn't newer than the last one of the sender, it was recorded and sent
+    /// again
+    Replayed,
+    /// A board announced itself, but there is no room for another peer
+    TooManyPeers,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// What boards send each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Message {
+    /// "I'm here", broadcast regularly, the tag proves that the sender has the key
+    ///
+    /// `counter` counts the announcements since the sender started for the `boot`th time. The tag
+    /// covers both and the sender's address, so it can't be replayed from another address, and
+    /// receivers reject it once they got a later one. See [`Message::announce`].
+    Announce {
+        boot: u32,
+        counter: u32,
+        tag: [u8; TAG_LEN],
+    },
+    /// Toggle your LED, repeated with the same `seq` until it's acknowledged
+    Toggle { seq: u16 },
+    /// The toggle `seq` arrived and the LED is `led` now
+    Ack { seq: u16, led: bool },
+}
+
+impl Message {
+    /// The `counter`th announcement of the board with `address` since its `boot`th start
+    pub fn announce(key: &Key, address: Address, boot: u32, counter: u32) -> Self {
+        let tag = announce_mac(key, address, boot, counter)
+            .finalize()
+            .into_bytes();
+        let mut truncated = [0; TAG_LEN];
+        truncated.copy_from_slice(&tag[..TAG_LEN]);
+        Self::Announce {
+            boot,
+            counter,
+            tag: truncated,
+        }
+    }
+
+    /// Encodes the message as magic, version, type, sequence number, LED state, boot, counter and
+    /// tag
+    ///
+    /// Only announcements have a boot, counter and tag, and only toggles and acks a sequence
+    /// number, the unused fields are zero.
+    pub fn encode(&self) -> [u8; MESSAGE_LEN] {
+        let (kind, seq, led, boot, counter, tag) = match *self {
+            Self::Announce { boot, counter, tag } => (0, 0, false, boot, counter, tag),
+            Self::Toggle { seq } => (1, seq, false, 0, 0, [0; TAG_LEN]),
+            Self::Ack { seq, led } => (2, seq, led, 0, 0, [0; TAG_LEN]),
+        };
+        let mut data = [0; MESSAGE_LEN];
+        data[..2].copy_from_slice(&MAGIC);
+        data[2] = VERSION;
+        data[3] = kind;
+        data[4..6].copy_from_slice(&seq.to_le_bytes());
+        data[6] = led as u8;
+        data[7..11].copy_from_slice(&boot.to_le_bytes());
+        data[11..15].copy_from_slice(&counter.to_le_bytes());
+        data[15..].copy_from_slice(&tag);
+        data
+    }
+
+    /// Decodes a message, anything after the first [`MESSAGE_LEN`] bytes is ignored
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let data: &[u8; MESSAGE_LEN] = data
+            .get(..MESSAGE_LEN)
+            .and_then(|data| data.try_into().ok())
+            .ok_or(Error::Truncated)?;
+        if data[..2] != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        if data[2] != VERSION {
+            return Err(Error::UnsupportedVersion(data[2]));
+        }
+        let seq = u16::from_le_bytes([data[4], data[5]]);
+        match data[3] {
+            0 => Ok(Self::Announce {
+                boot: u32::from_le_bytes(data[7..11].try_into().unwrap()),
+                counter: u32::from_le_bytes(data[11..15].try_into().unwrap()),
+                tag: data[15..].try_into().unwrap(),
+            }),
+            1 => Ok(Self::Toggle { seq }),
+            2 => Ok(Self::Ack {
+                seq,
+                led: data[6] != 0,
+            }),
+            kind => Err(Error::UnknownMessage(kind)),
+        }
+    }
+}
+
+/// HMAC-SHA256 over the magic bytes, the version, the address of the announcing board and the
+/// number of the announcement
+fn announce_mac(key: &Key, address: Address, boot: u32, counter: u32) -> Hmac<Sha256> {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(&MAGIC);
+    mac.update(&[VERSION]);
+    mac.update(&address);
+    mac.update(&boot.to_le_bytes());
+    mac.update(&counter.to_le_bytes());
+    mac
+}
+
+/// The number of an announcement, later ones compare greater
+type Number = (u32, u32);
+
+/// What happened because of a received message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A new peer announced itself, register it with the radio
+    Discovered(Address),
+    /// A peer toggled our LED, it's `led` now
+    Toggled { from: Address, led: bool },
+    /// A peer acknowledged our toggle, its LED is `led` now
+    Acknowledged { from: Address, led: bool },
+}
+
+/// What the application has to do, see [`Link::poll`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Broadcast the message
+    Broadcast(Message),
+    /// Send the message to the peer
+    Send(Address, Message),
+    /// The peer wasn't heard from for a while and was forgotten, unregister it from the radio
+    Lost(Address),
+    /// The peer didn't acknowledge the toggle `seq`, we gave up
+    Unacknowledged(Address, u16),
+}
+
+/// Timing of the link, all values are in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// How often we announce ourselves
+    pub announce_interval: u64,
+    /// A peer that wasn't heard from for this long is forgotten
+    pub peer_timeout: u64,
+    /// Delay before a toggle is repeated
+    pub retry_interval: u64,
+    /// How often a toggle is sent before giving up
+    pub max_attempts: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            announce_interval: 1_000,
+            peer_timeout: 5_000,
+            retry_interval: 50,
+            max_attempts: 5,
+        }
+    }
+}
+
+/// A toggle that wasn't acknowledged yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Pending {
+    seq: u16,
+    attempts: u8,
+    /// When to send it (again)
+    send_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Peer {
+    address: Address,
+    last_seen: u64,
+    /// The last announcement we accepted, older ones are replays
+    announced: Number,
+    /// The last toggle we got from the peer, repeated ones are acknowledged again but ignored
+    received: Option<u16>,
+    /// The ack we owe the peer
+    ack: Option<u16>,
+    /// Our toggle the peer didn't acknowledge yet
+    pending: Option<Pending>,
+}
+
+/// Peers and acknowledged toggles for up to `PEERS` other boards
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Link<const PEERS: usize> {
+    config: Config,
+    key: Key,
+    address: Address,
+    /// Our start, see [`Link::new`]
+    boot: u32,
+    /// Number of our next announcement
+    counter: u32,
+    peers: [Option<Peer>; PEERS],
+    /// The last announcements of the peers we forgot, so recordings don't bring them back
+    forgotten: [Option<(Address, Number)>; PEERS],
+    /// The slot of `forgotten` to overwrite next
+    next_forgotten: usize,
+    /// Sequence number of the next toggle
+    seq: u16,
+    led: bool,
+    announce_at: u64,
+}
+
+impl<const PEERS: usize> Link<PEERS> {
+    /// Creates a link for the board with `address`, without peers and the LED off
+    ///
+    /// Only boards that have the same `key` are accepted as peers. Start `seq` at a random number:
+    /// a peer remembers the last toggle we sent, and ignores a new one with the same number after
+    /// we restarted.
+    ///
+    /// `boot` has to be higher than at the previous start, e.g. a counter in flash. Our
+    /// announcements are numbered from zero at every start, and peers only accept them if they are
+    /// newer than the last one they got.
+    pub fn new(config: Config, address: Address, key: Key, seq: u16, boot: u32) -> Self {
+        Self {
+            config,
+            key,
+            address,
+            boot,
+            counter: 0,
+            peers: [None; PEERS],
+            forgotten: [None; PEERS],
+            next_forgotten: 0,
+            seq,
+            led: false,
+            announce_at: 0,
+        }
+    }
+
+    /// The state of our LED
+    pub fn led(&self) -> bool {
+        self.led
+    }
+
+    /// The addresses of the current peers
+    pub fn peers(&self) -> impl Iterator<Item = Address> + '_ {
+        self.peers.iter().flatten().map(|peer| peer.address)
+    }
+
+    /// Forgets the peer, e.g. because the radio couldn't register it
+    ///
+    /// It's discovered again with its next announcement.
+    pub fn forget(&mut self, address: Address) {
+        for index in 0..PEERS {
+            if self.peers[index].is_some_and(|peer| peer.address == address) {
+                self.remove(index);
+            }
+        }
+    }
+
+    /// Toggles the LED of every peer
+    ///
+    /// A toggle that wasn't acknowledged yet is replaced, the peer gets only the new one.
+    pub fn toggle_peers(&mut self, now: u64) {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        for peer in self.peers.iter_mut().flatten() {
+            peer.pending = Some(Pending {
+                seq,
+                attempts: 0,
+                send_at: now,
+            });
+        }
+    }
+
+    /// Handles a message from `from`, `broadcast` tells whether it was sent to everyone
+    ///
+    /// `now` is a monotonic timestamp in milliseconds. Answers are sent by [`poll`](Self::poll).
+    pub fn receive(
+        &mut self,
+        now: u64,
+        from: Address,
+        broadcast: bool,
+        data: &[u8],
+    ) -> Result<Option<Event>, Error> {
+        let message = Message::decode(data)?;
+        if let Message::Announce { boot, counter, tag } = message {
+            // anyone can broadcast, but only boards with the key can announce themselves
+            announce_mac(&self.key, from, boot, counter)
+                .verify_truncated_left(&tag)
+                .map_err(|_| Error::InvalidTag)?;
+            // and only once, a recording of the announcement is worthless
+            let number = (boot, counter);
+            if let Some(peer) = self.peer(from) {
+                if number <= peer.announced {
+                    return Err(Error::Replayed);
+                }
+                peer.announced = number;
+                peer.last_seen = now;
+                return Ok(None);
+            }
+            let forgotten = self
+                .forgotten
+                .iter()
+                .position(|slot| slot.is_some_and(|(address, _)| address == from));
+            if let Some(index) = forgotten {
+                if self.forgotten[index].is_some_and(|(_, announced)| number <= announced) {
+                    return Err(Error::Replayed);
+                }
+            }
+            let slot = self
+                .peers
+                .iter_mut()
+                .find(|slot| slot.is_none())
+                .ok_or(Error::TooManyPeers)?;
+            *slot = Some(Peer {
+                address: from,
+                last_seen: now,
+                announced: number,
+                received: None,
+                ack: None,
+                pending: None,
+            });
+            if let Some(index) = forgotten {
+                self.forgotten[index] = None;
+            }
+            // announce ourselves right away, so the new peer doesn't have to wait for us
+            self.announce_at = now;
+            return Ok(Some(Event::Discovered(from)));
+        }
+
+        // only announcements are broadcast, anything else could be from anyone
+        if broadcast {
+            return Err(Error::UnexpectedMessage);
+        }
+        let led = self.led;
+        let peer = self.peer(from).ok_or(Error::UnexpectedMessage)?;
+        peer.last_seen = now;
+        match message {
+            Message::Announce { .. } => Ok(None),
+            Message::Toggle { seq } => {
+                // the peer repeats the toggle until it gets our ack, which might have been lost
+                peer.ack = Some(seq);
+                if peer.received == Some(seq) {
+                    return Ok(None);
+                }
+                peer.received = Some(seq);
+                self.led = !led;
+                Ok(Some(Event::Toggled {
+                    from,
+                    led: self.led,
+                }))
+            }
+            Message::Ack { seq, led } => match peer.pending {
+                Some(pending) if pending.seq == seq => {
+                    peer.pending = None;
+                    Ok(Some(Event::Acknowledged { from, led }))
+                }
+                // the ack of a toggle that was replaced or that we gave up on
+                _ => Ok(None),
+            },
+        }
+    }
+
+    /// Returns what to do next, call this until it returns `None`
+    ///
+    /// `now` is a monotonic timestamp in milliseconds. Call this regularly, e.g. every time the
+    /// receive queue was checked.
+    pub fn poll(&mut self, now: u64) -> Option<Action> {
+        if now >= self.announce_at {
+            self.announce_at = now + self.config.announce_interval;
+            let announce = Message::announce(&self.key, self.address, self.boot, self.counter);
+            // at one announcement per second, that's more than a century
+            self.counter = self.counter.saturating_add(1);
+            return Some(Action::Broadcast(announce));
+        }
+
+        let led = self.led;
+        for index in 0..PEERS {
+            let Some(peer) = &mut self.peers[index] else {
+                continue;
+            };
+            if now.saturating_sub(peer.last_seen) >= self.config.peer_timeout {
+                let address = peer.address;
+                self.remove(index);
+                return Some(Action::Lost(address));
+            }
+            if let Some(seq) = peer.ack.take() {
+                return Some(Action::Send(peer.address, Message::Ack { seq, led }));
+            }
+            if let Some(pending) = &mut peer.pending {
+                if now < pending.send_at {
+                    continue;
+                }
+                let seq = pending.seq;
+                if pending.attempts >= self.config.max_attempts {
+                    peer.pending = None;
+                    return Some(Action::Unacknowledged(peer.address, seq));
+                }
+                pending.attempts += 1;
+                pending.send_at = now + self.config.retry_interval;
+                return Some(Action::Send(peer.address, Message::Toggle { seq }));
+            }
+        }
+        None
+    }
+
+    /// Removes the peer in `index`, its last announcement goes into `forgotten`
+    fn remove(&mut self, index: usize) {
+        if let Some(peer) = self.peers[index].take() {
+            self.forgotten[self.next_forgotten] = Some((peer.address, peer.announced));
+            self.next_forgotten = (self.next_forgotten + 1) % PEERS;
+        }
+    }
+
+    fn peer(&mut self, address: Address) -> Option<&mut Peer> {
+        self.peers
+            .iter_mut()
+            .flatten()
+            .find(|peer| peer.address == address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    const A: Address = [0x02, 0, 0, 0, 0, 0xa];
+    const B: Address = [0x02, 0, 0, 0, 0, 0xb];
+    const C: Address = [0x02, 0, 0, 0, 0, 0xc];
+    /// The board the link under test runs on
+    const US: Address = [0x02, 0, 0, 0, 0, 0x1];
+    const KEY: Key = *b"espnow-link-test";
+
+    fn config() -> Config {
+        Config {
+            announce_interval: 1_000,
+            peer_timeout: 3_000,
+            retry_interval: 50,
+            max_attempts: 3,
+        }
+    }
+
+    /// Everything `poll` asks for at `now`
+    fn drain<const PEERS: usize>(link: &mut Link<PEERS>, now: u64) -> Vec<Action> {
+        std::iter::from_fn(|| link.poll(now)).collect()
+    }
+
+    /// The announcement of `from` at `now`, the boards announce themselves at most once per
+    /// millisecond
+    fn announce<const PEERS: usize>(
+        link: &mut Link<PEERS>,
+        now: u64,
+        from: Address,
+    ) -> Result<Option<Event>, Error> {
+        let message = Message::announce(&KEY, from, 1, now as u32);
+        link.receive(now, from, true, &message.encode())
+    }
+
+    /// Our own `counter`th announcement
+    fn announced(counter: u32) -> Action {
+        Action::Broadcast(Message::announce(&KEY, US, 1, counter))
+    }
+
+    /// A link at time 10 that knows `B` and has sent its first two announcements
+    fn with_peer() -> Link<2> {
+        let mut link = Link::new(config(), US, KEY, 0, 1);
+        assert_eq!(drain(&mut link, 0), [announced(0)]);
+        assert_eq!(announce(&mut link, 10, B), Ok(Some(Event::Discovered(B))));
+        assert_eq!(drain(&mut link, 10), [announced(1)]);
+        link
+    }
+
+    #[test]
+    fn messages() {
+        for message in [
+            Message::announce(&KEY, A, 0, 0),
+            Message::announce(&KEY, A, u32::MAX, 0x0102_0304),
+            Message::Toggle { seq: 0 },
+            Message::Toggle { seq: 0xbeef },
+            Message::Ack {
+                seq: 0xffff,
+                led: true,
+            },
+            Message::Ack { seq: 1, led: false },
+        ] {
+            assert_eq!(Message::decode(&message.encode()), Ok(message));
+        }
+        assert_eq!(
+            Message::Ack {
+                seq: 0x0102,
+                led: true
+            }
+            .encode(),
+            *b"EL\x03\x02\x02\x01\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"
+        );
+        let Message::Announce { tag, .. } = Message::announce(&KEY, A, 0x0102_0304, 5) else {
+            unreachable!()
+        };
+        let mut announce = *b"EL\x03\x00\0\0\0\x04\x03\x02\x01\x05\0\0\0\0\0\0\0\0\0\0\0";
+        announce[15..].copy_from_slice(&tag);
+        assert_eq!(
+            Message::announce(&KEY, A, 0x0102_0304, 5).encode(),
+            announce
+        );
+        // ESP-NOW frames may be longer than what we sent
+        assert_eq!(
+            Message::decode(b"EL\x03\x01\x05\x00\x00\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+            Ok(Message::Toggle { seq: 5 })
+        );
+        // the tag depends on the key, the address and the number of the announcement
+        let tag = |message| match message {
+            Message::Announce { tag, .. } => tag,
+            _ => unreachable!(),
+        };
+        let announce = tag(Message::announce(&KEY, A, 1, 1));
+        assert_ne!(announce, tag(Message::announce(&KEY, B, 1, 1)));
+        assert_ne!(
+            announce,
+            tag(Message::announce(b"espnow-link-tesT", A, 1, 1))
+        );
+        assert_ne!(announce, tag(Message::announce(&KEY, A, 2, 1)));
+        assert_ne!(announce, tag(Message::announce(&KEY, A, 1, 2)));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert_eq!(Message::decode(b""), Err(Error::Truncated));
+        // the announcement of version 2 had no number
+        assert_eq!(
+            Message::decode(b"EL\x02\x00\x00\x00\x00\0\0\0\0\0\0\0\0"),
+            Err(Error::Truncated)
+        );
+        let mut message = Message::Toggle { seq: 1 }.encode();
+        message[0] = b'X';
+        assert_eq!(Message::decode(&message), Err(Error::InvalidMagic));
+        let mut message = Message::Toggle { seq: 1 }.encode();
+        message[2] = 2;
+        assert_eq!(Message::decode(&message), Err(Error::UnsupportedVersion(2)));
+        let mut message = Message::Toggle { seq: 1 }.encode();
+        message[3] = 3;
+        assert_eq!(Message::decode(&message), Err(Error::UnknownMessage(3)));
+    }
+
+    #[test]
+    fn derives_keys() {
+        let key = derive_key(b"no_std-train-pmk");
+        assert_eq!(key, derive_key(b"no_std-train-pmk"));
+        assert_ne!(&key, b"no_std-train-pmk");
+        assert_ne!(key, derive_key(b"no_std-train-pmK"));
+    }
+
+    #[test]
+    fn announces_regularly() {
+        let mut link: Link<2> = Link::new(config(), US, KEY, 0, 1);
+        assert_eq!(drain(&mut link, 0), [announced(0)]);
+        assert_eq!(drain(&mut link, 999), []);
+        assert_eq!(drain(&mut link, 1_000), [announced(1)]);
+        assert_eq!(drain(&mut link, 1_500), []);
+        // a new peer gets our announcement right away
+        assert_eq!(
+            announce(&mut link, 1_500, B),
+            Ok(Some(Event::Discovered(B)))
+        );
+        assert_eq!(drain(&mut link, 1_500), [announced(2)]);
+        assert_eq!(drain(&mut link, 2_000), []);
+        // the announcements of the next start are numbered from zero again
+        let mut link: Link<2> = Link::new(config(), US, KEY, 0, 2);
+        assert_eq!(
+            drain(&mut link, 0),
+            [Action::Broadcast(Message::announce(&KEY, US, 2, 0))]
+        );
+    }
+
+    #[test]
+    fn discovers_and_forgets_peers() {
+        let mut link = with_peer();
+        // known peers aren't discovered again
+        assert_eq!(announce(&mut link, 100, B), Ok(None));
+        assert_eq!(announce(&mut link, 100, C), Ok(Some(Event::Discovered(C))));
+        assert_eq!(announce(&mut link, 100, A), Err(Error::TooManyPeers));
+        assert_eq!(link.peers().collect::<Vec<_>>(), [B, C]);
+
+        // `C` stays quiet
+        assert_eq!(announce(&mut link, 2_000, B), Ok(None));
+        let outputs = drain(&mut link, 3_100);
+        assert!(outputs.contains(&Action::Lost(C)));
+        assert!(!outputs.contains(&Action::Lost(B)));
+        assert_eq!(link.peers().collect::<Vec<_>>(), [B]);
+
+        // which makes room for `A`
+        assert_eq!(
+            announce(&mut link, 3_100, A),
+            Ok(Some(Event::Discovered(A)))
+        );
+    }
+
+    #[test]
+    fn toggles_and_acknowledges() {
+        let mut sender = with_peer();
+        sender.toggle_peers(20);
+        let toggle = Message::Toggle { seq: 0 };
+        assert_eq!(drain(&mut sender, 20), [Action::Send(B, toggle)]);
+
+        let mut receiver: Link<2> = Link::new(config(), US, KEY, 0, 1);
+        announce(&mut receiver, 0, A).unwrap();
+        drain(&mut receiver, 0);
+        assert!(!receiver.led());
+        assert_eq!(
+            receiver.receive(25, A, false, &toggle.encode()),
+            Ok(Some(Event::Toggled { from: A, led: true }))
+        );
+        assert!(receiver.led());
+        let ack = Message::Ack { seq: 0, led: true };
+        assert_eq!(drain(&mut receiver, 25), [Action::Send(A, ack)]);
+
+        assert_eq!(
+            sender.receive(30, B, false, &ack.encode()),
+            Ok(Some(Event::Acknowledged { from: B, led: true }))
+        );
+        // nothing is repeated
+        assert_eq!(drain(&mut sender, 500), []);
+        // a late duplicate of the ack is ignored
+        assert_eq!(sender.receive(510, B, false, &ack.encode()), Ok(None));
+    }
+
+    #[test]
+    fn repeats_until_acknowledged() {
+        let mut link = with_peer();
+        link.toggle_peers(100);
+        let toggle = Message::Toggle { seq: 0 };
+        assert_eq!(drain(&mut link, 100), [Action::Send(B, toggle)]);
+        assert_eq!(drain(&mut link, 149), []);
+        assert_eq!(drain(&mut link, 150), [Action::Send(B, toggle)]);
+        assert_eq!(drain(&mut link, 200), [Action::Send(B, toggle)]);
+        assert_eq!(drain(&mut link, 250), [Action::Unacknowledged(B, 0)]);
+        assert_eq!(drain(&mut link, 300), []);
+    }
+
+    #[test]
+    fn toggles_once_per_press() {
+        let mut link = with_peer();
+        let toggle = Message::Toggle { seq: 7 }.encode();
+        assert_eq!(
+            link.receive(20, B, false, &toggle),
+            Ok(Some(Event::Toggled { from: B, led: true }))
+        );
+        // our ack got lost, the peer repeats the toggle
+        assert_eq!(link.receive(70, B, false, &toggle), Ok(None));
+        assert!(link.led());
+        let ack = Message::Ack { seq: 7, led: true };
+        assert_eq!(drain(&mut link, 70), [Action::Send(B, ack)]);
+
+        // the next press
+        let toggle = Message::Toggle { seq: 8 }.encode();
+        assert_eq!(
+            link.receive(500, B, false, &toggle),
+            Ok(Some(Event::Toggled {
+                from: B,
+                led: false
+            }))
+        );
+    }
+
+    #[test]
+    fn replaces_unacknowledged_toggles() {
+        let mut link = with_peer();
+        link.toggle_peers(20);
+        link.toggle_peers(30);
+        assert_eq!(
+            drain(&mut link, 30),
+            [Action::Send(B, Message::Toggle { seq: 1 })]
+        );
+        // the ack of the replaced toggle doesn't stop the new one
+        let ack = Message::Ack { seq: 0, led: true };
+        assert_eq!(link.receive(40, B, false, &ack.encode()), Ok(None));
+        assert_eq!(
+            drain(&mut link, 80),
+            [Action::Send(B, Message::Toggle { seq: 1 })]
+        );
+        // sequence numbers wrap around
+        let mut link: Link<1> = Link::new(config(), US, KEY, u16::MAX, 1);
+        announce(&mut link, 0, B).unwrap();
+        drain(&mut link, 0);
+        link.toggle_peers(0);
+        link.toggle_peers(100);
+        assert_eq!(
+            drain(&mut link, 100),
+            [Action::Send(B, Message::Toggle { seq: 0 })]
+        );
+    }
+
+    #[test]
+    fn rejects_messages_from_strangers() {
+        let mut link = with_peer();
+        let toggle = Message::Toggle { seq: 1 }.encode();
+        // toggles have to be sent to us, not to everyone
+        assert_eq!(
+            link.receive(20, B, true, &toggle),
+            Err(Error::UnexpectedMessage)
+        );
+        assert_eq!(
+            link.receive(20, C, false, &toggle),
+            Err(Error::UnexpectedMessage)
+        );
+        assert_eq!(link.receive(20, C, false, b"hello"), Err(Error::Truncated));
+        assert!(!link.led());
+        assert_eq!(drain(&mut link, 20), []);
+    }
+
+    #[test]
+    fn messages_keep_peers() {
+        let mut link = with_peer();
+        // no announcements from `B`, but acks
+        for now in (1_000..10_000).step_by(1_000) {
+            link.toggle_peers(now);
+            drain(&mut link, now);
+            let ack = Message::Ack {
+                seq: link.seq.wrapping_sub(1),
+                led: true,
+            };
+            link.receive(now + 5, B, false, &ack.encode()).unwrap();
+        }
+        assert_eq!(link.peers().collect::<Vec<_>>(), [B]);
+    }
+
+    #[test]
+    fn rejects_announcements_without_the_key() {
+        let mut link = with_peer();
+        let other_key = Message::announce(b"not the same key", C, 1, 20).encode();
+        assert_eq!(
+            link.receive(20, C, true, &other_key),
+            Err(Error::InvalidTag)
+        );
+        // a recorded announcement of `B` doesn't work from another address
+        let replayed = Message::announce(&KEY, B, 1, 20).encode();
+        assert_eq!(link.receive(20, C, true, &replayed), Err(Error::InvalidTag));
+        // neither does a forged one
+        let mut forged = Message::announce(&KEY, C, 1, 20).encode();
+        forged[MESSAGE_LEN - 1] ^= 1;
+        assert_eq!(link.receive(20, C, true, &forged), Err(Error::InvalidTag));
+        // nor one with another number
+        let mut renumbered = Message::announce(&KEY, C, 1, 20).encode();
+        renumbered[11] = 21;
+        assert_eq!(
+            link.receive(20, C, true, &renumbered),
+            Err(Error::InvalidTag)
+        );
+        // so there is still room for boards with the key
+        assert_eq!(link.peers().collect::<Vec<_>>(), [B]);
+        assert_eq!(announce(&mut link, 30, C), Ok(Some(Event::Discovered(C))));
+    }
+
+    #[test]
+    fn forgets_peers_on_request() {
+        let mut link = with_peer();
+        link.forget(C);
+        assert_eq!(link.peers().collect::<Vec<_>>(), [B]);
+        link.forget(B);
+        assert_eq!(link.peers().count(), 0);
+        // nothing to send to `B` anymore, but it's found again
+        link.toggle_peers(20);
+        assert_eq!(drain(&mut link, 20), []);
+        assert_eq!(announce(&mut link, 30, B), Ok(Some(Event::Discovered(B))));
+    }
+
+    #[test]
+    fn rejects_replayed_announcements() {
+        let mut link = with_peer();
+        let recorded = Message::announce(&KEY, B, 1, 10).encode();
+        assert_eq!(link.receive(20, B, true, &recorded), Err(Error::Replayed));
+        let older = Message::announce(&KEY, B, 1, 5).encode();
+        assert_eq!(link.receive(20, B, true, &older), Err(Error::Replayed));
+        // after a restart, `B` numbers its announcements from zero again
+        let restarted = Message::announce(&KEY, B, 2, 0).encode();
+        assert_eq!(link.receive(1_000, B, true, &restarted), Ok(None));
+        let before_restart = Message::announce(&KEY, B, 1, 2_000).encode();
+        assert_eq!(
+            link.receive(1_000, B, true, &before_restart),
+            Err(Error::Replayed)
+        );
+
+        // replays don't keep `B` around, it's lost once it stops announcing itself
+        assert!(drain(&mut link, 4_000).contains(&Action::Lost(B)));
+        // and they don't bring it back either
+        assert_eq!(
+            link.receive(4_000, B, true, &restarted),
+            Err(Error::Replayed)
+        );
+        assert_eq!(link.peers().count(), 0);
+        let next = Message::announce(&KEY, B, 2, 1).encode();
+        assert_eq!(
+            link.receive(4_000, B, true, &next),
+            Ok(Some(Event::Discovered(B)))
+        );
+
+        // a peer that couldn't be registered is remembered as well
+        link.forget(B);
+        assert_eq!(link.receive(4_010, B, true, &next), Err(Error::Replayed));
+    }
+}
@@ -0,0 +1,357 @@
+//! A small HTTP server: every socket serves one client after the other
+
+use alloc::{borrow::Cow, format};
+use core::fmt::Debug;
+use esp_hal::time::{Duration, Instant};
+use esp_println::println;
+use http_request::Request;
+use smoltcp::socket::tcp;
+
+// ANCHOR: response
+/// A response, the connection is closed after sending it
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    /// Additional header lines, each terminated by CRLF
+    pub headers: &'static str,
+    pub body: Cow<'static, str>,
+}
+
+impl Response {
+    pub fn new(
+        status: &'static str,
+        content_type: &'static str,
+        body: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            status,
+            content_type,
+            headers: "",
+            body: body.into(),
+        }
+    }
+
+    pub fn text(status: &'static str, body: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    /// Adds header lines, each terminated by CRLF
+    pub fn with_headers(self, headers: &'static str) -> Self {
+        Self { headers, ..self }
+    }
+}
+// ANCHOR_END: response
+
+/// A TCP socket as far as a [`Connection`] is concerned
+///
+/// It's implemented for the sockets of `smoltcp` and, with the `blocking-network-stack` feature,
+/// for the ones of `blocking-network-stack`.
+pub trait Socket {
+    type Error: Debug;
+
+    /// Sends and receives packets, for sockets that do that themselves
+    fn work(&mut self) {}
+
+    /// Listens for the next client, unless the socket is listening or connected already
+    fn listen(&mut self, port: u16) -> Result<(), Self::Error>;
+
+    /// A client connected
+    fn is_connected(&mut self) -> bool;
+
+    /// Reads what arrived without waiting for more, `None` once the client closed its end
+    fn read(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+
+    /// Queues `data` for sending, returns how much of it fit
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error>;
+
+    /// Closes our end, what was written is still sent
+    fn close(&mut self);
+
+    /// The client closed its end too, so it has the whole response
+    fn is_closed(&mut self) -> bool;
+
+    /// Drops the connection right away
+    fn abort(&mut self);
+}
+
+/// The errors of the `smoltcp` socket
+#[derive(Debug)]
+pub enum TcpError {
+    Listen(tcp::ListenError),
+    Recv(tcp::RecvError),
+    Send(tcp::SendError),
+}
+
+impl Socket for tcp::Socket<'_> {
+    type Error = TcpError;
+
+    fn listen(&mut self, port: u16) -> Result<(), TcpError> {
+        if !self.is_open() {
+            tcp::Socket::listen(self, port).map_err(TcpError::Listen)?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> bool {
+        self.may_recv()
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, TcpError> {
+        if !self.can_recv() {
+            return Ok(self.may_recv().then_some(0));
+        }
+        self.recv_slice(buffer).map(Some).map_err(TcpError::Recv)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TcpError> {
+        self.send_slice(data).map_err(TcpError::Send)
+    }
+
+    fn close(&mut self) {
+        tcp::Socket::close(self);
+    }
+
+    fn is_closed(&mut self) -> bool {
+        // both ends are done and the response was acknowledged
+        !self.is_open()
+    }
+
+    fn abort(&mut self) {
+        tcp::Socket::abort(self);
+    }
+}
+
+#[cfg(feature = "blocking-network-stack")]
+impl<D: smoltcp::phy::Device> Socket for blocking_network_stack::Socket<'_, '_, D> {
+    type Error = blocking_network_stack::IoError;
+
+    fn work(&mut self) {
+        blocking_network_stack::Socket::work(self);
+    }
+
+    fn listen(&mut self, port: u16) -> Result<(), Self::Error> {
+        if !self.is_open() {
+            self.listen_unblocking(port)?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> bool {
+        blocking_network_stack::Socket::is_connected(self)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        // `read` blocks until there is data, only call it if it returns right away
+        if !embedded_io::ReadReady::read_ready(self)? {
+            return Ok(Some(0));
+        }
+        embedded_io::Read::read(self, buffer).map(Some)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        // blocks until everything is sent, which is quick as it fits into the socket's buffer
+        embedded_io::Write::write_all(self, data)?;
+        embedded_io::Write::flush(self)?;
+        Ok(data.len())
+    }
+
+    fn close(&mut self) {
+        blocking_network_stack::Socket::close(self);
+    }
+
+    fn is_closed(&mut self) -> bool {
+        // `read_ready` fails once the client closed its end
+        embedded_io::ReadReady::read_ready(self).is_err()
+    }
+
+    fn abort(&mut self) {
+        // smoltcp keeps closed sockets around in `TIME-WAIT` for a while, aborting them makes
+        // them available right away
+        self.disconnect();
+    }
+}
+
+/// Everything that can go wrong with a client, the socket is ready for the next one afterwards
+#[derive(Debug)]
+pub enum ConnectionError<E> {
+    /// The socket reported an error
+    Socket(E),
+    /// The client didn't send its request in time
+    Timeout,
+    /// The client closed the connection before sending its request
+    Closed,
+    /// The response doesn't fit into the socket's buffer
+    ResponseTooLarge,
+}
+
+/// Timing of a [`Connection`]
+#[derive(Debug, Clone, Copy)]
+pub struct ServerConfig {
+    /// The port to listen on
+    pub port: u16,
+    /// Clients have this long to send their request
+    pub request_timeout: Duration,
+    /// Time the client gets to close its end after the response was sent
+    pub close_timeout: Duration,
+}
+
+// ANCHOR: connection
+/// What a connection is doing, every socket goes through these states over and over again
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Waiting for a client to connect
+    Listening,
+    /// Reading the request, the first `len` bytes of the buffer are filled
+    Reading { len: usize, deadline: Instant },
+    /// The response was sent, waiting for the client to close its end
+    Closing { deadline: Instant },
+}
+
+/// Serves the clients of one socket, requests with more than `N` bytes are rejected
+///
+/// `poll` never waits for the client, so a slow client doesn't hold up the others.
+pub struct Connection<const N: usize> {
+    config: ServerConfig,
+    state: State,
+    buffer: [u8; N],
+}
+
+impl<const N: usize> Connection<N> {
+    pub const fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            state: State::Listening,
+            buffer: [0; N],
+        }
+    }
+
+    /// Makes progress on the connection without waiting for the client
+    ///
+    /// Once the head and the body of a request arrived, `route` picks the response. Returns `true`
+    /// when the response was sent.
+    pub fn poll<S: Socket>(
+        &mut self,
+        socket: &mut S,
+        route: impl FnOnce(&Request<'_>, &[u8]) -> Response,
+    ) -> Result<bool, ConnectionError<S::Error>> {
+        socket.work();
+        let now = Instant::now();
+
+        match self.state {
+            State::Listening => {
+                socket
+                    .listen(self.config.port)
+                    .map_err(ConnectionError::Socket)?;
+                if socket.is_connected() {
+                    self.state = State::Reading {
+                        len: 0,
+                        deadline: now + self.config.request_timeout,
+                    };
+                }
+            }
+            State::Reading { len, deadline } => {
+                if now > deadline {
+                    return Err(ConnectionError::Timeout);
+                }
+                // `handle` answers once the buffer is full, so there is always room to read into
+                let read = socket
+                    .read(&mut self.buffer[len..])
+                    .map_err(ConnectionError::Socket)?
+                    .ok_or(ConnectionError::Closed)?;
+                if read == 0 {
+                    return Ok(false);
+                }
+                let len = len + read;
+
+                match self.handle(len, route) {
+                    Some(response) => {
+                        send(socket, &response)?;
+                        socket.close();
+                        self.state = State::Closing {
+                            deadline: now + self.config.close_timeout,
+                        };
+                        return Ok(true);
+                    }
+                    None => self.state = State::Reading { len, deadline },
+                }
+            }
+            State::Closing { deadline } => {
+                if now > deadline || socket.is_closed() {
+                    self.abort(socket);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Parses the first `len` bytes of the buffer, returns `None` if the request is incomplete
+    fn handle(
+        &self,
+        len: usize,
+        route: impl FnOnce(&Request<'_>, &[u8]) -> Response,
+    ) -> Option<Response> {
+        let response = match Request::parse(&self.buffer[..len]) {
+            Ok(Some(request)) => {
+                // a form is sent in the body, wait until it's complete
+                let end = request
+                    .content_length()
+                    .map(|body_len| request.head_len().saturating_add(body_len));
+                match end {
+                    Ok(end) if end > N => {
+                        Response::text("413 Content Too Large", "Request too large\n")
+                    }
+                    Ok(end) if end > len => return None,
+                    Ok(end) => {
+                        let response = route(&request, &self.buffer[request.head_len()..end]);
+                        println!(
+                            "{:?} {} -> {}",
+                            request.method, request.path, response.status
+                        );
+                        response
+                    }
+                    Err(err) => {
+                        println!("Bad request: {:?}", err);
+                        Response::text("400 Bad Request", "Bad request\n")
+                    }
+                }
+            }
+            Ok(None) if len == N => {
+                Response::text("431 Request Header Fields Too Large", "Request too large\n")
+            }
+            Ok(None) => return None,
+            Err(err) => {
+                println!("Bad request: {:?}", err);
+                Response::text("400 Bad Request", "Bad request\n")
+            }
+        };
+        Some(response)
+    }
+
+    /// Drops the connection, if any, and gets the socket ready for the next client
+    pub fn abort<S: Socket>(&mut self, socket: &mut S) {
+        socket.abort();
+        self.state = State::Listening;
+    }
+}
+// ANCHOR_END: connection
+
+// ANCHOR: send
+/// Queues the response, it has to fit into the socket's buffer
+fn send<S: Socket>(socket: &mut S, response: &Response) -> Result<(), ConnectionError<S::Error>> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.headers,
+    );
+    for part in [head.as_bytes(), response.body.as_bytes()] {
+        let written = socket.write(part).map_err(ConnectionError::Socket)?;
+        if written != part.len() {
+            return Err(ConnectionError::ResponseTooLarge);
+        }
+    }
+    Ok(())
+}
+// ANCHOR_END: send
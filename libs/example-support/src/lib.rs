@@ -0,0 +1,242 @@
+//! Glue shared by the examples.
+//!
+//! The examples handle errors the same way, and most of them keep a Wi-Fi station connected with
+//! the `Supervisor` of `wifi-supervisor` in the same way, too. Some of them keep settings in flash
+//! or serve web pages, also in the same way. That code lives here instead of in every example.
+//! Unlike the other libraries in `libs`, this one talks to the hardware, so it only builds for the
+//! board.
+//!
+//! The features pick what an example needs:
+//! - without features there is only [`restart`]
+//! - `wifi` adds `supervise`, `wait_for_ip` and `create_interface`
+//! - `blocking-network-stack` and `net-client` implement `Network` for their network stacks,
+//!   `blocking-network-stack` also adds `DnsServers`
+//! - `store` adds `open_store`, the functions for the Wi-Fi credentials in it and `count_boot`
+//! - `http-server` adds `Connection`, which serves HTTP requests on a TCP socket
+
+#![no_std]
+
+#[cfg(feature = "http-server")]
+extern crate alloc;
+
+use core::{convert::Infallible, fmt::Debug};
+use esp_hal::delay::Delay;
+use esp_println::println;
+
+#[cfg(feature = "http-server")]
+mod http;
+#[cfg(feature = "store")]
+mod store;
+
+#[cfg(feature = "http-server")]
+pub use http::*;
+#[cfg(feature = "store")]
+pub use store::*;
+#[cfg(feature = "wifi")]
+pub use wifi::*;
+
+/// Calls `run` over and over again, waiting 5 seconds after every error
+///
+/// `run` only returns if something went wrong, dropping everything it created shuts down the
+/// Wi-Fi driver so we can start over.
+pub fn restart<E: Debug>(mut run: impl FnMut() -> Result<Infallible, E>) -> ! {
+    loop {
+        let Err(err) = run();
+        println!("Error: {:?}, restarting in 5 seconds", err);
+        Delay::new().delay_millis(5_000);
+    }
+}
+
+#[cfg(feature = "wifi")]
+mod wifi {
+    use esp_hal::time;
+    use esp_println::println;
+    use esp_radio::wifi::{WifiController, WifiDevice};
+    use smoltcp::{
+        iface::{Config, Interface},
+        wire::{EthernetAddress, HardwareAddress, Ipv4Address},
+    };
+    use wifi_supervisor::{Action, Link, State, Supervisor};
+
+    /// The network stack on top of the station interface, as far as the supervisor is concerned
+    ///
+    /// It's implemented for mutable references, so [`supervise`] can borrow a network from a
+    /// caller who needs it afterwards.
+    pub trait Network {
+        /// Sends and receives packets
+        fn poll(&mut self);
+
+        /// The address we got by DHCP, if any
+        fn address(&self) -> Option<Ipv4Address>;
+
+        /// Forgets the address and starts DHCP over
+        fn restart_dhcp(&mut self);
+    }
+
+    impl<N: Network + ?Sized> Network for &mut N {
+        fn poll(&mut self) {
+            (**self).poll()
+        }
+
+        fn address(&self) -> Option<Ipv4Address> {
+            (**self).address()
+        }
+
+        fn restart_dhcp(&mut self) {
+            (**self).restart_dhcp()
+        }
+    }
+
+    // the sockets of a `Stack` borrow it, so everything works on shared references
+    #[cfg(feature = "blocking-network-stack")]
+    impl<D: smoltcp::phy::Device> Network for &blocking_network_stack::Stack<'_, D> {
+        fn poll(&mut self) {
+            self.work();
+        }
+
+        fn address(&self) -> Option<Ipv4Address> {
+            self.get_ip_info().ok().map(|info| info.ip)
+        }
+
+        fn restart_dhcp(&mut self) {
+            self.reset();
+        }
+    }
+
+    /// The DNS servers of the current lease, for the DNS socket of a `Stack`
+    ///
+    /// `Stack::configure_dns` borrows the storage for the queries, so it's called only once, with
+    /// no servers. A new lease after a reconnect might come with other servers, call
+    /// [`DnsServers::update`] after every [`wait_for_ip`] to switch to them.
+    #[cfg(feature = "blocking-network-stack")]
+    #[derive(Debug, Default)]
+    pub struct DnsServers {
+        current: [Option<Ipv4Address>; 2],
+    }
+
+    #[cfg(feature = "blocking-network-stack")]
+    impl DnsServers {
+        /// Hands the servers of the current lease to the DNS socket, if they changed
+        pub fn update<D: smoltcp::phy::Device>(
+            &mut self,
+            stack: &blocking_network_stack::Stack<'_, D>,
+        ) -> Result<(), blocking_network_stack::Error> {
+            let info = stack.get_ip_info()?;
+            let servers = [info.dns, info.secondary_dns];
+            if servers == self.current {
+                return Ok(());
+            }
+            let mut addresses = [smoltcp::wire::IpAddress::Ipv4(Ipv4Address::UNSPECIFIED); 2];
+            let mut len = 0;
+            for server in servers.into_iter().flatten() {
+                addresses[len] = server.into();
+                len += 1;
+            }
+            println!("Using DNS servers {:?}", &addresses[..len]);
+            stack.update_dns_servers(&addresses[..len]);
+            self.current = servers;
+            Ok(())
+        }
+    }
+
+    // ANCHOR: net_client
+    #[cfg(feature = "net-client")]
+    impl<D: smoltcp::phy::Device> Network for net_client::Network<'_, D> {
+        fn poll(&mut self) {
+            if let Some(event) = net_client::Network::poll(self, timestamp()) {
+                println!("Network: {:?}", event);
+            }
+        }
+
+        fn address(&self) -> Option<Ipv4Address> {
+            net_client::Network::address(self).map(|cidr| cidr.address())
+        }
+
+        fn restart_dhcp(&mut self) {
+            net_client::Network::reset(self);
+        }
+    }
+    // ANCHOR_END: net_client
+
+    /// Polls the network until the supervisor reports that we have an ip address
+    ///
+    /// The supervisor connects, waits for an ip address and reconnects whenever the link drops,
+    /// so this also waits for a reconnect if the link dropped in the meantime.
+    pub fn wait_for_ip(
+        controller: &mut WifiController<'_>,
+        mut network: impl Network,
+        supervisor: &mut Supervisor,
+    ) {
+        loop {
+            supervise(controller, &mut network, supervisor);
+            if supervisor.is_up() {
+                break;
+            }
+        }
+    }
+
+    /// Polls the network and carries out what the supervisor asks for
+    pub fn supervise(
+        controller: &mut WifiController<'_>,
+        mut network: impl Network,
+        supervisor: &mut Supervisor,
+    ) {
+        network.poll();
+
+        let link = Link {
+            connected: controller.is_connected().unwrap_or(false),
+            has_ip: network.address().is_some(),
+        };
+        let now = time::Instant::now().duration_since_epoch().as_millis();
+        let previous = supervisor.state();
+
+        match supervisor.update(now, link) {
+            Action::Connect => {
+                if let Err(err) = controller.connect() {
+                    println!("wifi_connect failed: {:?}", err);
+                    supervisor.connect_failed(now);
+                }
+            }
+            Action::Disconnect => {
+                controller.disconnect().ok();
+            }
+            // a new connection might be to a different network, don't keep the old lease
+            Action::RestartDhcp => network.restart_dhcp(),
+            Action::None => {}
+        }
+
+        if supervisor.state() != previous {
+            match supervisor.state() {
+                State::Connecting => println!("Wait to get connected"),
+                State::Connected => println!("Wait to get an ip address"),
+                State::GotIp => {
+                    if let Some(address) = network.address() {
+                        println!("got ip {}", address);
+                    }
+                }
+                State::Disconnected => println!("Wifi disconnected, retrying"),
+                State::Started => {}
+            }
+        }
+    }
+
+    /// The current time for smoltcp
+    pub fn timestamp() -> smoltcp::time::Instant {
+        smoltcp::time::Instant::from_micros(
+            time::Instant::now().duration_since_epoch().as_micros() as i64
+        )
+    }
+
+    /// Creates a smoltcp interface with the MAC address of `device`
+    pub fn create_interface(device: &mut WifiDevice<'_>) -> Interface {
+        // users could create multiple instances but since they only have one WifiDevice
+        // they probably can't do anything bad with that
+        Interface::new(
+            Config::new(HardwareAddress::Ethernet(EthernetAddress::from_bytes(
+                &device.mac_address(),
+            ))),
+            device,
+            timestamp(),
+        )
+    }
+}
@@ -0,0 +1,117 @@
+//! The key-value store in the NVS partition, and the Wi-Fi credentials and boot counter in it
+
+use esp_bootloader_esp_idf::partitions::{
+    self, DataPartitionSubType, PartitionType, PARTITION_TABLE_MAX_LEN,
+};
+use esp_println::println;
+use esp_storage::{FlashStorage, FlashStorageError};
+use kv_store::Store;
+use wifi_credentials::{Credentials, RECORD_LEN};
+
+/// The key-value store in the NVS partition
+pub type Settings<'d> = Store<FlashStorage<'d>>;
+
+/// The key the credentials are stored under, `provisioning` and `http-client` share them
+pub const CREDENTIALS_KEY: &str = "wifi";
+
+/// The key the boot counter is stored under, see [`count_boot`]
+pub const BOOT_KEY: &str = "boot";
+
+/// Errors returned by [`open_store`] and the functions reading and writing the store
+#[derive(Debug)]
+pub enum StoreError {
+    /// The partition table couldn't be read
+    Partitions(partitions::Error),
+    /// The partition table has no NVS partition for the store
+    NoPartition,
+    /// The key-value store in the NVS partition couldn't be read or written
+    Store(kv_store::Error<FlashStorageError>),
+    /// The stored credentials are invalid
+    Credentials(wifi_credentials::Error),
+    /// The stored boot counter isn't a `u32`
+    BootCounter,
+}
+
+impl From<partitions::Error> for StoreError {
+    fn from(err: partitions::Error) -> Self {
+        Self::Partitions(err)
+    }
+}
+
+impl From<kv_store::Error<FlashStorageError>> for StoreError {
+    fn from(err: kv_store::Error<FlashStorageError>) -> Self {
+        Self::Store(err)
+    }
+}
+
+impl From<wifi_credentials::Error> for StoreError {
+    fn from(err: wifi_credentials::Error) -> Self {
+        Self::Credentials(err)
+    }
+}
+
+// ANCHOR: open_store
+/// Opens the store in the NVS partition, formatting the partition if it holds anything else
+///
+/// Neither `esp-radio` nor the examples use the NVS format of ESP-IDF, so the partition is ours.
+/// `espflash flash` leaves it alone, what is stored survives flashing a new firmware.
+pub fn open_store(mut flash: FlashStorage<'_>) -> Result<Settings<'_>, StoreError> {
+    let mut table = [0; PARTITION_TABLE_MAX_LEN];
+    let partition = partitions::read_partition_table(&mut flash, &mut table)?
+        .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))?
+        .ok_or(StoreError::NoPartition)?;
+    let range = partition.offset()..partition.offset() + partition.len();
+
+    // mounting only reads and cleans up, find out first whether formatting would erase anything
+    if let Err(kv_store::Error::NotFormatted) = Store::mount(&mut flash, range.clone()) {
+        println!(
+            "The NVS partition at {:#x} holds something else, erasing it for the store",
+            range.start
+        );
+        return Ok(Store::format(flash, range)?);
+    }
+    Ok(Store::mount(flash, range)?)
+}
+// ANCHOR_END: open_store
+
+// ANCHOR: credentials
+/// Reads the stored credentials, returns `None` if none were stored yet
+pub fn load_credentials(store: &mut Settings<'_>) -> Result<Option<Credentials>, StoreError> {
+    let mut record = [0; RECORD_LEN];
+    match store.get(CREDENTIALS_KEY, &mut record)? {
+        Some(record) => Ok(Credentials::decode(record)?),
+        None => Ok(None),
+    }
+}
+
+/// Stores the credentials, replacing the previous ones
+///
+/// Nothing is written if they are stored already.
+pub fn save_credentials(
+    store: &mut Settings<'_>,
+    credentials: &Credentials,
+) -> Result<(), StoreError> {
+    Ok(store.set(CREDENTIALS_KEY, &credentials.encode())?)
+}
+
+/// Deletes the stored credentials
+pub fn erase_credentials(store: &mut Settings<'_>) -> Result<(), StoreError> {
+    Ok(store.remove(CREDENTIALS_KEY)?)
+}
+// ANCHOR_END: credentials
+
+/// Counts this start in the store, returns how many starts there were, this one included
+///
+/// Unlike the time since the start, the count tells a later start from an earlier one, even across
+/// power cycles.
+pub fn count_boot(store: &mut Settings<'_>) -> Result<u32, StoreError> {
+    let mut record = [0; 4];
+    let boots = match store.get(BOOT_KEY, &mut record)? {
+        Some(record) => u32::from_le_bytes(record.try_into().map_err(|_| StoreError::BootCounter)?),
+        None => 0,
+    };
+    // a board restarting every second takes more than a century to get there
+    let boots = boots.saturating_add(1);
+    store.set(BOOT_KEY, &boots.to_le_bytes())?;
+    Ok(boots)
+}
@@ -0,0 +1,517 @@
+//! HTTP/1.1 request head parser for servers.
+//!
+//! Requests sent to a device are small, so unlike `http-response` this parser doesn't work
+//! incrementally: read from the socket into a buffer and call [`Request::parse`] after every read
+//! until the whole head (the request line and the headers) has arrived. Nothing is copied, the
+//! [`Request`] borrows from the buffer.
+//!
+//! ```
+//! use http_request::{Method, Request};
+//!
+//! let buffer = b"POST /api/led?state=on HTTP/1.1\r\nHost: esp\r\nContent-Length: 2\r\n\r\nhi";
+//!
+//! // the head is incomplete, read more data
+//! assert_eq!(Request::parse(&buffer[..20]), Ok(None));
+//!
+//! let request = Request::parse(buffer).unwrap().unwrap();
+//! assert_eq!(request.method, Method::Post);
+//! assert_eq!(request.path, "/api/led");
+//! assert_eq!(request.query, Some("state=on"));
+//! assert_eq!(request.header("host"), Some(&b"esp"[..]));
+//! assert_eq!(request.content_length(), Ok(2));
+//!
+//! // the body starts right after the head
+//! assert_eq!(&buffer[request.head_len()..], b"hi");
+//! ```
+//!
+//! Request bodies with `Transfer-Encoding` are not supported, [`Request::content_length`] reports
+//! them as [`Error::UnsupportedTransferEncoding`].
+//!
+//! HTML forms are sent as `application/x-www-form-urlencoded` bodies, [`form_fields`] splits them
+//! and [`decode_form_value`] decodes the values:
+//!
+//! ```
+//! use http_request::{decode_form_value, form_fields};
+//!
+//! let body = b"ssid=My+Network&password=caf%C3%A9+au+lait";
+//! let (name, value) = form_fields(body).nth(1).unwrap();
+//! assert_eq!(name, b"password");
+//!
+//! let mut buffer = [0; 64];
+//! assert_eq!(decode_form_value(value, &mut buffer), Ok("café au lait"));
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// HTTP version of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+/// Request method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    /// Any other (syntactically valid) method, servers usually answer `501 Not Implemented`
+    Other,
+}
+
+impl Method {
+    fn parse(token: &[u8]) -> Self {
+        match token {
+            b"GET" => Self::Get,
+            b"HEAD" => Self::Head,
+            b"POST" => Self::Post,
+            b"PUT" => Self::Put,
+            b"DELETE" => Self::Delete,
+            b"PATCH" => Self::Patch,
+            b"OPTIONS" => Self::Options,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A single header field
+///
+/// Header values are not guaranteed to be valid UTF-8, so they are handed out as bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// Errors returned by the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The request line is malformed or uses an unsupported HTTP version
+    InvalidRequestLine,
+    /// A header line is malformed
+    InvalidHeader,
+    /// `Content-Length` is not a number or there are conflicting values
+    InvalidContentLength,
+    /// The request uses `Transfer-Encoding`, which is not supported
+    UnsupportedTransferEncoding,
+    /// A form value has an invalid percent escape or isn't UTF-8 once decoded
+    InvalidFormValue,
+    /// The decoded form value doesn't fit into the buffer
+    FormValueTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The head of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Request<'a> {
+    pub method: Method,
+    /// The path of the request target, without the query
+    pub path: &'a str,
+    /// The query of the request target, without the `?`
+    pub query: Option<&'a str>,
+    pub version: Version,
+    /// The header lines, each terminated by CRLF, already validated
+    headers: &'a [u8],
+    head_len: usize,
+}
+
+impl<'a> Request<'a> {
+    /// Parses the head of the request at the start of `buffer`
+    ///
+    /// Returns `None` if the head isn't complete yet. Data following the head (the body, if any)
+    /// is ignored, it starts at [`head_len`](Self::head_len).
+    pub fn parse(buffer: &'a [u8]) -> Result<Option<Self>, Error> {
+        let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") else {
+            return Ok(None);
+        };
+        let head = &buffer[..end + 2];
+
+        let line_end = head
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .unwrap_or(head.len());
+        let (method, path, query, version) = parse_request_line(&head[..line_end])?;
+
+        let headers = &head[line_end + 2..];
+        for line in lines(headers) {
+            parse_header(line)?;
+        }
+
+        Ok(Some(Self {
+            method,
+            path,
+            query,
+            version,
+            headers,
+            head_len: end + 4,
+        }))
+    }
+
+    /// The length of the head, including the empty line that terminates it
+    pub fn head_len(&self) -> usize {
+        self.head_len
+    }
+
+    /// Iterates over all header fields in the order they were sent
+    pub fn headers(&self) -> impl Iterator<Item = Header<'a>> + 'a {
+        lines(self.headers).filter_map(|line| parse_header(line).ok())
+    }
+
+    /// Returns the value of the first header field called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&'a [u8]> {
+        self.headers()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value)
+    }
+
+    /// Returns the length of the body, which is 0 if there is no `Content-Length` header
+    pub fn content_length(&self) -> Result<usize, Error> {
+        let mut content_length = None;
+        for header in self.headers() {
+            if header.name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(Error::UnsupportedTransferEncoding);
+            }
+            if header.name.eq_ignore_ascii_case("content-length") {
+                let len = parse_content_length(header.value)?;
+                if content_length.is_some_and(|previous| previous != len) {
+                    return Err(Error::InvalidContentLength);
+                }
+                content_length = Some(len);
+            }
+        }
+        Ok(content_length.unwrap_or(0))
+    }
+}
+
+/// Splits an `application/x-www-form-urlencoded` body, or a query, into names and values
+///
+/// Both are still encoded, see [`decode_form_value`]. A field without `=` has an empty value.
+pub fn form_fields(body: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    body.split(|&b| b == b'&')
+        .filter(|field| !field.is_empty())
+        .map(|field| match field.iter().position(|&b| b == b'=') {
+            Some(equals) => (&field[..equals], &field[equals + 1..]),
+            None => (field, &field[field.len()..]),
+        })
+}
+
+/// Decodes a form value into `buffer`, `+` is a space and `%XX` the byte `XX`
+pub fn decode_form_value<'b>(value: &[u8], buffer: &'b mut [u8]) -> Result<&'b str, Error> {
+    let mut len = 0;
+    let mut bytes = value.iter();
+    while let Some(&b) = bytes.next() {
+        let decoded = match b {
+            b'+' => b' ',
+            b'%' => {
+                let (Some(high), Some(low)) = (bytes.next(), bytes.next()) else {
+                    return Err(Error::InvalidFormValue);
+                };
+                let digit = |b: &u8| (*b as char).to_digit(16).ok_or(Error::InvalidFormValue);
+                (digit(high)? * 16 + digit(low)?) as u8
+            }
+            _ => b,
+        };
+        *buffer.get_mut(len).ok_or(Error::FormValueTooLong)? = decoded;
+        len += 1;
+    }
+    core::str::from_utf8(&buffer[..len]).map_err(|_| Error::InvalidFormValue)
+}
+
+/// Splits CRLF terminated lines
+fn lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn parse_request_line(line: &[u8]) -> Result<(Method, &str, Option<&str>, Version), Error> {
+    let line = core::str::from_utf8(line).map_err(|_| Error::InvalidRequestLine)?;
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Error::InvalidRequestLine);
+    };
+
+    if method.is_empty() || !method.bytes().all(|b| is_token(&b)) {
+        return Err(Error::InvalidRequestLine);
+    }
+
+    // only the origin form (`/path?query`) is used when talking to a server directly
+    if !target.starts_with('/') || target.bytes().any(|b| !b.is_ascii_graphic()) {
+        return Err(Error::InvalidRequestLine);
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+
+    let version = match version {
+        "HTTP/1.0" => Version::Http10,
+        "HTTP/1.1" => Version::Http11,
+        _ => return Err(Error::InvalidRequestLine),
+    };
+
+    Ok((Method::parse(method.as_bytes()), path, query, version))
+}
+
+fn parse_header(line: &[u8]) -> Result<Header<'_>, Error> {
+    let colon = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or(Error::InvalidHeader)?;
+
+    // header names are tokens, which are ASCII
+    if colon == 0 || !line[..colon].iter().all(is_token) {
+        return Err(Error::InvalidHeader);
+    }
+    let name = core::str::from_utf8(&line[..colon]).map_err(|_| Error::InvalidHeader)?;
+
+    Ok(Header {
+        name,
+        value: trim(&line[colon + 1..]),
+    })
+}
+
+fn is_token(b: &u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(b)
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let is_ws = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = bytes.iter().position(|b| !is_ws(b)).unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !is_ws(b))
+        .map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+fn parse_content_length(value: &[u8]) -> Result<usize, Error> {
+    if value.is_empty() {
+        return Err(Error::InvalidContentLength);
+    }
+    value.iter().try_fold(0usize, |len, &b| {
+        if !b.is_ascii_digit() {
+            return Err(Error::InvalidContentLength);
+        }
+        len.checked_mul(10)
+            .and_then(|len| len.checked_add(usize::from(b - b'0')))
+            .ok_or(Error::InvalidContentLength)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(buffer: &[u8]) -> Request<'_> {
+        Request::parse(buffer).unwrap().unwrap()
+    }
+
+    #[test]
+    fn get() {
+        let request = parse(b"GET / HTTP/1.1\r\nHost: esp\r\n\r\n");
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/");
+        assert_eq!(request.query, None);
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.content_length(), Ok(0));
+    }
+
+    #[test]
+    fn incomplete() {
+        let buffer = b"GET / HTTP/1.1\r\nHost: esp\r\n\r\n";
+        for len in 0..buffer.len() {
+            assert_eq!(Request::parse(&buffer[..len]), Ok(None), "{len}");
+        }
+    }
+
+    #[test]
+    fn no_headers() {
+        let request = parse(b"GET /index.html HTTP/1.0\r\n\r\n");
+        assert_eq!(request.path, "/index.html");
+        assert_eq!(request.version, Version::Http10);
+        assert_eq!(request.headers().count(), 0);
+        assert_eq!(request.head_len(), 28);
+    }
+
+    #[test]
+    fn query() {
+        let request = parse(b"GET /search?q=rust&page=2 HTTP/1.1\r\n\r\n");
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.query, Some("q=rust&page=2"));
+
+        let request = parse(b"GET /search? HTTP/1.1\r\n\r\n");
+        assert_eq!(request.query, Some(""));
+    }
+
+    #[test]
+    fn methods() {
+        let method = |line: &str| {
+            let mut buffer = [0; 64];
+            let len = line.len();
+            buffer[..len].copy_from_slice(line.as_bytes());
+            buffer[len..len + 4].copy_from_slice(b"\r\n\r\n");
+            Request::parse(&buffer[..len + 4]).unwrap().unwrap().method
+        };
+        assert_eq!(method("HEAD / HTTP/1.1"), Method::Head);
+        assert_eq!(method("PUT / HTTP/1.1"), Method::Put);
+        assert_eq!(method("DELETE / HTTP/1.1"), Method::Delete);
+        assert_eq!(method("PATCH / HTTP/1.1"), Method::Patch);
+        assert_eq!(method("OPTIONS / HTTP/1.1"), Method::Options);
+        assert_eq!(method("BREW / HTTP/1.1"), Method::Other);
+        // methods are case sensitive
+        assert_eq!(method("get / HTTP/1.1"), Method::Other);
+    }
+
+    #[test]
+    fn headers() {
+        let request =
+            parse(b"GET / HTTP/1.1\r\nHost: esp\r\nAccept:  text/html \r\nX-Empty:\r\n\r\n");
+        let mut headers = request.headers();
+        assert_eq!(
+            headers.next(),
+            Some(Header {
+                name: "Host",
+                value: b"esp"
+            })
+        );
+        assert_eq!(
+            headers.next(),
+            Some(Header {
+                name: "Accept",
+                value: b"text/html"
+            })
+        );
+        assert_eq!(
+            headers.next(),
+            Some(Header {
+                name: "X-Empty",
+                value: b""
+            })
+        );
+        assert_eq!(headers.next(), None);
+
+        assert_eq!(request.header("accept"), Some(&b"text/html"[..]));
+        assert_eq!(request.header("cookie"), None);
+    }
+
+    #[test]
+    fn body_follows_head() {
+        let buffer = b"POST /api HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let request = parse(buffer);
+        assert_eq!(request.content_length(), Ok(5));
+        assert_eq!(&buffer[request.head_len()..], b"hello");
+    }
+
+    #[test]
+    fn content_length() {
+        let content_length = |buffer: &[u8]| parse(buffer).content_length();
+        assert_eq!(
+            content_length(b"POST / HTTP/1.1\r\nContent-Length: 3\r\ncontent-length: 3\r\n\r\n"),
+            Ok(3)
+        );
+        assert_eq!(
+            content_length(b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n"),
+            Err(Error::InvalidContentLength)
+        );
+        assert_eq!(
+            content_length(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            Err(Error::InvalidContentLength)
+        );
+        assert_eq!(
+            content_length(b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n"),
+            Err(Error::InvalidContentLength)
+        );
+        assert_eq!(
+            content_length(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            Err(Error::UnsupportedTransferEncoding)
+        );
+    }
+
+    #[test]
+    fn form() {
+        let mut fields = form_fields(b"ssid=home&&password=&flag&a=b=c");
+        assert_eq!(fields.next(), Some((&b"ssid"[..], &b"home"[..])));
+        assert_eq!(fields.next(), Some((&b"password"[..], &b""[..])));
+        assert_eq!(fields.next(), Some((&b"flag"[..], &b""[..])));
+        assert_eq!(fields.next(), Some((&b"a"[..], &b"b=c"[..])));
+        assert_eq!(fields.next(), None);
+        assert_eq!(form_fields(b"").count(), 0);
+    }
+
+    #[test]
+    fn form_values() {
+        let mut buffer = [0; 16];
+        let mut decode = |value: &[u8]| decode_form_value(value, &mut buffer).map(|s| s.len());
+        assert_eq!(decode(b"a+b%20c%2b%2F"), Ok(7));
+        assert_eq!(decode(b""), Ok(0));
+        assert_eq!(decode(b"%"), Err(Error::InvalidFormValue));
+        assert_eq!(decode(b"%4"), Err(Error::InvalidFormValue));
+        assert_eq!(decode(b"%zz"), Err(Error::InvalidFormValue));
+        // a lone continuation byte isn't UTF-8
+        assert_eq!(decode(b"%80"), Err(Error::InvalidFormValue));
+        assert_eq!(decode(&[b'x'; 16]), Ok(16));
+        assert_eq!(decode(&[b'x'; 17]), Err(Error::FormValueTooLong));
+
+        let mut buffer = [0; 16];
+        assert_eq!(
+            decode_form_value(b"a+b%20c%2b%2F", &mut buffer),
+            Ok("a b c+/")
+        );
+    }
+
+    #[test]
+    fn invalid_request_line() {
+        for buffer in [
+            &b"\r\n\r\n"[..],
+            b"GET\r\n\r\n",
+            b"GET /\r\n\r\n",
+            b"GET / HTTP/2\r\n\r\n",
+            b"GET  / HTTP/1.1\r\n\r\n",
+            b"GET / HTTP/1.1 \r\n\r\n",
+            b"GET http://example.com/ HTTP/1.1\r\n\r\n",
+            b"G(T / HTTP/1.1\r\n\r\n",
+            b"GET /\xff HTTP/1.1\r\n\r\n",
+        ] {
+            assert_eq!(
+                Request::parse(buffer),
+                Err(Error::InvalidRequestLine),
+                "{:?}",
+                core::str::from_utf8(buffer)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_header() {
+        for buffer in [
+            &b"GET / HTTP/1.1\r\nHost\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\n: esp\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHo st: esp\r\n\r\n",
+        ] {
+            assert_eq!(Request::parse(buffer), Err(Error::InvalidHeader));
+        }
+    }
+}
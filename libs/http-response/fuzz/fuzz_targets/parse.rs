@@ -0,0 +1,15 @@
+//! Feeds arbitrary data to the parser, it must never panic or loop forever.
+
+#![no_main]
+
+use http_response::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut parser = Parser::<64>::new();
+    let mut data = data;
+    while let Ok((consumed, Some(_))) = parser.parse(data) {
+        data = &data[consumed..];
+    }
+    parser.finish().ok();
+});
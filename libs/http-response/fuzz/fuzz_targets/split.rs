@@ -0,0 +1,54 @@
+//! Checks that the parser reports the same response no matter how the data is split into chunks.
+
+#![no_main]
+
+use http_response::{Event, Parser};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, PartialEq)]
+enum Owned {
+    Status(u16, String),
+    Header(String, Vec<u8>),
+    HeadersEnd,
+    Body(Vec<u8>),
+    End,
+}
+
+/// Parses `data` in chunks of `chunk` bytes, merging consecutive body events
+fn parse(data: &[u8], chunk: usize) -> (Vec<Owned>, bool) {
+    let mut parser = Parser::<64>::new();
+    let mut events = Vec::new();
+    for mut data in data.chunks(chunk).chain([&[][..]]) {
+        loop {
+            let (consumed, event) = match parser.parse(data) {
+                Ok(result) => result,
+                Err(_) => return (events, false),
+            };
+            data = &data[consumed..];
+            let event = match event {
+                Some(Event::Status(status)) => Owned::Status(status.code, status.reason.into()),
+                Some(Event::Header(header)) => {
+                    Owned::Header(header.name.into(), header.value.into())
+                }
+                Some(Event::HeadersEnd) => Owned::HeadersEnd,
+                Some(Event::Body(body)) => {
+                    if let Some(Owned::Body(previous)) = events.last_mut() {
+                        previous.extend_from_slice(body);
+                        continue;
+                    }
+                    Owned::Body(body.into())
+                }
+                Some(Event::End) => Owned::End,
+                None => break,
+            };
+            events.push(event);
+        }
+    }
+    (events, parser.finish().is_ok())
+}
+
+fuzz_target!(|input: (u8, &[u8])| {
+    let (chunk, data) = input;
+    let chunk = usize::from(chunk).max(1);
+    assert_eq!(parse(data, data.len().max(1)), parse(data, chunk));
+});
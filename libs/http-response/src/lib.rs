@@ -0,0 +1,820 @@
+//! Incremental HTTP/1.1 response parser.
+//!
+//! The parser does not allocate and does not need the whole response in memory: data is fed in
+//! whatever chunks the socket hands out and the parser emits [`Event`]s for the status line, every
+//! header, the body data and the end of the response.
+//!
+//! ```
+//! use http_response::{Event, Parser};
+//!
+//! let mut parser = Parser::<128>::new();
+//! let mut data: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+//!
+//! loop {
+//!     let (consumed, event) = parser.parse(data).unwrap();
+//!     data = &data[consumed..];
+//!     match event {
+//!         Some(Event::Status(status)) => assert_eq!(status.code, 200),
+//!         Some(Event::Body(body)) => assert_eq!(body, b"hi"),
+//!         Some(_) => {}
+//!         // all data was consumed, wait for the next chunk
+//!         None => break,
+//!     }
+//! }
+//! assert!(parser.is_complete());
+//! ```
+//!
+//! The body is delimited by (in this order) the status code, `Transfer-Encoding: chunked`,
+//! `Content-Length` or the server closing the connection. In the last case the application has
+//! to call [`Parser::finish`] once the connection is closed.
+//!
+//! Responses to `HEAD` requests never have a body, use [`Parser::new_head`] for them.
+
+#![no_std]
+
+use core::{fmt, ops::Range};
+
+/// HTTP version of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+/// The status line of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status<'a> {
+    pub version: Version,
+    pub code: u16,
+    pub reason: &'a str,
+}
+
+/// A single header field
+///
+/// Header values are not guaranteed to be valid UTF-8, so they are handed out as bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// Something the parser found in the data fed to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event<'a> {
+    /// The status line was parsed
+    Status(Status<'a>),
+    /// A header field was parsed
+    Header(Header<'a>),
+    /// All headers were parsed, body data follows (if any)
+    HeadersEnd,
+    /// A piece of the body, with any chunked transfer encoding already removed
+    Body(&'a [u8]),
+    /// The response is complete
+    End,
+}
+
+/// Errors returned by the parser
+///
+/// After an error the parser stays in the error state until it is [`reset`](Parser::reset).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The status line, a header line or a chunk header doesn't fit into the line buffer
+    LineTooLong,
+    /// The status line is malformed
+    InvalidStatusLine,
+    /// A header line is malformed
+    InvalidHeader,
+    /// `Content-Length` is not a number or there are conflicting values
+    InvalidContentLength,
+    /// A chunk header or the line after the chunk data is malformed
+    InvalidChunk,
+    /// The connection was closed before the response was complete
+    UnexpectedEof,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    StatusLine,
+    Headers,
+    /// The empty line after the headers was parsed, decide how the body is delimited
+    HeadersEnd,
+    /// `Content-Length` body with the given number of bytes left
+    Body(u64),
+    /// Body until the connection is closed
+    BodyUntilClose,
+    ChunkSize,
+    /// Chunk data with the given number of bytes left
+    ChunkData(u64),
+    /// The line break after the chunk data
+    ChunkDataEnd,
+    Trailers,
+    /// Emit [`Event::End`] on the next call
+    End,
+    Done,
+    Error(Error),
+}
+
+/// Incremental HTTP/1.1 response parser
+///
+/// `N` is the size of the buffer used to collect the status line, header lines and chunk headers.
+/// Lines longer than that are rejected with [`Error::LineTooLong`].
+pub struct Parser<const N: usize> {
+    state: State,
+    line: [u8; N],
+    line_len: usize,
+    head: bool,
+    status: u16,
+    content_length: Option<u64>,
+    chunked: bool,
+}
+
+impl<const N: usize> Default for Parser<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Parser<N> {
+    /// Creates a parser for the response to a request
+    pub const fn new() -> Self {
+        Self {
+            state: State::StatusLine,
+            line: [0; N],
+            line_len: 0,
+            head: false,
+            status: 0,
+            content_length: None,
+            chunked: false,
+        }
+    }
+
+    /// Creates a parser for the response to a `HEAD` request, which never has a body
+    pub const fn new_head() -> Self {
+        let mut parser = Self::new();
+        parser.head = true;
+        parser
+    }
+
+    /// Resets the parser so it can be used for the next response
+    pub fn reset(&mut self) {
+        *self = Self {
+            head: self.head,
+            ..Self::new()
+        };
+    }
+
+    /// Returns `true` once the whole response was parsed
+    pub fn is_complete(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Returns the status code, or `None` if the status line wasn't parsed yet
+    pub fn status(&self) -> Option<u16> {
+        (self.status != 0).then_some(self.status)
+    }
+
+    /// Parses the next event from `input`
+    ///
+    /// Returns the number of bytes consumed from `input` and the event, if one was found. Call it
+    /// again with the remaining input until it returns no event, which means all of `input` was
+    /// consumed (or the response is complete) and more data is needed.
+    pub fn parse<'a>(&'a mut self, input: &'a [u8]) -> Result<(usize, Option<Event<'a>>), Error> {
+        let mut consumed = 0;
+        loop {
+            let rest = &input[consumed..];
+            match self.state {
+                State::StatusLine
+                | State::Headers
+                | State::ChunkSize
+                | State::ChunkDataEnd
+                | State::Trailers => {
+                    let (len, end) = self.collect_line(rest)?;
+                    consumed += len;
+                    let Some(end) = end else {
+                        return Ok((consumed, None));
+                    };
+                    let line = match self.handle_line(end) {
+                        // nothing to report, keep going with the rest of the input
+                        Ok(Line::Skip) => continue,
+                        Ok(line) => line,
+                        Err(err) => return self.fail(err),
+                    };
+                    return Ok((consumed, Some(self.line_event(line))));
+                }
+                State::HeadersEnd => self.state = self.body_state(),
+                State::Body(remaining) => {
+                    let (len, body) = take(rest, remaining);
+                    self.state = match remaining - len as u64 {
+                        0 => State::End,
+                        remaining => State::Body(remaining),
+                    };
+                    return Ok((consumed + len, body.map(Event::Body)));
+                }
+                State::BodyUntilClose => {
+                    let body = (!rest.is_empty()).then_some(Event::Body(rest));
+                    return Ok((input.len(), body));
+                }
+                State::ChunkData(remaining) => {
+                    let (len, body) = take(rest, remaining);
+                    self.state = match remaining - len as u64 {
+                        0 => State::ChunkDataEnd,
+                        remaining => State::ChunkData(remaining),
+                    };
+                    return Ok((consumed + len, body.map(Event::Body)));
+                }
+                State::End => {
+                    self.state = State::Done;
+                    return Ok((consumed, Some(Event::End)));
+                }
+                State::Done => return Ok((consumed, None)),
+                State::Error(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Tells the parser that the connection was closed
+    ///
+    /// This completes a response whose body is delimited by the end of the connection and fails
+    /// with [`Error::UnexpectedEof`] if the response is incomplete.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        match self.state {
+            State::BodyUntilClose | State::End | State::Done => {
+                self.state = State::Done;
+                Ok(())
+            }
+            State::Error(err) => Err(err),
+            _ => self.fail(Error::UnexpectedEof),
+        }
+    }
+
+    fn body_state(&self) -> State {
+        let no_body = self.head
+            || (100..200).contains(&self.status)
+            || self.status == 204
+            || self.status == 304;
+
+        if no_body {
+            State::End
+        } else if self.chunked {
+            State::ChunkSize
+        } else {
+            match self.content_length {
+                Some(0) => State::End,
+                Some(len) => State::Body(len),
+                None => State::BodyUntilClose,
+            }
+        }
+    }
+
+    fn fail<T>(&mut self, err: Error) -> Result<T, Error> {
+        self.state = State::Error(err);
+        Err(err)
+    }
+
+    /// Collects a line into the line buffer
+    ///
+    /// Returns the number of bytes consumed and, once the line is complete, its length without the
+    /// line break.
+    fn collect_line(&mut self, input: &[u8]) -> Result<(usize, Option<usize>), Error> {
+        let (len, complete) = match input.iter().position(|&b| b == b'\n') {
+            Some(pos) => (pos + 1, true),
+            None => (input.len(), false),
+        };
+
+        if self.line_len + len > N {
+            return self.fail(Error::LineTooLong);
+        }
+        self.line[self.line_len..][..len].copy_from_slice(&input[..len]);
+        self.line_len += len;
+
+        if !complete {
+            return Ok((len, None));
+        }
+
+        let mut end = self.line_len - 1;
+        if end > 0 && self.line[end - 1] == b'\r' {
+            end -= 1;
+        }
+        self.line_len = 0;
+        Ok((len, Some(end)))
+    }
+
+    fn line_event(&self, line: Line) -> Event<'_> {
+        match line {
+            Line::Status(version, code, reason) => Event::Status(Status {
+                version,
+                code,
+                // checked in `parse_status_line`
+                reason: core::str::from_utf8(&self.line[reason]).unwrap_or_default(),
+            }),
+            Line::Header(name, value) => Event::Header(Header {
+                // checked in `parse_header`
+                name: core::str::from_utf8(&self.line[name]).unwrap_or_default(),
+                value: &self.line[value],
+            }),
+            Line::HeadersEnd => Event::HeadersEnd,
+            Line::End => Event::End,
+            Line::Skip => unreachable!(),
+        }
+    }
+
+    /// Handles the complete line in `self.line[..end]`
+    fn handle_line(&mut self, end: usize) -> Result<Line, Error> {
+        let line = &self.line[..end];
+        match self.state {
+            State::StatusLine => {
+                // ignore empty lines in front of the status line
+                if line.is_empty() {
+                    return Ok(Line::Skip);
+                }
+                let (version, code, reason) = parse_status_line(line)?;
+                self.status = code;
+                self.content_length = None;
+                self.chunked = false;
+                self.state = State::Headers;
+                Ok(Line::Status(version, code, reason))
+            }
+            State::Headers if line.is_empty() => {
+                // interim responses are followed by the real response
+                self.state = if (100..200).contains(&self.status) && self.status != 101 {
+                    State::StatusLine
+                } else {
+                    State::HeadersEnd
+                };
+                Ok(Line::HeadersEnd)
+            }
+            State::Headers => {
+                let (name, value) = parse_header(line)?;
+                let (name_bytes, value_bytes) = (&line[name.clone()], &line[value.clone()]);
+                if name_bytes.eq_ignore_ascii_case(b"content-length") {
+                    let len = parse_content_length(value_bytes)?;
+                    if self.content_length.is_some_and(|previous| previous != len) {
+                        return Err(Error::InvalidContentLength);
+                    }
+                    self.content_length = Some(len);
+                } else if name_bytes.eq_ignore_ascii_case(b"transfer-encoding") {
+                    // only `chunked` as the last coding determines the framing
+                    self.chunked = value_bytes
+                        .rsplit(|&b| b == b',')
+                        .next()
+                        .is_some_and(|coding| trim(coding).eq_ignore_ascii_case(b"chunked"));
+                }
+                Ok(Line::Header(name, value))
+            }
+            State::ChunkSize => {
+                self.state = match parse_chunk_size(line)? {
+                    0 => State::Trailers,
+                    size => State::ChunkData(size),
+                };
+                Ok(Line::Skip)
+            }
+            State::ChunkDataEnd if line.is_empty() => {
+                self.state = State::ChunkSize;
+                Ok(Line::Skip)
+            }
+            State::ChunkDataEnd => Err(Error::InvalidChunk),
+            State::Trailers if line.is_empty() => {
+                self.state = State::Done;
+                Ok(Line::End)
+            }
+            // trailer fields are not reported
+            State::Trailers => Ok(Line::Skip),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// What a complete line turned out to be, with the ranges of the interesting parts of the line
+enum Line {
+    Status(Version, u16, Range<usize>),
+    Header(Range<usize>, Range<usize>),
+    HeadersEnd,
+    End,
+    Skip,
+}
+
+impl<const N: usize> fmt::Debug for Parser<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parser")
+            .field("state", &self.state)
+            .field("status", &self.status)
+            .field("content_length", &self.content_length)
+            .field("chunked", &self.chunked)
+            .finish()
+    }
+}
+
+/// Takes up to `remaining` bytes from `input`
+fn take(input: &[u8], remaining: u64) -> (usize, Option<&[u8]>) {
+    let len =
+        usize::try_from(remaining).map_or(input.len(), |remaining| remaining.min(input.len()));
+    (len, (len > 0).then_some(&input[..len]))
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    &bytes[trim_range(bytes, 0..bytes.len())]
+}
+
+/// Removes leading and trailing whitespace from `bytes[range]`
+fn trim_range(bytes: &[u8], range: Range<usize>) -> Range<usize> {
+    let is_ws = |b: &u8| *b == b' ' || *b == b'\t';
+    let part = &bytes[range.clone()];
+    let start = part.iter().position(|b| !is_ws(b)).unwrap_or(part.len());
+    let end = part
+        .iter()
+        .rposition(|b| !is_ws(b))
+        .map_or(start, |end| end + 1);
+    range.start + start..range.start + end
+}
+
+fn parse_status_line(line: &[u8]) -> Result<(Version, u16, Range<usize>), Error> {
+    let version = match line.get(..9) {
+        Some(b"HTTP/1.0 ") => Version::Http10,
+        Some(b"HTTP/1.1 ") => Version::Http11,
+        _ => return Err(Error::InvalidStatusLine),
+    };
+
+    let code = match line.get(9..12) {
+        Some(&[a, b, c]) if [a, b, c].iter().all(u8::is_ascii_digit) => {
+            u16::from(a - b'0') * 100 + u16::from(b - b'0') * 10 + u16::from(c - b'0')
+        }
+        _ => return Err(Error::InvalidStatusLine),
+    };
+
+    // the reason phrase is optional, but the space in front of it isn't always sent
+    let reason = match line.get(12..) {
+        Some([]) => 12..12,
+        Some([b' ', reason @ ..]) => {
+            core::str::from_utf8(reason).map_err(|_| Error::InvalidStatusLine)?;
+            13..line.len()
+        }
+        _ => return Err(Error::InvalidStatusLine),
+    };
+
+    Ok((version, code, reason))
+}
+
+fn parse_header(line: &[u8]) -> Result<(Range<usize>, Range<usize>), Error> {
+    let colon = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or(Error::InvalidHeader)?;
+
+    // header names are tokens, which are ASCII
+    let is_token = |b: &u8| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(b);
+    if colon == 0 || !line[..colon].iter().all(is_token) {
+        return Err(Error::InvalidHeader);
+    }
+
+    let value = trim_range(line, colon + 1..line.len());
+    Ok((0..colon, value))
+}
+
+fn parse_content_length(value: &[u8]) -> Result<u64, Error> {
+    if value.is_empty() {
+        return Err(Error::InvalidContentLength);
+    }
+    value.iter().try_fold(0u64, |len, &b| {
+        if !b.is_ascii_digit() {
+            return Err(Error::InvalidContentLength);
+        }
+        len.checked_mul(10)
+            .and_then(|len| len.checked_add(u64::from(b - b'0')))
+            .ok_or(Error::InvalidContentLength)
+    })
+}
+
+fn parse_chunk_size(line: &[u8]) -> Result<u64, Error> {
+    // chunk extensions are ignored
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    let size = trim(size);
+    if size.is_empty() {
+        return Err(Error::InvalidChunk);
+    }
+    size.iter().try_fold(0u64, |size, &b| {
+        let digit = (b as char).to_digit(16).ok_or(Error::InvalidChunk)?;
+        size.checked_mul(16)
+            .and_then(|size| size.checked_add(u64::from(digit)))
+            .ok_or(Error::InvalidChunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{string::String, vec::Vec};
+
+    use super::*;
+
+    /// Owned copy of an [`Event`] so events from different calls can be compared
+    #[derive(Debug, PartialEq, Eq)]
+    enum Owned {
+        Status(Version, u16, String),
+        Header(String, Vec<u8>),
+        HeadersEnd,
+        Body(Vec<u8>),
+        End,
+    }
+
+    /// Feeds `response` split into `chunk` sized pieces and collects the events, merging
+    /// consecutive body events
+    fn parse_chunked(
+        parser: &mut Parser<64>,
+        response: &[u8],
+        chunk: usize,
+    ) -> Result<Vec<Owned>, Error> {
+        let mut events = Vec::new();
+        for mut data in response.chunks(chunk) {
+            loop {
+                let (consumed, event) = parser.parse(data)?;
+                data = &data[consumed..];
+                let event = match event {
+                    Some(Event::Status(s)) => Owned::Status(s.version, s.code, s.reason.into()),
+                    Some(Event::Header(h)) => Owned::Header(h.name.into(), h.value.into()),
+                    Some(Event::HeadersEnd) => Owned::HeadersEnd,
+                    Some(Event::Body(body)) => {
+                        if let Some(Owned::Body(previous)) = events.last_mut() {
+                            previous.extend_from_slice(body);
+                            continue;
+                        }
+                        Owned::Body(body.into())
+                    }
+                    Some(Event::End) => Owned::End,
+                    None => break,
+                };
+                events.push(event);
+            }
+        }
+        // pick up an `End` that doesn't need more data
+        if let (0, Some(Event::End)) = parser.parse(&[])? {
+            events.push(Owned::End);
+        }
+        Ok(events)
+    }
+
+    /// Parses `response` with every chunk size and checks that all of them agree
+    fn parse(response: &[u8]) -> Result<Vec<Owned>, Error> {
+        let expected = parse_chunked(&mut Parser::new(), response, response.len().max(1));
+        for chunk in 1..response.len() {
+            assert_eq!(
+                parse_chunked(&mut Parser::new(), response, chunk),
+                expected,
+                "chunk size {chunk}"
+            );
+        }
+        expected
+    }
+
+    fn header(name: &str, value: &str) -> Owned {
+        Owned::Header(name.into(), value.into())
+    }
+
+    fn body(data: &str) -> Owned {
+        Owned::Body(data.into())
+    }
+
+    #[test]
+    fn content_length() {
+        let events =
+            parse(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nServer: test\r\n\r\nhello").unwrap();
+        assert_eq!(
+            events,
+            [
+                Owned::Status(Version::Http11, 200, "OK".into()),
+                header("Content-Length", "5"),
+                header("Server", "test"),
+                Owned::HeadersEnd,
+                body("hello"),
+                Owned::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn data_after_content_length_is_not_consumed() {
+        let mut parser = Parser::<64>::new();
+        let mut data: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhiHTTP/1.1";
+        loop {
+            let (consumed, event) = parser.parse(data).unwrap();
+            data = &data[consumed..];
+            if event.is_none() {
+                break;
+            }
+        }
+        assert!(parser.is_complete());
+        assert_eq!(data, b"HTTP/1.1");
+    }
+
+    #[test]
+    fn empty_content_length() {
+        let events = parse(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert_eq!(events.last(), Some(&Owned::End));
+        assert!(!events.iter().any(|e| matches!(e, Owned::Body(_))));
+    }
+
+    #[test]
+    fn chunked() {
+        let events = parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nTrailer: x\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            events,
+            [
+                Owned::Status(Version::Http11, 200, "OK".into()),
+                header("Transfer-Encoding", "chunked"),
+                Owned::HeadersEnd,
+                body("hello, world"),
+                Owned::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn chunked_takes_precedence_over_content_length() {
+        let events = parse(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+              A\r\n0123456789\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert!(events.contains(&body("0123456789")));
+        assert_eq!(events.last(), Some(&Owned::End));
+    }
+
+    #[test]
+    fn invalid_chunk() {
+        assert_eq!(
+            parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nxyz\r\n"),
+            Err(Error::InvalidChunk)
+        );
+        assert_eq!(
+            parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n"),
+            Err(Error::InvalidChunk)
+        );
+        assert_eq!(
+            parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffff\r\n"),
+            Err(Error::InvalidChunk)
+        );
+    }
+
+    #[test]
+    fn body_until_close() {
+        let mut parser = Parser::new();
+        let events = parse_chunked(&mut parser, b"HTTP/1.0 200 OK\r\n\r\nsome data", 4).unwrap();
+        assert_eq!(
+            events,
+            [
+                Owned::Status(Version::Http10, 200, "OK".into()),
+                Owned::HeadersEnd,
+                body("some data"),
+            ]
+        );
+        assert!(!parser.is_complete());
+        assert_eq!(parser.finish(), Ok(()));
+        assert!(parser.is_complete());
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let mut parser = Parser::new();
+        parse_chunked(
+            &mut parser,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc",
+            100,
+        )
+        .unwrap();
+        assert_eq!(parser.finish(), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn responses_without_body() {
+        for status in ["204 No Content", "304 Not Modified"] {
+            let response = std::format!("HTTP/1.1 {status}\r\nContent-Length: 10\r\n\r\n");
+            let events = parse(response.as_bytes()).unwrap();
+            assert_eq!(events.last(), Some(&Owned::End));
+        }
+
+        let mut parser = Parser::new_head();
+        let events = parse_chunked(
+            &mut parser,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n",
+            100,
+        )
+        .unwrap();
+        assert_eq!(events.last(), Some(&Owned::End));
+    }
+
+    #[test]
+    fn interim_response() {
+        let events =
+            parse(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\nx")
+                .unwrap();
+        assert_eq!(
+            events,
+            [
+                Owned::Status(Version::Http11, 100, "Continue".into()),
+                Owned::HeadersEnd,
+                Owned::Status(Version::Http11, 200, "OK".into()),
+                header("Content-Length", "1"),
+                Owned::HeadersEnd,
+                body("x"),
+                Owned::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_line_endings_and_reason() {
+        let events = parse(b"HTTP/1.1 404\nX-Empty:\n\n").unwrap();
+        assert_eq!(
+            events,
+            [
+                Owned::Status(Version::Http11, 404, "".into()),
+                header("X-Empty", ""),
+                Owned::HeadersEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_status_line() {
+        for line in [
+            &b"HTTP/2 200 OK\r\n"[..],
+            b"HTTP/1.1 20 OK\r\n",
+            b"HTTP/1.1 2x0 OK\r\n",
+            b"HTTP/1.1 200OK\r\n",
+            b"HTTP/1.1 200 \xff\r\n",
+        ] {
+            assert_eq!(parse(line), Err(Error::InvalidStatusLine));
+        }
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nno colon\r\n\r\n"[..],
+            b"HTTP/1.1 200 OK\r\n: no name\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nbad name: x\r\n\r\n",
+        ] {
+            assert_eq!(parse(response), Err(Error::InvalidHeader));
+        }
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nContent-Length: -1\r\n\r\n"[..],
+            b"HTTP/1.1 200 OK\r\nContent-Length:\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 99999999999999999999999\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+        ] {
+            assert_eq!(parse(response), Err(Error::InvalidContentLength));
+        }
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut response = Vec::from(&b"HTTP/1.1 200 OK\r\nX-Long: "[..]);
+        response.resize(response.len() + 64, b'a');
+        assert_eq!(parse(&response), Err(Error::LineTooLong));
+    }
+
+    #[test]
+    fn errors_are_sticky() {
+        let mut parser = Parser::<64>::new();
+        assert_eq!(
+            parser.parse(b"garbage\r\n").map(|_| ()),
+            Err(Error::InvalidStatusLine)
+        );
+        assert_eq!(
+            parser.parse(b"HTTP/1.1 200 OK\r\n").map(|_| ()),
+            Err(Error::InvalidStatusLine)
+        );
+        assert_eq!(parser.finish(), Err(Error::InvalidStatusLine));
+
+        parser.reset();
+        assert!(matches!(
+            parser.parse(b"HTTP/1.1 200 OK\r\n"),
+            Ok((_, Some(Event::Status(_))))
+        ));
+        assert_eq!(parser.status(), Some(200));
+    }
+}
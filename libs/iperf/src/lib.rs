@@ -0,0 +1,580 @@
+//! The test protocol of iperf 2, enough to run TCP and UDP tests against `iperf -s`.
+//!
+//! Like the other libraries, the crate doesn't touch the network. A TCP test is a stream of
+//! data that starts with a [`ClientHeader`]. A UDP test is a stream of datagrams, each starting
+//! with a [`Datagram`] header with its sequence number and the time it was sent. The last
+//! datagram carries its sequence number negated, and the receiver answers it with a
+//! [`ServerReport`] of what arrived. A [`Receiver`] counts the datagrams of a test and writes
+//! that report.
+//!
+//! iperf 2 and iperf3 speak different protocols, this is the one of iperf 2.0.10 and newer,
+//! the version that ships as `iperf`. All times are in microseconds.
+//!
+//! ```
+//! use iperf::{Datagram, Receiver, ServerReport, SERVER_REPORT_LEN, UDP_PAYLOAD_LEN};
+//!
+//! // the client sends 1000 datagrams, one every millisecond, datagram 7 is lost
+//! let mut receiver = Receiver::new();
+//! let mut datagram = [0; UDP_PAYLOAD_LEN];
+//! for seq in (0..1000).filter(|&seq| seq != 7) {
+//!     Datagram { seq, sent: seq as u64 * 1_000 }.encode(&mut datagram).unwrap();
+//!     let received = Datagram::decode(&datagram).unwrap();
+//!     receiver.received(&received, datagram.len(), 2_500 + seq as u64 * 1_000);
+//! }
+//! let last = Datagram { seq: -1000, sent: 1_000_000 };
+//! receiver.received(&last, UDP_PAYLOAD_LEN, 1_002_500);
+//!
+//! // the receiver answers the last datagram, the client reads the report
+//! let mut answer = [0; SERVER_REPORT_LEN];
+//! receiver.report().encode(&last, &mut answer).unwrap();
+//! let report = ServerReport::decode(&answer).unwrap();
+//! assert_eq!((report.lost, report.datagrams), (1, 1001));
+//! assert_eq!(
+//!     report.to_string(),
+//!     "11.76 Mbit/s, jitter 0.000 ms, 1/1001 lost (0.09%)"
+//! );
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// The port `iperf -s` listens on, for TCP and for UDP
+pub const PORT: u16 = 5001;
+
+/// Length of the [`ClientHeader`]
+pub const CLIENT_HEADER_LEN: usize = 24;
+
+/// Length of the [`Datagram`] header: sequence number, seconds, microseconds and the upper half
+/// of a 64-bit sequence number
+pub const DATAGRAM_HEADER_LEN: usize = 16;
+
+/// Length of the datagram with the [`ServerReport`], behind the header of the last datagram
+pub const SERVER_REPORT_LEN: usize = DATAGRAM_HEADER_LEN + 40;
+
+/// The length of the datagrams iperf sends by default, they fit into an Ethernet frame
+pub const UDP_PAYLOAD_LEN: usize = 1470;
+
+/// Set in the flags of a [`ClientHeader`] that asks for a test back to the client, and in the
+/// flags of every [`ServerReport`]
+const HEADER_VERSION1: u32 = 0x8000_0000;
+
+/// Errors returned by the encoders and decoders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer is too small for the header or the report
+    BufferTooSmall,
+    /// The datagram is shorter than its header or the report
+    Truncated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Fills `buf` with the data iperf sends, the digits `0123456789` over and over
+pub fn fill_pattern(buf: &mut [u8]) {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = b'0' + (i % 10) as u8;
+    }
+}
+
+/// What the client tells the server about the test, at the start of the TCP stream, or behind
+/// the [`Datagram`] header of the UDP datagrams
+///
+/// A server that gets a header with `tradeoff` set runs the test in the other direction once
+/// this one is done, like `iperf -c <server> -r`: it connects to `port` on the client, and
+/// sends for `duration` milliseconds. Without `tradeoff`, the server ignores the header, but it
+/// still has to be there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ClientHeader {
+    pub tradeoff: bool,
+    /// The port the server connects back to
+    pub port: u16,
+    /// The length of the server's writes or datagrams, 0 keeps its default
+    pub buffer_len: u32,
+    /// The rate of the server's UDP datagrams in bits per second, 0 keeps its default
+    pub rate: u32,
+    /// How long the server sends in milliseconds, iperf only counts hundredths of a second
+    pub duration: u32,
+}
+
+impl ClientHeader {
+    /// Encodes the header into `buf`, returns its length
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let header = buf
+            .get_mut(..CLIENT_HEADER_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        let flags = if self.tradeoff { HEADER_VERSION1 } else { 0 };
+        // a negative amount is a time in hundredths of a second, a positive one a number of bytes
+        let amount = -((self.duration / 10) as i32) as u32;
+        let fields = [
+            flags,
+            1, // the number of parallel streams
+            u32::from(self.port),
+            self.buffer_len,
+            self.rate,
+            amount,
+        ];
+        for (field, value) in header.as_chunks_mut::<4>().0.iter_mut().zip(fields) {
+            *field = value.to_be_bytes();
+        }
+        Ok(CLIENT_HEADER_LEN)
+    }
+}
+
+/// The header at the start of every UDP datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Datagram {
+    /// Counts up from 0, the last datagram of a test has its number negated
+    pub seq: i32,
+    /// When the client sent the datagram, on the client's clock
+    pub sent: u64,
+}
+
+impl Datagram {
+    /// `true` for the last datagram of a test, which asks the receiver for its report
+    pub fn is_last(&self) -> bool {
+        self.seq < 0
+    }
+
+    /// Writes the header to the start of `buf`, the rest of the datagram stays as it is
+    pub fn encode(&self, buf: &mut [u8]) -> Result<(), Error> {
+        let header = buf
+            .get_mut(..DATAGRAM_HEADER_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        // the upper half of the 64-bit sequence number, only read by servers the client asked
+        // for them
+        let upper: i32 = if self.seq < 0 { -1 } else { 0 };
+        let fields = [
+            self.seq as u32,
+            (self.sent / 1_000_000) as u32,
+            (self.sent % 1_000_000) as u32,
+            upper as u32,
+        ];
+        for (field, value) in header.as_chunks_mut::<4>().0.iter_mut().zip(fields) {
+            *field = value.to_be_bytes();
+        }
+        Ok(())
+    }
+
+    /// Reads the header of a received datagram
+    pub fn decode(datagram: &[u8]) -> Result<Self, Error> {
+        if datagram.len() < DATAGRAM_HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        let seconds = u32_at(datagram, 4);
+        let micros = u32_at(datagram, 8);
+        Ok(Self {
+            seq: u32_at(datagram, 0) as i32,
+            sent: u64::from(seconds) * 1_000_000 + u64::from(micros),
+        })
+    }
+}
+
+/// What the receiver of a UDP test tells the client at the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServerReport {
+    /// Received bytes, the UDP payload of the datagrams
+    pub bytes: u64,
+    /// From the first to the last received datagram
+    pub time: u64,
+    /// Datagrams that never arrived
+    pub lost: u32,
+    /// Datagrams that arrived after a later one
+    pub out_of_order: u32,
+    /// The datagrams the client sent, as far as the receiver can tell
+    pub datagrams: u32,
+    /// How much the time the datagrams take varies, like RTP computes it (RFC 3550)
+    pub jitter: u32,
+}
+
+impl ServerReport {
+    /// Encodes the report as the answer to `last`, the last datagram of the test
+    ///
+    /// Returns the length of the datagram.
+    pub fn encode(&self, last: &Datagram, buf: &mut [u8]) -> Result<usize, Error> {
+        let report = buf
+            .get_mut(..SERVER_REPORT_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        last.encode(report)?;
+        let fields = [
+            HEADER_VERSION1,
+            (self.bytes >> 32) as u32,
+            self.bytes as u32,
+            (self.time / 1_000_000) as u32,
+            (self.time % 1_000_000) as u32,
+            self.lost,
+            self.out_of_order,
+            self.datagrams,
+            self.jitter / 1_000_000,
+            self.jitter % 1_000_000,
+        ];
+        let (report_fields, _) = report[DATAGRAM_HEADER_LEN..].as_chunks_mut::<4>();
+        for (field, value) in report_fields.iter_mut().zip(fields) {
+            *field = value.to_be_bytes();
+        }
+        Ok(SERVER_REPORT_LEN)
+    }
+
+    /// Reads the report from the answer to the last datagram
+    pub fn decode(datagram: &[u8]) -> Result<Self, Error> {
+        if datagram.len() < SERVER_REPORT_LEN {
+            return Err(Error::Truncated);
+        }
+        let field = |index: usize| u32_at(datagram, DATAGRAM_HEADER_LEN + 4 * index);
+        let seconds =
+            |index: usize| u64::from(field(index)) * 1_000_000 + u64::from(field(index + 1));
+        Ok(Self {
+            bytes: u64::from(field(1)) << 32 | u64::from(field(2)),
+            time: seconds(3),
+            lost: field(5),
+            out_of_order: field(6),
+            datagrams: field(7),
+            jitter: seconds(8) as u32,
+        })
+    }
+
+    /// The throughput of the datagrams that arrived
+    pub fn throughput(&self) -> Throughput {
+        Throughput {
+            bytes: self.bytes,
+            time: self.time,
+        }
+    }
+}
+
+/// Prints the report like `9.87 Mbit/s, jitter 0.123 ms, 3/8500 lost (0.04%)`
+impl fmt::Display for ServerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loss = match self.datagrams {
+            0 => 0,
+            datagrams => u64::from(self.lost) * 10_000 / u64::from(datagrams),
+        };
+        write!(
+            f,
+            "{}, jitter {}.{:03} ms, {}/{} lost ({}.{:02}%)",
+            self.throughput(),
+            self.jitter / 1_000,
+            self.jitter % 1_000,
+            self.lost,
+            self.datagrams,
+            loss / 100,
+            loss % 100
+        )
+    }
+}
+
+/// Counts the datagrams of a UDP test as they arrive
+///
+/// The count starts with the first datagram that arrives, if the first ones the client sent are
+/// lost, they aren't counted as lost.
+#[derive(Debug, Clone, Default)]
+pub struct Receiver {
+    started: Option<u64>,
+    last: u64,
+    bytes: u64,
+    first_seq: i64,
+    next_seq: i64,
+    lost: u32,
+    out_of_order: u32,
+    /// The time the previous datagram took, on the two clocks
+    transit: Option<i64>,
+    /// Sixteen times the jitter, like RFC 3550 keeps it to avoid rounding
+    jitter: u64,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Self {
+            started: None,
+            last: 0,
+            bytes: 0,
+            first_seq: 0,
+            next_seq: 0,
+            lost: 0,
+            out_of_order: 0,
+            transit: None,
+            jitter: 0,
+        }
+    }
+
+    /// `true` once a datagram was received
+    pub fn has_started(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Counts a datagram of `len` bytes that arrived at `now`
+    pub fn received(&mut self, datagram: &Datagram, len: usize, now: u64) {
+        // the last datagram is counted like the others
+        let seq = i64::from(datagram.seq).abs();
+        if self.started.is_none() {
+            self.started = Some(now);
+            self.first_seq = seq;
+            self.next_seq = seq;
+        }
+        self.last = now;
+        self.bytes += len as u64;
+
+        if seq >= self.next_seq {
+            self.lost += (seq - self.next_seq) as u32;
+            self.next_seq = seq + 1;
+        } else {
+            // counted as lost when the later one arrived
+            self.out_of_order += 1;
+            self.lost = self.lost.saturating_sub(1);
+        }
+
+        // the clocks of client and receiver differ, but the difference cancels out
+        let transit = now as i64 - datagram.sent as i64;
+        if let Some(previous) = self.transit {
+            let change = (transit - previous).unsigned_abs();
+            self.jitter = self.jitter + change - ((self.jitter + 8) >> 4);
+        }
+        self.transit = Some(transit);
+    }
+
+    /// What arrived so far
+    pub fn report(&self) -> ServerReport {
+        ServerReport {
+            bytes: self.bytes,
+            time: self.started.map_or(0, |started| self.last - started),
+            lost: self.lost,
+            out_of_order: self.out_of_order,
+            datagrams: (self.next_seq - self.first_seq) as u32,
+            jitter: (self.jitter >> 4) as u32,
+        }
+    }
+}
+
+/// Bytes transferred in a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Throughput {
+    pub bytes: u64,
+    pub time: u64,
+}
+
+impl Throughput {
+    pub fn bits_per_second(&self) -> u64 {
+        match self.time {
+            0 => 0,
+            time => self.bytes * 8 * 1_000_000 / time,
+        }
+    }
+}
+
+/// Prints the throughput in megabits per second, like iperf, e.g. `9.87 Mbit/s`
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let centi = self.bits_per_second() / 10_000;
+        write!(f, "{}.{:02} Mbit/s", centi / 100, centi % 100)
+    }
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn encodes_a_client_header() {
+        let header = ClientHeader {
+            tradeoff: true,
+            port: PORT,
+            buffer_len: 1470,
+            rate: 10_000_000,
+            duration: 10_000,
+        };
+        let mut buf = [0xff; 32];
+        assert_eq!(header.encode(&mut buf), Ok(CLIENT_HEADER_LEN));
+        #[rustfmt::skip]
+        assert_eq!(buf[..CLIENT_HEADER_LEN], [
+            0x80, 0, 0, 0,          // flags
+            0, 0, 0, 1,             // streams
+            0, 0, 0x13, 0x89,       // port 5001
+            0, 0, 0x05, 0xbe,       // 1470 bytes
+            0, 0x98, 0x96, 0x80,    // 10 Mbit/s
+            0xff, 0xff, 0xfc, 0x18, // -1000 hundredths of a second
+        ]);
+        // the rest of the buffer is left alone
+        assert_eq!(buf[CLIENT_HEADER_LEN..], [0xff; 8]);
+    }
+
+    #[test]
+    fn header_without_tradeoff_has_no_flags() {
+        let mut buf = [0xff; CLIENT_HEADER_LEN];
+        ClientHeader::default().encode(&mut buf).unwrap();
+        assert_eq!(buf[..4], [0; 4]);
+        assert_eq!(buf[4..8], [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn buffers_too_small() {
+        let mut buf = [0; SERVER_REPORT_LEN - 1];
+        assert_eq!(
+            ClientHeader::default().encode(&mut buf[..CLIENT_HEADER_LEN - 1]),
+            Err(Error::BufferTooSmall)
+        );
+        let datagram = Datagram { seq: 1, sent: 0 };
+        assert_eq!(
+            datagram.encode(&mut buf[..DATAGRAM_HEADER_LEN - 1]),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(
+            ServerReport::default().encode(&datagram, &mut buf),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn encodes_and_decodes_datagrams() {
+        let mut buf = [b'x'; 20];
+        let datagram = Datagram {
+            seq: 258,
+            sent: 3_000_042,
+        };
+        datagram.encode(&mut buf).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(buf[..DATAGRAM_HEADER_LEN], [
+            0, 0, 1, 2,   // sequence number
+            0, 0, 0, 3,   // seconds
+            0, 0, 0, 42,  // microseconds
+            0, 0, 0, 0,   // upper half of the sequence number
+        ]);
+        assert_eq!(buf[DATAGRAM_HEADER_LEN..], *b"xxxx");
+        assert_eq!(Datagram::decode(&buf), Ok(datagram));
+        assert!(!datagram.is_last());
+    }
+
+    #[test]
+    fn last_datagram_is_negative() {
+        let mut buf = [0; DATAGRAM_HEADER_LEN];
+        let last = Datagram { seq: -258, sent: 0 };
+        last.encode(&mut buf).unwrap();
+        // the 64-bit sequence number is negative as well
+        assert_eq!(buf[..4], [0xff, 0xff, 0xfe, 0xfe]);
+        assert_eq!(buf[12..], [0xff; 4]);
+        let decoded = Datagram::decode(&buf).unwrap();
+        assert!(decoded.is_last());
+        assert_eq!(decoded.seq, -258);
+    }
+
+    #[test]
+    fn truncated_datagrams() {
+        assert_eq!(
+            Datagram::decode(&[0; DATAGRAM_HEADER_LEN - 1]),
+            Err(Error::Truncated)
+        );
+        assert_eq!(
+            ServerReport::decode(&[0; SERVER_REPORT_LEN - 1]),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn report_layout() {
+        let report = ServerReport {
+            bytes: 0x1_0000_0002,
+            time: 10_000_500,
+            lost: 3,
+            out_of_order: 1,
+            datagrams: 8500,
+            jitter: 1_234,
+        };
+        let mut buf = [0; 64];
+        let last = Datagram {
+            seq: -8500,
+            sent: 0,
+        };
+        assert_eq!(report.encode(&last, &mut buf), Ok(SERVER_REPORT_LEN));
+        // the header of the last datagram comes back
+        assert_eq!(Datagram::decode(&buf), Ok(last));
+        let field = |index: usize| u32_at(&buf, DATAGRAM_HEADER_LEN + 4 * index);
+        let fields: std::vec::Vec<u32> = (0..10).map(field).collect();
+        assert_eq!(fields, [0x8000_0000, 1, 2, 10, 500, 3, 1, 8500, 0, 1_234]);
+        assert_eq!(ServerReport::decode(&buf), Ok(report));
+    }
+
+    #[test]
+    fn counts_lost_and_late_datagrams() {
+        let mut receiver = Receiver::new();
+        assert!(!receiver.has_started());
+        // 5 is lost, 3 arrives after 4, the test started with 1
+        for seq in [1, 2, 4, 3, 6, 7, -8] {
+            receiver.received(&Datagram { seq, sent: 0 }, 100, 0);
+        }
+        assert!(receiver.has_started());
+        let report = receiver.report();
+        assert_eq!(report.bytes, 700);
+        assert_eq!(report.lost, 1);
+        assert_eq!(report.out_of_order, 1);
+        assert_eq!(report.datagrams, 8);
+    }
+
+    #[test]
+    fn jitter() {
+        // the same delay every time, no jitter
+        let mut receiver = Receiver::new();
+        for seq in 0..100 {
+            let sent = seq as u64 * 1_000;
+            receiver.received(&Datagram { seq, sent }, 100, sent + 5_000);
+        }
+        assert_eq!(receiver.report().jitter, 0);
+        assert_eq!(receiver.report().time, 99_000);
+
+        // every other datagram takes 1 ms longer, the jitter approaches 1 ms
+        let mut receiver = Receiver::new();
+        for seq in 0..1000 {
+            let sent = seq as u64 * 1_000;
+            let delay = if seq % 2 == 0 { 5_000 } else { 6_000 };
+            receiver.received(&Datagram { seq, sent }, 100, sent + delay);
+        }
+        assert!((990..=1_000).contains(&receiver.report().jitter));
+    }
+
+    #[test]
+    fn pattern() {
+        let mut buf = [0; 12];
+        fill_pattern(&mut buf);
+        assert_eq!(&buf, b"012345678901");
+    }
+
+    #[test]
+    fn prints_throughput() {
+        let throughput = Throughput {
+            bytes: 12_345_678,
+            time: 10_000_000,
+        };
+        assert_eq!(throughput.bits_per_second(), 9_876_542);
+        assert_eq!(throughput.to_string(), "9.87 Mbit/s");
+        assert_eq!(Throughput::default().to_string(), "0.00 Mbit/s");
+
+        let report = ServerReport {
+            bytes: 12_345_678,
+            time: 10_000_000,
+            lost: 3,
+            out_of_order: 0,
+            datagrams: 8500,
+            jitter: 123,
+        };
+        assert_eq!(
+            report.to_string(),
+            "9.87 Mbit/s, jitter 0.123 ms, 3/8500 lost (0.03%)"
+        );
+    }
+}
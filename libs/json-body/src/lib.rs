@@ -0,0 +1,319 @@
+//! Collects the body of an HTTP response into a fixed buffer and deserializes it as JSON.
+//!
+//! The [`http_response::Parser`] hands out the body in pieces, as it arrives. JSON can only be
+//! deserialized once the whole document is there, so [`Body`] collects the pieces into a buffer
+//! of `N` bytes and skips the headers. Responses that don't fit are rejected with
+//! [`Error::TooLarge`], as soon as the `Content-Length` header announces them or the buffer
+//! runs full. A response that ends before its body is complete is [`Error::Truncated`].
+//!
+//! [`Body::parse`] deserializes the body with `serde-json-core`, into types that borrow from the
+//! buffer or use `heapless` strings and vectors.
+//!
+//! ```
+//! use http_response::Parser;
+//! use json_body::Body;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Location<'a> {
+//!     city: &'a str,
+//!     lat: f32,
+//! }
+//!
+//! let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 34\r\n\r\n\
+//!                  {\"city\":\"Brno\",\"lat\":49.19,\"x\":[]}";
+//! let mut parser = Parser::<256>::new();
+//! let mut body = Body::<512>::new();
+//! let mut data = &response[..];
+//! while let (consumed, Some(event)) = parser.parse(data).unwrap() {
+//!     body.push(&event).unwrap();
+//!     data = &data[consumed..];
+//! }
+//!
+//! // fields we don't know are skipped
+//! let location: Location = body.parse().unwrap();
+//! assert_eq!(location.city, "Brno");
+//! assert_eq!(location.lat, 49.19);
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+use http_response::Event;
+use serde::Deserialize;
+
+/// Errors returned while collecting and deserializing the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The body doesn't fit into the buffer, the length is the announced one if the server sent
+    /// a `Content-Length` header
+    TooLarge(Option<u64>),
+    /// The response ended before the body was complete, e.g. the connection was closed early
+    Truncated,
+    /// The body isn't valid JSON, or doesn't match the type
+    Json(serde_json_core::de::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+// `serde-json-core` supports an older version of defmt, its error is formatted with `Debug`
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::TooLarge(len) => defmt::write!(f, "TooLarge({})", len),
+            Self::Truncated => defmt::write!(f, "Truncated"),
+            Self::Json(err) => defmt::write!(f, "Json({})", defmt::Debug2Format(err)),
+        }
+    }
+}
+
+/// The body of one response, in a buffer of `N` bytes
+#[derive(Debug, Clone)]
+pub struct Body<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    /// The response ended, with all of its body
+    complete: bool,
+}
+
+impl<const N: usize> Default for Body<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Body<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+            complete: false,
+        }
+    }
+
+    /// Forgets the body, to collect the one of the next response
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.complete = false;
+    }
+
+    /// Takes the next event of the response parser
+    ///
+    /// Fails with [`Error::TooLarge`] as soon as it's clear that the body won't fit. The
+    /// application should then stop reading the response, and close the connection.
+    pub fn push(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        match event {
+            Event::Header(header) if header.name.eq_ignore_ascii_case("content-length") => {
+                // the parser already rejects invalid lengths
+                let len = core::str::from_utf8(header.value)
+                    .ok()
+                    .and_then(|value| value.trim().parse::<u64>().ok());
+                if let Some(len) = len.filter(|&len| len > N as u64) {
+                    return Err(Error::TooLarge(Some(len)));
+                }
+            }
+            Event::Body(data) => {
+                let end = self.len + data.len();
+                if end > N {
+                    return Err(Error::TooLarge(None));
+                }
+                self.buffer[self.len..end].copy_from_slice(data);
+                self.len = end;
+            }
+            Event::End => self.complete = true,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The body received so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// `true` once the response ended with all of its body
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Deserializes the complete body
+    ///
+    /// Strings with escape sequences, like `\"` or `\u00e9`, are unescaped into the unused end
+    /// of the buffer, a body that fills the buffer completely can only have strings without.
+    pub fn parse<'de, T: Deserialize<'de>>(&'de mut self) -> Result<T, Error> {
+        if !self.complete {
+            return Err(Error::Truncated);
+        }
+        let (body, unescaped) = self.buffer.split_at_mut(self.len);
+        let (value, _) =
+            serde_json_core::from_slice_escaped(body, unescaped).map_err(Error::Json)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use heapless::{String, Vec};
+    use http_response::Parser;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Reading {
+        sensor: String<16>,
+        values: Vec<i32, 4>,
+        ok: bool,
+    }
+
+    /// Feeds `response` to a parser and the body, `chunk` bytes at a time
+    fn collect<const N: usize>(response: &[u8], chunk: usize) -> Result<Body<N>, Error> {
+        let mut parser = Parser::<256>::new();
+        let mut body = Body::new();
+        for mut data in response.chunks(chunk) {
+            loop {
+                let (consumed, event) = parser.parse(data).unwrap();
+                data = &data[consumed..];
+                match event {
+                    Some(event) => body.push(&event)?,
+                    None => break,
+                }
+            }
+        }
+        Ok(body)
+    }
+
+    fn response(body: &str) -> std::vec::Vec<u8> {
+        std::format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    const READING: &str = r#"{"sensor":"kitchen","values":[21,-3],"ok":true}"#;
+
+    fn reading() -> Reading {
+        Reading {
+            sensor: "kitchen".try_into().unwrap(),
+            values: Vec::from_slice(&[21, -3]).unwrap(),
+            ok: true,
+        }
+    }
+
+    #[test]
+    fn parses_the_body() {
+        let mut body = collect::<512>(&response(READING), 512).unwrap();
+        assert!(body.is_complete());
+        assert_eq!(body.as_bytes(), READING.as_bytes());
+        assert_eq!(body.parse::<Reading>(), Ok(reading()));
+    }
+
+    #[test]
+    fn body_in_pieces() {
+        for chunk in [1, 2, 7, 64] {
+            let mut body = collect::<512>(&response(READING), chunk).unwrap();
+            assert_eq!(body.parse::<Reading>(), Ok(reading()));
+        }
+    }
+
+    #[test]
+    fn chunked_body() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         f\r\n{\"sensor\":\"kitc\r\n20\r\nhen\",\"values\":[21,-3],\"ok\":true}\r\n0\r\n\r\n";
+        let mut body = collect::<512>(response, 5).unwrap();
+        assert_eq!(body.parse::<Reading>(), Ok(reading()));
+    }
+
+    #[test]
+    fn body_until_close() {
+        let response = std::format!("HTTP/1.0 200 OK\r\n\r\n{}", READING);
+        let mut parser = Parser::<256>::new();
+        let mut body = Body::<512>::new();
+        let mut data = response.as_bytes();
+        while let (consumed, Some(event)) = parser.parse(data).unwrap() {
+            body.push(&event).unwrap();
+            data = &data[consumed..];
+        }
+        // the body only ends when the server closes the connection
+        assert!(!body.is_complete());
+        assert_eq!(body.parse::<Reading>(), Err(Error::Truncated));
+
+        parser.finish().unwrap();
+        body.push(&Event::End).unwrap();
+        assert_eq!(body.parse::<Reading>(), Ok(reading()));
+    }
+
+    #[test]
+    fn announced_too_large() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 513\r\n\r\n{";
+        let err = collect::<512>(response, 512).unwrap_err();
+        assert_eq!(err, Error::TooLarge(Some(513)));
+    }
+
+    #[test]
+    fn fits_exactly() {
+        let body = std::format!("{:<1$}", READING, 64);
+        let mut body = collect::<64>(&response(&body), 16).unwrap();
+        assert_eq!(body.as_bytes().len(), 64);
+        assert_eq!(body.parse::<Reading>(), Ok(reading()));
+    }
+
+    #[test]
+    fn runs_full_without_length() {
+        let response = std::format!("HTTP/1.0 200 OK\r\n\r\n{:<65}", READING);
+        let err = collect::<64>(response.as_bytes(), 16).unwrap_err();
+        assert_eq!(err, Error::TooLarge(None));
+    }
+
+    #[test]
+    fn truncated() {
+        // the connection was closed after half of the body
+        let response = response(READING);
+        let response = &response[..response.len() - READING.len() / 2];
+        let mut body = collect::<512>(response, 512).unwrap();
+        assert!(!body.is_complete());
+        assert_eq!(body.parse::<Reading>(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn invalid_json() {
+        let mut body = collect::<512>(&response(r#"{"sensor":"kitchen","#), 512).unwrap();
+        assert!(matches!(body.parse::<Reading>(), Err(Error::Json(_))));
+
+        // a string longer than the field
+        let json = r#"{"sensor":"the sensor in the kitchen","values":[],"ok":true}"#;
+        let mut body = collect::<512>(&response(json), 512).unwrap();
+        assert!(matches!(body.parse::<Reading>(), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn escaped_strings() {
+        #[derive(Debug, Deserialize)]
+        struct Message {
+            text: String<32>,
+        }
+
+        let mut body =
+            collect::<512>(&response(r#"{"text":"caf\u00e9 \"Zum Hafen\""}"#), 512).unwrap();
+        assert_eq!(body.parse::<Message>().unwrap().text, "café \"Zum Hafen\"");
+    }
+
+    #[test]
+    fn reset() {
+        let mut body = collect::<512>(&response(READING), 512).unwrap();
+        body.reset();
+        assert_eq!(body.as_bytes(), b"");
+        assert_eq!(body.parse::<Reading>(), Err(Error::Truncated));
+    }
+}
@@ -0,0 +1,913 @@
+//! Key-value store for settings in NOR flash.
+//!
+//! Flash can't be overwritten in place: a sector of 4 KiB has to be erased before it can be
+//! written again, and it only survives so many erases. [`Store`] therefore never changes what it
+//! wrote. Setting a key appends an entry to the current sector, the newest entry of a key wins.
+//! When the sector is full, the store moves on to the next one, and the sectors are used in a
+//! ring, so all of them are erased equally often (*wear leveling*). Before the oldest sector is
+//! reused, the entries in it that are still current are copied to the new one.
+//!
+//! A write that is interrupted by a power failure is lost, but never damages older entries: every
+//! entry has a checksum and [`Store::mount`] finishes or rolls back an interrupted sector change.
+//!
+//! The store works with any [`NorFlash`], like `esp-storage`'s `FlashStorage` on the device or
+//! the [`mem::MemFlash`] in this crate on the host:
+//!
+//! ```
+//! use kv_store::{mem::MemFlash, Store};
+//!
+//! // four sectors of 4 KiB
+//! let flash: MemFlash<{ 4 * 4096 }, 4096> = MemFlash::new();
+//! let mut store = Store::mount(flash, 0..4 * 4096).unwrap();
+//!
+//! store.set("name", b"esp").unwrap();
+//! store.set("name", b"esp32c3").unwrap();
+//!
+//! let mut buffer = [0; 32];
+//! assert_eq!(store.get("name", &mut buffer), Ok(Some(&b"esp32c3"[..])));
+//!
+//! // the data is still there after a reset
+//! let mut store = Store::mount(store.into_inner(), 0..4 * 4096).unwrap();
+//! assert_eq!(store.get("name", &mut buffer), Ok(Some(&b"esp32c3"[..])));
+//! ```
+
+#![no_std]
+
+use core::{fmt, ops::Range};
+use embedded_storage::nor_flash::NorFlash;
+
+pub mod mem;
+
+/// Longest key in bytes
+pub const MAX_KEY_LEN: usize = 32;
+/// Longest value in bytes, values also have to fit into a sector
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// Entries and sector headers consist of 4 byte words, the flash must be able to read and write
+/// them
+const WORD: usize = 4;
+const ERASED: [u8; WORD] = [0xff; WORD];
+const MAGIC: [u8; WORD] = *b"KVS1";
+/// Magic, sequence number, inverted sequence number and the compacted marker
+const HEADER_LEN: u32 = 16;
+/// Offset of the word that is cleared once the live entries of the next sector were copied
+const COMPACTED: u32 = 12;
+/// The value length of a deleted key
+const DELETED: u16 = u16::MAX;
+/// The length word, the key and value padded to a word and the checksum
+const MAX_ENTRY_LEN: usize = WORD + padded(MAX_KEY_LEN + MAX_VALUE_LEN) + WORD;
+
+/// Errors returned by the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Reading, writing or erasing the flash failed
+    Flash(E),
+    /// The range isn't aligned to sectors, has fewer than two sectors, or the flash can't be read
+    /// and written in 4 byte words
+    InvalidRange,
+    /// The range contains data that wasn't written by the store, see [`Store::format`]
+    NotFormatted,
+    /// The key is empty or longer than [`MAX_KEY_LEN`]
+    InvalidKey,
+    /// The value is longer than [`MAX_VALUE_LEN`] or the entry doesn't fit into a sector
+    ValueTooLarge,
+    /// The value doesn't fit into the buffer
+    BufferTooSmall,
+    /// There is no room left, even after dropping all outdated entries
+    Full,
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// What a sector header says about the sector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sector {
+    /// The whole sector is erased
+    Erased,
+    /// The sector is in use, the higher the sequence number the newer its entries
+    Used { seq: u32, compacted: bool },
+    /// Neither, an erase or writing the header was interrupted, there's nothing to keep in it
+    Damaged,
+}
+
+/// What was found at an offset of a sector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// An entry of `len` bytes, which is only used if its checksum is `intact`
+    Entry { len: u32, intact: bool },
+    /// The rest of the sector is erased
+    Free,
+    /// The rest of the sector can't be used, e.g. because writing an entry was interrupted
+    End,
+}
+
+/// An entry read from flash or about to be written
+struct Entry {
+    bytes: [u8; MAX_ENTRY_LEN],
+}
+
+impl Entry {
+    fn encode(key: &[u8], value: Option<&[u8]>) -> (Self, usize) {
+        let mut entry = Self {
+            bytes: [0; MAX_ENTRY_LEN],
+        };
+        let value_len = value.map_or(DELETED, |value| value.len() as u16);
+        entry.bytes[..WORD].copy_from_slice(&length_word(key.len() as u8, value_len));
+        let value = value.unwrap_or_default();
+        entry.bytes[WORD..][..key.len()].copy_from_slice(key);
+        entry.bytes[WORD + key.len()..][..value.len()].copy_from_slice(value);
+
+        let len = entry_len(key.len(), value.len());
+        let crc = crc32(&entry.bytes[..len - WORD]);
+        entry.bytes[len - WORD..len].copy_from_slice(&crc.to_le_bytes());
+        (entry, len)
+    }
+
+    fn key_len(&self) -> usize {
+        self.bytes[0] as usize
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.bytes[WORD..][..self.key_len()]
+    }
+
+    /// The value, `None` if the key was deleted
+    fn value(&self) -> Option<&[u8]> {
+        match u16::from_le_bytes([self.bytes[1], self.bytes[2]]) {
+            DELETED => None,
+            len => Some(&self.bytes[WORD + self.key_len()..][..len as usize]),
+        }
+    }
+}
+
+/// The sector entries are appended to
+#[derive(Debug, Clone, Copy)]
+struct Active {
+    index: u32,
+    seq: u32,
+    /// Where the free space of the sector starts
+    offset: u32,
+}
+
+/// A key-value store in a range of flash sectors
+pub struct Store<F> {
+    flash: F,
+    range: Range<u32>,
+    /// `None` while nothing was written
+    active: Option<Active>,
+}
+
+impl<F: NorFlash> Store<F> {
+    /// Opens the store in `range` of `flash`, which must cover at least two whole sectors
+    ///
+    /// Erased flash is an empty store. An interrupted write or sector change is cleaned up, so
+    /// the store doesn't depend on whether the power failed.
+    pub fn mount(flash: F, range: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let mut store = Self::new(flash, range)?;
+        store.recover()?;
+        Ok(store)
+    }
+
+    /// Erases the whole range, deleting all keys
+    ///
+    /// This is needed if the range was used for something else before.
+    pub fn format(flash: F, range: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let mut store = Self::new(flash, range)?;
+        store.erase_all()?;
+        Ok(store)
+    }
+
+    /// Like [`mount`](Self::mount), but formats the range if it contains data that wasn't written
+    /// by the store
+    ///
+    /// Only use this if nothing else keeps data in the range, whatever was there is lost.
+    pub fn mount_or_format(flash: F, range: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let mut store = Self::new(flash, range)?;
+        match store.recover() {
+            Err(Error::NotFormatted) => store.erase_all()?,
+            result => result?,
+        }
+        Ok(store)
+    }
+
+    /// Returns the flash
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Checks the range, nothing is read yet
+    fn new(flash: F, range: Range<u32>) -> Result<Self, Error<F::Error>> {
+        let sector_size = F::ERASE_SIZE as u32;
+        let supported = |size: usize| size <= WORD && WORD.is_multiple_of(size);
+        if !supported(F::READ_SIZE)
+            || !supported(F::WRITE_SIZE)
+            || !range.start.is_multiple_of(sector_size)
+            || !range.end.is_multiple_of(sector_size)
+            || range.end as usize > flash.capacity()
+            || range.len() < 2 * sector_size as usize
+        {
+            return Err(Error::InvalidRange);
+        }
+
+        Ok(Self {
+            flash,
+            range,
+            active: None,
+        })
+    }
+
+    /// Finds the newest sector and finishes or rolls back an interrupted sector change
+    fn recover(&mut self) -> Result<(), Error<F::Error>> {
+        self.active = None;
+
+        let mut newest: Option<(u32, u32, bool)> = None;
+        let mut foreign = false;
+        for index in 0..self.sectors() {
+            match self.sector(index)? {
+                Sector::Erased => {}
+                Sector::Used { seq, compacted } => {
+                    if newest.is_none_or(|(_, newest, _)| seq > newest) {
+                        newest = Some((index, seq, compacted));
+                    }
+                }
+                // unless only writing the header was interrupted, the data isn't ours
+                Sector::Damaged => foreign |= !self.is_erased(index, HEADER_LEN)?,
+            }
+        }
+        if foreign && newest.is_none() {
+            return Err(Error::NotFormatted);
+        }
+
+        // there's nothing we need in damaged sectors: if the header of a new sector was
+        // interrupted, it's empty, if erasing a sector was interrupted, it wasn't needed
+        for index in 0..self.sectors() {
+            if self.sector(index)? == Sector::Damaged {
+                self.erase(index)?;
+            }
+        }
+
+        if let Some((index, seq, compacted)) = newest {
+            // the sector after the newest one is always erased, unless a sector change was
+            // interrupted
+            let next = self.next(index);
+            if matches!(self.sector(next)?, Sector::Used { .. }) {
+                if compacted {
+                    // its live entries were copied already
+                    self.erase(next)?;
+                } else {
+                    // copying its live entries was interrupted, start over
+                    self.erase(index)?;
+                    return self.recover();
+                }
+            }
+            let offset = self.free_offset(index)?;
+            self.active = Some(Active { index, seq, offset });
+        }
+
+        Ok(())
+    }
+
+    /// Erases all sectors, the store is empty afterwards
+    fn erase_all(&mut self) -> Result<(), Error<F::Error>> {
+        self.flash
+            .erase(self.range.start, self.range.end)
+            .map_err(Error::Flash)?;
+        self.active = None;
+        Ok(())
+    }
+
+    /// Reads the value of `key` into `buffer`, returns `None` if the key isn't set
+    pub fn get<'b>(
+        &mut self,
+        key: &str,
+        buffer: &'b mut [u8],
+    ) -> Result<Option<&'b [u8]>, Error<F::Error>> {
+        check_key(key)?;
+        let Some(active) = self.active else {
+            return Ok(None);
+        };
+
+        let mut latest = Entry {
+            bytes: [0; MAX_ENTRY_LEN],
+        };
+        let mut found = false;
+        let mut entry = Entry {
+            bytes: [0; MAX_ENTRY_LEN],
+        };
+        for index in self.oldest_first(active.index) {
+            if !matches!(self.sector(index)?, Sector::Used { .. }) {
+                continue;
+            }
+            let mut offset = HEADER_LEN;
+            while let Slot::Entry { len, intact } = self.read_entry(index, offset, &mut entry)? {
+                if intact && entry.key() == key.as_bytes() {
+                    latest.bytes = entry.bytes;
+                    found = true;
+                }
+                offset += len;
+            }
+        }
+
+        match latest.value().filter(|_| found) {
+            None => Ok(None),
+            Some(value) => {
+                let buffer = buffer.get_mut(..value.len()).ok_or(Error::BufferTooSmall)?;
+                buffer.copy_from_slice(value);
+                Ok(Some(buffer))
+            }
+        }
+    }
+
+    /// Sets `key` to `value`
+    ///
+    /// Nothing is written if the key already has this value.
+    pub fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error<F::Error>> {
+        check_key(key)?;
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::ValueTooLarge);
+        }
+        let mut current = [0; MAX_VALUE_LEN];
+        if self.get(key, &mut current)? == Some(value) {
+            return Ok(());
+        }
+        let (entry, len) = Entry::encode(key.as_bytes(), Some(value));
+        self.append(&entry.bytes[..len])
+    }
+
+    /// Deletes `key`, if it's set
+    pub fn remove(&mut self, key: &str) -> Result<(), Error<F::Error>> {
+        check_key(key)?;
+        let mut current = [0; MAX_VALUE_LEN];
+        if self.get(key, &mut current)?.is_none() {
+            return Ok(());
+        }
+        let (entry, len) = Entry::encode(key.as_bytes(), None);
+        self.append(&entry.bytes[..len])
+    }
+
+    /// Writes an entry, moving on to the next sector if it doesn't fit into the current one
+    fn append(&mut self, entry: &[u8]) -> Result<(), Error<F::Error>> {
+        if entry.len() as u32 > self.sector_size() - HEADER_LEN {
+            return Err(Error::ValueTooLarge);
+        }
+        // every sector change drops the outdated entries of one sector, after a full round
+        // there's nothing left to drop
+        for _ in 0..=self.sectors() {
+            if let Some(active) = self.active {
+                if active.offset + entry.len() as u32 <= self.sector_size() {
+                    return self.write_entry(entry);
+                }
+            }
+            self.next_sector()?;
+        }
+        Err(Error::Full)
+    }
+
+    /// Starts a new sector after the active one and frees the sector after it
+    ///
+    /// The sector after the active one is always erased. The one after it is the oldest, if it's
+    /// in use, its live entries are copied to the new sector before it's erased. Only then the
+    /// new sector is marked as compacted, if the power fails before, [`mount`](Self::mount)
+    /// throws the new sector away and the oldest one is still complete.
+    fn next_sector(&mut self) -> Result<(), Error<F::Error>> {
+        let (index, seq) = match self.active {
+            Some(active) => (self.next(active.index), active.seq.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        let mut header = [0xff; HEADER_LEN as usize];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        header[8..12].copy_from_slice(&(!seq).to_le_bytes());
+        self.write(index, 0, &header[..COMPACTED as usize])?;
+        self.active = Some(Active {
+            index,
+            seq,
+            offset: HEADER_LEN,
+        });
+
+        let oldest = self.next(index);
+        let oldest_used = matches!(self.sector(oldest)?, Sector::Used { .. });
+        if oldest_used {
+            let mut entry = Entry {
+                bytes: [0; MAX_ENTRY_LEN],
+            };
+            let mut offset = HEADER_LEN;
+            while let Slot::Entry { len, intact } = self.read_entry(oldest, offset, &mut entry)? {
+                offset += len;
+                // deleted keys don't need to be remembered, there's nothing older left
+                if intact
+                    && entry.value().is_some()
+                    && !self.newer_entry(entry.key(), oldest, offset)?
+                {
+                    self.write_entry(&entry.bytes[..len as usize])?;
+                }
+            }
+        }
+
+        self.write(index, COMPACTED, &[0; WORD])?;
+        if oldest_used {
+            self.erase(oldest)?;
+        }
+        Ok(())
+    }
+
+    /// Whether there's an entry for `key` after `offset` in sector `index` or in a newer sector
+    fn newer_entry(
+        &mut self,
+        key: &[u8],
+        index: u32,
+        offset: u32,
+    ) -> Result<bool, Error<F::Error>> {
+        let Some(active) = self.active else {
+            return Ok(false);
+        };
+        let mut entry = Entry {
+            bytes: [0; MAX_ENTRY_LEN],
+        };
+        let mut offset = offset;
+        let mut index = index;
+        loop {
+            while let Slot::Entry { len, intact } = self.read_entry(index, offset, &mut entry)? {
+                if intact && entry.key() == key {
+                    return Ok(true);
+                }
+                offset += len;
+            }
+            if index == active.index {
+                return Ok(false);
+            }
+            index = self.next(index);
+            offset = HEADER_LEN;
+        }
+    }
+
+    /// Appends an entry to the active sector, which has room for it
+    fn write_entry(&mut self, entry: &[u8]) -> Result<(), Error<F::Error>> {
+        let Some(active) = self.active else {
+            return Ok(());
+        };
+        // if writing fails half way, the rest of the sector can't be used
+        self.active = Some(Active {
+            offset: self.sector_size(),
+            ..active
+        });
+        self.write(active.index, active.offset, entry)?;
+        self.active = Some(Active {
+            offset: active.offset + entry.len() as u32,
+            ..active
+        });
+        Ok(())
+    }
+
+    /// Reads the entry at `offset` of sector `index` into `entry`
+    fn read_entry(
+        &mut self,
+        index: u32,
+        offset: u32,
+        entry: &mut Entry,
+    ) -> Result<Slot, Error<F::Error>> {
+        let sector_size = self.sector_size();
+        if offset + WORD as u32 > sector_size {
+            return Ok(Slot::End);
+        }
+        let mut word = [0; WORD];
+        self.read(index, offset, &mut word)?;
+        if word == ERASED {
+            return Ok(match self.is_erased(index, offset)? {
+                true => Slot::Free,
+                false => Slot::End,
+            });
+        }
+
+        let key_len = word[0];
+        let value_len = u16::from_le_bytes([word[1], word[2]]);
+        if word != length_word(key_len, value_len)
+            || key_len == 0
+            || key_len as usize > MAX_KEY_LEN
+            || (value_len as usize > MAX_VALUE_LEN && value_len != DELETED)
+        {
+            // writing the length was interrupted
+            return Ok(Slot::End);
+        }
+        let value_len = if value_len == DELETED { 0 } else { value_len };
+        let len = entry_len(key_len as usize, value_len as usize);
+        if offset + len as u32 > sector_size {
+            return Ok(Slot::End);
+        }
+
+        self.read(index, offset, &mut entry.bytes[..len])?;
+        let crc = crc32(&entry.bytes[..len - WORD]).to_le_bytes();
+        Ok(Slot::Entry {
+            len: len as u32,
+            intact: entry.bytes[len - WORD..len] == crc,
+        })
+    }
+
+    /// Finds the free space of sector `index`
+    fn free_offset(&mut self, index: u32) -> Result<u32, Error<F::Error>> {
+        let mut entry = Entry {
+            bytes: [0; MAX_ENTRY_LEN],
+        };
+        let mut offset = HEADER_LEN;
+        loop {
+            match self.read_entry(index, offset, &mut entry)? {
+                Slot::Entry { len, .. } => offset += len,
+                Slot::Free => return Ok(offset),
+                Slot::End => return Ok(self.sector_size()),
+            }
+        }
+    }
+
+    fn sector(&mut self, index: u32) -> Result<Sector, Error<F::Error>> {
+        let mut header = [0; HEADER_LEN as usize];
+        self.read(index, 0, &mut header)?;
+        let word =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        if header[..4] == MAGIC && word(8) == !word(4) {
+            return Ok(Sector::Used {
+                seq: word(4),
+                // anything else means that clearing the marker was interrupted
+                compacted: header[COMPACTED as usize..] == [0; WORD],
+            });
+        }
+        Ok(match self.is_erased(index, 0)? {
+            true => Sector::Erased,
+            false => Sector::Damaged,
+        })
+    }
+
+    /// Whether sector `index` is erased from `offset` to its end
+    fn is_erased(&mut self, index: u32, offset: u32) -> Result<bool, Error<F::Error>> {
+        let mut chunk = [0; 64];
+        let mut offset = offset;
+        while offset < self.sector_size() {
+            let len = chunk.len().min((self.sector_size() - offset) as usize);
+            self.read(index, offset, &mut chunk[..len])?;
+            if chunk[..len].iter().any(|&b| b != 0xff) {
+                return Ok(false);
+            }
+            offset += len as u32;
+        }
+        Ok(true)
+    }
+
+    /// The sectors in the order they were used, ending with `active`
+    fn oldest_first(&self, active: u32) -> impl Iterator<Item = u32> {
+        let sectors = self.sectors();
+        (1..=sectors).map(move |i| (active + i) % sectors)
+    }
+
+    fn read(&mut self, index: u32, offset: u32, bytes: &mut [u8]) -> Result<(), Error<F::Error>> {
+        let address = self.address(index) + offset;
+        self.flash.read(address, bytes).map_err(Error::Flash)
+    }
+
+    fn write(&mut self, index: u32, offset: u32, bytes: &[u8]) -> Result<(), Error<F::Error>> {
+        let address = self.address(index) + offset;
+        self.flash.write(address, bytes).map_err(Error::Flash)
+    }
+
+    fn erase(&mut self, index: u32) -> Result<(), Error<F::Error>> {
+        let address = self.address(index);
+        self.flash
+            .erase(address, address + self.sector_size())
+            .map_err(Error::Flash)
+    }
+
+    fn address(&self, index: u32) -> u32 {
+        self.range.start + index * self.sector_size()
+    }
+
+    fn next(&self, index: u32) -> u32 {
+        (index + 1) % self.sectors()
+    }
+
+    fn sectors(&self) -> u32 {
+        self.range.len() as u32 / self.sector_size()
+    }
+
+    fn sector_size(&self) -> u32 {
+        F::ERASE_SIZE as u32
+    }
+}
+
+fn check_key<E>(key: &str) -> Result<(), Error<E>> {
+    match key.len() {
+        1..=MAX_KEY_LEN => Ok(()),
+        _ => Err(Error::InvalidKey),
+    }
+}
+
+/// The first word of an entry: the key length, the value length and a check byte, so that a
+/// partially written word is detected
+fn length_word(key_len: u8, value_len: u16) -> [u8; WORD] {
+    let [low, high] = value_len.to_le_bytes();
+    [key_len, low, high, !(key_len ^ low ^ high)]
+}
+
+const fn padded(len: usize) -> usize {
+    len.next_multiple_of(WORD)
+}
+
+fn entry_len(key_len: usize, value_len: usize) -> usize {
+    WORD + padded(key_len + value_len) + WORD
+}
+
+/// CRC-32 as used by Ethernet and zlib
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use mem::{MemError, MemFlash};
+    use std::{collections::BTreeMap, format, vec::Vec};
+
+    /// Small sectors, so that a few entries fill them
+    type Flash = MemFlash<{ 4 * 256 }, 256>;
+    const RANGE: Range<u32> = 0..4 * 256;
+
+    fn mount(flash: Flash) -> Store<Flash> {
+        Store::mount(flash, RANGE).unwrap()
+    }
+
+    fn get(store: &mut Store<Flash>, key: &str) -> Option<Vec<u8>> {
+        let mut buffer = [0; MAX_VALUE_LEN];
+        store.get(key, &mut buffer).unwrap().map(<[u8]>::to_vec)
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let mut store = mount(Flash::new());
+        assert_eq!(get(&mut store, "a"), None);
+
+        store.set("a", b"1").unwrap();
+        store.set("b", b"").unwrap();
+        store.set("a", b"22").unwrap();
+        assert_eq!(get(&mut store, "a"), Some(b"22".to_vec()));
+        assert_eq!(get(&mut store, "b"), Some(Vec::new()));
+
+        store.remove("a").unwrap();
+        assert_eq!(get(&mut store, "a"), None);
+        assert_eq!(get(&mut store, "b"), Some(Vec::new()));
+
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "a"), None);
+        assert_eq!(get(&mut store, "b"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn skips_unchanged_values() {
+        let mut store = mount(Flash::new());
+        store.set("a", b"1").unwrap();
+        let before = *store.flash.data();
+
+        store.set("a", b"1").unwrap();
+        store.remove("b").unwrap();
+        assert_eq!(*store.flash.data(), before);
+    }
+
+    #[test]
+    fn checks_arguments() {
+        let mut store = mount(Flash::new());
+        let mut buffer = [0; 4];
+        assert_eq!(store.set("", b""), Err(Error::InvalidKey));
+        assert_eq!(
+            store.get(&"k".repeat(33), &mut buffer),
+            Err(Error::InvalidKey)
+        );
+        assert_eq!(store.set("k", &[0; 257]), Err(Error::ValueTooLarge));
+        // fits the limit, but not into a small sector
+        assert_eq!(store.set("k", &[0; 256]), Err(Error::ValueTooLarge));
+
+        store.set("k", b"12345").unwrap();
+        assert_eq!(store.get("k", &mut buffer), Err(Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn checks_range() {
+        let mount = |range| Store::mount(Flash::new(), range).err();
+        assert_eq!(mount(0..256), Some(Error::InvalidRange));
+        assert_eq!(mount(0..300), Some(Error::InvalidRange));
+        assert_eq!(mount(128..640), Some(Error::InvalidRange));
+        assert_eq!(mount(512..1280), Some(Error::InvalidRange));
+        assert_eq!(mount(512..1024), None);
+    }
+
+    #[test]
+    fn keeps_other_data_untouched() {
+        let mut flash = Flash::new();
+        flash.data_mut()[..256].fill(0x55);
+
+        // the store only uses its range
+        let mut store = Store::mount(flash, 256..1024).unwrap();
+        for i in 0..100 {
+            store.set("counter", format!("{i}").as_bytes()).unwrap();
+        }
+        assert!(store.into_inner().data()[..256].iter().all(|&b| b == 0x55));
+    }
+
+    #[test]
+    fn refuses_foreign_data() {
+        let mut flash = Flash::new();
+        // e.g. a record of `wifi-credentials`
+        flash.data_mut()[..108].fill(0x42);
+        assert_eq!(
+            Store::mount(flash.clone(), RANGE).err(),
+            Some(Error::NotFormatted)
+        );
+
+        let mut store = Store::format(flash.clone(), RANGE).unwrap();
+        assert_eq!(get(&mut store, "a"), None);
+        store.set("a", b"1").unwrap();
+
+        let mut store = Store::mount_or_format(flash, RANGE).unwrap();
+        assert_eq!(get(&mut store, "a"), None);
+        store.set("a", b"1").unwrap();
+        // a store is kept
+        let mut store = Store::mount_or_format(store.into_inner(), RANGE).unwrap();
+        assert_eq!(get(&mut store, "a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn moves_live_entries() {
+        let mut store = mount(Flash::new());
+        store.set("constant", b"stays").unwrap();
+        store.set("deleted", b"goes").unwrap();
+        store.remove("deleted").unwrap();
+        // many sector changes, each one copies `constant`
+        for i in 0..200 {
+            store.set("counter", format!("{i}").as_bytes()).unwrap();
+        }
+
+        let mut store = mount(store.into_inner());
+        assert_eq!(get(&mut store, "constant"), Some(b"stays".to_vec()));
+        assert_eq!(get(&mut store, "deleted"), None);
+        assert_eq!(get(&mut store, "counter"), Some(b"199".to_vec()));
+    }
+
+    #[test]
+    fn levels_wear() {
+        let mut store = mount(Flash::new());
+        for i in 0..2000 {
+            store.set("counter", format!("{i}").as_bytes()).unwrap();
+        }
+        let flash = store.into_inner();
+        let erases: Vec<usize> = (0..4).map(|sector| flash.erases(sector)).collect();
+        let (min, max) = (erases.iter().min().unwrap(), erases.iter().max().unwrap());
+        assert!(*min > 25, "{erases:?}");
+        assert!(max - min <= 1, "{erases:?}");
+    }
+
+    #[test]
+    fn fills_up() {
+        let mut store = mount(Flash::new());
+        let value = [0xaa; 50];
+        let mut keys = 0;
+        loop {
+            match store.set(&format!("key{keys}"), &value) {
+                Ok(()) => keys += 1,
+                Err(Error::Full) => break,
+                Err(err) => panic!("{err:?}"),
+            }
+        }
+        // three of the four sectors, one is kept free, with three 64 byte entries each
+        assert_eq!(keys, 9);
+
+        let mut store = mount(store.into_inner());
+        for key in 0..keys {
+            assert_eq!(get(&mut store, &format!("key{key}")), Some(value.to_vec()));
+        }
+        // deleting makes room again
+        store.remove("key0").unwrap();
+        store.set("key0", b"small").unwrap();
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Set(&'static str, Vec<u8>),
+        Remove(&'static str),
+    }
+
+    impl Op {
+        fn key(&self) -> &'static str {
+            match self {
+                Self::Set(key, _) | Self::Remove(key) => key,
+            }
+        }
+
+        fn apply(&self, store: &mut Store<Flash>) -> Result<(), Error<MemError>> {
+            match self {
+                Self::Set(key, value) => store.set(key, value),
+                Self::Remove(key) => store.remove(key),
+            }
+        }
+
+        fn apply_to(&self, model: &mut BTreeMap<&'static str, Vec<u8>>) {
+            match self {
+                Self::Set(key, value) => model.insert(key, value.clone()),
+                Self::Remove(key) => model.remove(key),
+            };
+        }
+    }
+
+    /// Checks that the store holds the committed values, except for the key of the
+    /// interrupted operation, which has either the old or the new value
+    fn check(
+        store: &mut Store<Flash>,
+        committed: &BTreeMap<&'static str, Vec<u8>>,
+        interrupted: &Op,
+    ) {
+        let mut done = committed.clone();
+        interrupted.apply_to(&mut done);
+        for key in ["a", "b", "c", "d"] {
+            let value = get(store, key);
+            let old = committed.get(key).cloned();
+            let new = done.get(key).cloned();
+            assert!(
+                value == old || (key == interrupted.key() && value == new),
+                "{key}: {value:?}, expected {old:?} or {new:?} after {interrupted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn survives_power_failures() {
+        let keys = ["a", "b", "c", "d"];
+        let ops: Vec<Op> = (0..150)
+            .map(|i| match i % 7 {
+                3 => Op::Remove(keys[i % 4]),
+                _ => Op::Set(keys[i % 4], format!("{i}").repeat(i % 5).into_bytes()),
+            })
+            .collect();
+
+        for budget in 0.. {
+            let mut flash = Flash::new();
+            flash.fail_after(budget);
+            let mut store = mount(flash);
+
+            let mut committed = BTreeMap::new();
+            let mut interrupted = None;
+            for op in &ops {
+                match op.apply(&mut store) {
+                    Ok(()) => op.apply_to(&mut committed),
+                    Err(Error::Flash(MemError::PowerLoss)) => {
+                        interrupted = Some(op);
+                        break;
+                    }
+                    Err(err) => panic!("{err:?}"),
+                }
+            }
+            // the whole workload ran, every operation was interrupted once
+            let Some(interrupted) = interrupted else {
+                // the workload went around the ring, so sector changes were interrupted too
+                assert!((0..4).all(|sector| store.flash.erases(sector) > 0));
+                break;
+            };
+
+            let mut flash = store.into_inner();
+            flash.restore_power();
+
+            // the power may fail again while cleaning up
+            for recovery_budget in 0..3 {
+                let mut flash = flash.clone();
+                flash.fail_after(recovery_budget);
+                if let Ok(store) = Store::mount(flash.clone(), RANGE) {
+                    flash = store.into_inner();
+                }
+                flash.restore_power();
+                check(&mut mount(flash), &committed, interrupted);
+            }
+
+            let mut store = mount(flash);
+            check(&mut store, &committed, interrupted);
+
+            // and the store keeps working
+            for op in &ops[..20] {
+                op.apply(&mut store).unwrap();
+            }
+            store.set("a", b"after").unwrap();
+            let mut store = mount(store.into_inner());
+            assert_eq!(get(&mut store, "a"), Some(b"after".to_vec()));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! An in-memory NOR flash for tests on the host.
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Errors returned by [`MemFlash`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MemError {
+    /// The offset or length isn't a multiple of the read, write or erase size
+    NotAligned,
+    OutOfBounds,
+    /// The simulated power failure happened, see [`MemFlash::fail_after`]
+    PowerLoss,
+}
+
+impl NorFlashError for MemError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::NotAligned => NorFlashErrorKind::NotAligned,
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// `SIZE` bytes of flash with sectors of `SECTOR` bytes, written in 4 byte words
+///
+/// Like real NOR flash, erasing sets all bits of a sector and writing can only clear bits.
+/// Writing a word that isn't erased panics, the store never needs to.
+///
+/// The flash can simulate a power failure: the operation that exceeds the budget set with
+/// [`fail_after`](Self::fail_after) is torn, a word is only partially written or a sector only
+/// partially erased, and fails with [`MemError::PowerLoss`]. So do all following operations,
+/// until the power is back with [`restore_power`](Self::restore_power).
+#[derive(Debug, Clone)]
+pub struct MemFlash<const SIZE: usize, const SECTOR: usize> {
+    data: [u8; SIZE],
+    /// Word writes and sector erases left until the power fails
+    budget: Option<usize>,
+    /// The power failed, nothing is written or erased anymore
+    failed: bool,
+    /// Erases of each sector so far
+    erases: [usize; MAX_SECTORS],
+}
+
+/// Erases are counted for this many sectors
+const MAX_SECTORS: usize = 64;
+
+impl<const SIZE: usize, const SECTOR: usize> MemFlash<SIZE, SECTOR> {
+    /// Erased flash
+    pub const fn new() -> Self {
+        Self {
+            data: [0xff; SIZE],
+            budget: None,
+            failed: false,
+            erases: [0; MAX_SECTORS],
+        }
+    }
+
+    /// The power fails during the operation after `operations` word writes and sector erases
+    pub fn fail_after(&mut self, operations: usize) {
+        self.budget = Some(operations);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.budget = None;
+        self.failed = false;
+    }
+
+    pub fn data(&self) -> &[u8; SIZE] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8; SIZE] {
+        &mut self.data
+    }
+
+    /// How often `sector` was erased so far, only counted for the first 64 sectors
+    pub fn erases(&self, sector: usize) -> usize {
+        self.erases[sector]
+    }
+
+    /// Uses up one operation of the budget, returns `false` if the power fails during it
+    fn spend(&mut self) -> bool {
+        match &mut self.budget {
+            None => true,
+            Some(0) => {
+                self.failed = true;
+                false
+            }
+            Some(budget) => {
+                *budget -= 1;
+                true
+            }
+        }
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<usize, MemError> {
+        let offset = offset as usize;
+        if !offset.is_multiple_of(align) || !len.is_multiple_of(align) {
+            return Err(MemError::NotAligned);
+        }
+        if offset + len > SIZE {
+            return Err(MemError::OutOfBounds);
+        }
+        Ok(offset)
+    }
+}
+
+impl<const SIZE: usize, const SECTOR: usize> Default for MemFlash<SIZE, SECTOR> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const SECTOR: usize> ErrorType for MemFlash<SIZE, SECTOR> {
+    type Error = MemError;
+}
+
+impl<const SIZE: usize, const SECTOR: usize> ReadNorFlash for MemFlash<SIZE, SECTOR> {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+}
+
+impl<const SIZE: usize, const SECTOR: usize> NorFlash for MemFlash<SIZE, SECTOR> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let from = self.check(from, 0, SECTOR)?;
+        let to = self.check(to, 0, SECTOR)?;
+        if self.failed {
+            return Err(MemError::PowerLoss);
+        }
+        for start in (from..to).step_by(SECTOR) {
+            let powered = self.spend();
+            let sector = &mut self.data[start..start + SECTOR];
+            if !powered {
+                // only the first half was erased
+                sector[..SECTOR / 2].fill(0xff);
+                return Err(MemError::PowerLoss);
+            }
+            sector.fill(0xff);
+            if let Some(erases) = self.erases.get_mut(start / SECTOR) {
+                *erases += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+        if self.failed {
+            return Err(MemError::PowerLoss);
+        }
+        for (i, word) in bytes.as_chunks::<4>().0.iter().enumerate() {
+            assert_eq!(
+                self.data[offset + i * 4..][..4],
+                [0xff; 4],
+                "writing to a word that isn't erased at {:#x}",
+                offset + i * 4
+            );
+            let powered = self.spend();
+            let target = &mut self.data[offset + i * 4..][..4];
+            if !powered {
+                // only some of the bits were cleared
+                target[0] &= word[0];
+                target[1] &= word[1] | 0x0f;
+                return Err(MemError::PowerLoss);
+            }
+            target.copy_from_slice(word);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+//! Answers arbitrary queries, which must never panic, and checks that the responder doesn't
+//! answer its own responses.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mdns::{Error, Responder, Service};
+
+const SERVICES: &[Service<'static>] = &[Service {
+    instance: "LED control",
+    service_type: "_http._tcp",
+    port: 80,
+    txt: &["path=/"],
+}];
+
+fuzz_target!(|query: &[u8]| {
+    let responder = Responder::new("esp-radio", [192, 168, 1, 42]).with_services(SERVICES);
+    let mut response = [0; 1024];
+    if let Ok(Some(len)) = responder.respond(query, &mut response) {
+        assert!(len > 12);
+        let mut answer = [0; 1024];
+        assert_eq!(
+            responder.respond(&response[..len], &mut answer),
+            Err(Error::NotAQuery)
+        );
+    }
+});
@@ -0,0 +1,822 @@
+//! Multicast DNS responder.
+//!
+//! Without a DNS server that knows about it, a device on the local network can only be reached by
+//! its address, which DHCP may change at any time. With [mDNS], hosts resolve names ending in
+//! `.local` by multicasting the query to every device on the network, and the device with that
+//! name answers. [DNS-SD] builds on top of it: a browser asks who offers e.g. `_http._tcp`, and
+//! every device with a web server answers with the name, port and host of its service.
+//!
+//! A [`Responder`] answers the queries received on UDP port [`PORT`] of the multicast group
+//! [`MULTICAST_ADDRESS`], its responses are sent to the same group and port:
+//!
+//! ```
+//! use mdns::Responder;
+//!
+//! let responder = Responder::new("esp-radio", [192, 168, 1, 42]);
+//!
+//! // a query for the A record of `esp-radio.local`
+//! let query = [
+//!     0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+//!     9, b'e', b's', b'p', b'-', b'r', b'a', b'd', b'i', b'o', 5, b'l', b'o', b'c', b'a', b'l', 0,
+//!     0x00, 0x01, 0x00, 0x01, // type A, class IN
+//! ];
+//!
+//! let mut response = [0; 512];
+//! let len = responder.respond(&query, &mut response).unwrap().unwrap();
+//!
+//! // the address is the last part of the only answer
+//! assert_eq!(&response[len - 4..len], &[192, 168, 1, 42]);
+//! ```
+//!
+//! [mDNS]: https://www.rfc-editor.org/rfc/rfc6762
+//! [DNS-SD]: https://www.rfc-editor.org/rfc/rfc6763
+
+#![no_std]
+
+use core::fmt;
+
+/// The UDP port of mDNS, both queries and responses are sent from and to it
+pub const PORT: u16 = 5353;
+
+/// The IPv4 multicast group mDNS queries and responses are sent to
+pub const MULTICAST_ADDRESS: [u8; 4] = [224, 0, 0, 251];
+
+/// How long other hosts may cache the records that contain our address or name, in seconds
+///
+/// These change when DHCP gives us a new address, so they are kept shorter than the others.
+pub const HOST_TTL: u32 = 120;
+
+/// How long other hosts may cache the records that list our services, in seconds
+pub const SERVICE_TTL: u32 = 4500;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// The top bit of the class of a record tells caches to replace what they know about the name
+const CACHE_FLUSH: u16 = 0x8000;
+/// The top bit of the class of a question asks for a unicast response
+const UNICAST_RESPONSE: u16 = 0x8000;
+const MAX_NAME_LEN: usize = 255;
+const MAX_LABEL_LEN: usize = 63;
+/// Records per section of a response, further ones are left out
+const MAX_RECORDS: usize = 16;
+
+/// The name DNS-SD browsers query to find out which service types exist
+const SERVICES: Name<'static> = Name {
+    first: None,
+    rest: "_services._dns-sd._udp",
+};
+
+/// Errors returned by [`Responder`], don't send a response for any of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The message is not a standard query, e.g. the response of another responder
+    NotAQuery,
+    /// The query is truncated or a name in it is invalid
+    Malformed,
+    /// A label of our own names is empty or longer than 63 bytes, or a TXT entry is longer than
+    /// 255 bytes
+    InvalidName,
+    /// The buffer is too small for the response
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// A service advertised with DNS-SD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Service<'a> {
+    /// The name shown to users, e.g. `LED control`, it may contain spaces and dots
+    pub instance: &'a str,
+    /// The service type and protocol, e.g. `_http._tcp`
+    pub service_type: &'a str,
+    pub port: u16,
+    /// `key=value` pairs with details about the service, e.g. `path=/`
+    pub txt: &'a [&'a str],
+}
+
+impl<'a> Service<'a> {
+    /// `<service_type>.local`, the name that browsers for the service type query
+    fn type_name(&self) -> Name<'a> {
+        Name {
+            first: None,
+            rest: self.service_type,
+        }
+    }
+
+    /// `<instance>.<service_type>.local`, the name of the SRV and TXT records
+    fn instance_name(&self) -> Name<'a> {
+        Name {
+            first: Some(self.instance),
+            rest: self.service_type,
+        }
+    }
+}
+
+/// Answers the queries for our hostname and services
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Responder<'a> {
+    hostname: &'a str,
+    address: [u8; 4],
+    services: &'a [Service<'a>],
+}
+
+impl<'a> Responder<'a> {
+    /// Answers the queries for `<hostname>.local` with `address`
+    pub const fn new(hostname: &'a str, address: [u8; 4]) -> Self {
+        Self {
+            hostname,
+            address,
+            services: &[],
+        }
+    }
+
+    /// Also advertises `services`, all of them on this host
+    pub const fn with_services(self, services: &'a [Service<'a>]) -> Self {
+        Self { services, ..self }
+    }
+
+    /// Changes the address, e.g. after DHCP gave us a new one
+    ///
+    /// Send an [`announce`](Self::announce)ment afterwards, so caches update their records.
+    pub fn set_address(&mut self, address: [u8; 4]) {
+        self.address = address;
+    }
+
+    pub fn address(&self) -> [u8; 4] {
+        self.address
+    }
+
+    /// Writes the response to `query` into `response` and returns its length
+    ///
+    /// Returns `None` if the query isn't about us, which is the case for most queries on the
+    /// network. The response answers all questions about our names at once and includes the
+    /// records the querier will ask for next, e.g. our address along with a service.
+    ///
+    /// Responses are always multicast, and the answers the querier already knows aren't left
+    /// out.
+    pub fn respond(&self, query: &[u8], response: &mut [u8]) -> Result<Option<usize>, Error> {
+        let header = query.get(..HEADER_LEN).ok_or(Error::Malformed)?;
+        // QR must be 0 (query) and the opcode 0 (standard query)
+        if header[2] & 0xf8 != 0 {
+            return Err(Error::NotAQuery);
+        }
+        let questions = u16::from_be_bytes([header[4], header[5]]);
+
+        let mut answers = Records::new();
+        let mut additionals = Records::new();
+        let mut pos = HEADER_LEN;
+        for _ in 0..questions {
+            let name = pos;
+            pos = skip_name(query, pos)?;
+            let fixed = query.get(pos..pos + 4).ok_or(Error::Malformed)?;
+            pos += 4;
+
+            let qtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+            // we always respond with multicast, which the querier receives as well
+            let qclass = u16::from_be_bytes([fixed[2], fixed[3]]) & !UNICAST_RESPONSE;
+            if matches!(qclass, CLASS_IN | CLASS_ANY) {
+                self.answer(query, name, qtype, &mut answers, &mut additionals)?;
+            }
+        }
+
+        if answers.is_empty() {
+            return Ok(None);
+        }
+        // no need to repeat an answer
+        additionals.retain(|record| !answers.contains(record));
+        self.write(&answers, &additionals, None, response).map(Some)
+    }
+
+    /// Writes an unsolicited response with all our records into `response`
+    ///
+    /// Send it when the address was assigned or changed, so that others notice us without
+    /// asking. Sending it twice, one second apart, makes up for lost packets.
+    pub fn announce(&self, response: &mut [u8]) -> Result<usize, Error> {
+        self.write(&self.all_records(), &Records::new(), None, response)
+    }
+
+    /// Writes a response that tells others to forget our records into `response`
+    ///
+    /// Send it before shutting down or leaving the network.
+    pub fn goodbye(&self, response: &mut [u8]) -> Result<usize, Error> {
+        self.write(&self.all_records(), &Records::new(), Some(0), response)
+    }
+
+    fn host_name(&self) -> Name<'a> {
+        Name {
+            first: Some(self.hostname),
+            rest: "",
+        }
+    }
+
+    /// Collects the records that answer the question at `name` in `query`
+    fn answer(
+        &self,
+        query: &[u8],
+        name: usize,
+        qtype: u16,
+        answers: &mut Records,
+        additionals: &mut Records,
+    ) -> Result<(), Error> {
+        let wants = |rtype| qtype == rtype || qtype == TYPE_ANY;
+
+        if name_eq(query, name, self.host_name())? {
+            if wants(TYPE_A) {
+                answers.push(Record::Address);
+            }
+            return Ok(());
+        }
+
+        if name_eq(query, name, SERVICES)? && wants(TYPE_PTR) {
+            for (i, service) in self.services.iter().enumerate() {
+                // every type is listed once, even if several services have it
+                let listed = self.services[..i]
+                    .iter()
+                    .any(|s| s.service_type.eq_ignore_ascii_case(service.service_type));
+                if !listed {
+                    answers.push(Record::ServiceType(i));
+                }
+            }
+            return Ok(());
+        }
+
+        for (i, service) in self.services.iter().enumerate() {
+            if name_eq(query, name, service.type_name())? && wants(TYPE_PTR) {
+                answers.push(Record::Instance(i));
+                additionals.push(Record::Srv(i));
+                additionals.push(Record::Txt(i));
+                additionals.push(Record::Address);
+            }
+            if name_eq(query, name, service.instance_name())? {
+                if wants(TYPE_SRV) {
+                    answers.push(Record::Srv(i));
+                    additionals.push(Record::Address);
+                }
+                if wants(TYPE_TXT) {
+                    answers.push(Record::Txt(i));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn all_records(&self) -> Records {
+        let mut records = Records::new();
+        records.push(Record::Address);
+        for i in 0..self.services.len() {
+            records.push(Record::ServiceType(i));
+            records.push(Record::Instance(i));
+            records.push(Record::Srv(i));
+            records.push(Record::Txt(i));
+        }
+        records
+    }
+
+    /// Writes a response with the given sections, `ttl` replaces the TTL of all records
+    fn write(
+        &self,
+        answers: &Records,
+        additionals: &Records,
+        ttl: Option<u32>,
+        response: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut out = Writer {
+            buf: response,
+            len: 0,
+        };
+        // mDNS responses have the id 0, are authoritative and have no questions
+        out.put(&[0, 0, 0x84, 0, 0, 0])?;
+        out.put(&(answers.len as u16).to_be_bytes())?;
+        out.put(&[0, 0])?;
+        out.put(&(additionals.len as u16).to_be_bytes())?;
+
+        for &record in answers.iter().chain(additionals.iter()) {
+            self.write_record(&mut out, record, ttl)?;
+        }
+        Ok(out.len)
+    }
+
+    fn write_record(
+        &self,
+        out: &mut Writer,
+        record: Record,
+        ttl: Option<u32>,
+    ) -> Result<(), Error> {
+        // our address and names are unique on the network, the lists of services are shared with
+        // other devices and must not be flushed from caches
+        let (name, rtype, class, default_ttl) = match record {
+            Record::Address => (self.host_name(), TYPE_A, CLASS_IN | CACHE_FLUSH, HOST_TTL),
+            Record::ServiceType(_) => (SERVICES, TYPE_PTR, CLASS_IN, SERVICE_TTL),
+            Record::Instance(i) => (
+                self.services[i].type_name(),
+                TYPE_PTR,
+                CLASS_IN,
+                SERVICE_TTL,
+            ),
+            Record::Srv(i) => {
+                let name = self.services[i].instance_name();
+                (name, TYPE_SRV, CLASS_IN | CACHE_FLUSH, HOST_TTL)
+            }
+            Record::Txt(i) => {
+                let name = self.services[i].instance_name();
+                (name, TYPE_TXT, CLASS_IN | CACHE_FLUSH, SERVICE_TTL)
+            }
+        };
+        out.put_name(name)?;
+        out.put(&rtype.to_be_bytes())?;
+        out.put(&class.to_be_bytes())?;
+        out.put(&ttl.unwrap_or(default_ttl).to_be_bytes())?;
+
+        // the length of the data is filled in once it's written
+        let length = out.len;
+        out.put(&[0, 0])?;
+        match record {
+            Record::Address => out.put(&self.address)?,
+            Record::ServiceType(i) => out.put_name(self.services[i].type_name())?,
+            Record::Instance(i) => out.put_name(self.services[i].instance_name())?,
+            Record::Srv(i) => {
+                // priority and weight only matter with several hosts for the same service
+                out.put(&[0, 0, 0, 0])?;
+                out.put(&self.services[i].port.to_be_bytes())?;
+                out.put_name(self.host_name())?;
+            }
+            Record::Txt(i) => {
+                let txt = self.services[i].txt;
+                for entry in txt {
+                    let len = u8::try_from(entry.len()).map_err(|_| Error::InvalidName)?;
+                    out.put(&[len])?;
+                    out.put(entry.as_bytes())?;
+                }
+                // a TXT record must not be empty, an empty string stands for no entries
+                if txt.is_empty() {
+                    out.put(&[0])?;
+                }
+            }
+        }
+        let data_len = (out.len - length - 2) as u16;
+        out.buf[length..length + 2].copy_from_slice(&data_len.to_be_bytes());
+        Ok(())
+    }
+}
+
+/// A name in the `.local` domain
+#[derive(Debug, Clone, Copy)]
+struct Name<'a> {
+    /// A label that may contain dots, e.g. the instance name of a service
+    first: Option<&'a str>,
+    /// The labels after it, separated by dots
+    rest: &'a str,
+}
+
+impl<'a> Name<'a> {
+    fn labels(self) -> impl Iterator<Item = &'a [u8]> {
+        self.first
+            .into_iter()
+            .chain(self.rest.split('.').filter(|label| !label.is_empty()))
+            .chain(["local"])
+            .map(str::as_bytes)
+    }
+}
+
+/// A record we can send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Record {
+    /// The A record of our hostname
+    Address,
+    /// The PTR record from the list of service types to the type of the `n`th service
+    ServiceType(usize),
+    /// The PTR record from the type of the `n`th service to its instance
+    Instance(usize),
+    /// The SRV record with the port and host of the `n`th service
+    Srv(usize),
+    /// The TXT record with the details of the `n`th service
+    Txt(usize),
+}
+
+/// A set of records in the order they were added
+struct Records {
+    records: [Record; MAX_RECORDS],
+    len: usize,
+}
+
+impl Records {
+    fn new() -> Self {
+        Self {
+            records: [Record::Address; MAX_RECORDS],
+            len: 0,
+        }
+    }
+
+    /// Adds `record` unless it's already there or the set is full
+    fn push(&mut self, record: Record) {
+        if !self.contains(&record) && self.len < MAX_RECORDS {
+            self.records[self.len] = record;
+            self.len += 1;
+        }
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Record) -> bool) {
+        let mut len = 0;
+        for i in 0..self.len {
+            if keep(&self.records[i]) {
+                self.records[len] = self.records[i];
+                len += 1;
+            }
+        }
+        self.len = len;
+    }
+
+    fn contains(&self, record: &Record) -> bool {
+        self.iter().any(|r| r == record)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Record> {
+        self.records[..self.len].iter()
+    }
+}
+
+struct Writer<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Writes `name` uncompressed, as a sequence of length-prefixed labels ending with an empty
+    /// one
+    fn put_name(&mut self, name: Name<'_>) -> Result<(), Error> {
+        let start = self.len;
+        for label in name.labels() {
+            if label.is_empty() || label.len() > MAX_LABEL_LEN {
+                return Err(Error::InvalidName);
+            }
+            self.put(&[label.len() as u8])?;
+            self.put(label)?;
+        }
+        self.put(&[0])?;
+        if self.len - start > MAX_NAME_LEN {
+            return Err(Error::InvalidName);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the position after the name at `pos` in `message`
+fn skip_name(message: &[u8], mut pos: usize) -> Result<usize, Error> {
+    let start = pos;
+    loop {
+        let len = *message.get(pos).ok_or(Error::Malformed)? as usize;
+        match len & 0xc0 {
+            // a pointer to the rest of the name ends it
+            0xc0 => {
+                return message
+                    .get(pos + 1)
+                    .map(|_| pos + 2)
+                    .ok_or(Error::Malformed)
+            }
+            0x00 => {}
+            // reserved label types
+            _ => return Err(Error::Malformed),
+        }
+        pos += 1 + len;
+        if pos - start > MAX_NAME_LEN {
+            return Err(Error::Malformed);
+        }
+        if len == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+/// Compares the name at `pos` in `message` with `name`, ignoring the case of ASCII letters
+///
+/// Names in mDNS messages may be compressed: a label can be replaced by a pointer to the rest of
+/// the name somewhere earlier in the message.
+fn name_eq(message: &[u8], mut pos: usize, name: Name<'_>) -> Result<bool, Error> {
+    let mut expected = name.labels();
+    // every pointer has to point before the part of the name read so far, so that we don't go
+    // around in circles
+    let mut limit = pos;
+    let mut equal = true;
+    let mut total = 0;
+    loop {
+        let len = *message.get(pos).ok_or(Error::Malformed)? as usize;
+        match len & 0xc0 {
+            0xc0 => {
+                let low = *message.get(pos + 1).ok_or(Error::Malformed)? as usize;
+                let target = (len & 0x3f) << 8 | low;
+                if target >= limit {
+                    return Err(Error::Malformed);
+                }
+                pos = target;
+                limit = target;
+                continue;
+            }
+            0x00 => {}
+            _ => return Err(Error::Malformed),
+        }
+
+        total += 1 + len;
+        if total > MAX_NAME_LEN {
+            return Err(Error::Malformed);
+        }
+        if len == 0 {
+            return Ok(equal && expected.next().is_none());
+        }
+        let label = message
+            .get(pos + 1..pos + 1 + len)
+            .ok_or(Error::Malformed)?;
+        // keep going after a mismatch, a malformed name is an error either way
+        equal &= expected
+            .next()
+            .is_some_and(|expected| expected.eq_ignore_ascii_case(label));
+        pos += 1 + len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    const ADDRESS: [u8; 4] = [192, 168, 1, 42];
+    const SERVICES: &[Service<'static>] = &[Service {
+        instance: "LED control",
+        service_type: "_http._tcp",
+        port: 80,
+        txt: &["path=/"],
+    }];
+
+    fn responder() -> Responder<'static> {
+        Responder::new("esp-radio", ADDRESS).with_services(SERVICES)
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for label in name.split('.') {
+            encoded.push(label.len() as u8);
+            encoded.extend_from_slice(label.as_bytes());
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    fn query(questions: &[(&str, u16)]) -> Vec<u8> {
+        let mut query = std::vec![0, 0, 0, 0, 0, questions.len() as u8, 0, 0, 0, 0, 0, 0];
+        for &(question, qtype) in questions {
+            query.extend(name(question));
+            query.extend_from_slice(&qtype.to_be_bytes());
+            query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        query
+    }
+
+    /// A record of a response: name, type, class, TTL and data
+    type Rr = (String, u16, u16, u32, Vec<u8>);
+
+    /// Splits a response into its answers and additional records
+    fn parse_response(response: &[u8]) -> (Vec<Rr>, Vec<Rr>) {
+        assert_eq!(response[..6], [0, 0, 0x84, 0, 0, 0]);
+        let answers = u16::from_be_bytes([response[6], response[7]]);
+        let additionals = u16::from_be_bytes([response[10], response[11]]);
+
+        let mut pos = HEADER_LEN;
+        let mut records = Vec::new();
+        for _ in 0..answers + additionals {
+            let end = skip_name(response, pos).unwrap();
+            let mut labels = Vec::new();
+            while response[pos] != 0 {
+                let len = response[pos] as usize;
+                labels.push(String::from_utf8(response[pos + 1..pos + 1 + len].to_vec()).unwrap());
+                pos += 1 + len;
+            }
+            let fixed = &response[end..end + 10];
+            let data_len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+            records.push((
+                labels.join("."),
+                u16::from_be_bytes([fixed[0], fixed[1]]),
+                u16::from_be_bytes([fixed[2], fixed[3]]),
+                u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+                response[end + 10..end + 10 + data_len].to_vec(),
+            ));
+            pos = end + 10 + data_len;
+        }
+        assert_eq!(pos, response.len());
+        let additionals = records.split_off(answers as usize);
+        (records, additionals)
+    }
+
+    fn respond(query: &[u8]) -> Option<(Vec<Rr>, Vec<Rr>)> {
+        let mut response = [0; 1024];
+        let len = responder().respond(query, &mut response).unwrap()?;
+        Some(parse_response(&response[..len]))
+    }
+
+    fn types(records: &[Rr]) -> Vec<u16> {
+        records.iter().map(|record| record.1).collect()
+    }
+
+    #[test]
+    fn answers_a_query_for_the_hostname() {
+        let (answers, additionals) = respond(&query(&[("ESP-Radio.Local", TYPE_A)])).unwrap();
+        assert_eq!(
+            answers,
+            [(
+                "esp-radio.local".into(),
+                TYPE_A,
+                CLASS_IN | CACHE_FLUSH,
+                HOST_TTL,
+                ADDRESS.to_vec()
+            )]
+        );
+        assert!(additionals.is_empty());
+    }
+
+    #[test]
+    fn ignores_queries_for_others() {
+        assert_eq!(respond(&query(&[("printer.local", TYPE_A)])), None);
+        assert_eq!(respond(&query(&[("esp-radio.lan", TYPE_A)])), None);
+        assert_eq!(respond(&query(&[("esp-radio.local.local", TYPE_A)])), None);
+        // we only have an IPv4 address
+        assert_eq!(respond(&query(&[("esp-radio.local", 28)])), None);
+        assert_eq!(respond(&query(&[])), None);
+    }
+
+    #[test]
+    fn browses_services() {
+        let (answers, additionals) = respond(&query(&[("_http._tcp.local", TYPE_PTR)])).unwrap();
+        assert_eq!(
+            answers,
+            [(
+                "_http._tcp.local".into(),
+                TYPE_PTR,
+                CLASS_IN,
+                SERVICE_TTL,
+                name("LED control._http._tcp.local")
+            )]
+        );
+        assert_eq!(types(&additionals), [TYPE_SRV, TYPE_TXT, TYPE_A]);
+
+        let mut srv = std::vec![0, 0, 0, 0, 0, 80];
+        srv.extend(name("esp-radio.local"));
+        assert_eq!(additionals[0].0, "LED control._http._tcp.local");
+        assert_eq!(additionals[0].4, srv);
+        assert_eq!(additionals[1].4, b"\x06path=/");
+    }
+
+    #[test]
+    fn lists_service_types_once() {
+        let services = [
+            SERVICES[0],
+            Service {
+                instance: "API",
+                ..SERVICES[0]
+            },
+        ];
+        let responder = Responder::new("esp-radio", ADDRESS).with_services(&services);
+        let mut response = [0; 1024];
+        let query = query(&[("_services._dns-sd._udp.local", TYPE_PTR)]);
+        let len = responder.respond(&query, &mut response).unwrap().unwrap();
+
+        let (answers, additionals) = parse_response(&response[..len]);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].4, name("_http._tcp.local"));
+        assert!(additionals.is_empty());
+    }
+
+    #[test]
+    fn resolves_a_service_instance() {
+        let instance = "LED control._http._tcp.local";
+        let (answers, additionals) =
+            respond(&query(&[(instance, TYPE_SRV), (instance, TYPE_TXT)])).unwrap();
+        assert_eq!(types(&answers), [TYPE_SRV, TYPE_TXT]);
+        assert_eq!(types(&additionals), [TYPE_A]);
+
+        // the address is already an answer
+        let (answers, additionals) = respond(&query(&[
+            (instance, TYPE_ANY),
+            ("esp-radio.local", TYPE_ANY),
+        ]))
+        .unwrap();
+        assert_eq!(types(&answers), [TYPE_SRV, TYPE_TXT, TYPE_A]);
+        assert!(additionals.is_empty());
+    }
+
+    #[test]
+    fn follows_compressed_names() {
+        // the second question points to `local` in the first one
+        let mut query = query(&[("printer.local", TYPE_A)]);
+        query[5] = 2;
+        query.extend_from_slice(b"\x09esp-radio\xc0\x14\x00\x01\x00\x01");
+        let (answers, _) = respond(&query).unwrap();
+        assert_eq!(types(&answers), [TYPE_A]);
+
+        // a pointer back to the start of the name
+        let mut looping = query.clone();
+        let end = looping.len();
+        looping[end - 6..end - 4].copy_from_slice(&((end as u16 - 16) | 0xc000).to_be_bytes());
+        let mut response = [0; 512];
+        assert_eq!(
+            responder().respond(&looping, &mut response),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn rejects_responses_and_malformed_queries() {
+        let mut query = query(&[("esp-radio.local", TYPE_A)]);
+        let mut response = [0; 512];
+        for len in 0..query.len() {
+            assert_eq!(
+                responder().respond(&query[..len], &mut response),
+                Err(Error::Malformed),
+                "{len}"
+            );
+        }
+
+        query[2] = 0x84;
+        assert_eq!(
+            responder().respond(&query, &mut response),
+            Err(Error::NotAQuery)
+        );
+    }
+
+    #[test]
+    fn announces_and_says_goodbye() {
+        let mut response = [0; 1024];
+        let len = responder().announce(&mut response).unwrap();
+        let (answers, additionals) = parse_response(&response[..len]);
+        assert_eq!(
+            types(&answers),
+            [TYPE_A, TYPE_PTR, TYPE_PTR, TYPE_SRV, TYPE_TXT]
+        );
+        assert!(additionals.is_empty());
+        assert!(answers.iter().all(|record| record.3 > 0));
+
+        let len = responder().goodbye(&mut response).unwrap();
+        let (answers, _) = parse_response(&response[..len]);
+        assert_eq!(answers.len(), 5);
+        assert!(answers.iter().all(|record| record.3 == 0));
+    }
+
+    #[test]
+    fn follows_address_changes() {
+        let mut responder = Responder::new("esp-radio", ADDRESS);
+        responder.set_address([10, 0, 0, 7]);
+        let mut response = [0; 512];
+        let query = query(&[("esp-radio.local", TYPE_A)]);
+        let len = responder.respond(&query, &mut response).unwrap().unwrap();
+        assert_eq!(response[len - 4..len], [10, 0, 0, 7]);
+
+        // without services, there is nothing to browse
+        let query = self::query(&[("_services._dns-sd._udp.local", TYPE_PTR)]);
+        assert_eq!(responder.respond(&query, &mut response), Ok(None));
+    }
+
+    #[test]
+    fn checks_names_and_room() {
+        let query = query(&[("esp-radio.local", TYPE_A)]);
+        let mut response = [0; 64];
+        let len = responder().respond(&query, &mut response).unwrap().unwrap();
+        assert_eq!(
+            responder().respond(&query, &mut response[..len - 1]),
+            Err(Error::BufferTooSmall)
+        );
+
+        let long = "a".repeat(64);
+        let responder = Responder::new(&long, ADDRESS);
+        assert_eq!(responder.announce(&mut response), Err(Error::InvalidName));
+        let responder = Responder::new("", ADDRESS);
+        assert_eq!(responder.announce(&mut response), Err(Error::InvalidName));
+    }
+}
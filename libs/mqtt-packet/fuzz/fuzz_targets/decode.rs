@@ -0,0 +1,21 @@
+//! Decodes arbitrary data, which must never panic, and checks that decoded messages encode to
+//! the same message again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mqtt_packet::{decode, Packet};
+
+fuzz_target!(|data: &[u8]| {
+    let mut data = data;
+    while let Ok((consumed, Some(packet))) = decode(data) {
+        assert!(consumed > 0 && consumed <= data.len());
+        data = &data[consumed..];
+
+        if let Packet::Publish(publish) = packet {
+            let mut buffer = vec![0; consumed];
+            let len = publish.encode(&mut buffer).unwrap();
+            assert_eq!(decode(&buffer[..len]), Ok((len, Some(packet))));
+        }
+    }
+});
@@ -0,0 +1,896 @@
+//! MQTT 3.1.1 packet encoder and decoder for clients.
+//!
+//! Only the packets a client sends are encoded and only the packets a client receives are
+//! decoded. Nothing is allocated: packets are encoded into a buffer provided by the application
+//! and decoded packets borrow from the receive buffer.
+//!
+//! ```
+//! use mqtt_packet::{decode, Packet, Publish, QoS};
+//!
+//! let mut buffer = [0; 64];
+//! let publish = Publish {
+//!     topic: "esp/button",
+//!     payload: b"1",
+//!     qos: QoS::AtMostOnce,
+//!     retain: false,
+//!     dup: false,
+//!     packet_id: None,
+//! };
+//! let len = publish.encode(&mut buffer).unwrap();
+//!
+//! // a broker forwards the same packet to its subscribers
+//! let (consumed, packet) = decode(&buffer[..len]).unwrap();
+//! assert_eq!(consumed, len);
+//! assert_eq!(packet, Some(Packet::Publish(publish)));
+//!
+//! // `decode` returns `None` until a whole packet was received
+//! assert_eq!(decode(&buffer[..len - 1]), Ok((0, None)));
+//! ```
+//!
+//! [`KeepAlive`] keeps track of when the client has to ping the broker and notices when the
+//! broker stopped answering.
+//!
+//! QoS 2 is not supported: the packets of its handshake are reported as
+//! [`Error::UnexpectedPacket`], so don't subscribe with or publish at QoS 2.
+
+#![no_std]
+
+use core::fmt;
+
+/// Largest value of the remaining length field, 256 MiB
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+/// Quality of service of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl QoS {
+    fn from_bits(bits: u8) -> Result<Self, Error> {
+        match bits {
+            0 => Ok(Self::AtMostOnce),
+            1 => Ok(Self::AtLeastOnce),
+            2 => Ok(Self::ExactlyOnce),
+            _ => Err(Error::Malformed),
+        }
+    }
+}
+
+/// Errors returned by the encoder and decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer is too small for the packet
+    BufferTooSmall,
+    /// A string or the payload is too long to be encoded
+    TooLong,
+    /// Messages with QoS 1 and 2 need a non-zero packet identifier
+    InvalidPacketId,
+    /// MQTT 3.1.1 only allows a password together with a user name
+    PasswordWithoutUsername,
+    /// The packet violates the protocol
+    Malformed,
+    /// A client never receives packets of this type, or they are not supported
+    UnexpectedPacket(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The message the broker publishes when the client disconnects without saying goodbye
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// Opens the session, the first packet sent on a new connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    /// The longest time in seconds between two packets sent by the client, 0 disables it
+    pub keep_alive: u16,
+    /// Start with a new session instead of resuming the subscriptions of the last one
+    pub clean_session: bool,
+    pub will: Option<Will<'a>>,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+}
+
+impl Connect<'_> {
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.password.is_some() && self.username.is_none() {
+            return Err(Error::PasswordWithoutUsername);
+        }
+
+        let mut flags = 0;
+        if self.clean_session {
+            flags |= 0x02;
+        }
+        if let Some(will) = &self.will {
+            flags |= 0x04 | (will.qos as u8) << 3;
+            if will.retain {
+                flags |= 0x20;
+            }
+        }
+        if self.password.is_some() {
+            flags |= 0x40;
+        }
+        if self.username.is_some() {
+            flags |= 0x80;
+        }
+
+        encode_packet(buffer, 0x10, |w| {
+            // protocol name and level 4, which is MQTT 3.1.1
+            w.string("MQTT")?;
+            w.u8(4)?;
+            w.u8(flags)?;
+            w.u16(self.keep_alive)?;
+
+            w.string(self.client_id)?;
+            if let Some(will) = &self.will {
+                w.string(will.topic)?;
+                w.binary(will.payload)?;
+            }
+            if let Some(username) = self.username {
+                w.string(username)?;
+            }
+            if let Some(password) = self.password {
+                w.binary(password)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Answer of the broker to [`Connect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectReturnCode {
+    Accepted,
+    UnacceptableProtocolVersion,
+    IdentifierRejected,
+    ServerUnavailable,
+    BadUsernameOrPassword,
+    NotAuthorized,
+}
+
+/// Acknowledges the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnAck {
+    /// The broker still had a session for the client, only possible without `clean_session`
+    pub session_present: bool,
+    pub code: ConnectReturnCode,
+}
+
+/// A message, sent by the client to publish it and by the broker to deliver it to subscribers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Publish<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QoS,
+    /// The broker keeps the last retained message of a topic and sends it to new subscribers
+    pub retain: bool,
+    /// The message is sent again because it wasn't acknowledged
+    pub dup: bool,
+    /// Identifies the message in the acknowledgement, only used with QoS 1 and 2
+    pub packet_id: Option<u16>,
+}
+
+impl Publish<'_> {
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let packet_id = match (self.qos, self.packet_id) {
+            (QoS::AtMostOnce, _) => None,
+            (_, Some(0) | None) => return Err(Error::InvalidPacketId),
+            (_, packet_id) => packet_id,
+        };
+
+        let mut first = 0x30 | (self.qos as u8) << 1;
+        if self.dup {
+            first |= 0x08;
+        }
+        if self.retain {
+            first |= 0x01;
+        }
+
+        encode_packet(buffer, first, |w| {
+            w.string(self.topic)?;
+            if let Some(packet_id) = packet_id {
+                w.u16(packet_id)?;
+            }
+            w.bytes(self.payload)
+        })
+    }
+}
+
+/// Subscribes to topics, the broker answers with [`SubAck`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Subscribe<'a> {
+    pub packet_id: u16,
+    /// Topic filters, which may contain wildcards, with the maximum QoS to deliver them with
+    pub topics: &'a [(&'a str, QoS)],
+}
+
+impl Subscribe<'_> {
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.packet_id == 0 {
+            return Err(Error::InvalidPacketId);
+        }
+        encode_packet(buffer, 0x82, |w| {
+            w.u16(self.packet_id)?;
+            for (topic, qos) in self.topics {
+                w.string(topic)?;
+                w.u8(*qos as u8)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Acknowledges a subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SubAck<'a> {
+    pub packet_id: u16,
+    /// One validated return code per topic
+    return_codes: &'a [u8],
+}
+
+impl SubAck<'_> {
+    /// The QoS granted for each topic of the subscription, `None` if the broker rejected it
+    pub fn granted(&self) -> impl Iterator<Item = Option<QoS>> + '_ {
+        self.return_codes
+            .iter()
+            .map(|&code| QoS::from_bits(code).ok())
+    }
+}
+
+/// Acknowledges a message with QoS 1 received from the broker
+pub fn encode_puback(packet_id: u16, buffer: &mut [u8]) -> Result<usize, Error> {
+    encode_packet(buffer, 0x40, |w| w.u16(packet_id))
+}
+
+/// Asks the broker whether it is still there, it answers with [`Packet::PingResp`]
+pub fn encode_pingreq(buffer: &mut [u8]) -> Result<usize, Error> {
+    encode_packet(buffer, 0xc0, |_| Ok(()))
+}
+
+/// Closes the session, the broker discards the will
+pub fn encode_disconnect(buffer: &mut [u8]) -> Result<usize, Error> {
+    encode_packet(buffer, 0xe0, |_| Ok(()))
+}
+
+/// A packet sent by the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Packet<'a> {
+    ConnAck(ConnAck),
+    Publish(Publish<'a>),
+    /// Acknowledges a message with QoS 1 published by the client
+    PubAck(u16),
+    SubAck(SubAck<'a>),
+    UnsubAck(u16),
+    PingResp,
+}
+
+/// Decodes the packet at the start of `buffer`
+///
+/// Returns the number of bytes the packet takes up, or `(0, None)` if it isn't complete yet. The
+/// application has to make sure the buffer is large enough for the largest packet it expects.
+pub fn decode(buffer: &[u8]) -> Result<(usize, Option<Packet<'_>>), Error> {
+    let Some(&first) = buffer.first() else {
+        return Ok((0, None));
+    };
+
+    // the remaining length is encoded in 7 bits per byte, with the highest bit set on all but the
+    // last byte
+    let mut remaining = 0;
+    let mut header_len = 1;
+    loop {
+        let Some(&byte) = buffer.get(header_len) else {
+            return Ok((0, None));
+        };
+        remaining |= usize::from(byte & 0x7f) << (7 * (header_len - 1));
+        header_len += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if header_len == 5 {
+            return Err(Error::Malformed);
+        }
+    }
+
+    let len = header_len + remaining;
+    let Some(body) = buffer.get(header_len..len) else {
+        return Ok((0, None));
+    };
+
+    let packet_type = first >> 4;
+    let flags = first & 0x0f;
+    let mut r = Reader { buffer: body };
+
+    let packet = match (packet_type, flags) {
+        (2, 0) => {
+            let session_present = match r.u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(Error::Malformed),
+            };
+            let code = match r.u8()? {
+                0 => ConnectReturnCode::Accepted,
+                1 => ConnectReturnCode::UnacceptableProtocolVersion,
+                2 => ConnectReturnCode::IdentifierRejected,
+                3 => ConnectReturnCode::ServerUnavailable,
+                4 => ConnectReturnCode::BadUsernameOrPassword,
+                5 => ConnectReturnCode::NotAuthorized,
+                _ => return Err(Error::Malformed),
+            };
+            r.end()?;
+            Packet::ConnAck(ConnAck {
+                session_present,
+                code,
+            })
+        }
+        (3, _) => {
+            let qos = QoS::from_bits((flags >> 1) & 0x03)?;
+            let topic = r.string()?;
+            // wildcards are only allowed in subscriptions
+            if topic.contains(['+', '#']) {
+                return Err(Error::Malformed);
+            }
+            let packet_id = match qos {
+                QoS::AtMostOnce => None,
+                _ => Some(r.packet_id()?),
+            };
+            Packet::Publish(Publish {
+                topic,
+                payload: r.rest(),
+                qos,
+                retain: flags & 0x01 != 0,
+                dup: flags & 0x08 != 0,
+                packet_id,
+            })
+        }
+        (4, 0) => {
+            let packet_id = r.packet_id()?;
+            r.end()?;
+            Packet::PubAck(packet_id)
+        }
+        (9, 0) => {
+            let packet_id = r.packet_id()?;
+            let return_codes = r.rest();
+            if return_codes.is_empty()
+                || return_codes
+                    .iter()
+                    .any(|code| !matches!(code, 0x00 | 0x01 | 0x02 | 0x80))
+            {
+                return Err(Error::Malformed);
+            }
+            Packet::SubAck(SubAck {
+                packet_id,
+                return_codes,
+            })
+        }
+        (11, 0) => {
+            let packet_id = r.packet_id()?;
+            r.end()?;
+            Packet::UnsubAck(packet_id)
+        }
+        (13, 0) => {
+            r.end()?;
+            Packet::PingResp
+        }
+        (2 | 4 | 9 | 11 | 13, _) => return Err(Error::Malformed),
+        (packet_type, _) => return Err(Error::UnexpectedPacket(packet_type)),
+    };
+
+    Ok((len, Some(packet)))
+}
+
+/// What the client has to do to keep the connection alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeepAliveAction {
+    /// Nothing to do
+    None,
+    /// Send a ping, nothing was sent for a while
+    Ping,
+    /// The broker didn't answer the ping, the connection is dead
+    TimedOut,
+}
+
+/// Keeps track of the keep alive interval
+///
+/// The broker drops clients that don't send anything for 1.5 times the keep alive interval of
+/// their [`Connect`], so the client pings the broker when it was quiet for a whole interval. All
+/// times are in milliseconds.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeepAlive {
+    interval: u64,
+    last_sent: u64,
+    /// When the ping that hasn't been answered yet was sent
+    ping_sent: Option<u64>,
+}
+
+impl KeepAlive {
+    /// `keep_alive` is the value sent in [`Connect`], in seconds
+    pub const fn new(keep_alive: u16, now: u64) -> Self {
+        Self {
+            interval: keep_alive as u64 * 1_000,
+            last_sent: now,
+            ping_sent: None,
+        }
+    }
+
+    /// Call this after sending a packet
+    pub fn sent(&mut self, now: u64) {
+        self.last_sent = now;
+    }
+
+    /// Call this after receiving a packet, anything the broker sends shows it is alive
+    pub fn received(&mut self) {
+        self.ping_sent = None;
+    }
+
+    /// When [`poll`](Self::poll) has something to do next, `None` if the keep alive is disabled
+    pub fn deadline(&self) -> Option<u64> {
+        if self.interval == 0 {
+            return None;
+        }
+        let since = self.ping_sent.unwrap_or(self.last_sent);
+        Some(since.saturating_add(self.interval))
+    }
+
+    /// Returns what to do, call this at the deadline or regularly
+    pub fn poll(&mut self, now: u64) -> KeepAliveAction {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => {
+                if self.ping_sent.is_some() {
+                    KeepAliveAction::TimedOut
+                } else {
+                    self.ping_sent = Some(now);
+                    KeepAliveAction::Ping
+                }
+            }
+            _ => KeepAliveAction::None,
+        }
+    }
+}
+
+/// Encodes the fixed header and the body written by `body`
+///
+/// The body is written twice: first only to find out its length, which goes into the header.
+fn encode_packet(
+    buffer: &mut [u8],
+    first: u8,
+    body: impl Fn(&mut Writer<'_>) -> Result<(), Error>,
+) -> Result<usize, Error> {
+    let mut counter = Writer {
+        buffer: None,
+        len: 0,
+    };
+    body(&mut counter)?;
+    let mut remaining = counter.len;
+    if remaining > MAX_REMAINING_LENGTH {
+        return Err(Error::TooLong);
+    }
+
+    let mut w = Writer {
+        buffer: Some(buffer),
+        len: 0,
+    };
+    w.u8(first)?;
+    loop {
+        let byte = (remaining & 0x7f) as u8;
+        remaining >>= 7;
+        if remaining == 0 {
+            w.u8(byte)?;
+            break;
+        }
+        w.u8(byte | 0x80)?;
+    }
+    body(&mut w)?;
+    Ok(w.len)
+}
+
+/// Writes into a buffer, or only counts the bytes without a buffer
+struct Writer<'a> {
+    buffer: Option<&'a mut [u8]>,
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        if let Some(buffer) = &mut self.buffer {
+            buffer
+                .get_mut(self.len..end)
+                .ok_or(Error::BufferTooSmall)?
+                .copy_from_slice(bytes);
+        }
+        self.len = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<(), Error> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), Error> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Binary data prefixed with its length
+    fn binary(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.u16(bytes.len().try_into().map_err(|_| Error::TooLong)?)?;
+        self.bytes(bytes)
+    }
+
+    fn string(&mut self, string: &str) -> Result<(), Error> {
+        self.binary(string.as_bytes())
+    }
+}
+
+/// Reads from the body of a packet, running out of data means the packet is malformed
+struct Reader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buffer.len() < len {
+            return Err(Error::Malformed);
+        }
+        let (bytes, rest) = self.buffer.split_at(len);
+        self.buffer = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn packet_id(&mut self) -> Result<u16, Error> {
+        match self.u16()? {
+            0 => Err(Error::Malformed),
+            packet_id => Ok(packet_id),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a str, Error> {
+        let len = self.u16()?;
+        let bytes = self.bytes(usize::from(len))?;
+        core::str::from_utf8(bytes).map_err(|_| Error::Malformed)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        core::mem::take(&mut self.buffer)
+    }
+
+    fn end(&self) -> Result<(), Error> {
+        if self.buffer.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Malformed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(encode: impl FnOnce(&mut [u8]) -> Result<usize, Error>) -> ([u8; 256], usize) {
+        let mut buffer = [0; 256];
+        let len = encode(&mut buffer).unwrap();
+        (buffer, len)
+    }
+
+    const CONNECT: Connect<'static> = Connect {
+        client_id: "esp",
+        keep_alive: 60,
+        clean_session: true,
+        will: None,
+        username: None,
+        password: None,
+    };
+
+    #[test]
+    fn connect() {
+        let (buffer, len) = encoded(|buffer| CONNECT.encode(buffer));
+        assert_eq!(
+            &buffer[..len],
+            b"\x10\x0f\x00\x04MQTT\x04\x02\x00\x3c\x00\x03esp"
+        );
+    }
+
+    #[test]
+    fn connect_with_will_and_credentials() {
+        let connect = Connect {
+            will: Some(Will {
+                topic: "s",
+                payload: b"off",
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            username: Some("u"),
+            password: Some(b"pw"),
+            ..CONNECT
+        };
+        let (buffer, len) = encoded(|buffer| connect.encode(buffer));
+        assert_eq!(
+            &buffer[..len],
+            b"\x10\x1e\x00\x04MQTT\x04\xee\x00\x3c\x00\x03esp\x00\x01s\x00\x03off\x00\x01u\x00\x02pw"
+        );
+    }
+
+    #[test]
+    fn password_needs_username() {
+        let connect = Connect {
+            password: Some(b"pw"),
+            ..CONNECT
+        };
+        assert_eq!(
+            connect.encode(&mut [0; 64]),
+            Err(Error::PasswordWithoutUsername)
+        );
+    }
+
+    #[test]
+    fn publish() {
+        let publish = Publish {
+            topic: "a/b",
+            payload: b"on",
+            qos: QoS::AtLeastOnce,
+            retain: true,
+            dup: true,
+            packet_id: Some(10),
+        };
+        let (buffer, len) = encoded(|buffer| publish.encode(buffer));
+        assert_eq!(&buffer[..len], b"\x3b\x09\x00\x03a/b\x00\x0aon");
+        assert_eq!(
+            decode(&buffer[..len]),
+            Ok((len, Some(Packet::Publish(publish))))
+        );
+    }
+
+    #[test]
+    fn publish_needs_packet_id() {
+        let publish = Publish {
+            topic: "a",
+            payload: b"",
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            dup: false,
+            packet_id: None,
+        };
+        assert_eq!(publish.encode(&mut [0; 64]), Err(Error::InvalidPacketId));
+        let publish = Publish {
+            packet_id: Some(0),
+            ..publish
+        };
+        assert_eq!(publish.encode(&mut [0; 64]), Err(Error::InvalidPacketId));
+    }
+
+    #[test]
+    fn long_remaining_length() {
+        let payload = [0x55; 200];
+        let publish = Publish {
+            topic: "t",
+            payload: &payload,
+            qos: QoS::AtMostOnce,
+            retain: false,
+            dup: false,
+            packet_id: None,
+        };
+        let (buffer, len) = encoded(|buffer| publish.encode(buffer));
+        // 203 = 0x4b + 1 * 128
+        assert_eq!(&buffer[..5], b"\x30\xcb\x01\x00\x01");
+        assert_eq!(len, 206);
+        assert_eq!(
+            decode(&buffer[..len]),
+            Ok((len, Some(Packet::Publish(publish))))
+        );
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        assert_eq!(CONNECT.encode(&mut [0; 16]), Err(Error::BufferTooSmall));
+        assert_eq!(encode_pingreq(&mut [0; 1]), Err(Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn subscribe() {
+        let subscribe = Subscribe {
+            packet_id: 1,
+            topics: &[("a/#", QoS::AtLeastOnce), ("b", QoS::AtMostOnce)],
+        };
+        let (buffer, len) = encoded(|buffer| subscribe.encode(buffer));
+        assert_eq!(
+            &buffer[..len],
+            b"\x82\x0c\x00\x01\x00\x03a/#\x01\x00\x01b\x00"
+        );
+    }
+
+    #[test]
+    fn fixed_packets() {
+        let (buffer, len) = encoded(|buffer| encode_puback(0x1234, buffer));
+        assert_eq!(&buffer[..len], b"\x40\x02\x12\x34");
+        let (buffer, len) = encoded(encode_pingreq);
+        assert_eq!(&buffer[..len], b"\xc0\x00");
+        let (buffer, len) = encoded(encode_disconnect);
+        assert_eq!(&buffer[..len], b"\xe0\x00");
+    }
+
+    #[test]
+    fn connack() {
+        assert_eq!(
+            decode(b"\x20\x02\x01\x00"),
+            Ok((
+                4,
+                Some(Packet::ConnAck(ConnAck {
+                    session_present: true,
+                    code: ConnectReturnCode::Accepted
+                }))
+            ))
+        );
+        assert_eq!(
+            decode(b"\x20\x02\x00\x05"),
+            Ok((
+                4,
+                Some(Packet::ConnAck(ConnAck {
+                    session_present: false,
+                    code: ConnectReturnCode::NotAuthorized
+                }))
+            ))
+        );
+        assert_eq!(decode(b"\x20\x02\x00\x06"), Err(Error::Malformed));
+        assert_eq!(decode(b"\x20\x02\x02\x00"), Err(Error::Malformed));
+        assert_eq!(decode(b"\x20\x03\x00\x00\x00"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn acks() {
+        assert_eq!(
+            decode(b"\x40\x02\x00\x07"),
+            Ok((4, Some(Packet::PubAck(7))))
+        );
+        assert_eq!(
+            decode(b"\xb0\x02\x00\x07"),
+            Ok((4, Some(Packet::UnsubAck(7))))
+        );
+        assert_eq!(decode(b"\xd0\x00"), Ok((2, Some(Packet::PingResp))));
+        // packet identifiers are never 0
+        assert_eq!(decode(b"\x40\x02\x00\x00"), Err(Error::Malformed));
+        // reserved flags must be 0
+        assert_eq!(decode(b"\xd1\x00"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn suback() {
+        let (len, packet) = decode(b"\x90\x05\x00\x01\x01\x80\x00").unwrap();
+        assert_eq!(len, 7);
+        let Some(Packet::SubAck(suback)) = packet else {
+            panic!("{packet:?}");
+        };
+        assert_eq!(suback.packet_id, 1);
+        let mut granted = suback.granted();
+        assert_eq!(granted.next(), Some(Some(QoS::AtLeastOnce)));
+        assert_eq!(granted.next(), Some(None));
+        assert_eq!(granted.next(), Some(Some(QoS::AtMostOnce)));
+        assert_eq!(granted.next(), None);
+
+        assert_eq!(decode(b"\x90\x02\x00\x01"), Err(Error::Malformed));
+        assert_eq!(decode(b"\x90\x03\x00\x01\x03"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn publish_from_broker() {
+        assert_eq!(
+            decode(b"\x30\x05\x00\x01tab"),
+            Ok((
+                7,
+                Some(Packet::Publish(Publish {
+                    topic: "t",
+                    payload: b"ab",
+                    qos: QoS::AtMostOnce,
+                    retain: false,
+                    dup: false,
+                    packet_id: None,
+                }))
+            ))
+        );
+        // QoS 3
+        assert_eq!(decode(b"\x36\x03\x00\x01t"), Err(Error::Malformed));
+        // wildcard in the topic name
+        assert_eq!(decode(b"\x30\x03\x00\x01#"), Err(Error::Malformed));
+        // topic longer than the packet
+        assert_eq!(decode(b"\x30\x03\x00\x05t"), Err(Error::Malformed));
+        // invalid UTF-8
+        assert_eq!(decode(b"\x30\x03\x00\x01\xff"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn incomplete() {
+        let packet = b"\x30\x05\x00\x01tab\xd0\x00";
+        for len in 0..7 {
+            assert_eq!(decode(&packet[..len]), Ok((0, None)), "{len}");
+        }
+        // only the first packet is decoded
+        assert_eq!(decode(packet).unwrap().0, 7);
+        assert_eq!(decode(&packet[7..]), Ok((2, Some(Packet::PingResp))));
+    }
+
+    #[test]
+    fn invalid_remaining_length() {
+        assert_eq!(decode(b"\x30\xff\xff\xff\xff\x01"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn unexpected_packets() {
+        // CONNECT, PUBREC and PINGREQ are never sent to a client or not supported
+        assert_eq!(decode(b"\x10\x00"), Err(Error::UnexpectedPacket(1)));
+        assert_eq!(decode(b"\x50\x02\x00\x01"), Err(Error::UnexpectedPacket(5)));
+        assert_eq!(decode(b"\xc0\x00"), Err(Error::UnexpectedPacket(12)));
+    }
+
+    #[test]
+    fn keep_alive_pings_when_quiet() {
+        let mut keep_alive = KeepAlive::new(10, 0);
+        assert_eq!(keep_alive.deadline(), Some(10_000));
+        assert_eq!(keep_alive.poll(9_999), KeepAliveAction::None);
+
+        // sending anything postpones the ping
+        keep_alive.sent(5_000);
+        assert_eq!(keep_alive.poll(10_000), KeepAliveAction::None);
+        assert_eq!(keep_alive.poll(15_000), KeepAliveAction::Ping);
+        keep_alive.sent(15_000);
+
+        // the broker answers
+        keep_alive.received();
+        assert_eq!(keep_alive.deadline(), Some(25_000));
+        assert_eq!(keep_alive.poll(20_000), KeepAliveAction::None);
+    }
+
+    #[test]
+    fn keep_alive_times_out() {
+        let mut keep_alive = KeepAlive::new(10, 0);
+        assert_eq!(keep_alive.poll(10_000), KeepAliveAction::Ping);
+        keep_alive.sent(10_000);
+        assert_eq!(keep_alive.deadline(), Some(20_000));
+
+        // sending more doesn't help if the broker doesn't answer
+        keep_alive.sent(15_000);
+        assert_eq!(keep_alive.poll(19_999), KeepAliveAction::None);
+        assert_eq!(keep_alive.poll(20_000), KeepAliveAction::TimedOut);
+    }
+
+    #[test]
+    fn keep_alive_disabled() {
+        let mut keep_alive = KeepAlive::new(0, 0);
+        assert_eq!(keep_alive.deadline(), None);
+        assert_eq!(keep_alive.poll(u64::MAX), KeepAliveAction::None);
+    }
+}
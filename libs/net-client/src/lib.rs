@@ -0,0 +1,661 @@
+//! DHCP, DNS and HTTP requests over any smoltcp device.
+//!
+//! The examples talk to the network through the Wi-Fi driver, so their network code only runs on
+//! the board. [`Network`] and [`Get`] only need a [`smoltcp::phy::Device`]: on the board that's
+//! the `WifiDevice` of `esp-radio`, on the host it can be smoltcp's [`Loopback`] or a Linux TAP
+//! interface. That way the same code can be tested without a board.
+//!
+//! - [`Network`] owns the device and the sockets. It gets an address by DHCP, or uses a static
+//!   one, and keeps the DNS servers up to date.
+//! - [`Get`] makes one HTTP GET request: it resolves the host name, connects, sends the request
+//!   and hands out the response as [`http_response::Event`]s.
+//! - [`Resolve`] only resolves a host name, e.g. for sockets the application adds itself.
+//!
+//! Neither of them blocks or reads a clock: the application polls them with the current time.
+//!
+//! ```
+//! use core::task::Poll;
+//! use net_client::{Config, Event, Get, Ipv4, Network, Storage};
+//! use smoltcp::iface::SocketStorage;
+//! use smoltcp::phy::{Loopback, Medium};
+//! use smoltcp::socket::tcp;
+//! use smoltcp::time::Instant;
+//! use smoltcp::wire::{EthernetAddress, Ipv4Cidr};
+//!
+//! let mut config = Config::new(EthernetAddress([0x02, 0, 0, 0, 0, 1]).into());
+//! config.ipv4 = Ipv4::Static {
+//!     address: Ipv4Cidr::new([127, 0, 0, 1].into(), 8),
+//!     gateway: None,
+//!     dns_server: None,
+//! };
+//! let (mut sockets, mut rx, mut tx, mut queries) = (
+//!     [SocketStorage::EMPTY; 4],
+//!     [0; 1024],
+//!     [0; 1024],
+//!     [None; 1],
+//! );
+//! let storage = Storage {
+//!     sockets: &mut sockets,
+//!     rx_buffer: &mut rx,
+//!     tx_buffer: &mut tx,
+//!     dns_queries: &mut queries,
+//! };
+//! let mut network = Network::new(Loopback::new(Medium::Ethernet), config, storage, Instant::ZERO);
+//!
+//! // a server on the same interface
+//! let (mut server_rx, mut server_tx) = ([0; 1024], [0; 1024]);
+//! let mut server = tcp::Socket::new(
+//!     tcp::SocketBuffer::new(&mut server_rx[..]),
+//!     tcp::SocketBuffer::new(&mut server_tx[..]),
+//! );
+//! server.listen(80).unwrap();
+//! let server = network.sockets_mut().add(server);
+//!
+//! assert_eq!(network.poll(Instant::ZERO), Some(Event::Up(Ipv4Cidr::new([127, 0, 0, 1].into(), 8))));
+//! let mut get = Get::new("127.0.0.1", 80, "/");
+//! let mut body = Vec::new();
+//! let result = loop {
+//!     network.poll(Instant::ZERO);
+//!     let server = network.sockets_mut().get_mut::<tcp::Socket>(server);
+//!     if server.can_recv() {
+//!         server.recv(|request| (request.len(), ())).unwrap();
+//!         server.send_slice(b"HTTP/1.0 200 OK\r\n\r\nhello").unwrap();
+//!         server.close();
+//!     }
+//!     let poll = get.poll(&mut network, Instant::ZERO, |event| {
+//!         if let http_response::Event::Body(data) = event {
+//!             body.extend_from_slice(data);
+//!         }
+//!     });
+//!     if let Poll::Ready(result) = poll {
+//!         break result;
+//!     }
+//! };
+//! assert_eq!(result, Ok(()));
+//! assert_eq!(body, b"hello");
+//! ```
+//!
+//! [`Loopback`]: https://docs.rs/smoltcp/0.12/smoltcp/phy/struct.Loopback.html
+
+#![no_std]
+
+use core::{fmt, task::Poll};
+
+use http_response::Parser;
+use smoltcp::{
+    iface::{Interface, SocketHandle, SocketSet, SocketStorage},
+    phy::Device,
+    socket::{
+        dhcpv4,
+        dns::{self, DnsQuery, GetQueryResultError, QueryHandle},
+        tcp,
+    },
+    time::{Duration, Instant},
+    wire::{DhcpOption, DnsQueryType, HardwareAddress, IpAddress, IpCidr, Ipv4Address, Ipv4Cidr},
+};
+
+/// The first local port of outgoing connections, the start of the dynamic port range
+const EPHEMERAL_PORTS: u16 = 49152;
+
+/// Errors returned by [`Get::poll`] and [`Resolve::poll`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The network has no address yet, or lost it
+    Down,
+    /// The host name isn't a valid DNS name
+    InvalidHost,
+    /// The host name didn't resolve to an IPv4 address
+    NoAddress,
+    /// There is no route to the server
+    Unreachable,
+    /// The request doesn't fit into the transmit buffer
+    RequestTooLong,
+    /// The server refused or reset the connection
+    Reset,
+    /// The server didn't send anything for [`Config::timeout`]
+    Timeout,
+    /// The server sent something that isn't a valid HTTP response
+    Response(http_response::Error),
+    /// The application cancelled the request
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<http_response::Error> for Error {
+    fn from(err: http_response::Error) -> Self {
+        Self::Response(err)
+    }
+}
+
+/// How the interface gets its IPv4 address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipv4 {
+    /// From the DHCP server of the network
+    Dhcp,
+    Static {
+        address: Ipv4Cidr,
+        gateway: Option<Ipv4Address>,
+        dns_server: Option<Ipv4Address>,
+    },
+}
+
+/// Configuration of a [`Network`]
+#[derive(Debug, Clone, Copy)]
+pub struct Config<'a> {
+    /// The MAC address of the device
+    pub hardware_address: HardwareAddress,
+    pub ipv4: Ipv4,
+    /// Sent along with the DHCP requests, e.g. the hostname (option 12)
+    pub dhcp_options: &'a [DhcpOption<'a>],
+    /// Picks the TCP sequence numbers and local ports, should be different on every boot
+    pub random_seed: u64,
+    /// A request fails if the server doesn't send anything for this long
+    pub timeout: Duration,
+}
+
+impl<'a> Config<'a> {
+    /// DHCP without options, and a timeout of 20 seconds
+    pub const fn new(hardware_address: HardwareAddress) -> Self {
+        Self {
+            hardware_address,
+            ipv4: Ipv4::Dhcp,
+            dhcp_options: &[],
+            random_seed: 0,
+            timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+/// The memory of the sockets, it has to outlive the [`Network`]
+pub struct Storage<'a> {
+    /// One entry for each socket: the DHCP, the DNS and the TCP socket of the network need 3,
+    /// more are needed for the sockets added with [`Network::sockets_mut`]
+    pub sockets: &'a mut [SocketStorage<'a>],
+    /// The TCP receive buffer, which is also the receive window
+    pub rx_buffer: &'a mut [u8],
+    /// The TCP transmit buffer, the request has to fit into it
+    pub tx_buffer: &'a mut [u8],
+    /// One entry for each DNS query at a time, one is enough for [`Get`]
+    pub dns_queries: &'a mut [Option<DnsQuery>],
+}
+
+/// Reported by [`Network::poll`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The interface got an address
+    Up(#[cfg_attr(feature = "defmt", defmt(Display2Format))] Ipv4Cidr),
+    /// The interface lost its address, e.g. because the lease ended
+    Down,
+}
+
+/// A smoltcp interface on the device `D`, with the sockets for DHCP, DNS and HTTP
+pub struct Network<'a, D: Device> {
+    iface: Interface,
+    device: D,
+    sockets: SocketSet<'a>,
+    /// `None` for a static address
+    dhcp: Option<SocketHandle>,
+    dns: SocketHandle,
+    tcp: SocketHandle,
+    timeout: Duration,
+    address: Option<Ipv4Cidr>,
+    gateway: Option<Ipv4Address>,
+    /// A static address is reported by the first poll
+    event: Option<Event>,
+    next_port: u16,
+}
+
+impl<'a, D: Device> Network<'a, D> {
+    pub fn new(mut device: D, config: Config<'a>, storage: Storage<'a>, now: Instant) -> Self {
+        let mut iface_config = smoltcp::iface::Config::new(config.hardware_address);
+        iface_config.random_seed = config.random_seed;
+        let mut iface = Interface::new(iface_config, &mut device, now);
+        let mut sockets = SocketSet::new(storage.sockets);
+
+        let tcp = sockets.add(tcp::Socket::new(
+            tcp::SocketBuffer::new(storage.rx_buffer),
+            tcp::SocketBuffer::new(storage.tx_buffer),
+        ));
+
+        let (dhcp, dns_servers, address, gateway) = match config.ipv4 {
+            Ipv4::Dhcp => {
+                let mut dhcp = dhcpv4::Socket::new();
+                dhcp.set_outgoing_options(config.dhcp_options);
+                (Some(sockets.add(dhcp)), None, None, None)
+            }
+            Ipv4::Static {
+                address,
+                gateway,
+                dns_server,
+            } => {
+                configure(&mut iface, address, gateway);
+                (None, dns_server, Some(address), gateway)
+            }
+        };
+        let dns_servers = dns_servers.map(IpAddress::Ipv4);
+        let dns = sockets.add(dns::Socket::new(
+            dns_servers.as_slice(),
+            storage.dns_queries,
+        ));
+
+        Self {
+            iface,
+            device,
+            sockets,
+            dhcp,
+            dns,
+            tcp,
+            timeout: config.timeout,
+            address,
+            gateway,
+            event: address.map(Event::Up),
+            next_port: EPHEMERAL_PORTS + (config.random_seed % 16384) as u16,
+        }
+    }
+
+    /// Sends and receives packets, and applies what the DHCP server hands out
+    ///
+    /// Call it whenever the device may have received something, and before polling a [`Get`].
+    pub fn poll(&mut self, now: Instant) -> Option<Event> {
+        self.iface.poll(now, &mut self.device, &mut self.sockets);
+        if let Some(event) = self.event.take() {
+            return Some(event);
+        }
+
+        let dhcp = self.dhcp?;
+        let lease = match self.sockets.get_mut::<dhcpv4::Socket>(dhcp).poll()? {
+            dhcpv4::Event::Configured(config) => {
+                // DHCP hands out up to 3 DNS servers
+                let mut servers = [IpAddress::Ipv4(Ipv4Address::UNSPECIFIED); 3];
+                for (server, address) in servers.iter_mut().zip(&config.dns_servers) {
+                    *server = IpAddress::Ipv4(*address);
+                }
+                let count = config.dns_servers.len().min(servers.len());
+                Some((config.address, config.router, servers, count))
+            }
+            dhcpv4::Event::Deconfigured => None,
+        };
+
+        match lease {
+            Some((address, router, servers, count)) => {
+                configure(&mut self.iface, address, router);
+                self.sockets
+                    .get_mut::<dns::Socket>(self.dns)
+                    .update_servers(&servers[..count]);
+                self.address = Some(address);
+                self.gateway = router;
+                Some(Event::Up(address))
+            }
+            // the DHCP socket also reports this when it starts
+            None if self.is_up() => {
+                self.deconfigure();
+                Some(Event::Down)
+            }
+            None => None,
+        }
+    }
+
+    /// How long the application may wait before the next [`poll`](Self::poll), if nothing is
+    /// received in the meantime
+    pub fn poll_delay(&mut self, now: Instant) -> Option<Duration> {
+        self.iface.poll_delay(now, &self.sockets)
+    }
+
+    /// Forgets the address and asks the DHCP server again, e.g. after joining another network
+    pub fn reset(&mut self) {
+        if let Some(dhcp) = self.dhcp {
+            self.sockets.get_mut::<dhcpv4::Socket>(dhcp).reset();
+            self.deconfigure();
+        }
+    }
+
+    /// Removes the address, the default route and the DNS servers
+    fn deconfigure(&mut self) {
+        self.iface.update_ip_addrs(|addrs| addrs.clear());
+        self.iface.routes_mut().remove_default_ipv4_route();
+        self.sockets
+            .get_mut::<dns::Socket>(self.dns)
+            .update_servers(&[]);
+        self.address = None;
+        self.gateway = None;
+    }
+
+    /// The address of the interface, `None` until the DHCP server handed one out
+    pub fn address(&self) -> Option<Ipv4Cidr> {
+        self.address
+    }
+
+    /// The default gateway, if the DHCP server announced one
+    pub fn gateway(&self) -> Option<Ipv4Address> {
+        self.gateway
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.address.is_some()
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// The sockets, to add more of them, e.g. a UDP socket for mDNS
+    pub fn sockets_mut(&mut self) -> &mut SocketSet<'a> {
+        &mut self.sockets
+    }
+
+    /// The local port for the next connection, ports are reused only after 16384 connections
+    fn local_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = if port == u16::MAX {
+            EPHEMERAL_PORTS
+        } else {
+            port + 1
+        };
+        port
+    }
+}
+
+/// Sets the address and the default route
+fn configure(iface: &mut Interface, address: Ipv4Cidr, gateway: Option<Ipv4Address>) {
+    iface.update_ip_addrs(|addrs| {
+        addrs.clear();
+        addrs.push(IpCidr::Ipv4(address)).ok();
+    });
+    match gateway {
+        Some(gateway) => {
+            iface.routes_mut().add_default_ipv4_route(gateway).ok();
+        }
+        None => {
+            iface.routes_mut().remove_default_ipv4_route();
+        }
+    }
+}
+
+/// Resolves a host name to an IPv4 address with the DNS servers of the [`Network`]
+///
+/// An IPv4 address is taken as it is, without asking a DNS server. The query needs one of the
+/// [`Storage::dns_queries`], so a `Resolve` and a [`Get`] can't resolve at the same time with a
+/// single entry.
+#[derive(Clone, Copy)]
+pub struct Resolve<'r> {
+    host: &'r str,
+    query: Option<QueryHandle>,
+}
+
+impl<'r> Resolve<'r> {
+    /// Nothing is sent before the first [`poll`](Self::poll)
+    pub fn new(host: &'r str) -> Self {
+        Self { host, query: None }
+    }
+
+    /// Starts the query, or checks whether the answer arrived
+    ///
+    /// The DNS socket gives up by itself if no server answers. Once the query is ready, polling
+    /// it again starts over.
+    pub fn poll<D: Device>(
+        &mut self,
+        network: &mut Network<'_, D>,
+    ) -> Poll<Result<Ipv4Address, Error>> {
+        if !network.is_up() {
+            self.cancel(network);
+            return Poll::Ready(Err(Error::Down));
+        }
+        if let Ok(address) = self.host.parse::<Ipv4Address>() {
+            return Poll::Ready(Ok(address));
+        }
+
+        let dns = network.sockets.get_mut::<dns::Socket>(network.dns);
+        let handle = match self.query {
+            Some(handle) => handle,
+            None => {
+                let Ok(handle) =
+                    dns.start_query(network.iface.context(), self.host, DnsQueryType::A)
+                else {
+                    return Poll::Ready(Err(Error::InvalidHost));
+                };
+                *self.query.insert(handle)
+            }
+        };
+
+        let result = match dns.get_query_result(handle) {
+            Ok(addresses) => addresses
+                .iter()
+                .find_map(|address| match address {
+                    IpAddress::Ipv4(address) => Some(*address),
+                    #[allow(unreachable_patterns)]
+                    _ => None,
+                })
+                .ok_or(Error::NoAddress),
+            Err(GetQueryResultError::Pending) => return Poll::Pending,
+            Err(GetQueryResultError::Failed) => Err(Error::NoAddress),
+        };
+        // the query is gone once its result was taken
+        self.query = None;
+        Poll::Ready(result)
+    }
+
+    /// Cancels the query, if one is running
+    pub fn cancel<D: Device>(&mut self, network: &mut Network<'_, D>) {
+        if let Some(handle) = self.query.take() {
+            network
+                .sockets
+                .get_mut::<dns::Socket>(network.dns)
+                .cancel_query(handle);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State<'r> {
+    Start,
+    Resolving(Resolve<'r>),
+    Connecting,
+    Receiving,
+    Done(Result<(), Error>),
+}
+
+/// An HTTP GET request
+///
+/// The request is sent with HTTP/1.0, so the server neither keeps the connection open nor sends
+/// the body in chunks. A [`Network`] has one TCP socket, so it makes one request at a time.
+pub struct Get<'r> {
+    host: &'r str,
+    port: u16,
+    path: &'r str,
+    state: State<'r>,
+    parser: Parser<256>,
+    /// The request fails if nothing happens until then
+    deadline: Instant,
+}
+
+impl<'r> Get<'r> {
+    /// A request for `path` on `host`, which is a host name or an IPv4 address
+    ///
+    /// Nothing is sent before the first [`poll`](Self::poll).
+    pub fn new(host: &'r str, port: u16, path: &'r str) -> Self {
+        Self {
+            host,
+            port,
+            path,
+            state: State::Start,
+            parser: Parser::new(),
+            deadline: Instant::ZERO,
+        }
+    }
+
+    /// Moves the request forward and hands the parts of the response to `on_event`
+    ///
+    /// Returns `Ready` once the response is complete or the request failed, the connection is
+    /// closed then. A request that is ready stays ready, polling it again returns the same result.
+    pub fn poll<D: Device>(
+        &mut self,
+        network: &mut Network<'_, D>,
+        now: Instant,
+        on_event: impl FnMut(http_response::Event<'_>),
+    ) -> Poll<Result<(), Error>> {
+        if let State::Done(result) = self.state {
+            return Poll::Ready(result);
+        }
+        match self.step(network, now, on_event) {
+            Ok(Poll::Pending) if now < self.deadline => Poll::Pending,
+            result => {
+                let result = match result {
+                    Ok(Poll::Pending) => Err(Error::Timeout),
+                    Ok(Poll::Ready(())) => Ok(()),
+                    Err(err) => Err(err),
+                };
+                self.finish(network, result);
+                Poll::Ready(result)
+            }
+        }
+    }
+
+    fn step<D: Device>(
+        &mut self,
+        network: &mut Network<'_, D>,
+        now: Instant,
+        mut on_event: impl FnMut(http_response::Event<'_>),
+    ) -> Result<Poll<()>, Error> {
+        if !network.is_up() {
+            return Err(Error::Down);
+        }
+
+        if matches!(self.state, State::Start) {
+            self.deadline = now + network.timeout;
+            self.state = State::Resolving(Resolve::new(self.host));
+        }
+
+        if let State::Resolving(resolve) = &mut self.state {
+            let result = resolve.poll(network);
+            match result {
+                Poll::Ready(Ok(address)) => self.connect(network, address)?,
+                Poll::Ready(Err(err)) => {
+                    // the query is gone already
+                    self.state = State::Start;
+                    return Err(err);
+                }
+                Poll::Pending => return Ok(Poll::Pending),
+            }
+        }
+
+        let socket = network.sockets.get_mut::<tcp::Socket>(network.tcp);
+        if matches!(self.state, State::Connecting) {
+            if socket.state() == tcp::State::Closed {
+                return Err(Error::Reset);
+            }
+            if !socket.may_send() {
+                return Ok(Poll::Pending);
+            }
+            let request = [
+                b"GET ",
+                self.path.as_bytes(),
+                b" HTTP/1.0\r\nHost: ",
+                self.host.as_bytes(),
+                b"\r\n\r\n",
+            ];
+            let len = request.iter().map(|part| part.len()).sum::<usize>();
+            if len > socket.send_capacity() - socket.send_queue() {
+                return Err(Error::RequestTooLong);
+            }
+            for part in request {
+                // there is room for all of it
+                socket.send_slice(part).ok();
+            }
+            self.state = State::Receiving;
+        }
+
+        while socket.can_recv() {
+            let parser = &mut self.parser;
+            let result = socket.recv(|data| {
+                let len = data.len();
+                let mut rest: &[u8] = data;
+                let result = loop {
+                    match parser.parse(rest) {
+                        Ok((consumed, event)) => {
+                            rest = &rest[consumed..];
+                            match event {
+                                Some(http_response::Event::End) => {
+                                    on_event(http_response::Event::End);
+                                    break Ok(true);
+                                }
+                                Some(event) => on_event(event),
+                                None => break Ok(false),
+                            }
+                        }
+                        Err(err) => break Err(err),
+                    }
+                };
+                (len - rest.len(), result)
+            });
+            self.deadline = now + network.timeout;
+            match result {
+                Ok(Ok(true)) => return Ok(Poll::Ready(())),
+                Ok(Ok(false)) => {}
+                Ok(Err(err)) => return Err(err.into()),
+                Err(_) => return Err(Error::Reset),
+            }
+        }
+
+        match socket.state() {
+            // an abort, or a reset by the server
+            tcp::State::Closed => Err(Error::Reset),
+            // the server closed the connection, which ends a body without a length
+            _ if !socket.may_recv() => {
+                self.parser.finish()?;
+                on_event(http_response::Event::End);
+                Ok(Poll::Ready(()))
+            }
+            _ => Ok(Poll::Pending),
+        }
+    }
+
+    fn connect<D: Device>(
+        &mut self,
+        network: &mut Network<'_, D>,
+        address: Ipv4Address,
+    ) -> Result<(), Error> {
+        let port = network.local_port();
+        let socket = network.sockets.get_mut::<tcp::Socket>(network.tcp);
+        // the previous connection may still be closing
+        socket.abort();
+        socket
+            .connect(network.iface.context(), (address, self.port), port)
+            .map_err(|_| Error::Unreachable)?;
+        self.state = State::Connecting;
+        Ok(())
+    }
+
+    /// Gives up on the request, e.g. because the application can't take the response
+    ///
+    /// The connection is reset, the request is ready with [`Error::Cancelled`].
+    pub fn cancel<D: Device>(&mut self, network: &mut Network<'_, D>) {
+        if !matches!(self.state, State::Done(_)) {
+            self.finish(network, Err(Error::Cancelled));
+        }
+    }
+
+    /// Closes the connection, or cancels the DNS query
+    fn finish<D: Device>(&mut self, network: &mut Network<'_, D>, result: Result<(), Error>) {
+        match self.state {
+            State::Resolving(mut resolve) => resolve.cancel(network),
+            State::Connecting | State::Receiving => {
+                let socket = network.sockets.get_mut::<tcp::Socket>(network.tcp);
+                match result {
+                    Ok(()) => socket.close(),
+                    Err(_) => socket.abort(),
+                }
+            }
+            State::Start | State::Done(_) => {}
+        }
+        self.state = State::Done(result);
+    }
+}
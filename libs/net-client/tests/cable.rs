@@ -0,0 +1,346 @@
+//! A client and a server on a simulated Ethernet cable
+//!
+//! The server is a plain smoltcp interface, it answers DHCP with the `dhcp-server` library, DNS
+//! with the `captive-dns` library and HTTP with a fixed response, like the access point examples.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::task::Poll;
+
+use net_client::{Config, Error, Event, Get, Network, Resolve, Storage};
+use smoltcp::iface::{Interface, SocketHandle, SocketSet, SocketStorage};
+use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium};
+use smoltcp::socket::{dns::DnsQuery, tcp, udp};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{DhcpOption, EthernetAddress, IpCidr, Ipv4Address, Ipv4Cidr};
+
+const CLIENT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const SERVER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+const SERVER: [u8; 4] = [192, 168, 4, 1];
+const LEASE_TIME: u32 = 60;
+const HOSTNAME: &[u8] = b"sensor";
+const RESPONSE: &[u8] = b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok";
+
+type Queue = Rc<RefCell<VecDeque<Vec<u8>>>>;
+
+/// One end of the cable, it receives what the other end transmits
+struct End {
+    rx: Queue,
+    tx: Queue,
+}
+
+fn cable() -> (End, End) {
+    let (a, b) = (Queue::default(), Queue::default());
+    (
+        End {
+            rx: a.clone(),
+            tx: b.clone(),
+        },
+        End { rx: b, tx: a },
+    )
+}
+
+impl Device for End {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ethernet;
+        capabilities.max_transmission_unit = 1514;
+        capabilities.checksum = ChecksumCapabilities::ignored();
+        capabilities
+    }
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(RxToken, TxToken)> {
+        let frame = self.rx.borrow_mut().pop_front()?;
+        Some((RxToken(frame), TxToken(self.tx.clone())))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<TxToken> {
+        Some(TxToken(self.tx.clone()))
+    }
+}
+
+struct RxToken(Vec<u8>);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F: FnOnce(&[u8]) -> R>(self, f: F) -> R {
+        f(&self.0)
+    }
+}
+
+struct TxToken(Queue);
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let mut frame = vec![0; len];
+        let result = f(&mut frame);
+        self.0.borrow_mut().push_back(frame);
+        result
+    }
+}
+
+/// The access point side of the cable
+struct Lan {
+    device: End,
+    iface: Interface,
+    sockets: SocketSet<'static>,
+    dhcp: SocketHandle,
+    dns: SocketHandle,
+    http: SocketHandle,
+    server: dhcp_server::Server<4>,
+    /// Answer DHCP requests, switched off to let the lease run out
+    serve_dhcp: bool,
+}
+
+impl Lan {
+    fn new(mut device: End) -> Self {
+        let config = smoltcp::iface::Config::new(EthernetAddress(SERVER_MAC).into());
+        let mut iface = Interface::new(config, &mut device, Instant::ZERO);
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::new(Ipv4Address::from(SERVER).into(), 24))
+                .unwrap();
+        });
+
+        let mut sockets = SocketSet::new(Vec::new());
+        let dhcp = sockets.add(udp_socket(dhcp_server::SERVER_PORT));
+        let dns = sockets.add(udp_socket(captive_dns::PORT));
+        let mut http = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; 1024]),
+            tcp::SocketBuffer::new(vec![0; 1024]),
+        );
+        http.listen(80).unwrap();
+        let http = sockets.add(http);
+
+        let mut config = dhcp_server::Config::new(SERVER);
+        config.lease_time = LEASE_TIME;
+        Self {
+            device,
+            iface,
+            sockets,
+            dhcp,
+            dns,
+            http,
+            server: dhcp_server::Server::new(config),
+            serve_dhcp: true,
+        }
+    }
+
+    fn poll(&mut self, now: Instant) {
+        self.iface.poll(now, &mut self.device, &mut self.sockets);
+
+        let mut reply = [0; 576];
+        let socket = self.sockets.get_mut::<udp::Socket>(self.dhcp);
+        while let Ok((message, _)) = socket.recv() {
+            if !self.serve_dhcp {
+                continue;
+            }
+            let now = now.total_millis() as u64;
+            if let Ok(Some((len, destination))) = self.server.handle(now, message, &mut reply) {
+                let destination = (Ipv4Address::from(destination), dhcp_server::CLIENT_PORT);
+                socket.send_slice(&reply[..len], destination).unwrap();
+            }
+        }
+
+        let socket = self.sockets.get_mut::<udp::Socket>(self.dns);
+        while let Ok((query, metadata)) = socket.recv() {
+            let mut response = [0; 512];
+            if let Ok(len) = captive_dns::respond(query, SERVER, &mut response) {
+                socket
+                    .send_slice(&response[..len], metadata.endpoint)
+                    .unwrap();
+            }
+        }
+
+        let socket = self.sockets.get_mut::<tcp::Socket>(self.http);
+        if socket.can_recv() {
+            socket.recv(|request| (request.len(), ())).unwrap();
+            socket.send_slice(RESPONSE).unwrap();
+            socket.close();
+        }
+        // listen again for the next request
+        if !socket.is_open() {
+            socket.listen(80).unwrap();
+        }
+
+        self.iface.poll(now, &mut self.device, &mut self.sockets);
+    }
+}
+
+fn udp_socket(port: u16) -> udp::Socket<'static> {
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 2048]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 2048]),
+    );
+    socket.bind(port).unwrap();
+    socket
+}
+
+struct Buffers {
+    sockets: [SocketStorage<'static>; 4],
+    rx: Vec<u8>,
+    tx: Vec<u8>,
+    queries: [Option<DnsQuery>; 1],
+}
+
+/// The client side of the cable and the access point, the buffers are leaked so the network
+/// can borrow them for good
+fn setup() -> (Network<'static, End>, Lan) {
+    let buffers = Box::leak(Box::new(Buffers {
+        sockets: Default::default(),
+        rx: vec![0; 1024],
+        tx: vec![0; 1024],
+        queries: [None],
+    }));
+    let storage = Storage {
+        sockets: &mut buffers.sockets,
+        rx_buffer: &mut buffers.rx,
+        tx_buffer: &mut buffers.tx,
+        dns_queries: &mut buffers.queries,
+    };
+    let mut config = Config::new(EthernetAddress(CLIENT_MAC).into());
+    config.dhcp_options = &[DhcpOption {
+        kind: 12,
+        data: HOSTNAME,
+    }];
+    config.random_seed = 42;
+    config.timeout = Duration::from_secs(5);
+
+    let (client, server) = cable();
+    (
+        Network::new(client, config, storage, Instant::ZERO),
+        Lan::new(server),
+    )
+}
+
+/// Polls both sides until the client reports an event
+fn wait_for_event(
+    network: &mut Network<'_, End>,
+    lan: &mut Lan,
+    clock: &mut Instant,
+    limit: Duration,
+) -> Event {
+    let end = *clock + limit;
+    while *clock < end {
+        if let Some(event) = network.poll(*clock) {
+            return event;
+        }
+        lan.poll(*clock);
+        *clock += Duration::from_millis(10);
+    }
+    panic!("no event within {limit}");
+}
+
+fn fetch(
+    network: &mut Network<'_, End>,
+    lan: &mut Lan,
+    clock: &mut Instant,
+    host: &str,
+) -> (Result<(), Error>, Vec<u8>) {
+    let mut get = Get::new(host, 80, "/");
+    let mut body = Vec::new();
+    loop {
+        network.poll(*clock);
+        lan.poll(*clock);
+        let poll = get.poll(network, *clock, |event| {
+            if let http_response::Event::Body(data) = event {
+                body.extend_from_slice(data);
+            }
+        });
+        if let Poll::Ready(result) = poll {
+            return (result, body);
+        }
+        *clock += Duration::from_millis(10);
+    }
+}
+
+fn up() -> Event {
+    Event::Up(Ipv4Cidr::new([192, 168, 4, 2].into(), 24))
+}
+
+#[test]
+fn gets_an_address() {
+    let (mut network, mut lan) = setup();
+    let mut clock = Instant::ZERO;
+
+    let event = wait_for_event(&mut network, &mut lan, &mut clock, Duration::from_secs(10));
+    assert_eq!(event, up());
+    assert_eq!(
+        network.address(),
+        Some(Ipv4Cidr::new([192, 168, 4, 2].into(), 24))
+    );
+    // the server announces itself as router
+    assert_eq!(network.gateway(), Some(Ipv4Address::from(SERVER)));
+
+    let now = clock.total_millis() as u64;
+    let lease = lan.server.leases(now).next().unwrap();
+    assert!(lease.bound);
+    assert_eq!(lease.mac, CLIENT_MAC);
+    assert_eq!(lease.hostname(), Some("sensor"));
+}
+
+#[test]
+fn resolves_the_host() {
+    let (mut network, mut lan) = setup();
+    let mut clock = Instant::ZERO;
+    wait_for_event(&mut network, &mut lan, &mut clock, Duration::from_secs(10));
+
+    // the captive DNS server answers every name with its own address
+    let (result, body) = fetch(&mut network, &mut lan, &mut clock, "portal.example");
+    assert_eq!(result, Ok(()));
+    assert_eq!(body, b"ok");
+
+    let (result, body) = fetch(&mut network, &mut lan, &mut clock, "192.168.4.1");
+    assert_eq!(result, Ok(()));
+    assert_eq!(body, b"ok");
+}
+
+#[test]
+fn resolves_without_a_request() {
+    let (mut network, mut lan) = setup();
+    let mut clock = Instant::ZERO;
+    wait_for_event(&mut network, &mut lan, &mut clock, Duration::from_secs(10));
+
+    let mut resolve = Resolve::new("portal.example");
+    let result = loop {
+        network.poll(clock);
+        lan.poll(clock);
+        if let Poll::Ready(result) = resolve.poll(&mut network) {
+            break result;
+        }
+        clock += Duration::from_millis(10);
+    };
+    assert_eq!(result, Ok(Ipv4Address::from(SERVER)));
+
+    // addresses don't need a DNS server
+    let mut resolve = Resolve::new("10.0.0.1");
+    assert_eq!(
+        resolve.poll(&mut network),
+        Poll::Ready(Ok(Ipv4Address::new(10, 0, 0, 1)))
+    );
+}
+
+#[test]
+fn lease_runs_out() {
+    let (mut network, mut lan) = setup();
+    let mut clock = Instant::ZERO;
+    wait_for_event(&mut network, &mut lan, &mut clock, Duration::from_secs(10));
+
+    lan.serve_dhcp = false;
+    let limit = Duration::from_secs(LEASE_TIME as u64 + 10);
+    let event = wait_for_event(&mut network, &mut lan, &mut clock, limit);
+    assert_eq!(event, Event::Down);
+    assert!(!network.is_up());
+    assert_eq!(network.gateway(), None);
+
+    let (result, _) = fetch(&mut network, &mut lan, &mut clock, "192.168.4.1");
+    assert_eq!(result, Err(Error::Down));
+
+    // the client gets its address back once the server answers again
+    lan.serve_dhcp = true;
+    let event = wait_for_event(&mut network, &mut lan, &mut clock, Duration::from_secs(120));
+    assert_eq!(event, up());
+}
@@ -0,0 +1,363 @@
+//! Requests to a server on the same interface, over smoltcp's loopback device
+
+use std::task::Poll;
+
+use net_client::{Config, Error, Event, Get, Ipv4, Network, Storage};
+use smoltcp::iface::{SocketHandle, SocketStorage};
+use smoltcp::phy::{Loopback, Medium};
+use smoltcp::socket::{dns::DnsQuery, tcp};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{EthernetAddress, Ipv4Cidr};
+
+const ADDRESS: &str = "127.0.0.1";
+
+/// What the server does with a request
+#[derive(Clone, Copy)]
+enum Answer {
+    /// Sends the response and closes the connection
+    Close(&'static [u8]),
+    /// Sends the response and leaves the connection open
+    KeepOpen(&'static [u8]),
+    Nothing,
+}
+
+struct Buffers {
+    sockets: [SocketStorage<'static>; 6],
+    rx: Vec<u8>,
+    tx: Vec<u8>,
+    queries: [Option<DnsQuery>; 1],
+}
+
+impl Buffers {
+    /// Leaks the buffers, so the network can borrow them for good
+    fn new(tx_len: usize) -> &'static mut Self {
+        Box::leak(Box::new(Self {
+            sockets: Default::default(),
+            rx: vec![0; 4096],
+            tx: vec![0; tx_len],
+            queries: [None],
+        }))
+    }
+}
+
+fn network(buffers: &'static mut Buffers, ipv4: Ipv4) -> Network<'static, Loopback> {
+    let mut config = Config::new(EthernetAddress([0x02, 0, 0, 0, 0, 1]).into());
+    config.ipv4 = ipv4;
+    config.timeout = Duration::from_secs(1);
+    let storage = Storage {
+        sockets: &mut buffers.sockets,
+        rx_buffer: &mut buffers.rx,
+        tx_buffer: &mut buffers.tx,
+        dns_queries: &mut buffers.queries,
+    };
+    let mut network = Network::new(
+        Loopback::new(Medium::Ethernet),
+        config,
+        storage,
+        Instant::ZERO,
+    );
+    assert_eq!(
+        network.poll(Instant::ZERO),
+        Some(Event::Up(Ipv4Cidr::new([127, 0, 0, 1].into(), 8)))
+    );
+    network
+}
+
+fn static_address() -> Ipv4 {
+    Ipv4::Static {
+        address: Ipv4Cidr::new([127, 0, 0, 1].into(), 8),
+        gateway: None,
+        dns_server: None,
+    }
+}
+
+fn listen(network: &mut Network<'_, Loopback>, port: u16) -> SocketHandle {
+    let mut socket = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 4096]),
+        tcp::SocketBuffer::new(vec![0; 4096]),
+    );
+    socket.listen(port).unwrap();
+    network.sockets_mut().add(socket)
+}
+
+/// Everything the client got, and the request the server got
+#[derive(Debug, Default)]
+struct Exchange {
+    status: Option<u16>,
+    body: Vec<u8>,
+    ended: bool,
+    request: Vec<u8>,
+}
+
+/// Polls the request and the server until the request is ready, one millisecond at a time
+fn run(
+    network: &mut Network<'_, Loopback>,
+    server: SocketHandle,
+    answer: Answer,
+    get: &mut Get<'_>,
+) -> (Result<(), Error>, Exchange) {
+    let mut exchange = Exchange::default();
+    for ms in 0..10_000 {
+        let now = Instant::from_millis(ms);
+        network.poll(now);
+
+        let socket = network.sockets_mut().get_mut::<tcp::Socket>(server);
+        if socket.can_recv() {
+            socket
+                .recv(|data| {
+                    exchange.request.extend_from_slice(data);
+                    (data.len(), ())
+                })
+                .unwrap();
+            if exchange.request.ends_with(b"\r\n\r\n") {
+                match answer {
+                    Answer::Close(response) => {
+                        socket.send_slice(response).unwrap();
+                        socket.close();
+                    }
+                    Answer::KeepOpen(response) => {
+                        socket.send_slice(response).unwrap();
+                    }
+                    Answer::Nothing => {}
+                }
+            }
+        }
+
+        let poll = get.poll(network, now, |event| match event {
+            http_response::Event::Status(status) => exchange.status = Some(status.code),
+            http_response::Event::Body(data) => exchange.body.extend_from_slice(data),
+            http_response::Event::End => exchange.ended = true,
+            _ => {}
+        });
+        if let Poll::Ready(result) = poll {
+            return (result, exchange);
+        }
+    }
+    panic!("the request never finished");
+}
+
+#[test]
+fn response_with_length() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/index.html");
+    let answer = Answer::KeepOpen(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+    let (result, exchange) = run(&mut network, server, answer, &mut get);
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        exchange.request,
+        b"GET /index.html HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n"
+    );
+    assert_eq!(exchange.status, Some(200));
+    assert_eq!(exchange.body, b"hello");
+    assert!(exchange.ended);
+
+    // ready requests stay ready
+    let poll = get.poll(&mut network, Instant::from_secs(20), |_| {});
+    assert_eq!(poll, Poll::Ready(Ok(())));
+}
+
+#[test]
+fn response_until_close() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let answer = Answer::Close(b"HTTP/1.0 404 Not Found\r\n\r\nnothing here");
+    let (result, exchange) = run(&mut network, server, answer, &mut get);
+
+    // the status is up to the application
+    assert_eq!(result, Ok(()));
+    assert_eq!(exchange.status, Some(404));
+    assert_eq!(exchange.body, b"nothing here");
+    assert!(exchange.ended);
+}
+
+#[test]
+fn large_response() {
+    // several times the receive window
+    static RESPONSE: std::sync::LazyLock<Vec<u8>> = std::sync::LazyLock::new(|| {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 20000\r\n\r\n".to_vec();
+        response.extend((0..20_000).map(|i| i as u8));
+        response
+    });
+
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let mut server = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 1024]),
+        tcp::SocketBuffer::new(vec![0; RESPONSE.len()]),
+    );
+    server.listen(80).unwrap();
+    let server = network.sockets_mut().add(server);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let (result, exchange) = run(&mut network, server, Answer::Close(&RESPONSE), &mut get);
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(exchange.body.len(), 20_000);
+    assert!(exchange.body.iter().enumerate().all(|(i, &b)| b == i as u8));
+}
+
+#[test]
+fn refused() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 81, "/");
+    let (result, _) = run(&mut network, server, Answer::Nothing, &mut get);
+    assert_eq!(result, Err(Error::Reset));
+}
+
+#[test]
+fn times_out() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let (result, exchange) = run(&mut network, server, Answer::Nothing, &mut get);
+    assert_eq!(result, Err(Error::Timeout));
+    assert!(exchange.status.is_none());
+}
+
+#[test]
+fn truncated_response() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let answer = Answer::Close(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello");
+    let (result, exchange) = run(&mut network, server, answer, &mut get);
+    assert_eq!(
+        result,
+        Err(Error::Response(http_response::Error::UnexpectedEof))
+    );
+    assert_eq!(exchange.body, b"hello");
+    assert!(!exchange.ended);
+}
+
+#[test]
+fn invalid_response() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let answer = Answer::KeepOpen(b"SSH-2.0-OpenSSH_9.6\r\n");
+    let (result, _) = run(&mut network, server, answer, &mut get);
+    assert!(matches!(result, Err(Error::Response(_))));
+}
+
+#[test]
+fn request_too_long() {
+    let buffers = Buffers::new(64);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let path = format!("/{}", "a".repeat(64));
+    let mut get = Get::new(ADDRESS, 80, &path);
+    let (result, _) = run(&mut network, server, Answer::Nothing, &mut get);
+    assert_eq!(result, Err(Error::RequestTooLong));
+}
+
+#[test]
+fn one_request_after_the_other() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let first = listen(&mut network, 80);
+    let second = listen(&mut network, 80);
+
+    let answer = Answer::Close(b"HTTP/1.0 200 OK\r\n\r\none");
+    let (result, exchange) = run(
+        &mut network,
+        first,
+        answer,
+        &mut Get::new(ADDRESS, 80, "/1"),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(exchange.body, b"one");
+
+    let answer = Answer::Close(b"HTTP/1.0 200 OK\r\n\r\ntwo");
+    let (result, exchange) = run(
+        &mut network,
+        second,
+        answer,
+        &mut Get::new(ADDRESS, 80, "/2"),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(exchange.body, b"two");
+}
+
+#[test]
+fn needs_an_address() {
+    let buffers = Buffers::new(1024);
+    let mut config = Config::new(EthernetAddress([0x02, 0, 0, 0, 0, 1]).into());
+    config.timeout = Duration::from_secs(1);
+    let storage = Storage {
+        sockets: &mut buffers.sockets,
+        rx_buffer: &mut buffers.rx,
+        tx_buffer: &mut buffers.tx,
+        dns_queries: &mut buffers.queries,
+    };
+    // nobody answers the DHCP requests
+    let mut network = Network::new(
+        Loopback::new(Medium::Ethernet),
+        config,
+        storage,
+        Instant::ZERO,
+    );
+    assert_eq!(network.poll(Instant::ZERO), None);
+    assert!(!network.is_up());
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let poll = get.poll(&mut network, Instant::ZERO, |_| {});
+    assert_eq!(poll, Poll::Ready(Err(Error::Down)));
+}
+
+#[test]
+fn cancelled() {
+    let buffers = Buffers::new(1024);
+    let mut network = network(buffers, static_address());
+    let server = listen(&mut network, 80);
+
+    let mut get = Get::new(ADDRESS, 80, "/");
+    let mut cancel = false;
+    for ms in 0..1_000 {
+        let now = Instant::from_millis(ms);
+        network.poll(now);
+        let socket = network.sockets_mut().get_mut::<tcp::Socket>(server);
+        if socket.can_recv() {
+            socket.recv(|data| (data.len(), ())).unwrap();
+            socket
+                .send_slice(b"HTTP/1.1 200 OK\r\nContent-Length: 100000\r\n\r\n")
+                .unwrap();
+        }
+        let poll = get.poll(&mut network, now, |event| {
+            // the application doesn't want this one
+            cancel |= matches!(event, http_response::Event::HeadersEnd);
+        });
+        assert_eq!(poll, Poll::Pending);
+        if cancel {
+            break;
+        }
+    }
+    assert!(cancel);
+
+    get.cancel(&mut network);
+    let poll = get.poll(&mut network, Instant::from_secs(1), |_| {});
+    assert_eq!(poll, Poll::Ready(Err(Error::Cancelled)));
+
+    // the server sees the reset, the client sends it on the next poll
+    for _ in 0..2 {
+        network.poll(Instant::from_secs(1));
+    }
+    let socket = network.sockets_mut().get_mut::<tcp::Socket>(server);
+    assert!(!socket.is_active());
+}
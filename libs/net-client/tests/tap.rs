@@ -0,0 +1,85 @@
+//! A request to a real server on the host, through a Linux TAP interface
+//!
+//! The test needs a TAP interface that the user may open, and a server on the host side of it:
+//!
+//! ```shell
+//! sudo ip tuntap add name tap0 mode tap user $USER
+//! sudo ip link set tap0 up
+//! sudo ip addr add 192.168.69.1/24 dev tap0
+//! python3 -m http.server --bind 192.168.69.1 8000
+//! ```
+//!
+//! Then run it with `cargo test --test tap -- --ignored`. `NET_CLIENT_TAP`,
+//! `NET_CLIENT_ADDRESS`, `NET_CLIENT_SERVER` and `NET_CLIENT_PATH` change the interface, the
+//! address of the client, the server and the requested path.
+
+use std::os::fd::AsRawFd;
+use std::task::Poll;
+
+use net_client::{Config, Event, Get, Ipv4, Network, Storage};
+use smoltcp::iface::SocketStorage;
+use smoltcp::phy::{self, Medium, TunTapInterface};
+use smoltcp::socket::dns::DnsQuery;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{EthernetAddress, Ipv4Cidr};
+
+fn env(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.into())
+}
+
+#[test]
+#[ignore = "needs a TAP interface and a server, see the module docs"]
+fn get_from_the_host() {
+    let tap = env("NET_CLIENT_TAP", "tap0");
+    let address: Ipv4Cidr = env("NET_CLIENT_ADDRESS", "192.168.69.2/24")
+        .parse()
+        .unwrap();
+    let server = env("NET_CLIENT_SERVER", "192.168.69.1:8000");
+    let (host, port) = server.split_once(':').unwrap();
+    let path = env("NET_CLIENT_PATH", "/");
+
+    let device = TunTapInterface::new(&tap, Medium::Ethernet).unwrap();
+    let mut config = Config::new(EthernetAddress([0x02, 0, 0, 0, 0, 1]).into());
+    config.ipv4 = Ipv4::Static {
+        address,
+        gateway: None,
+        dns_server: None,
+    };
+    config.random_seed = std::process::id().into();
+    config.timeout = Duration::from_secs(5);
+
+    let mut sockets: [SocketStorage; 4] = Default::default();
+    let (mut rx, mut tx) = (vec![0; 4096], vec![0; 1024]);
+    let mut queries: [Option<DnsQuery>; 1] = [None];
+    let storage = Storage {
+        sockets: &mut sockets,
+        rx_buffer: &mut rx,
+        tx_buffer: &mut tx,
+        dns_queries: &mut queries,
+    };
+    let mut network = Network::new(device, config, storage, Instant::now());
+    assert_eq!(network.poll(Instant::now()), Some(Event::Up(address)));
+
+    let mut get = Get::new(host, port.parse().unwrap(), &path);
+    let mut status = None;
+    let mut len = 0;
+    let result = loop {
+        network.poll(Instant::now());
+        let poll = get.poll(&mut network, Instant::now(), |event| match event {
+            http_response::Event::Status(s) => status = Some(s.code),
+            http_response::Event::Body(data) => len += data.len(),
+            _ => {}
+        });
+        if let Poll::Ready(result) = poll {
+            break result;
+        }
+        // sleep until a frame arrives or smoltcp has something to do
+        let delay = network.poll_delay(Instant::now());
+        let fd = network.device_mut().as_raw_fd();
+        phy::wait(fd, delay).unwrap();
+    };
+
+    println!("{status:?}, {len} bytes");
+    assert_eq!(result, Ok(()));
+    assert_eq!(status, Some(200));
+}
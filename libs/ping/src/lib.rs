@@ -0,0 +1,555 @@
+//! ICMP echo requests and replies, and the statistics the `ping` command prints.
+//!
+//! Like the other libraries, the crate doesn't touch the network: [`encode_request`] encodes an
+//! echo request to send with an ICMP socket, [`EchoReply::decode`] checks what the socket
+//! received, and a [`Session`] matches the replies to the requests and computes the round-trip
+//! times. [`Summary`] prints them the way Linux `ping` does.
+//!
+//! All times are in microseconds.
+//!
+//! ```
+//! use ping::{encode_request, EchoReply, Reply, Session};
+//!
+//! let mut session: Session<8> = Session::new();
+//! let mut request = [0; 64];
+//! let len = encode_request(0x1234, 1, ping::DEFAULT_PAYLOAD_LEN, &mut request).unwrap();
+//! session.sent(1, 1_000_000);
+//!
+//! // the host answers with the same message, as an echo reply
+//! let mut reply = request;
+//! reply[0] = 0;
+//! let checksum = ping::checksum(&[&reply[..2], &reply[4..len]]);
+//! reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+//!
+//! let reply = EchoReply::decode(&reply[..len]).unwrap();
+//! assert_eq!((reply.ident, reply.seq), (0x1234, 1));
+//! assert_eq!(session.received(reply.seq, 1_002_310), Reply::Received(2_310));
+//!
+//! let summary = session.summary(1_002_310);
+//! assert_eq!(
+//!     summary.to_string(),
+//!     "1 packets transmitted, 1 received, 0% packet loss, time 2ms\n\
+//!      rtt min/avg/max/mdev = 2.310/2.310/2.310/0.000 ms"
+//! );
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// Length of the ICMP echo header: type, code, checksum, identifier and sequence number
+pub const HEADER_LEN: usize = 8;
+
+/// The payload length Linux `ping` sends by default
+pub const DEFAULT_PAYLOAD_LEN: usize = 56;
+
+/// Length of an IPv4 header without options, `ping` counts it in `56(84) bytes of data`
+pub const IPV4_HEADER_LEN: usize = 20;
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+
+/// Errors returned by the encoder and decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer is too small for the request
+    BufferTooSmall,
+    /// The message is shorter than an echo header
+    Truncated,
+    /// The checksum doesn't match, the message was corrupted on the way
+    Checksum,
+    /// Another ICMP message than an echo reply, with its type
+    NotEchoReply(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The internet checksum (RFC 1071) over `parts`, as if they were one message
+///
+/// Every part but the last has to have an even length.
+pub fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for part in parts {
+        let (words, rest) = part.as_chunks::<2>();
+        for word in words {
+            sum += u32::from(u16::from_be_bytes(*word));
+        }
+        if let [last] = rest {
+            sum += u32::from(*last) << 8;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Encodes an echo request with `payload_len` bytes of payload into `buf`
+///
+/// The payload is filled with the bytes `0, 1, 2, ...`, the host sends it back unchanged.
+/// Returns the length of the message.
+pub fn encode_request(
+    ident: u16,
+    seq: u16,
+    payload_len: usize,
+    buf: &mut [u8],
+) -> Result<usize, Error> {
+    let len = HEADER_LEN + payload_len;
+    let message = buf.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+    message[0] = ECHO_REQUEST;
+    message[1] = 0;
+    message[2..4].fill(0);
+    message[4..6].copy_from_slice(&ident.to_be_bytes());
+    message[6..8].copy_from_slice(&seq.to_be_bytes());
+    for (i, byte) in message[HEADER_LEN..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let checksum = checksum(&[message]);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+    Ok(len)
+}
+
+/// An echo reply received from a host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EchoReply<'a> {
+    /// The identifier of the request, which tells the pings of different programs apart
+    pub ident: u16,
+    /// The sequence number of the request
+    pub seq: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> EchoReply<'a> {
+    /// Checks an ICMP message, without the IP header in front of it
+    pub fn decode(message: &'a [u8]) -> Result<Self, Error> {
+        if message.len() < HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        if checksum(&[message]) != 0 {
+            return Err(Error::Checksum);
+        }
+        if message[0] != ECHO_REPLY || message[1] != 0 {
+            return Err(Error::NotEchoReply(message[0]));
+        }
+        Ok(Self {
+            ident: u16::from_be_bytes([message[4], message[5]]),
+            seq: u16::from_be_bytes([message[6], message[7]]),
+            payload: &message[HEADER_LEN..],
+        })
+    }
+}
+
+/// What [`Session::received`] made of a reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reply {
+    /// The first reply to a request, with the round-trip time
+    Received(u64),
+    /// Another reply to a request that was answered already, with its round-trip time
+    Duplicate(u64),
+    /// A reply to a request we didn't send, or that is too old to remember
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Request {
+    seq: u16,
+    sent: u64,
+    answered: bool,
+}
+
+/// The requests sent to one host and the replies received
+///
+/// The session remembers the last `N` requests, a reply to an older one is
+/// [`Reply::Unknown`]. Requests that are never answered count as lost.
+#[derive(Debug, Clone)]
+pub struct Session<const N: usize> {
+    /// Request `seq` is kept in slot `seq % N`
+    requests: [Option<Request>; N],
+    started: Option<u64>,
+    transmitted: u32,
+    received: u32,
+    duplicates: u32,
+    min: u64,
+    max: u64,
+    sum: u64,
+    /// The sum of the squared round-trip times, for the standard deviation
+    sum_squares: u64,
+}
+
+impl<const N: usize> Default for Session<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Session<N> {
+    pub const fn new() -> Self {
+        Self {
+            requests: [None; N],
+            started: None,
+            transmitted: 0,
+            received: 0,
+            duplicates: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+            sum_squares: 0,
+        }
+    }
+
+    /// Records that request `seq` was sent at `now`
+    pub fn sent(&mut self, seq: u16, now: u64) {
+        self.started.get_or_insert(now);
+        self.transmitted += 1;
+        self.requests[usize::from(seq) % N] = Some(Request {
+            seq,
+            sent: now,
+            answered: false,
+        });
+    }
+
+    /// Matches a reply to request `seq` received at `now`
+    pub fn received(&mut self, seq: u16, now: u64) -> Reply {
+        let Some(request) = &mut self.requests[usize::from(seq) % N] else {
+            return Reply::Unknown;
+        };
+        if request.seq != seq {
+            return Reply::Unknown;
+        }
+        let rtt = now.saturating_sub(request.sent);
+        if request.answered {
+            self.duplicates += 1;
+            return Reply::Duplicate(rtt);
+        }
+        request.answered = true;
+        self.received += 1;
+        self.min = self.min.min(rtt);
+        self.max = self.max.max(rtt);
+        self.sum += rtt;
+        self.sum_squares = self.sum_squares.saturating_add(rtt * rtt);
+        Reply::Received(rtt)
+    }
+
+    /// `true` if request `seq` is remembered and still waits for its reply
+    pub fn is_pending(&self, seq: u16) -> bool {
+        self.requests[usize::from(seq) % N]
+            .is_some_and(|request| request.seq == seq && !request.answered)
+    }
+
+    /// The statistics so far, `now` is the end of the session
+    pub fn summary(&self, now: u64) -> Summary {
+        let rtt = (self.received > 0).then(|| {
+            let count = u64::from(self.received);
+            let avg = self.sum / count;
+            // the standard deviation, sqrt(E[rtt²] - E[rtt]²)
+            let variance = (self.sum_squares / count).saturating_sub(avg * avg);
+            Rtt {
+                min: self.min,
+                avg,
+                max: self.max,
+                mdev: variance.isqrt(),
+            }
+        });
+        Summary {
+            transmitted: self.transmitted,
+            received: self.received,
+            duplicates: self.duplicates,
+            time: self
+                .started
+                .map_or(0, |started| now.saturating_sub(started)),
+            rtt,
+        }
+    }
+}
+
+/// Round-trip times, in microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rtt {
+    pub min: u64,
+    pub avg: u64,
+    pub max: u64,
+    /// The standard deviation, `ping` calls it mean deviation
+    pub mdev: u64,
+}
+
+/// The statistics `ping` prints at the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Summary {
+    pub transmitted: u32,
+    /// Requests that got a reply, duplicates aren't counted
+    pub received: u32,
+    pub duplicates: u32,
+    /// From the first request to the end of the session
+    pub time: u64,
+    /// `None` if no request was answered
+    pub rtt: Option<Rtt>,
+}
+
+impl Summary {
+    /// The share of the requests that got no reply, in millionths
+    pub fn loss_ppm(&self) -> u32 {
+        if self.transmitted == 0 {
+            return 0;
+        }
+        let lost = u64::from(self.transmitted.saturating_sub(self.received));
+        ((lost * 100_000_000 / u64::from(self.transmitted) + 50) / 100) as u32
+    }
+}
+
+/// Prints the statistics like Linux `ping`, e.g.
+///
+/// ```text
+/// 4 packets transmitted, 3 received, 25% packet loss, time 3004ms
+/// rtt min/avg/max/mdev = 1.234/2.345/3.456/0.907 ms
+/// ```
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets transmitted, {} received, ",
+            self.transmitted, self.received
+        )?;
+        if self.duplicates > 0 {
+            write!(f, "+{} duplicates, ", self.duplicates)?;
+        }
+        // like `%g`, at most 4 decimals and without trailing zeros
+        let loss = self.loss_ppm();
+        write!(f, "{}", loss / 10_000)?;
+        let mut decimals = loss % 10_000;
+        if decimals > 0 {
+            let mut digits = 4;
+            while decimals.is_multiple_of(10) {
+                decimals /= 10;
+                digits -= 1;
+            }
+            write!(f, ".{:0digits$}", decimals)?;
+        }
+        write!(f, "% packet loss, time {}ms", self.time / 1_000)?;
+
+        if let Some(rtt) = self.rtt {
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {}/{}/{}/{} ms",
+                Millis(rtt.min),
+                Millis(rtt.avg),
+                Millis(rtt.max),
+                Millis(rtt.mdev)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a time in microseconds as milliseconds with three decimals, e.g. `2.310`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Millis(pub u64);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0 / 1_000, self.0 % 1_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    /// Turns a request into the reply a host sends back
+    fn answer(request: &[u8]) -> std::vec::Vec<u8> {
+        let mut reply = request.to_vec();
+        reply[0] = ECHO_REPLY;
+        reply[2..4].fill(0);
+        let checksum = checksum(&[&reply]);
+        reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+        reply
+    }
+
+    #[test]
+    fn encodes_a_request() {
+        let mut buf = [0; 64];
+        let len = encode_request(0xbeef, 7, 4, &mut buf).unwrap();
+        assert_eq!(len, 12);
+        // type 8, code 0, checksum, identifier, sequence number, payload
+        assert_eq!(buf[..len], [8, 0, 0x37, 0x05, 0xbe, 0xef, 0, 7, 0, 1, 2, 3]);
+        // a message with a valid checksum sums up to zero
+        assert_eq!(checksum(&[&buf[..len]]), 0);
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buf = [0; 63];
+        assert_eq!(
+            encode_request(1, 1, DEFAULT_PAYLOAD_LEN, &mut buf),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn checksum_of_odd_length() {
+        // the last byte is padded with a zero
+        assert_eq!(checksum(&[&[0x12, 0x34, 0x56]]), !0x6834);
+        assert_eq!(
+            checksum(&[&[0x12, 0x34], &[0x56]]),
+            checksum(&[&[0x12, 0x34, 0x56, 0]])
+        );
+    }
+
+    #[test]
+    fn decodes_a_reply() {
+        let mut request = [0; 64];
+        let len = encode_request(0x1234, 42, DEFAULT_PAYLOAD_LEN, &mut request).unwrap();
+        let reply = answer(&request[..len]);
+        let reply = EchoReply::decode(&reply).unwrap();
+        assert_eq!(reply.ident, 0x1234);
+        assert_eq!(reply.seq, 42);
+        assert_eq!(reply.payload, &request[HEADER_LEN..len]);
+    }
+
+    #[test]
+    fn rejects_other_messages() {
+        let mut request = [0; 16];
+        let len = encode_request(1, 1, 8, &mut request).unwrap();
+        // our own request, e.g. on a loopback interface
+        assert_eq!(
+            EchoReply::decode(&request[..len]),
+            Err(Error::NotEchoReply(8))
+        );
+        assert_eq!(EchoReply::decode(&request[..7]), Err(Error::Truncated));
+
+        let mut reply = answer(&request[..len]);
+        reply[10] ^= 0xff;
+        assert_eq!(EchoReply::decode(&reply), Err(Error::Checksum));
+    }
+
+    #[test]
+    fn statistics() {
+        let mut session: Session<4> = Session::new();
+        for (seq, rtt) in [(1, 1_000), (2, 2_000), (3, 3_000)] {
+            let sent = u64::from(seq) * 1_000_000;
+            session.sent(seq, sent);
+            assert_eq!(session.received(seq, sent + rtt), Reply::Received(rtt));
+        }
+        let summary = session.summary(3_003_000);
+        assert_eq!(summary.transmitted, 3);
+        assert_eq!(summary.received, 3);
+        assert_eq!(summary.time, 2_003_000);
+        assert_eq!(
+            summary.rtt,
+            Some(Rtt {
+                min: 1_000,
+                avg: 2_000,
+                max: 3_000,
+                // sqrt(2/3) ms
+                mdev: 816,
+            })
+        );
+        assert_eq!(
+            summary.to_string(),
+            "3 packets transmitted, 3 received, 0% packet loss, time 2003ms\n\
+             rtt min/avg/max/mdev = 1.000/2.000/3.000/0.816 ms"
+        );
+    }
+
+    #[test]
+    fn lost_requests() {
+        let mut session: Session<4> = Session::new();
+        for seq in 1..=3 {
+            session.sent(seq, u64::from(seq) * 1_000_000);
+        }
+        assert!(session.is_pending(2));
+        assert_eq!(session.received(2, 2_010_000), Reply::Received(10_000));
+        assert!(!session.is_pending(2));
+
+        let summary = session.summary(4_000_000);
+        assert_eq!(summary.loss_ppm(), 666_667);
+        assert_eq!(
+            summary.to_string(),
+            "3 packets transmitted, 1 received, 66.6667% packet loss, time 3000ms\n\
+             rtt min/avg/max/mdev = 10.000/10.000/10.000/0.000 ms"
+        );
+    }
+
+    #[test]
+    fn nothing_received() {
+        let mut session: Session<4> = Session::new();
+        session.sent(1, 0);
+        session.sent(2, 1_000_000);
+        // without replies there are no round-trip times to print
+        assert_eq!(
+            session.summary(2_000_000).to_string(),
+            "2 packets transmitted, 0 received, 100% packet loss, time 2000ms"
+        );
+        assert_eq!(
+            Session::<4>::new().summary(0).to_string(),
+            "0 packets transmitted, 0 received, 0% packet loss, time 0ms"
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut session: Session<4> = Session::new();
+        session.sent(1, 0);
+        assert_eq!(session.received(1, 5_000), Reply::Received(5_000));
+        assert_eq!(session.received(1, 6_000), Reply::Duplicate(6_000));
+
+        let summary = session.summary(1_000_000);
+        assert_eq!(summary.received, 1);
+        assert_eq!(summary.duplicates, 1);
+        assert!(summary
+            .to_string()
+            .starts_with("1 packets transmitted, 1 received, +1 duplicates, 0% packet loss"));
+    }
+
+    #[test]
+    fn unknown_replies() {
+        let mut session: Session<4> = Session::new();
+        assert_eq!(session.received(1, 0), Reply::Unknown);
+
+        // request 5 takes the slot of request 1, which is forgotten
+        for seq in 1..=5 {
+            session.sent(seq, 0);
+        }
+        assert_eq!(session.received(1, 1_000), Reply::Unknown);
+        assert_eq!(session.received(5, 1_000), Reply::Received(1_000));
+        // sequence numbers wrap around
+        session.sent(u16::MAX, 2_000);
+        assert_eq!(session.received(u16::MAX, 3_000), Reply::Received(1_000));
+        assert_eq!(session.summary(3_000).received, 2);
+    }
+
+    #[test]
+    fn loss_like_printf() {
+        let summary = |transmitted, received| Summary {
+            transmitted,
+            received,
+            duplicates: 0,
+            time: 0,
+            rtt: None,
+        };
+        let loss = |transmitted, received| {
+            let text = summary(transmitted, received).to_string();
+            let end = text.find("% packet loss").unwrap();
+            let start = text[..end].rfind(' ').unwrap() + 1;
+            text[start..end].to_string()
+        };
+        assert_eq!(loss(4, 3), "25");
+        assert_eq!(loss(8, 7), "12.5");
+        assert_eq!(loss(3, 2), "33.3333");
+        assert_eq!(loss(7, 6), "14.2857");
+        assert_eq!(loss(1, 1), "0");
+        assert_eq!(loss(1, 0), "100");
+    }
+}
@@ -0,0 +1,80 @@
+//! Pings smoltcp's own interface over its loopback device, the interface answers echo requests
+//! by itself
+
+use ping::{encode_request, EchoReply, Error, Reply, Session};
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{Loopback, Medium};
+use smoltcp::socket::icmp;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{HardwareAddress, IpAddress, IpCidr, Ipv4Address};
+
+const IDENT: u16 = 0x2222;
+
+fn setup() -> (
+    Interface,
+    Loopback,
+    SocketSet<'static>,
+    smoltcp::iface::SocketHandle,
+) {
+    let mut device = Loopback::new(Medium::Ip);
+    let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, Instant::ZERO);
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(Ipv4Address::new(127, 0, 0, 1).into(), 8))
+            .unwrap();
+    });
+
+    let mut socket = icmp::Socket::new(
+        icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY; 8], vec![0; 2048]),
+        icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY; 8], vec![0; 2048]),
+    );
+    socket.bind(icmp::Endpoint::Ident(IDENT)).unwrap();
+    let mut sockets = SocketSet::new(Vec::new());
+    let handle = sockets.add(socket);
+    (iface, device, sockets, handle)
+}
+
+#[test]
+fn the_stack_answers() {
+    let (mut iface, mut device, mut sockets, handle) = setup();
+    let host = IpAddress::v4(127, 0, 0, 1);
+    let mut session: Session<4> = Session::new();
+    let mut clock = Instant::ZERO;
+
+    for seq in 1..=3 {
+        let mut request = [0; 64];
+        let len = encode_request(IDENT, seq, ping::DEFAULT_PAYLOAD_LEN, &mut request).unwrap();
+        let socket = sockets.get_mut::<icmp::Socket>(handle);
+        socket.send_slice(&request[..len], host).unwrap();
+        session.sent(seq, clock.total_micros() as u64);
+
+        // the request goes out, the interface receives it and answers, the reply comes back
+        clock += Duration::from_millis(1);
+        for _ in 0..3 {
+            iface.poll(clock, &mut device, &mut sockets);
+        }
+
+        let socket = sockets.get_mut::<icmp::Socket>(handle);
+        let mut replies = 0;
+        while let Ok((message, from)) = socket.recv() {
+            assert_eq!(from, host);
+            match EchoReply::decode(message) {
+                Ok(reply) => {
+                    assert_eq!(reply.ident, IDENT);
+                    assert_eq!(reply.payload, &request[ping::HEADER_LEN..len]);
+                    let now = clock.total_micros() as u64;
+                    assert_eq!(session.received(reply.seq, now), Reply::Received(1_000));
+                    replies += 1;
+                }
+                // the socket also sees our own request on the loopback device
+                Err(err) => assert_eq!(err, Error::NotEchoReply(8)),
+            }
+        }
+        assert_eq!(replies, 1);
+    }
+
+    let summary = session.summary(clock.total_micros() as u64);
+    assert_eq!(summary.transmitted, 3);
+    assert_eq!(summary.received, 3);
+    assert_eq!(summary.loss_ppm(), 0);
+}
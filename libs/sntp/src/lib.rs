@@ -0,0 +1,618 @@
+//! SNTP (RFC 4330) client packets and a UTC wall clock.
+//!
+//! The crate doesn't send anything itself: [`encode_request`] encodes the packet to send to the
+//! server on [`PORT`], [`Response::decode`] checks the answer, and [`Sample::new`] computes the
+//! offset of the local clock and the round-trip delay from it. [`Clock`] turns a monotonic time,
+//! e.g. the time since boot, into UTC using the latest sample.
+//!
+//! All times are in microseconds.
+//!
+//! ```
+//! use sntp::{encode_request, Clock, DateTime, Response, Sample, Timestamp};
+//!
+//! // the transmit timestamp identifies our request, the server copies it into its response
+//! let request = Timestamp::from_bits(0x1234_5678_9abc_def0);
+//! let mut packet = [0; sntp::PACKET_LEN];
+//! let len = encode_request(request, &mut packet).unwrap();
+//! let sent = 1_000_000; // local time when the request was sent
+//!
+//! // what a server answers at 2024-01-01 00:00:00 UTC
+//! let now = Timestamp::from_unix_micros(1_704_067_200_000_000);
+//! packet[0] = 0x24; // version 4, server
+//! packet[1] = 2; // stratum
+//! packet[24..32].copy_from_slice(&request.to_bits().to_be_bytes());
+//! packet[32..40].copy_from_slice(&now.to_bits().to_be_bytes());
+//! packet[40..48].copy_from_slice(&now.to_bits().to_be_bytes());
+//! let received = 1_020_000; // local time when the response arrived
+//!
+//! let response = Response::decode(&packet[..len], request).unwrap();
+//! let sample = Sample::new(sent, &response, received);
+//! assert_eq!(sample.delay, 20_000);
+//!
+//! let mut clock = Clock::new();
+//! clock.update(received, &sample);
+//! let utc = clock.unix_micros(received).unwrap();
+//! assert_eq!(DateTime::from_unix_micros(utc).to_string(), "2024-01-01T00:00:00.010000Z");
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// The UDP port of NTP servers
+pub const PORT: u16 = 123;
+
+/// Length of an SNTP packet without extensions
+pub const PACKET_LEN: usize = 48;
+
+/// DHCP option carrying the addresses of NTP servers, four bytes per server
+pub const DHCP_OPTION_NTP_SERVERS: u8 = 42;
+
+/// Seconds from the NTP epoch, 1900-01-01, to the Unix epoch, 1970-01-01
+const UNIX_EPOCH: u64 = 2_208_988_800;
+
+const MICROS_PER_SECOND: u64 = 1_000_000;
+
+/// Version 4, mode 3 (client)
+const CLIENT_HEADER: u8 = (4 << 3) | 3;
+/// Mode of the responses of a server to a client
+const MODE_SERVER: u8 = 4;
+
+/// Errors returned by the encoder and decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer is too small for the packet
+    BufferTooSmall,
+    /// The response is shorter than an SNTP packet
+    Truncated,
+    /// The packet is not a response of a server to a client, e.g. a broadcast
+    UnexpectedMode(u8),
+    /// The server speaks a version of the protocol we don't know
+    UnsupportedVersion(u8),
+    /// The response doesn't belong to our request, it's a late answer to an earlier one or forged
+    UnexpectedResponse,
+    /// The server refuses to answer, the code tells why, e.g. `RATE` if we ask too often
+    KissOfDeath([u8; 4]),
+    /// The server's clock is not synchronized itself
+    Unsynchronized,
+    /// The server sent a zero timestamp
+    InvalidTimestamp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// An NTP timestamp, seconds since 1900 as 32.32 bits fixed point number
+///
+/// The seconds wrap around every 136 years, the next time in 2036. The conversions from and to
+/// Unix time cover 1970 up to 2106 by treating timestamps before 1970 as after the wrap-around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Converts microseconds since the Unix epoch
+    pub const fn from_unix_micros(micros: u64) -> Self {
+        let seconds = (micros / MICROS_PER_SECOND + UNIX_EPOCH) & 0xffff_ffff;
+        let fraction = ((micros % MICROS_PER_SECOND) << 32) / MICROS_PER_SECOND;
+        Self((seconds << 32) | fraction)
+    }
+
+    /// Converts to microseconds since the Unix epoch, rounded to the nearest microsecond
+    pub const fn to_unix_micros(self) -> u64 {
+        let seconds = self.0 >> 32;
+        let seconds = if seconds >= UNIX_EPOCH {
+            seconds - UNIX_EPOCH
+        } else {
+            seconds + (1 << 32) - UNIX_EPOCH
+        };
+        let fraction = ((self.0 & 0xffff_ffff) * MICROS_PER_SECOND + (1 << 31)) >> 32;
+        seconds * MICROS_PER_SECOND + fraction
+    }
+}
+
+/// Encodes a client request into `buf`, returns the length of the packet
+///
+/// Servers copy `transmit` into the originate timestamp of their response, which is how
+/// [`Response::decode`] recognizes the answer to this request. The calculation of the offset
+/// doesn't use it, so a random value works as well as the local time and doesn't reveal it.
+pub fn encode_request(transmit: Timestamp, buf: &mut [u8]) -> Result<usize, Error> {
+    let packet = buf.get_mut(..PACKET_LEN).ok_or(Error::BufferTooSmall)?;
+    packet.fill(0);
+    packet[0] = CLIENT_HEADER;
+    packet[40..].copy_from_slice(&transmit.to_bits().to_be_bytes());
+    Ok(PACKET_LEN)
+}
+
+/// Leap second announced by the server for the end of the current day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Leap {
+    None,
+    /// The last minute of the day has 61 seconds
+    Insert,
+    /// The last minute of the day has 59 seconds
+    Delete,
+}
+
+/// The parts of a server's response that a client needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response {
+    pub leap: Leap,
+    /// Distance to the reference clock, 1 for servers attached to one, e.g. a GPS receiver
+    pub stratum: u8,
+    /// For stratum 1 the kind of reference clock in ASCII, otherwise the IPv4 address of the
+    /// server's upstream server
+    pub reference_id: [u8; 4],
+    /// When the server received the request
+    pub receive: Timestamp,
+    /// When the server sent the response
+    pub transmit: Timestamp,
+}
+
+impl Response {
+    /// Decodes and checks the response to the request sent with the transmit timestamp `request`
+    pub fn decode(buf: &[u8], request: Timestamp) -> Result<Self, Error> {
+        let packet = buf.get(..PACKET_LEN).ok_or(Error::Truncated)?;
+        let timestamp = |offset: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&packet[offset..offset + 8]);
+            Timestamp::from_bits(u64::from_be_bytes(bytes))
+        };
+
+        let version = (packet[0] >> 3) & 0x07;
+        if !(1..=4).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let mode = packet[0] & 0x07;
+        if mode != MODE_SERVER {
+            return Err(Error::UnexpectedMode(mode));
+        }
+        if timestamp(24) != request {
+            return Err(Error::UnexpectedResponse);
+        }
+
+        let stratum = packet[1];
+        let mut reference_id = [0; 4];
+        reference_id.copy_from_slice(&packet[12..16]);
+        if stratum == 0 {
+            return Err(Error::KissOfDeath(reference_id));
+        }
+
+        let leap = match packet[0] >> 6 {
+            0 => Leap::None,
+            1 => Leap::Insert,
+            2 => Leap::Delete,
+            _ => return Err(Error::Unsynchronized),
+        };
+        if stratum > 15 {
+            return Err(Error::Unsynchronized);
+        }
+
+        let receive = timestamp(32);
+        let transmit = timestamp(40);
+        if receive.to_bits() == 0 || transmit.to_bits() == 0 {
+            return Err(Error::InvalidTimestamp);
+        }
+
+        Ok(Self {
+            leap,
+            stratum,
+            reference_id,
+            receive,
+            transmit,
+        })
+    }
+}
+
+/// The result of one request: how far off the local clock is and how long the request took
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample {
+    /// Add this to the local time to get the time since the Unix epoch
+    pub offset: i64,
+    /// Time the request and the response spent in the network, without the time on the server
+    pub delay: u64,
+}
+
+impl Sample {
+    /// Calculates the offset and delay from the local times when the request was `sent` and the
+    /// response `received`
+    ///
+    /// The local times can be from any monotonic clock, e.g. the time since boot. The offset
+    /// assumes that the request and the response took equally long, so its error is at most half
+    /// of the delay.
+    pub fn new(sent: u64, response: &Response, received: u64) -> Self {
+        let t1 = sent as i64;
+        let t2 = response.receive.to_unix_micros() as i64;
+        let t3 = response.transmit.to_unix_micros() as i64;
+        let t4 = received as i64;
+
+        Self {
+            offset: ((t2 - t1) + (t3 - t4)) / 2,
+            delay: ((t4 - t1) - (t3 - t2)).max(0) as u64,
+        }
+    }
+}
+
+/// A UTC wall clock on top of a monotonic clock
+///
+/// The clock doesn't run by itself: it adds the offset of the latest [`Sample`] to the monotonic
+/// time passed in, which keeps it monotonic between updates. Crystals drift, update it
+/// regularly.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Clock {
+    offset: Option<i64>,
+    synchronized_at: u64,
+}
+
+impl Clock {
+    pub const fn new() -> Self {
+        Self {
+            offset: None,
+            synchronized_at: 0,
+        }
+    }
+
+    /// Sets the clock from a sample taken at the monotonic time `now`
+    ///
+    /// Returns how far the clock jumped, or `None` if it wasn't set before.
+    pub fn update(&mut self, now: u64, sample: &Sample) -> Option<i64> {
+        let correction = self.offset.map(|offset| sample.offset - offset);
+        self.offset = Some(sample.offset);
+        self.synchronized_at = now;
+        correction
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    /// The monotonic time of the last update
+    pub fn synchronized_at(&self) -> Option<u64> {
+        self.offset.map(|_| self.synchronized_at)
+    }
+
+    /// Microseconds since the Unix epoch at the monotonic time `now`, `None` until the first
+    /// update
+    pub fn unix_micros(&self, now: u64) -> Option<u64> {
+        now.checked_add_signed(self.offset?)
+    }
+
+    /// The UTC date and time at the monotonic time `now`, `None` until the first update
+    pub fn now(&self, now: u64) -> Option<DateTime> {
+        self.unix_micros(now).map(DateTime::from_unix_micros)
+    }
+}
+
+/// A UTC date and time, displayed in ISO 8601 format, e.g. `2024-01-01T12:00:00.000000Z`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u32,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub micros: u32,
+}
+
+impl DateTime {
+    /// Converts microseconds since the Unix epoch, ignoring leap seconds like Unix time does
+    pub const fn from_unix_micros(micros: u64) -> Self {
+        let seconds = micros / MICROS_PER_SECOND;
+        let days = seconds / 86_400;
+        let seconds_of_day = seconds % 86_400;
+
+        // the days since 1970 to a date in the proleptic Gregorian calendar, this is
+        // `civil_from_days` from http://howardhinnant.github.io/date_algorithms.html
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // months starting with March, so that the leap day is the last day of the year
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3_600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            micros: (micros % MICROS_PER_SECOND) as u32,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.micros
+        )
+    }
+}
+
+/// The IPv4 addresses in the data of the DHCP option [`DHCP_OPTION_NTP_SERVERS`]
+pub fn dhcp_ntp_servers(data: &[u8]) -> impl Iterator<Item = [u8; 4]> + '_ {
+    // a trailing partial address is ignored
+    data.as_chunks::<4>().0.iter().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    /// 2024-01-01T00:00:00Z
+    const NEW_YEAR_2024: u64 = 1_704_067_200 * MICROS_PER_SECOND;
+    const REQUEST: Timestamp = Timestamp::from_bits(0x0123_4567_89ab_cdef);
+
+    fn response(receive: u64, transmit: u64) -> [u8; PACKET_LEN] {
+        let mut packet = [0; PACKET_LEN];
+        packet[0] = 0x24;
+        packet[1] = 1;
+        packet[12..16].copy_from_slice(b"GPS\0");
+        packet[24..32].copy_from_slice(&REQUEST.to_bits().to_be_bytes());
+        packet[32..40]
+            .copy_from_slice(&Timestamp::from_unix_micros(receive).to_bits().to_be_bytes());
+        packet[40..48].copy_from_slice(
+            &Timestamp::from_unix_micros(transmit)
+                .to_bits()
+                .to_be_bytes(),
+        );
+        packet
+    }
+
+    #[test]
+    fn encodes_request() {
+        let mut buffer = [0xff; 64];
+        assert_eq!(encode_request(REQUEST, &mut buffer), Ok(PACKET_LEN));
+        assert_eq!(buffer[0], 0x23);
+        assert!(buffer[1..40].iter().all(|&byte| byte == 0));
+        assert_eq!(buffer[40..48], REQUEST.to_bits().to_be_bytes());
+        assert_eq!(buffer[48], 0xff);
+    }
+
+    #[test]
+    fn request_needs_whole_packet() {
+        let mut buffer = [0; PACKET_LEN - 1];
+        assert_eq!(
+            encode_request(REQUEST, &mut buffer),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn converts_unix_time() {
+        let timestamp = Timestamp::from_unix_micros(NEW_YEAR_2024 + 500_000);
+        assert_eq!(timestamp.to_bits(), (3_913_056_000 << 32) | 0x8000_0000);
+        assert_eq!(timestamp.to_unix_micros(), NEW_YEAR_2024 + 500_000);
+        assert_eq!(Timestamp::from_unix_micros(0).to_bits(), UNIX_EPOCH << 32);
+    }
+
+    #[test]
+    fn conversion_keeps_microseconds() {
+        for micros in [0, 1, 2, 499_999, 500_001, 999_998, 999_999] {
+            let timestamp = Timestamp::from_unix_micros(NEW_YEAR_2024 + micros);
+            assert_eq!(timestamp.to_unix_micros(), NEW_YEAR_2024 + micros);
+        }
+    }
+
+    #[test]
+    fn handles_wrap_around_in_2036() {
+        // 2036-02-07T06:28:16Z is 2^32 seconds after 1900
+        let wrap_around = ((1 << 32) - UNIX_EPOCH) * MICROS_PER_SECOND;
+        assert_eq!(Timestamp::from_bits(0).to_unix_micros(), wrap_around);
+
+        let timestamp = Timestamp::from_unix_micros(wrap_around + MICROS_PER_SECOND);
+        assert_eq!(timestamp.to_bits(), 1 << 32);
+        assert_eq!(timestamp.to_unix_micros(), wrap_around + MICROS_PER_SECOND);
+    }
+
+    #[test]
+    fn decodes_response() {
+        let packet = response(NEW_YEAR_2024, NEW_YEAR_2024 + 100);
+        let response = Response::decode(&packet, REQUEST).unwrap();
+        assert_eq!(response.leap, Leap::None);
+        assert_eq!(response.stratum, 1);
+        assert_eq!(&response.reference_id, b"GPS\0");
+        assert_eq!(response.receive.to_unix_micros(), NEW_YEAR_2024);
+        assert_eq!(response.transmit.to_unix_micros(), NEW_YEAR_2024 + 100);
+    }
+
+    #[test]
+    fn rejects_truncated_response() {
+        let packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        assert_eq!(
+            Response::decode(&packet[..PACKET_LEN - 1], REQUEST),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn rejects_other_modes_and_versions() {
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[0] = 0x25; // broadcast
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::UnexpectedMode(5))
+        );
+        packet[0] = 0x2c; // version 5
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::UnsupportedVersion(5))
+        );
+        packet[0] = 0x1c; // version 3 is fine
+        assert!(Response::decode(&packet, REQUEST).is_ok());
+    }
+
+    #[test]
+    fn rejects_response_to_other_request() {
+        let packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        assert_eq!(
+            Response::decode(&packet, Timestamp::from_bits(1)),
+            Err(Error::UnexpectedResponse)
+        );
+    }
+
+    #[test]
+    fn reports_kiss_of_death() {
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[1] = 0;
+        packet[12..16].copy_from_slice(b"RATE");
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::KissOfDeath(*b"RATE"))
+        );
+    }
+
+    #[test]
+    fn rejects_unsynchronized_server() {
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[0] |= 0xc0;
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::Unsynchronized)
+        );
+
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[1] = 16;
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::Unsynchronized)
+        );
+
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[0] |= 0x40;
+        assert_eq!(
+            Response::decode(&packet, REQUEST).unwrap().leap,
+            Leap::Insert
+        );
+    }
+
+    #[test]
+    fn rejects_zero_timestamps() {
+        let mut packet = response(NEW_YEAR_2024, NEW_YEAR_2024);
+        packet[40..48].fill(0);
+        assert_eq!(
+            Response::decode(&packet, REQUEST),
+            Err(Error::InvalidTimestamp)
+        );
+    }
+
+    #[test]
+    fn calculates_offset_and_delay() {
+        // the request takes 30 ms to the server, which answers 5 ms later, the response takes
+        // 10 ms back
+        let sent = 2_000_000;
+        let packet = response(NEW_YEAR_2024 + 30_000, NEW_YEAR_2024 + 35_000);
+        let response = Response::decode(&packet, REQUEST).unwrap();
+        let sample = Sample::new(sent, &response, sent + 45_000);
+
+        assert_eq!(sample.delay, 40_000);
+        // the true offset is `NEW_YEAR_2024 - sent`, off by half the asymmetry of the delays
+        assert_eq!(sample.offset, (NEW_YEAR_2024 - sent) as i64 + 10_000);
+    }
+
+    #[test]
+    fn delay_is_never_negative() {
+        // the server's clock runs faster than ours
+        let packet = response(NEW_YEAR_2024, NEW_YEAR_2024 + 10_000);
+        let response = Response::decode(&packet, REQUEST).unwrap();
+        assert_eq!(Sample::new(0, &response, 5_000).delay, 0);
+    }
+
+    #[test]
+    fn clock_follows_the_latest_sample() {
+        let mut clock = Clock::new();
+        assert!(!clock.is_synchronized());
+        assert_eq!(clock.unix_micros(1_000), None);
+        assert_eq!(clock.synchronized_at(), None);
+
+        let sample = Sample {
+            offset: NEW_YEAR_2024 as i64,
+            delay: 0,
+        };
+        assert_eq!(clock.update(1_000, &sample), None);
+        assert_eq!(clock.synchronized_at(), Some(1_000));
+        assert_eq!(clock.unix_micros(5_000), Some(NEW_YEAR_2024 + 5_000));
+
+        let sample = Sample {
+            offset: NEW_YEAR_2024 as i64 - 250,
+            delay: 0,
+        };
+        assert_eq!(clock.update(9_000, &sample), Some(-250));
+        assert_eq!(clock.unix_micros(10_000), Some(NEW_YEAR_2024 + 9_750));
+    }
+
+    #[test]
+    fn converts_to_date_and_time() {
+        let date = |micros| DateTime::from_unix_micros(micros);
+        assert_eq!(
+            date(0),
+            DateTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+                micros: 0,
+            }
+        );
+        assert_eq!(
+            date(1_700_000_000_123_456).to_string(),
+            "2023-11-14T22:13:20.123456Z"
+        );
+        // leap days, including 2000 which is a leap year although divisible by 100
+        assert_eq!(
+            date(951_782_400 * MICROS_PER_SECOND).to_string(),
+            "2000-02-29T00:00:00.000000Z"
+        );
+        assert_eq!(
+            date(1_709_251_199 * MICROS_PER_SECOND).to_string(),
+            "2024-02-29T23:59:59.000000Z"
+        );
+        assert_eq!(
+            date(4_107_542_400 * MICROS_PER_SECOND).to_string(),
+            "2100-03-01T00:00:00.000000Z"
+        );
+    }
+
+    #[test]
+    fn reads_dhcp_option() {
+        let servers: [[u8; 4]; 2] = [[192, 168, 1, 1], [10, 0, 0, 1]];
+        let data = [192, 168, 1, 1, 10, 0, 0, 1, 99];
+        assert!(dhcp_ntp_servers(&data).eq(servers));
+        assert_eq!(dhcp_ntp_servers(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,503 @@
+//! Telemetry readings, the HTTP POST requests that upload them and a queue for retries.
+//!
+//! Like the other libraries it doesn't touch the network:
+//! - [`Reading::encode`] encodes a reading as JSON, [`encode_post`] puts it into an HTTP/1.1
+//!   POST request with a `Content-Length` header, asking the server to keep the connection open.
+//! - [`KeepAlive`] tells from the response whether the server does, so the application can send
+//!   the next request on the same connection.
+//! - [`Uploader`] says when to take the next reading, and queues the readings until they are
+//!   uploaded. While the server is unreachable, the uploads are retried with an exponential
+//!   backoff, and the oldest readings are dropped once the queue is full.
+//!
+//! All times are in milliseconds.
+//!
+//! ```
+//! use telemetry::{encode_post, Config, Reading, Uploader};
+//!
+//! let mut uploader: Uploader<8> = Uploader::new(Config::default());
+//! assert!(uploader.reading_due(0));
+//! let reading = Reading {
+//!     free_heap: 40_000,
+//!     rssi: Some(-52),
+//!     presses: 1,
+//!     ..Default::default()
+//! };
+//! uploader.push(0, reading);
+//!
+//! let reading = uploader.next(0).unwrap();
+//! let mut body = [0; 128];
+//! let len = reading.encode(&mut body).unwrap();
+//! assert_eq!(
+//!     &body[..len],
+//!     br#"{"seq":0,"uptime":0,"free_heap":40000,"rssi":-52,"presses":1}"#
+//! );
+//!
+//! let mut request = [0; 256];
+//! let len = encode_post("example.com", "/telemetry", &body[..len], &mut request).unwrap();
+//! assert!(request[..len].starts_with(b"POST /telemetry HTTP/1.1\r\nHost: example.com\r\n"));
+//!
+//! // the server is unreachable, the reading stays queued for a retry a second later
+//! assert_eq!(uploader.failed(10), 1_000);
+//! assert!(uploader.next(500).is_none());
+//! assert!(uploader.next(1_010).is_some());
+//! uploader.sent();
+//! assert!(uploader.is_empty());
+//! ```
+
+#![no_std]
+
+use core::fmt::{self, Write};
+
+use backoff::Backoff;
+use http_response::{Event, Version};
+use serde::Serialize;
+
+/// Errors returned by the encoders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The buffer is too small for the JSON or the request
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// What the device reports
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reading {
+    /// Numbers the readings, so the server can tell which ones were dropped, set by
+    /// [`Uploader::push`]
+    pub seq: u32,
+    /// Time since boot
+    pub uptime: u64,
+    /// Free heap in bytes
+    pub free_heap: u32,
+    /// Signal strength of the access point in dBm, if connected
+    pub rssi: Option<i8>,
+    /// Button presses since boot
+    pub presses: u32,
+}
+
+impl Reading {
+    const EMPTY: Self = Self {
+        seq: 0,
+        uptime: 0,
+        free_heap: 0,
+        rssi: None,
+        presses: 0,
+    };
+
+    /// Encodes the reading as a JSON object into `buf`, returns its length
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        serde_json_core::to_slice(self, buf).map_err(|_| Error::BufferTooSmall)
+    }
+}
+
+/// Encodes a POST request for `path` on `host` with `body` as JSON into `buf`, returns its
+/// length
+///
+/// The request asks the server to keep the connection open for the next one.
+pub fn encode_post(host: &str, path: &str, body: &[u8], buf: &mut [u8]) -> Result<usize, Error> {
+    let mut cursor = Cursor { buf, len: 0 };
+    write!(
+        cursor,
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: keep-alive\r\n\
+         \r\n",
+        body.len()
+    )
+    .map_err(|_| Error::BufferTooSmall)?;
+    cursor.write_bytes(body)?;
+    Ok(cursor.len)
+}
+
+/// Writes into a slice, failing once it's full
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Cursor<'_> {
+    fn write_bytes(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.len + data.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Tells from a response whether the server keeps the connection open
+///
+/// HTTP/1.1 servers do unless they send `Connection: close`, HTTP/1.0 servers only if they
+/// send `Connection: keep-alive`. Feed it the events of every response.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeepAlive {
+    version: Option<Version>,
+    close: bool,
+    keep_alive: bool,
+}
+
+impl KeepAlive {
+    pub const fn new() -> Self {
+        Self {
+            version: None,
+            close: false,
+            keep_alive: false,
+        }
+    }
+
+    pub fn update(&mut self, event: &Event<'_>) {
+        match event {
+            // a new response
+            Event::Status(status) => {
+                *self = Self::new();
+                self.version = Some(status.version);
+            }
+            Event::Header(header) if header.name.eq_ignore_ascii_case("connection") => {
+                // e.g. `keep-alive, Upgrade`
+                for option in header.value.split(|&b| b == b',') {
+                    let option = option.trim_ascii();
+                    self.close |= option.eq_ignore_ascii_case(b"close");
+                    self.keep_alive |= option.eq_ignore_ascii_case(b"keep-alive");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `true` if the next request can be sent on the same connection
+    pub fn keeps_alive(&self) -> bool {
+        match self.version {
+            Some(Version::Http11) => !self.close,
+            Some(Version::Http10) => self.keep_alive && !self.close,
+            None => false,
+        }
+    }
+}
+
+/// Timing of the uploads, all values are in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Time between two readings
+    pub interval: u64,
+    /// Delay before the first retry of a failed upload
+    pub initial_backoff: u64,
+    /// Upper limit for the delay between retries
+    pub max_backoff: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: 10_000,
+            initial_backoff: 1_000,
+            max_backoff: 60_000,
+        }
+    }
+}
+
+/// Schedules the readings and queues up to `N` of them until they are uploaded
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Uploader<const N: usize> {
+    config: Config,
+    /// A ring buffer, the oldest reading is at `head`
+    queue: [Reading; N],
+    head: usize,
+    len: usize,
+    seq: u32,
+    next_reading: u64,
+    retry_at: u64,
+    backoff: Backoff,
+    dropped: u32,
+}
+
+impl<const N: usize> Uploader<N> {
+    pub const fn new(config: Config) -> Self {
+        Self {
+            backoff: Backoff::new(config.initial_backoff, config.max_backoff),
+            config,
+            queue: [Reading::EMPTY; N],
+            head: 0,
+            len: 0,
+            seq: 0,
+            next_reading: 0,
+            retry_at: 0,
+            dropped: 0,
+        }
+    }
+
+    /// `true` when it's time to take the next reading
+    pub fn reading_due(&self, now: u64) -> bool {
+        now >= self.next_reading
+    }
+
+    /// Queues a reading taken at `now` and numbers it
+    ///
+    /// If the queue is full, the oldest reading is dropped and returned.
+    pub fn push(&mut self, now: u64, mut reading: Reading) -> Option<Reading> {
+        reading.seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        self.next_reading = now + self.config.interval;
+        if N == 0 {
+            self.dropped += 1;
+            return Some(reading);
+        }
+
+        let dropped = if self.len == N {
+            let oldest = self.queue[self.head];
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+            self.dropped += 1;
+            Some(oldest)
+        } else {
+            None
+        };
+        self.queue[(self.head + self.len) % N] = reading;
+        self.len += 1;
+        dropped
+    }
+
+    /// The oldest queued reading, if it's time to upload it
+    pub fn next(&self, now: u64) -> Option<&Reading> {
+        (self.len > 0 && now >= self.retry_at).then(|| &self.queue[self.head])
+    }
+
+    /// The reading returned by [`next`](Self::next) was uploaded
+    pub fn sent(&mut self) {
+        if self.len > 0 {
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+        }
+        self.backoff.reset();
+        self.retry_at = 0;
+    }
+
+    /// Uploading the reading returned by [`next`](Self::next) failed at `now`, returns the
+    /// delay until the next attempt
+    pub fn failed(&mut self, now: u64) -> u64 {
+        let delay = self.backoff.next_delay();
+        self.retry_at = now + delay;
+        delay
+    }
+
+    /// The queued readings, the oldest first
+    pub fn queued(&self) -> impl Iterator<Item = &Reading> {
+        (0..self.len).map(move |i| &self.queue[(self.head + i) % N])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of readings dropped because the queue was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use http_response::{Header, Status};
+    use std::vec::Vec;
+
+    fn reading(presses: u32) -> Reading {
+        Reading {
+            presses,
+            ..Default::default()
+        }
+    }
+
+    fn status(version: Version) -> Event<'static> {
+        Event::Status(Status {
+            version,
+            code: 200,
+            reason: "OK",
+        })
+    }
+
+    fn connection(value: &'static [u8]) -> Event<'static> {
+        Event::Header(Header {
+            name: "Connection",
+            value,
+        })
+    }
+
+    #[test]
+    fn reading_as_json() {
+        let reading = Reading {
+            seq: 7,
+            uptime: 123_456,
+            free_heap: 65_536,
+            rssi: None,
+            presses: 3,
+        };
+        let mut buf = [0; 128];
+        let len = reading.encode(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            br#"{"seq":7,"uptime":123456,"free_heap":65536,"rssi":null,"presses":3}"#
+        );
+
+        assert_eq!(reading.encode(&mut buf[..10]), Err(Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn post_request() {
+        let mut buf = [0; 256];
+        let len = encode_post("192.168.1.10:8000", "/", b"{\"seq\":1}", &mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"POST / HTTP/1.1\r\n\
+              Host: 192.168.1.10:8000\r\n\
+              Content-Type: application/json\r\n\
+              Content-Length: 9\r\n\
+              Connection: keep-alive\r\n\
+              \r\n\
+              {\"seq\":1}"
+        );
+    }
+
+    #[test]
+    fn request_too_long() {
+        let mut buf = [0; 256];
+        let len = encode_post("example.com", "/", b"{}", &mut buf).unwrap();
+        for short in [0, 10, len - 2, len - 1] {
+            let result = encode_post("example.com", "/", b"{}", &mut buf[..short]);
+            assert_eq!(result, Err(Error::BufferTooSmall));
+        }
+    }
+
+    #[test]
+    fn keep_alive() {
+        let mut keep_alive = KeepAlive::new();
+        assert!(!keep_alive.keeps_alive());
+
+        keep_alive.update(&status(Version::Http11));
+        assert!(keep_alive.keeps_alive());
+        keep_alive.update(&connection(b"Close"));
+        assert!(!keep_alive.keeps_alive());
+
+        // the next response starts over
+        keep_alive.update(&status(Version::Http10));
+        assert!(!keep_alive.keeps_alive());
+        keep_alive.update(&connection(b"Keep-Alive, Upgrade"));
+        assert!(keep_alive.keeps_alive());
+    }
+
+    #[test]
+    fn schedules_readings() {
+        let mut uploader: Uploader<4> = Uploader::new(Config::default());
+        assert!(uploader.reading_due(0));
+        uploader.push(100, reading(0));
+        assert!(!uploader.reading_due(10_099));
+        assert!(uploader.reading_due(10_100));
+    }
+
+    #[test]
+    fn uploads_in_order() {
+        let mut uploader: Uploader<4> = Uploader::new(Config::default());
+        for presses in 0..3 {
+            uploader.push(0, reading(presses));
+        }
+        let mut uploaded = Vec::new();
+        while let Some(reading) = uploader.next(0) {
+            uploaded.push((reading.seq, reading.presses));
+            uploader.sent();
+        }
+        assert_eq!(uploaded, [(0, 0), (1, 1), (2, 2)]);
+        assert!(uploader.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest() {
+        let mut uploader: Uploader<3> = Uploader::new(Config::default());
+        assert_eq!(uploader.push(0, reading(0)), None);
+        uploader.push(0, reading(1));
+        uploader.push(0, reading(2));
+        let dropped = uploader.push(0, reading(3)).unwrap();
+        assert_eq!(dropped.presses, 0);
+        assert_eq!(uploader.dropped(), 1);
+
+        let seqs: Vec<u32> = uploader.queued().map(|reading| reading.seq).collect();
+        assert_eq!(seqs, [1, 2, 3]);
+    }
+
+    #[test]
+    fn backs_off() {
+        let mut uploader: Uploader<4> = Uploader::new(Config {
+            interval: 10_000,
+            initial_backoff: 1_000,
+            max_backoff: 5_000,
+        });
+        uploader.push(0, reading(0));
+
+        let mut now = 0;
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            let delay = uploader.failed(now);
+            assert!(uploader.next(now + delay - 1).is_none());
+            now += delay;
+            assert!(uploader.next(now).is_some());
+            delays.push(delay);
+        }
+        assert_eq!(delays, [1_000, 2_000, 4_000, 5_000, 5_000]);
+
+        // a success starts over, the next reading goes out right away
+        uploader.push(now, reading(1));
+        uploader.sent();
+        assert_eq!(uploader.next(now).unwrap().seq, 1);
+        assert_eq!(uploader.failed(now), 1_000);
+    }
+
+    #[test]
+    fn keeps_readings_while_failing() {
+        let mut uploader: Uploader<2> = Uploader::new(Config::default());
+        uploader.push(0, reading(0));
+        uploader.failed(0);
+        uploader.push(10_000, reading(1));
+        uploader.failed(10_000);
+        uploader.push(20_000, reading(2));
+
+        // the oldest one that's still queued goes first
+        assert_eq!(uploader.next(20_000).unwrap().seq, 1);
+        assert_eq!(uploader.len(), 2);
+    }
+
+    #[test]
+    fn without_a_queue() {
+        let mut uploader: Uploader<0> = Uploader::new(Config::default());
+        assert_eq!(uploader.push(0, reading(0)).unwrap().seq, 0);
+        assert!(uploader.next(0).is_none());
+        uploader.sent();
+        assert_eq!(uploader.dropped(), 1);
+    }
+}
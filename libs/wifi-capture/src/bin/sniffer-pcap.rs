@@ -0,0 +1,121 @@
+//! Turns what the `sniffer` example sends over the serial port into a pcap file.
+//!
+//! ```text
+//! sniffer-pcap <serial port or capture> [<pcap file>]
+//! ```
+//!
+//! Reads from stdin if the input is `-`, and writes the pcap file to stdout if there is no
+//! output, which allows piping it into Wireshark:
+//!
+//! ```text
+//! sniffer-pcap /dev/ttyACM0 | wireshark -k -i -
+//! ```
+//!
+//! The log text of the device goes to stderr.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, Read, Write},
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use wifi_capture::{
+    file_header, max_encoded_len, packet_header, Clock, Decoder, Error, Packet, MAX_FRAME_LEN,
+};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (input, output) = match args.as_slice() {
+        [input] => (input.as_str(), "-"),
+        [input, output] => (input.as_str(), output.as_str()),
+        _ => {
+            eprintln!("usage: sniffer-pcap <serial port or capture> [<pcap file>]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match convert(input, output) {
+        Ok(stats) => {
+            eprintln!("{} frames, {} invalid packets", stats.frames, stats.invalid);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("sniffer-pcap: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    frames: usize,
+    invalid: usize,
+}
+
+/// Converts until the input ends
+fn convert(input: &str, output: &str) -> io::Result<Stats> {
+    let mut input: Box<dyn Read> = match input {
+        "-" => Box::new(io::stdin().lock()),
+        path => Box::new(File::open(path)?),
+    };
+    let mut output: Box<dyn Write> = match output {
+        "-" => Box::new(io::stdout().lock()),
+        path => Box::new(File::create(path)?),
+    };
+    output.write_all(&file_header())?;
+    output.flush()?;
+
+    let mut decoder: Decoder<{ max_encoded_len(MAX_FRAME_LEN) }> = Decoder::new();
+    let mut clock = Clock::new();
+    // the device counts from boot, the pcap file from 1970: the first frame is now
+    let mut offset = None;
+    let mut stats = Stats::default();
+    let mut buf = [0; 4096];
+    loop {
+        let len = match input.read(&mut buf) {
+            Ok(0) => return Ok(stats),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        for &byte in &buf[..len] {
+            match decoder.push(byte) {
+                Some(Packet::Record(record)) => {
+                    let micros = clock.micros(record.timestamp);
+                    let offset = *offset.get_or_insert_with(|| now().saturating_sub(micros));
+                    output.write_all(&packet_header(&record, offset + micros))?;
+                    output.write_all(record.data)?;
+                    stats.frames += 1;
+                }
+                Some(Packet::Invalid { data, error }) => {
+                    log(data, error);
+                    stats.invalid += 1;
+                }
+                None => {}
+            }
+        }
+        // Wireshark shows the frames as soon as they arrive
+        output.flush()?;
+    }
+}
+
+/// Prints log text of the device as it is, and what went wrong with anything else
+fn log(data: &[u8], error: Error) {
+    let text = data
+        .iter()
+        .all(|&byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace());
+    if text {
+        eprint!("{}", String::from_utf8_lossy(data));
+    } else {
+        eprintln!("skipped {} bytes: {}", data.len(), error);
+    }
+}
+
+/// Microseconds since 1970
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_micros() as u64)
+}
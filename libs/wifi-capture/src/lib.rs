@@ -0,0 +1,660 @@
+//! Framed 802.11 frames over a serial port, and their conversion to pcap files.
+//!
+//! The `sniffer` example captures frames with the radio in promiscuous mode and writes them to
+//! the same serial port `println!` uses. Each frame becomes a [`Record`] with the channel, the
+//! signal strength and a timestamp, protected by a CRC-32, and is sent COBS encoded between two
+//! zero bytes. Log text contains no zero bytes, so it can't be mistaken for a record: the
+//! [`Decoder`] on the host returns it as [`Packet::Invalid`], and so does a record that lost
+//! bytes on the way.
+//!
+//! [`packet_header`] turns a record into a pcap packet with a radiotap header, which Wireshark
+//! shows along with the frame. The `sniffer-pcap` binary of this crate does that for a serial
+//! port or a file.
+//!
+//! ```
+//! use wifi_capture::{Clock, Decoder, Packet, Record};
+//!
+//! let frame = [0x80, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+//! let record = Record {
+//!     channel: 6,
+//!     rssi: -52,
+//!     noise_floor: -95,
+//!     timestamp: 1_000_000,
+//!     len: 10,
+//!     data: &frame,
+//! };
+//! let mut wire = [0; 64];
+//! let len = record.encode(&mut wire).unwrap();
+//!
+//! // the device prints a line before the record
+//! let mut decoder: Decoder<256> = Decoder::new();
+//! let mut stream = b"Sniffing on channel 6\n".to_vec();
+//! stream.extend_from_slice(&wire[..len]);
+//!
+//! let mut clock = Clock::new();
+//! for byte in stream {
+//!     match decoder.push(byte) {
+//!         Some(Packet::Record(received)) => {
+//!             assert_eq!(received, record);
+//!             let header = wifi_capture::packet_header(&received, clock.micros(received.timestamp));
+//!             // a pcap file is `file_header` followed by the header and the data of every packet
+//!             assert_eq!(header.len(), wifi_capture::PACKET_HEADER_LEN);
+//!         }
+//!         Some(Packet::Invalid { data, .. }) => assert_eq!(data, b"Sniffing on channel 6\n"),
+//!         None => {}
+//!     }
+//! }
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// Version of the record format, the first byte of every record
+pub const VERSION: u8 = 1;
+
+/// Length of the record header: version, channel, RSSI, noise floor, timestamp and length
+pub const HEADER_LEN: usize = 10;
+
+/// Length of the CRC-32 at the end of every record
+pub const CRC_LEN: usize = 4;
+
+/// The largest 802.11 frame
+pub const MAX_FRAME_LEN: usize = 2346;
+
+/// The pcap link type of 802.11 frames with a radiotap header
+pub const LINKTYPE_IEEE802_11_RADIOTAP: u32 = 127;
+
+/// Length of the pcap file header
+pub const FILE_HEADER_LEN: usize = 24;
+
+/// Length of the radiotap header: version, length, present flags, channel and signal
+pub const RADIOTAP_LEN: usize = 14;
+
+/// Length of the pcap packet header and the radiotap header in front of every frame
+pub const PACKET_HEADER_LEN: usize = 16 + RADIOTAP_LEN;
+
+/// The largest packet written to the pcap file
+pub const SNAPLEN: u32 = (RADIOTAP_LEN + MAX_FRAME_LEN) as u32;
+
+/// Errors returned by [`Record::encode`], [`Record::decode`] and the [`Decoder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The record doesn't fit into the buffer, see [`max_encoded_len`]
+    BufferTooSmall,
+    /// The record carries more data than the frame is long
+    InvalidLength,
+    /// The data isn't COBS encoded, e.g. it's log text
+    Cobs,
+    /// The record is shorter than its header and CRC
+    Truncated,
+    /// The CRC doesn't match, bytes got lost or changed on the way
+    Checksum,
+    /// The record was encoded by a different version of this crate
+    UnsupportedVersion(u8),
+    /// More data than the decoder's buffer holds arrived without a zero byte
+    TooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// A captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record<'a> {
+    /// The channel the frame was received on
+    pub channel: u8,
+    /// Signal strength in dBm
+    pub rssi: i8,
+    /// Noise floor of the radio in dBm
+    pub noise_floor: i8,
+    /// When the frame was received, in microseconds, wraps after 71 minutes
+    pub timestamp: u32,
+    /// Length of the frame on air, `data` only holds the first bytes of longer ones
+    pub len: u16,
+    /// The frame, starting with the frame control field
+    pub data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Encodes the record for the serial port, returns the length
+    ///
+    /// The output starts and ends with a zero byte, so a record is found again after log text
+    /// or lost bytes.
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, Error> {
+        if self.data.len() > usize::from(self.len) {
+            return Err(Error::InvalidLength);
+        }
+        let (first, rest) = out.split_first_mut().ok_or(Error::BufferTooSmall)?;
+        *first = 0;
+
+        let [t0, t1, t2, t3] = self.timestamp.to_le_bytes();
+        let [l0, l1] = self.len.to_le_bytes();
+        let header = [
+            VERSION,
+            self.channel,
+            self.rssi as u8,
+            self.noise_floor as u8,
+            t0,
+            t1,
+            t2,
+            t3,
+            l0,
+            l1,
+        ];
+        let crc = crc32(&[&header, self.data]);
+
+        let mut cobs = CobsEncoder::new(rest)?;
+        for &byte in header.iter().chain(self.data).chain(&crc.to_le_bytes()) {
+            cobs.push(byte)?;
+        }
+        let len = cobs.finish();
+        *rest.get_mut(len).ok_or(Error::BufferTooSmall)? = 0;
+        Ok(len + 2)
+    }
+
+    /// Decodes a record that was already COBS decoded, and checks its CRC
+    pub fn decode(record: &'a [u8]) -> Result<Self, Error> {
+        if record.len() < HEADER_LEN + CRC_LEN {
+            return Err(Error::Truncated);
+        }
+        let (body, crc) = record.split_at(record.len() - CRC_LEN);
+        if crc32(&[body]).to_le_bytes() != crc {
+            return Err(Error::Checksum);
+        }
+        if body[0] != VERSION {
+            return Err(Error::UnsupportedVersion(body[0]));
+        }
+        let record = Self {
+            channel: body[1],
+            rssi: body[2] as i8,
+            noise_floor: body[3] as i8,
+            timestamp: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+            len: u16::from_le_bytes([body[8], body[9]]),
+            data: &body[HEADER_LEN..],
+        };
+        if record.data.len() > usize::from(record.len) {
+            return Err(Error::InvalidLength);
+        }
+        Ok(record)
+    }
+}
+
+/// The buffer [`Record::encode`] needs for `data_len` bytes of a frame
+pub const fn max_encoded_len(data_len: usize) -> usize {
+    let raw = HEADER_LEN + data_len + CRC_LEN;
+    // COBS adds a byte for every 254 bytes, and we add the two zero bytes around the record
+    raw + raw / 254 + 1 + 2
+}
+
+/// What the [`Decoder`] found between two zero bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Packet<'a> {
+    /// A record with a valid CRC
+    Record(Record<'a>),
+    /// Something else, like log text or a record that lost bytes
+    Invalid {
+        /// The bytes as received, only the first ones if they were [`Error::TooLong`]
+        data: &'a [u8],
+        /// Why it isn't a record
+        error: Error,
+    },
+}
+
+/// Finds the records in the bytes received from the serial port
+///
+/// `N` is the longest record it decodes, [`max_encoded_len`] of [`MAX_FRAME_LEN`] holds every
+/// frame.
+pub struct Decoder<const N: usize> {
+    raw: [u8; N],
+    len: usize,
+    overflow: bool,
+    decoded: [u8; N],
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Decoder<N> {
+    pub fn new() -> Self {
+        Self {
+            raw: [0; N],
+            len: 0,
+            overflow: false,
+            decoded: [0; N],
+        }
+    }
+
+    /// Takes the next byte, returns what ends with it
+    pub fn push(&mut self, byte: u8) -> Option<Packet<'_>> {
+        if byte != 0 {
+            match self.raw.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        let overflow = core::mem::take(&mut self.overflow);
+        // the zero bytes around a record mean there are empty packets in between
+        if len == 0 && !overflow {
+            return None;
+        }
+        let data = &self.raw[..len];
+        if overflow {
+            return Some(Packet::Invalid {
+                data,
+                error: Error::TooLong,
+            });
+        }
+        let decoded = &mut self.decoded;
+        match cobs_decode(data, decoded).and_then(|len| Record::decode(&decoded[..len])) {
+            Ok(record) => Some(Packet::Record(record)),
+            Err(error) => Some(Packet::Invalid { data, error }),
+        }
+    }
+}
+
+/// Extends the timestamps of the records, which wrap after 71 minutes, to 64 bits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Clock {
+    latest: Option<u64>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The microseconds since the device booted
+    pub fn micros(&mut self, timestamp: u32) -> u64 {
+        let Some(latest) = self.latest else {
+            self.latest = Some(timestamp.into());
+            return timestamp.into();
+        };
+        // the distance to the latest timestamp, frames reported a little late are before it
+        let step = timestamp.wrapping_sub(latest as u32) as i32;
+        let micros = latest.saturating_add_signed(step.into());
+        self.latest = Some(latest.max(micros));
+        micros
+    }
+}
+
+/// The header of a pcap file with 802.11 frames and radiotap headers
+pub fn file_header() -> [u8; FILE_HEADER_LEN] {
+    let mut header = [0; FILE_HEADER_LEN];
+    header[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    // version 2.4, no time zone offset and no accuracy
+    header[4..6].copy_from_slice(&2u16.to_le_bytes());
+    header[6..8].copy_from_slice(&4u16.to_le_bytes());
+    header[16..20].copy_from_slice(&SNAPLEN.to_le_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_IEEE802_11_RADIOTAP.to_le_bytes());
+    header
+}
+
+/// The pcap packet header and the radiotap header for a record received at `micros`
+///
+/// The packet is this header followed by the data of the record.
+pub fn packet_header(record: &Record<'_>, micros: u64) -> [u8; PACKET_HEADER_LEN] {
+    let mut header = [0; PACKET_HEADER_LEN];
+    let captured = (RADIOTAP_LEN + record.data.len()) as u32;
+    let original = (RADIOTAP_LEN + usize::from(record.len)) as u32;
+    header[0..4].copy_from_slice(&((micros / 1_000_000) as u32).to_le_bytes());
+    header[4..8].copy_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+    header[8..12].copy_from_slice(&captured.to_le_bytes());
+    header[12..16].copy_from_slice(&original.to_le_bytes());
+
+    // radiotap version 0, the length, and which fields follow: channel (bit 3), signal (bit 5)
+    // and noise (bit 6), in the order of their bits
+    let radiotap = &mut header[16..];
+    radiotap[2..4].copy_from_slice(&(RADIOTAP_LEN as u16).to_le_bytes());
+    radiotap[4..8].copy_from_slice(&(1u32 << 3 | 1 << 5 | 1 << 6).to_le_bytes());
+    radiotap[8..10].copy_from_slice(&frequency(record.channel).to_le_bytes());
+    // a 2.4 GHz channel
+    radiotap[10..12].copy_from_slice(&0x0080u16.to_le_bytes());
+    radiotap[12] = record.rssi as u8;
+    radiotap[13] = record.noise_floor as u8;
+    header
+}
+
+/// The center frequency of a 2.4 GHz channel in MHz
+pub fn frequency(channel: u8) -> u16 {
+    match channel {
+        14 => 2484,
+        channel => 2407 + 5 * u16::from(channel),
+    }
+}
+
+/// The CRC-32 of Ethernet and zip files over all `parts`
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Consistent Overhead Byte Stuffing: replaces the zero bytes, so zero can mark the end
+///
+/// Every block starts with a code byte, the distance to the next zero byte. A block of 254
+/// non-zero bytes has the code 255 and no zero byte after it.
+struct CobsEncoder<'a> {
+    out: &'a mut [u8],
+    /// Where the code of the current block goes
+    code_at: usize,
+    code: u8,
+    len: usize,
+}
+
+impl<'a> CobsEncoder<'a> {
+    fn new(out: &'a mut [u8]) -> Result<Self, Error> {
+        if out.is_empty() {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(Self {
+            out,
+            code_at: 0,
+            code: 1,
+            len: 1,
+        })
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), Error> {
+        if byte == 0 {
+            return self.end_block();
+        }
+        *self.out.get_mut(self.len).ok_or(Error::BufferTooSmall)? = byte;
+        self.len += 1;
+        self.code += 1;
+        if self.code == 0xff {
+            self.end_block()?;
+        }
+        Ok(())
+    }
+
+    fn end_block(&mut self) -> Result<(), Error> {
+        self.out[self.code_at] = self.code;
+        if self.len == self.out.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        self.code_at = self.len;
+        self.code = 1;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Writes the code of the last block, returns the length
+    fn finish(self) -> usize {
+        self.out[self.code_at] = self.code;
+        self.len
+    }
+}
+
+/// Reverses [`CobsEncoder`], returns the length of the decoded data
+fn cobs_decode(data: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    let (mut i, mut len) = (0, 0);
+    while i < data.len() {
+        let code = usize::from(data[i]);
+        if code == 0 {
+            return Err(Error::Cobs);
+        }
+        let block = data.get(i + 1..i + code).ok_or(Error::Cobs)?;
+        out.get_mut(len..len + block.len())
+            .ok_or(Error::TooLong)?
+            .copy_from_slice(block);
+        len += block.len();
+        i += code;
+        // the zero byte that ended the block, the last block ends with the data instead
+        if code != 0xff && i < data.len() {
+            *out.get_mut(len).ok_or(Error::TooLong)? = 0;
+            len += 1;
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn record(data: &[u8]) -> Record<'_> {
+        Record {
+            channel: 11,
+            rssi: -60,
+            noise_floor: -96,
+            timestamp: 0x1234_5678,
+            len: data.len() as u16,
+            data,
+        }
+    }
+
+    fn encode(record: &Record<'_>) -> Vec<u8> {
+        let mut out = std::vec![0; max_encoded_len(record.data.len())];
+        let len = record.encode(&mut out).unwrap();
+        out.truncate(len);
+        out
+    }
+
+    /// The data of a record, or the bytes that aren't one and why
+    type Found = Result<Vec<u8>, (Vec<u8>, Error)>;
+
+    /// Everything the decoder finds in `stream`
+    fn decode_all(stream: &[u8]) -> Vec<Found> {
+        let mut decoder: Decoder<{ max_encoded_len(MAX_FRAME_LEN) }> = Decoder::new();
+        stream
+            .iter()
+            .filter_map(|&byte| match decoder.push(byte)? {
+                Packet::Record(record) => Some(Ok(record.data.to_vec())),
+                Packet::Invalid { data, error } => Some(Err((data.to_vec(), error))),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        let cases: [&[u8]; 5] = [&[], &[0], &[0, 0], &[1, 0, 2], &[0x11, 0x22, 0, 0x33]];
+        for data in cases {
+            let mut encoded = [0; 16];
+            let mut cobs = CobsEncoder::new(&mut encoded).unwrap();
+            data.iter().for_each(|&byte| cobs.push(byte).unwrap());
+            let len = cobs.finish();
+            assert!(!encoded[..len].contains(&0), "{:?}", data);
+
+            let mut decoded = [0; 16];
+            let decoded_len = cobs_decode(&encoded[..len], &mut decoded).unwrap();
+            assert_eq!(&decoded[..decoded_len], data);
+        }
+    }
+
+    #[test]
+    fn cobs_long_blocks() {
+        // 254 non-zero bytes fill a block, the next one starts without a zero byte
+        for len in [253, 254, 255, 508, 600] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 255 + 1) as u8).collect();
+            let mut encoded = std::vec![0; len + len / 254 + 1];
+            let mut cobs = CobsEncoder::new(&mut encoded).unwrap();
+            data.iter().for_each(|&byte| cobs.push(byte).unwrap());
+            let encoded_len = cobs.finish();
+            assert!(!encoded[..encoded_len].contains(&0));
+
+            let mut decoded = std::vec![0; len + 1];
+            let decoded_len = cobs_decode(&encoded[..encoded_len], &mut decoded).unwrap();
+            assert_eq!(decoded[..decoded_len], data[..], "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let data = [0x80, 0, 0, 0, 0xff, 0xff, 0, 0, 0x12, 0];
+        let encoded = encode(&record(&data));
+        assert_eq!(encoded[0], 0);
+        assert_eq!(encoded[encoded.len() - 1], 0);
+        assert!(!encoded[1..encoded.len() - 1].contains(&0));
+
+        let mut decoded = [0; 64];
+        let len = cobs_decode(&encoded[1..encoded.len() - 1], &mut decoded).unwrap();
+        assert_eq!(Record::decode(&decoded[..len]), Ok(record(&data)));
+    }
+
+    #[test]
+    fn encode_checks_the_buffer_and_the_length() {
+        let data = [1; 20];
+        let mut out = [0; max_encoded_len(20)];
+        assert_eq!(record(&data).encode(&mut out), Ok(max_encoded_len(20)));
+        assert_eq!(
+            record(&data).encode(&mut out[..max_encoded_len(20) - 1]),
+            Err(Error::BufferTooSmall)
+        );
+
+        let longer_than_the_frame = Record {
+            len: 19,
+            ..record(&data)
+        };
+        assert_eq!(
+            longer_than_the_frame.encode(&mut out),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn decode_rejects_broken_records() {
+        let data = [0x40, 0, 0, 0];
+        let encoded = encode(&record(&data));
+        let mut decoded = [0; 64];
+        let len = cobs_decode(&encoded[1..encoded.len() - 1], &mut decoded).unwrap();
+
+        assert_eq!(Record::decode(&decoded[..7]), Err(Error::Truncated));
+        let mut flipped = decoded;
+        flipped[HEADER_LEN] ^= 0x01;
+        assert_eq!(Record::decode(&flipped[..len]), Err(Error::Checksum));
+
+        let mut version = decoded;
+        version[0] = 2;
+        let crc = crc32(&[&version[..len - CRC_LEN]]);
+        version[len - CRC_LEN..len].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            Record::decode(&version[..len]),
+            Err(Error::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn decoder_skips_text_and_lost_bytes() {
+        let first = encode(&record(&[0x80, 1, 2, 3]));
+        let second = encode(&record(&[0x40, 0, 0, 0]));
+
+        let mut stream = b"boot: ok\r\n".to_vec();
+        stream.extend_from_slice(&first[..first.len() - 3]);
+        stream.extend_from_slice(&second);
+
+        let packets = decode_all(&stream);
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0], Err((b"boot: ok\r\n".to_vec(), Error::Cobs)));
+        // the first record lost its last bytes, the second one arrives nevertheless
+        assert!(matches!(
+            packets[1],
+            Err((_, Error::Checksum | Error::Cobs))
+        ));
+        assert_eq!(packets[2], Ok(std::vec![0x40, 0, 0, 0]));
+    }
+
+    #[test]
+    fn decoder_overflow() {
+        let mut decoder: Decoder<8> = Decoder::new();
+        for &byte in b"0123456789" {
+            assert_eq!(decoder.push(byte), None);
+        }
+        assert_eq!(
+            decoder.push(0),
+            Some(Packet::Invalid {
+                data: b"01234567",
+                error: Error::TooLong
+            })
+        );
+
+        // the next record fits again
+        let mut decoder: Decoder<{ max_encoded_len(4) }> = Decoder::new();
+        let encoded = encode(&record(&[0xd4, 0, 0, 0]));
+        let mut found = 0;
+        for &byte in &encoded {
+            if let Some(Packet::Record(_)) = decoder.push(byte) {
+                found += 1;
+            }
+        }
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn clock_wraps() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.micros(u32::MAX - 10), u64::from(u32::MAX - 10));
+        assert_eq!(clock.micros(5), (1 << 32) + 5);
+        // a frame reported a little late isn't a wrap
+        assert_eq!(clock.micros(3), (1 << 32) + 3);
+        // neither is one from before the wrap
+        assert_eq!(clock.micros(u32::MAX - 2), u64::from(u32::MAX - 2));
+        assert_eq!(clock.micros(100), (1 << 32) + 100);
+    }
+
+    #[test]
+    fn pcap_headers() {
+        let header = file_header();
+        assert_eq!(header[..8], [0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0]);
+        assert_eq!(header[20..], [127, 0, 0, 0]);
+
+        let data = [0x80, 0, 0, 0];
+        let record = Record {
+            channel: 6,
+            rssi: -52,
+            noise_floor: -95,
+            len: 100,
+            ..record(&data)
+        };
+        let header = packet_header(&record, 3_000_250);
+        // seconds, microseconds, captured and original length
+        assert_eq!(
+            header[..16],
+            [3, 0, 0, 0, 0xfa, 0, 0, 0, 18, 0, 0, 0, 114, 0, 0, 0]
+        );
+        // radiotap: version, pad, length, present flags, 2437 MHz, 2.4 GHz, signal and noise
+        assert_eq!(
+            header[16..],
+            [0, 0, 14, 0, 0x68, 0, 0, 0, 0x85, 0x09, 0x80, 0, 0xcc, 0xa1]
+        );
+    }
+
+    #[test]
+    fn channel_frequencies() {
+        assert_eq!(frequency(1), 2412);
+        assert_eq!(frequency(13), 2472);
+        assert_eq!(frequency(14), 2484);
+    }
+}
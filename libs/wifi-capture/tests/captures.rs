@@ -0,0 +1,113 @@
+//! Runs `sniffer-pcap` on captures of the serial port and checks the pcap files it writes.
+//!
+//! `channel-6.bin` starts with the boot messages of the device, and has a log line between
+//! the frames. In `lost-bytes.bin`, a record lost bytes on the way, a frame is cut off at 512
+//! bytes and the clock of the device wraps around.
+
+use std::{fs, path::PathBuf, process::Command};
+
+/// A packet of the pcap file
+#[derive(Debug)]
+struct Packet {
+    micros: u64,
+    original_len: usize,
+    frequency: u16,
+    signal: i8,
+    frame: Vec<u8>,
+}
+
+/// Converts the capture, returns the packets and what the tool printed to stderr
+fn convert(capture: &str) -> (Vec<Packet>, String) {
+    let input = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/captures")
+        .join(capture);
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(capture.replace(".bin", ".pcap"));
+    let result = Command::new(env!("CARGO_BIN_EXE_sniffer-pcap"))
+        .arg(&input)
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    (read_pcap(&fs::read(output).unwrap()), stderr)
+}
+
+fn read_pcap(pcap: &[u8]) -> Vec<Packet> {
+    let u16_at = |at: usize| u16::from_le_bytes([pcap[at], pcap[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes(pcap[at..at + 4].try_into().unwrap());
+    assert_eq!(u32_at(0), 0xa1b2_c3d4);
+    assert_eq!(u32_at(20), wifi_capture::LINKTYPE_IEEE802_11_RADIOTAP);
+
+    let mut packets = Vec::new();
+    let mut at = wifi_capture::FILE_HEADER_LEN;
+    while at < pcap.len() {
+        let micros = u64::from(u32_at(at)) * 1_000_000 + u64::from(u32_at(at + 4));
+        let captured = u32_at(at + 8) as usize;
+        let original_len = u32_at(at + 12) as usize;
+        let radiotap = at + 16;
+        let radiotap_len = usize::from(u16_at(radiotap + 2));
+        packets.push(Packet {
+            micros,
+            original_len: original_len - radiotap_len,
+            frequency: u16_at(radiotap + 8),
+            signal: pcap[radiotap + 12] as i8,
+            frame: pcap[radiotap + radiotap_len..radiotap + captured].to_vec(),
+        });
+        at = radiotap + captured;
+    }
+    packets
+}
+
+/// The SSID of a beacon
+fn ssid(frame: &[u8]) -> &[u8] {
+    // the MAC header and the fixed fields, then the SSID element
+    assert_eq!(frame[0], 0x80);
+    assert_eq!(frame[36], 0);
+    &frame[38..38 + usize::from(frame[37])]
+}
+
+#[test]
+fn converts_a_capture() {
+    let (packets, stderr) = convert("channel-6.bin");
+
+    // beacon, probe request, probe response, beacon, QoS data, beacon
+    let types: Vec<u8> = packets.iter().map(|packet| packet.frame[0]).collect();
+    assert_eq!(types, [0x80, 0x40, 0x50, 0x80, 0x88, 0x80]);
+    assert!(packets.iter().all(|packet| packet.frequency == 2437));
+    assert!(packets
+        .iter()
+        .all(|packet| packet.frame.len() == packet.original_len));
+    assert_eq!(ssid(&packets[0].frame), b"esp-rs");
+    assert_eq!(packets[0].signal, -48);
+    assert_eq!(packets[1].signal, -71);
+
+    // the beacons are 102.4 ms apart, like on the device
+    assert_eq!(packets[3].micros - packets[0].micros, 102_400);
+    assert_eq!(packets[5].micros - packets[3].micros, 102_400);
+
+    // the log text of the device isn't lost
+    assert!(stderr.contains("Sniffing on channel 6"));
+    assert!(stderr.contains("Dropped 3 frames"));
+    assert!(stderr.ends_with("6 frames, 2 invalid packets\n"));
+}
+
+#[test]
+fn survives_lost_bytes_and_a_wrapping_clock() {
+    let (packets, stderr) = convert("lost-bytes.bin");
+
+    // the QoS data frame that lost bytes is skipped
+    let types: Vec<u8> = packets.iter().map(|packet| packet.frame[0]).collect();
+    assert_eq!(types, [0x80, 0x88, 0x80, 0x80]);
+    assert!(stderr.contains("skipped 299 bytes: Cobs"));
+    assert!(stderr.ends_with("4 frames, 2 invalid packets\n"));
+    assert!(packets.iter().all(|packet| packet.frequency == 2462));
+    assert_eq!(ssid(&packets[0].frame), b"warehouse");
+
+    // the frame was longer than what the device sent
+    assert_eq!(packets[1].frame.len(), 512);
+    assert_eq!(packets[1].original_len, 1400 + 34);
+
+    // the device clock wrapped between the second and the third beacon
+    assert_eq!(packets[2].micros - packets[0].micros, 102_400);
+    assert_eq!(packets[3].micros - packets[2].micros, 102_400);
+}
@@ -0,0 +1,285 @@
+//! Wi-Fi credentials as a record for flash.
+//!
+//! Devices that are set up in the field get their network credentials at runtime and keep them in
+//! flash. [`Credentials::encode`] turns them into a fixed-size record with a checksum, and
+//! [`Credentials::decode`] reads it back. An erased record means that the device was never set
+//! up, a record with a wrong checksum was only partially written, e.g. because the power failed.
+//!
+//! ```
+//! use wifi_credentials::Credentials;
+//!
+//! let credentials = Credentials::new("home", "correct horse").unwrap();
+//! let record = credentials.encode();
+//!
+//! assert_eq!(Credentials::decode(&record), Ok(Some(credentials)));
+//! // erased flash reads as 0xff
+//! assert_eq!(Credentials::decode(&[0xff; wifi_credentials::RECORD_LEN]), Ok(None));
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// Longest SSID in bytes
+pub const MAX_SSID_LEN: usize = 32;
+/// Longest password, a 64 digit hex key, passphrases are at most 63 characters long
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+/// Length of an encoded record
+pub const RECORD_LEN: usize = HEADER_LEN + MAX_SSID_LEN + MAX_PASSWORD_LEN + 4;
+
+const MAGIC: [u8; 4] = *b"WIFI";
+const VERSION: u8 = 1;
+/// Magic, version, SSID length, password length and a reserved byte
+const HEADER_LEN: usize = 8;
+
+/// Errors returned when creating or decoding credentials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The SSID is empty or longer than 32 bytes
+    InvalidSsid,
+    /// The password is neither empty, a passphrase of 8 to 63 printable ASCII characters nor a
+    /// key of 64 hex digits
+    InvalidPassword,
+    /// The record is shorter than [`RECORD_LEN`]
+    Truncated,
+    /// The record was only partially written or isn't a credentials record at all
+    Corrupted,
+    /// The record was written by a newer version of this crate
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// The SSID and password of a network
+///
+/// The password is never printed, neither by `Debug` nor by `defmt`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    ssid: [u8; MAX_SSID_LEN],
+    ssid_len: u8,
+    password: [u8; MAX_PASSWORD_LEN],
+    password_len: u8,
+}
+
+impl Credentials {
+    /// Checks and copies the credentials, an empty password is an open network
+    pub fn new(ssid: &str, password: &str) -> Result<Self, Error> {
+        if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+            return Err(Error::InvalidSsid);
+        }
+        let passphrase = (8..MAX_PASSWORD_LEN).contains(&password.len())
+            && password.bytes().all(|b| (b' '..=b'~').contains(&b));
+        let key =
+            password.len() == MAX_PASSWORD_LEN && password.bytes().all(|b| b.is_ascii_hexdigit());
+        if !(password.is_empty() || passphrase || key) {
+            return Err(Error::InvalidPassword);
+        }
+
+        let mut credentials = Self {
+            ssid: [0; MAX_SSID_LEN],
+            ssid_len: ssid.len() as u8,
+            password: [0; MAX_PASSWORD_LEN],
+            password_len: password.len() as u8,
+        };
+        credentials.ssid[..ssid.len()].copy_from_slice(ssid.as_bytes());
+        credentials.password[..password.len()].copy_from_slice(password.as_bytes());
+        Ok(credentials)
+    }
+
+    pub fn ssid(&self) -> &str {
+        // checked when the credentials were created or decoded
+        core::str::from_utf8(&self.ssid[..self.ssid_len as usize]).unwrap_or_default()
+    }
+
+    pub fn password(&self) -> &str {
+        core::str::from_utf8(&self.password[..self.password_len as usize]).unwrap_or_default()
+    }
+
+    /// Encodes the credentials into a record
+    pub fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut record = [0; RECORD_LEN];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4] = VERSION;
+        record[5] = self.ssid_len;
+        record[6] = self.password_len;
+        let (ssid, rest) = record[HEADER_LEN..].split_at_mut(MAX_SSID_LEN);
+        ssid.copy_from_slice(&self.ssid);
+        rest[..MAX_PASSWORD_LEN].copy_from_slice(&self.password);
+
+        let crc = crc32(&record[..RECORD_LEN - 4]);
+        record[RECORD_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decodes a record, returns `None` if it's erased
+    pub fn decode(record: &[u8]) -> Result<Option<Self>, Error> {
+        let record = record.get(..RECORD_LEN).ok_or(Error::Truncated)?;
+        if record.iter().all(|&b| b == 0xff) {
+            return Ok(None);
+        }
+
+        let (data, crc) = record.split_at(RECORD_LEN - 4);
+        if data[..4] != MAGIC || crc32(data).to_le_bytes() != crc {
+            return Err(Error::Corrupted);
+        }
+        if data[4] != VERSION {
+            return Err(Error::UnsupportedVersion(data[4]));
+        }
+
+        let ssid_len = data[5] as usize;
+        let password_len = data[6] as usize;
+        if ssid_len > MAX_SSID_LEN || password_len > MAX_PASSWORD_LEN {
+            return Err(Error::Corrupted);
+        }
+        let ssid = &data[HEADER_LEN..HEADER_LEN + ssid_len];
+        let password = &data[HEADER_LEN + MAX_SSID_LEN..][..password_len];
+        let ssid = core::str::from_utf8(ssid).map_err(|_| Error::Corrupted)?;
+        let password = core::str::from_utf8(password).map_err(|_| Error::Corrupted)?;
+        Self::new(ssid, password).map(Some)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("ssid", &self.ssid())
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Credentials {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Credentials {{ ssid: {}, password: *** }}", self.ssid())
+    }
+}
+
+/// CRC-32 as used by Ethernet and zlib
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let hex_key = "0123456789abcdef".repeat(4);
+        for (ssid, password) in [
+            ("home", "correct horse"),
+            ("Café ☕", ""),
+            ("a", "12345678"),
+            ("x".repeat(32).as_str(), "~".repeat(63).as_str()),
+            ("office", hex_key.as_str()),
+        ] {
+            let credentials = Credentials::new(ssid, password).unwrap();
+            assert_eq!(credentials.ssid(), ssid);
+            assert_eq!(credentials.password(), password);
+            assert_eq!(
+                Credentials::decode(&credentials.encode()),
+                Ok(Some(credentials))
+            );
+        }
+    }
+
+    #[test]
+    fn validates_ssid() {
+        assert_eq!(Credentials::new("", ""), Err(Error::InvalidSsid));
+        assert_eq!(
+            Credentials::new(&"x".repeat(33), ""),
+            Err(Error::InvalidSsid)
+        );
+    }
+
+    #[test]
+    fn validates_password() {
+        for password in [
+            "1234567",
+            "passwört",
+            "tab\tstop",
+            &"a".repeat(65),
+            &"g".repeat(64),
+        ] {
+            assert_eq!(
+                Credentials::new("home", password),
+                Err(Error::InvalidPassword),
+                "{password}"
+            );
+        }
+    }
+
+    #[test]
+    fn erased_record_is_empty() {
+        assert_eq!(Credentials::decode(&[0xff; RECORD_LEN]), Ok(None));
+        // anything after the record doesn't matter
+        assert_eq!(Credentials::decode(&[0xff; 4096]), Ok(None));
+    }
+
+    #[test]
+    fn detects_partial_writes() {
+        let record = Credentials::new("home", "correct horse").unwrap().encode();
+        // flash can only clear bits until the sector is erased, a write that was interrupted
+        // leaves the rest of the record erased (nothing written at all is an erased record)
+        for written in 1..RECORD_LEN {
+            let mut partial = [0xff; RECORD_LEN];
+            partial[..written].copy_from_slice(&record[..written]);
+            assert!(Credentials::decode(&partial).is_err(), "{written}");
+        }
+
+        let mut flipped = record;
+        flipped[HEADER_LEN] ^= 0x01;
+        assert_eq!(Credentials::decode(&flipped), Err(Error::Corrupted));
+    }
+
+    #[test]
+    fn rejects_other_versions_and_data() {
+        let mut record = Credentials::new("home", "").unwrap().encode();
+        record[4] = 2;
+        let crc = crc32(&record[..RECORD_LEN - 4]);
+        record[RECORD_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            Credentials::decode(&record),
+            Err(Error::UnsupportedVersion(2))
+        );
+
+        assert_eq!(Credentials::decode(&[0; RECORD_LEN]), Err(Error::Corrupted));
+        assert_eq!(
+            Credentials::decode(&[0xff; RECORD_LEN - 1]),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn debug_hides_password() {
+        let credentials = Credentials::new("home", "correct horse").unwrap();
+        let debug = format!("{credentials:?}");
+        assert_eq!(debug, r#"Credentials { ssid: "home", password: "***" }"#);
+    }
+}
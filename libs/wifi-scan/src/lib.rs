@@ -0,0 +1,568 @@
+//! Tracks the access points found by Wi-Fi scans.
+//!
+//! A single scan is a snapshot: access points that are far away or on a busy channel are missed
+//! in some scans and found in others. [`Tracker`] merges the results of consecutive scans, keeps
+//! the access points sorted by signal strength, and reports which ones appeared and which ones
+//! are gone. An access point only counts as gone after it was missing from
+//! [`Config::missed_scans`] scans in a row, so one missed beacon doesn't make it flicker.
+//!
+//! Like the `wifi-supervisor` crate, the tracker doesn't talk to the radio itself: the application
+//! converts the scan results into [`AccessPoint`]s and hands them over, which allows testing it on
+//! the host.
+//!
+//! ```
+//! use wifi_scan::{AccessPoint, Change, Config, Security, Tracker};
+//!
+//! let home = AccessPoint::new("home", [0x02, 0, 0, 0, 0, 1], 6, -48, Security::Wpa2);
+//! let cafe = AccessPoint::new("cafe", [0x02, 0, 0, 0, 0, 2], 11, -71, Security::Open);
+//!
+//! let mut tracker: Tracker<8> = Tracker::new(Config { missed_scans: 1 });
+//! tracker.update([cafe, home]);
+//! let ssids: Vec<_> = tracker.access_points().map(|ap| ap.ssid()).collect();
+//! assert_eq!(ssids, ["home", "cafe"]);
+//! assert_eq!(tracker.changes().count(), 2);
+//!
+//! // the cafe closed
+//! tracker.update([home]);
+//! let changes: Vec<_> = tracker.changes().collect();
+//! assert_eq!(changes, [Change::Disappeared(cafe)]);
+//!
+//! println!("{}", tracker.table());
+//! ```
+
+#![no_std]
+
+use core::cmp::Ordering;
+use core::fmt;
+
+/// The longest SSID 802.11 allows, in bytes
+pub const MAX_SSID_LEN: usize = 32;
+
+/// The MAC address of an access point
+pub type Bssid = [u8; 6];
+
+/// How an access point protects its network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Security {
+    Open,
+    Wep,
+    Wpa,
+    Wpa2,
+    /// WPA and WPA2 at the same time, for old clients
+    WpaWpa2,
+    /// WPA2 with a RADIUS server instead of a password
+    Wpa2Enterprise,
+    Wpa3,
+    /// WPA2 and WPA3 at the same time, for old clients
+    Wpa2Wpa3,
+    /// The Chinese WLAN standard
+    Wapi,
+    /// The driver couldn't tell
+    Unknown,
+}
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Security::Open => "open",
+            Security::Wep => "WEP",
+            Security::Wpa => "WPA",
+            Security::Wpa2 => "WPA2",
+            Security::WpaWpa2 => "WPA/WPA2",
+            Security::Wpa2Enterprise => "WPA2-EAP",
+            Security::Wpa3 => "WPA3",
+            Security::Wpa2Wpa3 => "WPA2/WPA3",
+            Security::Wapi => "WAPI",
+            Security::Unknown => "?",
+        })
+    }
+}
+
+/// Formats a [`Bssid`] as `aa:bb:cc:dd:ee:ff`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mac(pub Bssid);
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// An access point found by a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessPoint {
+    ssid: [u8; MAX_SSID_LEN],
+    ssid_len: u8,
+    /// Identifies the access point, several of them can share an SSID
+    pub bssid: Bssid,
+    /// The primary channel
+    pub channel: u8,
+    /// Signal strength in dBm, higher is better
+    pub rssi: i8,
+    pub security: Security,
+}
+
+impl AccessPoint {
+    /// Creates an access point
+    ///
+    /// Hidden networks send an empty SSID, or one made of NUL bytes, both end up as `""`. An SSID
+    /// longer than [`MAX_SSID_LEN`] bytes is cut at the last character that fits.
+    pub fn new(ssid: &str, bssid: Bssid, channel: u8, rssi: i8, security: Security) -> Self {
+        let ssid = ssid.trim_end_matches('\0');
+        let mut len = ssid.len().min(MAX_SSID_LEN);
+        while !ssid.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut buf = [0; MAX_SSID_LEN];
+        buf[..len].copy_from_slice(&ssid.as_bytes()[..len]);
+        Self {
+            ssid: buf,
+            ssid_len: len as u8,
+            bssid,
+            channel,
+            rssi,
+            security,
+        }
+    }
+
+    /// The name of the network, empty if it's hidden
+    pub fn ssid(&self) -> &str {
+        // `new` only copies whole characters
+        core::str::from_utf8(&self.ssid[..self.ssid_len as usize]).unwrap_or_default()
+    }
+
+    /// Whether the access point doesn't tell the name of its network
+    pub fn is_hidden(&self) -> bool {
+        self.ssid_len == 0
+    }
+
+    /// Strongest signal first, then by name and BSSID, so the order doesn't change between scans
+    /// with the same signal
+    fn by_signal(&self, other: &Self) -> Ordering {
+        other
+            .rssi
+            .cmp(&self.rssi)
+            .then_with(|| self.ssid().cmp(other.ssid()))
+            .then_with(|| self.bssid.cmp(&other.bssid))
+    }
+}
+
+/// Configuration of a [`Tracker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// An access point is gone after it was missing from this many scans in a row, at least 1
+    pub missed_scans: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { missed_scans: 3 }
+    }
+}
+
+/// What changed with the last scan, see [`Tracker::changes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Change {
+    /// The access point was found for the first time, or again after it was gone
+    Appeared(AccessPoint),
+    /// The access point wasn't found anymore, with what the last scan that found it reported
+    Disappeared(AccessPoint),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum State {
+    /// Found by the last scan for the first time
+    Appeared,
+    Present,
+    /// Missed by the last scan once too often, removed by the next one
+    Gone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Entry {
+    ap: AccessPoint,
+    state: State,
+    /// How many scans in a row missed it
+    missed: u8,
+}
+
+/// The access points found by recent scans, up to `N` of them
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tracker<const N: usize> {
+    config: Config,
+    /// Sorted by signal, the free slots at the end
+    entries: [Option<Entry>; N],
+    scans: u32,
+    dropped: usize,
+}
+
+impl<const N: usize> Tracker<N> {
+    /// Creates a tracker that didn't see any scan yet
+    pub const fn new(config: Config) -> Self {
+        Self {
+            config,
+            entries: [None; N],
+            scans: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Merges the results of a scan
+    ///
+    /// An access point found several times, e.g. on two channels, keeps the strongest signal. If
+    /// there is no room for a new access point, it replaces the weakest one if its signal is
+    /// stronger, otherwise it's dropped.
+    pub fn update(&mut self, scan: impl IntoIterator<Item = AccessPoint>) {
+        self.scans = self.scans.wrapping_add(1);
+        self.dropped = 0;
+
+        for slot in &mut self.entries {
+            match slot {
+                Some(Entry {
+                    state: State::Gone, ..
+                }) => *slot = None,
+                Some(entry) => {
+                    entry.state = State::Present;
+                    entry.missed = entry.missed.saturating_add(1);
+                }
+                None => {}
+            }
+        }
+
+        for ap in scan {
+            self.insert(ap);
+        }
+
+        let missed_scans = self.config.missed_scans.max(1);
+        for entry in self.entries.iter_mut().flatten() {
+            if entry.missed >= missed_scans {
+                entry.state = State::Gone;
+            }
+        }
+        self.sort();
+    }
+
+    fn insert(&mut self, ap: AccessPoint) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.ap.bssid == ap.bssid)
+        {
+            // seen twice by this scan
+            if entry.missed == 0 && entry.ap.rssi > ap.rssi {
+                return;
+            }
+            entry.ap = ap;
+            entry.missed = 0;
+            return;
+        }
+
+        let entry = Entry {
+            ap,
+            state: State::Appeared,
+            missed: 0,
+        };
+        if let Some(slot) = self.entries.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(entry);
+            return;
+        }
+        let weakest = self
+            .entries
+            .iter_mut()
+            .flatten()
+            .min_by_key(|entry| entry.ap.rssi);
+        if let Some(weakest) = weakest.filter(|weakest| weakest.ap.rssi < ap.rssi) {
+            *weakest = entry;
+        }
+        self.dropped += 1;
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_unstable_by(|a, b| match (a, b) {
+            (Some(a), Some(b)) => a.ap.by_signal(&b.ap),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+
+    /// The access points that aren't gone, strongest signal first
+    ///
+    /// One that was missed by the last scan keeps what the last scan that found it reported.
+    pub fn access_points(&self) -> impl Iterator<Item = &AccessPoint> + '_ {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|entry| entry.state != State::Gone)
+            .map(|entry| &entry.ap)
+    }
+
+    /// The access points that appeared or disappeared with the last scan, strongest signal first
+    pub fn changes(&self) -> impl Iterator<Item = Change> + '_ {
+        self.entries
+            .iter()
+            .flatten()
+            .filter_map(|entry| match entry.state {
+                State::Appeared => Some(Change::Appeared(entry.ap)),
+                State::Gone => Some(Change::Disappeared(entry.ap)),
+                State::Present => None,
+            })
+    }
+
+    /// How many access points aren't gone
+    pub fn len(&self) -> usize {
+        self.access_points().count()
+    }
+
+    /// Whether there are no access points
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many scans were merged so far
+    pub fn scans(&self) -> u32 {
+        self.scans
+    }
+
+    /// How many access points the last scan found that didn't fit into the tracker, or pushed out
+    /// a weaker one
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Formats the access points as a table
+    pub fn table(&self) -> Table<'_, N> {
+        Table(self)
+    }
+}
+
+/// The access points of a [`Tracker`] as a table, one line per access point
+///
+/// ```text
+/// SSID                             BSSID              CH  RSSI  SECURITY
+/// home                             02:00:00:00:00:01   6   -48  WPA2
+/// <hidden>                         02:00:00:00:00:03   1   -60  WPA2/WPA3
+/// cafe                             02:00:00:00:00:02  11   -71  open
+/// ```
+///
+/// Access points that the last scan missed are marked with a `?` after their signal.
+pub struct Table<'a, const N: usize>(&'a Tracker<N>);
+
+impl<const N: usize> fmt::Display for Table<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:<17}  {:>2}  {:>4}  SECURITY",
+            "SSID", "BSSID", "CH", "RSSI"
+        )?;
+        for entry in self.0.entries.iter().flatten() {
+            if entry.state == State::Gone {
+                continue;
+            }
+            let ap = &entry.ap;
+            let ssid = if ap.is_hidden() {
+                "<hidden>"
+            } else {
+                ap.ssid()
+            };
+            let missed = if entry.missed > 0 { "?" } else { " " };
+            write!(
+                f,
+                "\n{:<32} {}  {:>2}  {:>4}{} {}",
+                ssid,
+                Mac(ap.bssid),
+                ap.channel,
+                ap.rssi,
+                missed,
+                ap.security
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn ap(n: u8, ssid: &str, rssi: i8) -> AccessPoint {
+        AccessPoint::new(ssid, [0x02, 0, 0, 0, 0, n], n, rssi, Security::Wpa2)
+    }
+
+    fn ssids<const N: usize>(tracker: &Tracker<N>) -> Vec<&str> {
+        tracker.access_points().map(|ap| ap.ssid()).collect()
+    }
+
+    #[test]
+    fn decodes_ssids() {
+        let hidden = AccessPoint::new("\0\0\0\0", [0; 6], 1, -50, Security::Open);
+        assert_eq!(hidden.ssid(), "");
+        assert!(hidden.is_hidden());
+
+        let long = "a".repeat(31) + "ü";
+        let ap = AccessPoint::new(&long, [0; 6], 1, -50, Security::Open);
+        assert_eq!(ap.ssid(), "a".repeat(31));
+        assert!(!ap.is_hidden());
+
+        let full = "b".repeat(40);
+        let ap = AccessPoint::new(&full, [0; 6], 1, -50, Security::Open);
+        assert_eq!(ap.ssid().len(), MAX_SSID_LEN);
+    }
+
+    #[test]
+    fn formats_bssids() {
+        let mac = Mac([0x02, 0xab, 0, 0x10, 0xff, 1]);
+        assert_eq!(mac.to_string(), "02:ab:00:10:ff:01");
+    }
+
+    #[test]
+    fn sorts_by_signal() {
+        let mut tracker: Tracker<8> = Tracker::new(Config::default());
+        tracker.update([
+            ap(1, "b", -70),
+            ap(2, "a", -40),
+            ap(3, "c", -70),
+            ap(4, "a", -90),
+        ]);
+        assert_eq!(ssids(&tracker), ["a", "b", "c", "a"]);
+
+        // the order follows the signal
+        tracker.update([
+            ap(1, "b", -30),
+            ap(2, "a", -40),
+            ap(3, "c", -70),
+            ap(4, "a", -90),
+        ]);
+        assert_eq!(ssids(&tracker), ["b", "a", "c", "a"]);
+    }
+
+    #[test]
+    fn reports_new_access_points() {
+        let mut tracker: Tracker<8> = Tracker::new(Config::default());
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(
+            tracker.changes().collect::<Vec<_>>(),
+            [Change::Appeared(ap(1, "a", -50))]
+        );
+
+        tracker.update([ap(1, "a", -55), ap(2, "b", -60)]);
+        assert_eq!(
+            tracker.changes().collect::<Vec<_>>(),
+            [Change::Appeared(ap(2, "b", -60))]
+        );
+
+        tracker.update([ap(1, "a", -55), ap(2, "b", -60)]);
+        assert_eq!(tracker.changes().count(), 0);
+        assert_eq!(tracker.scans(), 3);
+    }
+
+    #[test]
+    fn tolerates_missed_scans() {
+        let mut tracker: Tracker<8> = Tracker::new(Config { missed_scans: 2 });
+        tracker.update([ap(1, "a", -50), ap(2, "b", -80)]);
+
+        // one missed scan keeps it with its last signal
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(tracker.changes().count(), 0);
+        assert_eq!(ssids(&tracker), ["a", "b"]);
+
+        // found again, the count starts over
+        tracker.update([ap(1, "a", -50), ap(2, "b", -75)]);
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(tracker.changes().count(), 0);
+
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(
+            tracker.changes().collect::<Vec<_>>(),
+            [Change::Disappeared(ap(2, "b", -75))]
+        );
+        assert_eq!(ssids(&tracker), ["a"]);
+        assert_eq!(tracker.len(), 1);
+
+        // reported once
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(tracker.changes().count(), 0);
+    }
+
+    #[test]
+    fn reappears() {
+        let mut tracker: Tracker<8> = Tracker::new(Config { missed_scans: 1 });
+        tracker.update([ap(1, "a", -50)]);
+        tracker.update([]);
+        assert!(tracker.is_empty());
+
+        tracker.update([ap(1, "a", -60)]);
+        assert_eq!(
+            tracker.changes().collect::<Vec<_>>(),
+            [Change::Appeared(ap(1, "a", -60))]
+        );
+    }
+
+    #[test]
+    fn gone_and_back_in_one_scan() {
+        let mut tracker: Tracker<8> = Tracker::new(Config { missed_scans: 1 });
+        tracker.update([ap(1, "a", -50)]);
+        tracker.update([]);
+        assert_eq!(tracker.changes().count(), 1);
+
+        // zero means one
+        let mut tracker: Tracker<8> = Tracker::new(Config { missed_scans: 0 });
+        tracker.update([ap(1, "a", -50)]);
+        tracker.update([ap(1, "a", -50)]);
+        assert_eq!(tracker.changes().count(), 0);
+        tracker.update([]);
+        assert_eq!(tracker.changes().count(), 1);
+    }
+
+    #[test]
+    fn keeps_the_strongest_duplicate() {
+        let mut tracker: Tracker<8> = Tracker::new(Config::default());
+        tracker.update([ap(1, "a", -70), ap(1, "a", -50), ap(1, "a", -60)]);
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.access_points().next().unwrap().rssi, -50);
+    }
+
+    #[test]
+    fn replaces_the_weakest_when_full() {
+        let mut tracker: Tracker<2> = Tracker::new(Config::default());
+        tracker.update([ap(1, "a", -50), ap(2, "b", -80), ap(3, "c", -60)]);
+        assert_eq!(ssids(&tracker), ["a", "c"]);
+        assert_eq!(tracker.dropped(), 1);
+
+        tracker.update([ap(1, "a", -50), ap(3, "c", -60), ap(4, "d", -90)]);
+        assert_eq!(ssids(&tracker), ["a", "c"]);
+        assert_eq!(tracker.dropped(), 1);
+
+        tracker.update([ap(1, "a", -50), ap(3, "c", -60)]);
+        assert_eq!(tracker.dropped(), 0);
+    }
+
+    #[test]
+    fn table() {
+        let mut tracker: Tracker<4> = Tracker::new(Config::default());
+        let hidden = AccessPoint::new("", [0x02, 0, 0, 0, 0, 3], 1, -60, Security::Wpa2Wpa3);
+        let cafe = AccessPoint::new("cafe", [0x02, 0, 0, 0, 0, 2], 11, -71, Security::Open);
+        tracker.update([ap(1, "home", -48), hidden, cafe]);
+        tracker.update([ap(1, "home", -48), hidden]);
+
+        let expected = "\
+SSID                             BSSID              CH  RSSI  SECURITY
+home                             02:00:00:00:00:01   1   -48  WPA2
+<hidden>                         02:00:00:00:00:03   1   -60  WPA2/WPA3
+cafe                             02:00:00:00:00:02  11   -71? open";
+        assert_eq!(tracker.table().to_string(), expected);
+    }
+}
@@ -0,0 +1,289 @@
+//! Keeps a Wi-Fi station connected.
+//!
+//! [`Supervisor`] is a state machine that doesn't talk to the radio itself: the application
+//! regularly reports the state of the link and whether the network stack has an IP address, and
+//! the supervisor answers with the [`Action`] to take. This keeps it independent of the Wi-Fi
+//! driver and network stack, and allows testing it on the host.
+//!
+//! ```
+//! use wifi_supervisor::{Action, Config, Link, State, Supervisor};
+//!
+//! let mut supervisor = Supervisor::new(Config::default());
+//!
+//! // right after starting the controller the supervisor wants to connect
+//! let link = Link { connected: false, has_ip: false };
+//! assert_eq!(supervisor.update(0, link), Action::Connect);
+//! assert_eq!(supervisor.state(), State::Connecting);
+//!
+//! // once associated, DHCP is started from scratch
+//! let link = Link { connected: true, has_ip: false };
+//! assert_eq!(supervisor.update(100, link), Action::RestartDhcp);
+//! assert_eq!(supervisor.state(), State::Connected);
+//! ```
+
+#![no_std]
+
+/// State of the station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    /// The controller was started but no connection was attempted yet
+    Started,
+    /// Waiting for the connection to the access point
+    Connecting,
+    /// Connected to the access point, waiting for an IP address
+    Connected,
+    /// Connected and the network stack has an IP address
+    GotIp,
+    /// Not connected, waiting for the next attempt
+    Disconnected,
+}
+
+/// What the application has to do to get the station connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Nothing to do
+    None,
+    /// Start connecting to the access point
+    Connect,
+    /// Abort the current connection (attempt)
+    Disconnect,
+    /// Forget the current DHCP lease and request a new one
+    RestartDhcp,
+}
+
+/// The state of the link as seen by the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Link {
+    /// The station is associated with the access point
+    pub connected: bool,
+    /// The network stack has an IP address
+    pub has_ip: bool,
+}
+
+/// Timing of the supervisor, all values are in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Delay before the first retry
+    pub initial_backoff: u64,
+    /// Upper limit for the delay between retries
+    pub max_backoff: u64,
+    /// Give up on a connection attempt after this long
+    pub connect_timeout: u64,
+    /// Reconnect if DHCP didn't give us an address after this long
+    pub dhcp_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            initial_backoff: 1_000,
+            max_backoff: 60_000,
+            connect_timeout: 15_000,
+            dhcp_timeout: 30_000,
+        }
+    }
+}
+
+// the delays between connection attempts, re-exported for the applications that used it from here
+pub use backoff::Backoff;
+
+/// Wi-Fi station connection state machine
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Supervisor {
+    config: Config,
+    state: State,
+    backoff: Backoff,
+    /// When the current state was entered
+    since: u64,
+    /// When to attempt the next connection while disconnected
+    retry_at: u64,
+}
+
+impl Supervisor {
+    pub const fn new(config: Config) -> Self {
+        Self {
+            backoff: Backoff::new(config.initial_backoff, config.max_backoff),
+            config,
+            state: State::Started,
+            since: 0,
+            retry_at: 0,
+        }
+    }
+
+    /// The current state
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns `true` if the network can be used
+    pub fn is_up(&self) -> bool {
+        self.state == State::GotIp
+    }
+
+    /// Feeds the current state of the link and returns what to do next
+    ///
+    /// `now` is a monotonic timestamp in milliseconds. Call this regularly, e.g. every time the
+    /// network stack is polled.
+    pub fn update(&mut self, now: u64, link: Link) -> Action {
+        match self.state {
+            State::Started => self.connect(now),
+            State::Connecting if link.connected => {
+                self.enter(State::Connected, now);
+                Action::RestartDhcp
+            }
+            State::Connecting if now.saturating_sub(self.since) >= self.config.connect_timeout => {
+                self.retry_later(now);
+                Action::Disconnect
+            }
+            State::Connected | State::GotIp if !link.connected => {
+                self.retry_later(now);
+                Action::None
+            }
+            State::Connected if link.has_ip => {
+                self.enter(State::GotIp, now);
+                self.backoff.reset();
+                Action::None
+            }
+            State::Connected if now.saturating_sub(self.since) >= self.config.dhcp_timeout => {
+                self.retry_later(now);
+                Action::Disconnect
+            }
+            State::GotIp if !link.has_ip => {
+                // the lease expired, the DHCP client is already asking for a new one
+                self.enter(State::Connected, now);
+                Action::None
+            }
+            State::Disconnected if now >= self.retry_at => self.connect(now),
+            _ => Action::None,
+        }
+    }
+
+    /// Reports that starting the connection failed right away
+    pub fn connect_failed(&mut self, now: u64) {
+        self.retry_later(now);
+    }
+
+    fn connect(&mut self, now: u64) -> Action {
+        self.enter(State::Connecting, now);
+        Action::Connect
+    }
+
+    fn retry_later(&mut self, now: u64) {
+        self.enter(State::Disconnected, now);
+        self.retry_at = now.saturating_add(self.backoff.next_delay());
+    }
+
+    fn enter(&mut self, state: State, now: u64) {
+        self.state = state;
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWN: Link = Link {
+        connected: false,
+        has_ip: false,
+    };
+    const ASSOCIATED: Link = Link {
+        connected: true,
+        has_ip: false,
+    };
+    const UP: Link = Link {
+        connected: true,
+        has_ip: true,
+    };
+
+    fn config() -> Config {
+        Config {
+            initial_backoff: 100,
+            max_backoff: 1_000,
+            connect_timeout: 500,
+            dhcp_timeout: 2_000,
+        }
+    }
+
+    /// Brings a new supervisor up to `GotIp` at time 20
+    fn up() -> Supervisor {
+        let mut supervisor = Supervisor::new(config());
+        assert_eq!(supervisor.update(0, DOWN), Action::Connect);
+        assert_eq!(supervisor.update(10, ASSOCIATED), Action::RestartDhcp);
+        assert_eq!(supervisor.update(20, UP), Action::None);
+        assert_eq!(supervisor.state(), State::GotIp);
+        supervisor
+    }
+
+    #[test]
+    fn connects_and_gets_ip() {
+        let supervisor = up();
+        assert!(supervisor.is_up());
+    }
+
+    #[test]
+    fn connect_timeout_retries_with_backoff() {
+        let mut supervisor = Supervisor::new(config());
+        assert_eq!(supervisor.update(0, DOWN), Action::Connect);
+        assert_eq!(supervisor.update(499, DOWN), Action::None);
+        assert_eq!(supervisor.update(500, DOWN), Action::Disconnect);
+        assert_eq!(supervisor.state(), State::Disconnected);
+
+        // first retry after the initial backoff
+        assert_eq!(supervisor.update(599, DOWN), Action::None);
+        assert_eq!(supervisor.update(600, DOWN), Action::Connect);
+
+        // the next one waits twice as long
+        assert_eq!(supervisor.update(1_100, DOWN), Action::Disconnect);
+        assert_eq!(supervisor.update(1_299, DOWN), Action::None);
+        assert_eq!(supervisor.update(1_300, DOWN), Action::Connect);
+    }
+
+    #[test]
+    fn connect_failed_retries_with_backoff() {
+        let mut supervisor = Supervisor::new(config());
+        assert_eq!(supervisor.update(0, DOWN), Action::Connect);
+        supervisor.connect_failed(0);
+        assert_eq!(supervisor.state(), State::Disconnected);
+        assert_eq!(supervisor.update(99, DOWN), Action::None);
+        assert_eq!(supervisor.update(100, DOWN), Action::Connect);
+    }
+
+    #[test]
+    fn reconnects_and_restarts_dhcp_after_link_loss() {
+        let mut supervisor = up();
+
+        assert_eq!(supervisor.update(1_000, DOWN), Action::None);
+        assert_eq!(supervisor.state(), State::Disconnected);
+
+        // the backoff was reset by the successful connection
+        assert_eq!(supervisor.update(1_100, DOWN), Action::Connect);
+        assert_eq!(supervisor.update(1_200, ASSOCIATED), Action::RestartDhcp);
+        assert_eq!(supervisor.update(1_300, UP), Action::None);
+        assert!(supervisor.is_up());
+    }
+
+    #[test]
+    fn dhcp_timeout_reconnects() {
+        let mut supervisor = Supervisor::new(config());
+        supervisor.update(0, DOWN);
+        supervisor.update(10, ASSOCIATED);
+        assert_eq!(supervisor.update(2_009, ASSOCIATED), Action::None);
+        assert_eq!(supervisor.update(2_010, ASSOCIATED), Action::Disconnect);
+        assert_eq!(supervisor.state(), State::Disconnected);
+    }
+
+    #[test]
+    fn lost_lease_waits_for_dhcp() {
+        let mut supervisor = up();
+        assert_eq!(supervisor.update(1_000, ASSOCIATED), Action::None);
+        assert_eq!(supervisor.state(), State::Connected);
+        assert_eq!(supervisor.update(1_100, UP), Action::None);
+        assert_eq!(supervisor.state(), State::GotIp);
+    }
+}